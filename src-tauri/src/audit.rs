@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::AppConfig;
+use crate::db::{Database, PlanRecord};
+use crate::error::{AppError, Result};
+use crate::utils::path::{to_native_path, to_posix_string};
+use crate::utils::time::now_timestamp;
+
+/// `prevHash` of the first line in a log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum AuditEvent {
+    /// Written at the start of every export, so each appended run carries
+    /// the context it was made in.
+    #[serde(rename_all = "camelCase")]
+    Session {
+        app_version: String,
+        exported_at: String,
+        image_root: String,
+        output_root: String,
+        plan_generated_at: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Operation {
+        log_id: i64,
+        at: String,
+        operation: String,
+        status: String,
+        error: Option<String>,
+        origin: Option<String>,
+        target: Option<String>,
+        file_size: Option<u64>,
+        blake3: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditLine {
+    sequence: u64,
+    #[serde(flatten)]
+    event: AuditEvent,
+    prev_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditExport {
+    pub path: String,
+    /// Operations added by this export.
+    pub appended: usize,
+    pub total_lines: u64,
+    /// Hash of the last line; keeping it elsewhere pins the whole log.
+    pub head_hash: String,
+}
+
+/// Where an existing log ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainHead {
+    pub lines: u64,
+    pub hash: String,
+    pub last_log_id: i64,
+}
+
+impl Default for ChainHead {
+    fn default() -> Self {
+        Self {
+            lines: 0,
+            hash: GENESIS_HASH.to_string(),
+            last_log_id: 0,
+        }
+    }
+}
+
+/// Appends every operation not yet in the JSONL log at `destination`,
+/// creating it if needed. Each line carries the BLAKE3 of the line before
+/// it, so editing, dropping or reordering lines breaks the chain. An
+/// existing log is verified first and never rewritten.
+pub fn export_audit_log(
+    config: &AppConfig,
+    database: &Database,
+    destination: &Path,
+) -> Result<AuditExport> {
+    let mut head = if destination.exists() {
+        verify_audit_log(destination)?
+    } else {
+        ChainHead::default()
+    };
+
+    let logs = database.operation_logs_after(head.last_log_id)?;
+    let entries: HashMap<i64, PlanRecord> = database
+        .plan_entries()?
+        .into_iter()
+        .map(|entry| (entry.id, entry))
+        .collect();
+
+    let mut events = vec![AuditEvent::Session {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: now_timestamp()?,
+        image_root: to_posix_string(&config.image_root).into_owned(),
+        output_root: to_posix_string(&config.output_root).into_owned(),
+        plan_generated_at: database.get_meta("plan_generated_at")?,
+    }];
+    for log in &logs {
+        let entry = log.plan_entry_id.and_then(|id| entries.get(&id));
+        events.push(AuditEvent::Operation {
+            log_id: log.id,
+            at: log.created_at.clone(),
+            operation: log.operation.clone(),
+            status: log.status.clone(),
+            error: log.error.clone(),
+            origin: entry
+                .map(|entry| entry.origin_full_path.clone())
+                .or_else(|| log.path.clone()),
+            target: entry.map(|entry| {
+                let target = to_native_path(&entry.target_path).join(&entry.target_file_name);
+                to_posix_string(&target).into_owned()
+            }),
+            file_size: entry.map(|entry| entry.file_size),
+            blake3: entry.and_then(|entry| entry.blake3_hash.clone()),
+        });
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(destination)?;
+    let mut writer = BufWriter::new(file);
+    for event in events {
+        let line = AuditLine {
+            sequence: head.lines,
+            event,
+            prev_hash: head.hash.clone(),
+        };
+        let mut value = serde_json::to_value(&line)?;
+        head.hash = line_hash(&value);
+        value["hash"] = Value::String(head.hash.clone());
+        writeln!(writer, "{}", serde_json::to_string(&value)?)?;
+        head.lines += 1;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    Ok(AuditExport {
+        path: to_posix_string(destination).into_owned(),
+        appended: logs.len(),
+        total_lines: head.lines,
+        head_hash: head.hash,
+    })
+}
+
+/// Checks every line's sequence, link and hash, returning where the log
+/// ends. The first broken line is reported by number.
+pub fn verify_audit_log(path: &Path) -> Result<ChainHead> {
+    let mut head = ChainHead::default();
+    for (idx, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let broken = || {
+            AppError::Config(format!(
+                "audit log {} fails verification at line {}",
+                path.display(),
+                idx + 1
+            ))
+        };
+        let mut value: Value = serde_json::from_str(line).map_err(|_| broken())?;
+        let stored = value
+            .as_object_mut()
+            .and_then(|object| object.remove("hash"))
+            .and_then(|hash| hash.as_str().map(str::to_string))
+            .ok_or_else(broken)?;
+        if value["sequence"].as_u64() != Some(head.lines)
+            || value["prevHash"].as_str() != Some(head.hash.as_str())
+            || line_hash(&value) != stored
+        {
+            return Err(broken());
+        }
+        if let Some(log_id) = value["logId"].as_i64() {
+            head.last_log_id = log_id;
+        }
+        head.hash = stored;
+        head.lines += 1;
+    }
+    Ok(head)
+}
+
+/// BLAKE3 of the line without its `hash`, with keys in sorted order.
+fn line_hash(value: &Value) -> String {
+    blake3::hash(value.to_string().as_bytes())
+        .to_hex()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::{NewOperationLog, NewPlanEntry};
+    use tempfile::tempdir;
+
+    #[test]
+    fn exports_append_and_tampering_is_detected() -> Result<()> {
+        let dir = tempdir()?;
+        let config = test_config(dir.path());
+        let database = Database::initialize(&config)?;
+        database.replace_plan_entries(&[NewPlanEntry {
+            file_hash: "md5".into(),
+            blake3_hash: Some("b3".into()),
+            file_size: 4,
+            origin_file_name: "a.jpg".into(),
+            origin_full_path: "/photos/a.jpg".into(),
+            target_path: "/library/2021/".into(),
+            target_file_name: "a.jpg".into(),
+            is_duplicate: false,
+            volume_root: None,
+            strip_metadata: false,
+            reason: None,
+        }])?;
+        let entry_id = database.plan_entries()?[0].id;
+        let log = |status: &str| NewOperationLog {
+            plan_entry_id: Some(entry_id),
+            path: None,
+            operation: "copy".into(),
+            status: status.into(),
+            error: None,
+        };
+        database.append_operation_log(log("failure"))?;
+
+        let path = dir.path().join("audit.jsonl");
+        let first = export_audit_log(&config, &database, &path)?;
+        assert_eq!((first.appended, first.total_lines), (1, 2));
+
+        database.append_operation_log(log("success"))?;
+        let second = export_audit_log(&config, &database, &path)?;
+        assert_eq!((second.appended, second.total_lines), (1, 4));
+        assert_eq!(verify_audit_log(&path)?.hash, second.head_hash);
+
+        let tampered = fs::read_to_string(&path)?.replacen("failure", "success", 1);
+        fs::write(&path, tampered)?;
+        assert!(verify_audit_log(&path).is_err());
+        Ok(())
+    }
+}
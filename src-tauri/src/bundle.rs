@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{AppConfig, ConfigService, SCHEMA_VERSION};
+use crate::db::{Database, DB_VERSION};
+use crate::error::{AppError, Result};
+use crate::utils::fs::ensure_dir;
+use crate::utils::json::{read_json, write_json};
+use crate::utils::time::now_timestamp;
+
+/// Bumped whenever the bundle layout itself changes.
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const CONFIG_FILE: &str = "config.json";
+const DATABASE_FILE: &str = "phototidy.sqlite3";
+const ORIGIN_INFO_FILE: &str = "origin-info.json";
+const TARGET_PLAN_FILE: &str = "target-plan.json";
+
+/// Describes an exported state bundle: a directory holding the config, a
+/// copy of the database and the JSON artifacts, with the versions that wrote
+/// them so an import can refuse or migrate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub config_schema_version: i32,
+    pub database_version: i32,
+    pub exported_at: String,
+    pub artifacts: Vec<String>,
+}
+
+/// Exports the app state into `destination`, which must be missing or an
+/// empty directory.
+pub fn export_state(
+    config: &AppConfig,
+    database: &Database,
+    destination: &Path,
+) -> Result<StateManifest> {
+    if destination.exists() && fs::read_dir(destination)?.next().is_some() {
+        return Err(AppError::Config(format!(
+            "export destination {} is not empty",
+            destination.display()
+        )));
+    }
+    ensure_dir(destination)?;
+
+    let document: Value = if config.config_file_path.exists() {
+        read_json(&config.config_file_path)?
+    } else {
+        Value::Object(Default::default())
+    };
+    write_json(destination.join(CONFIG_FILE), &document)?;
+    database.export_to(&destination.join(DATABASE_FILE))?;
+
+    let mut artifacts = Vec::new();
+    for (source, name) in artifact_paths(config) {
+        if source.is_file() {
+            fs::copy(&source, destination.join(name))?;
+            artifacts.push(name.to_string());
+        }
+    }
+
+    let manifest = StateManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        config_schema_version: SCHEMA_VERSION,
+        database_version: DB_VERSION,
+        exported_at: now_timestamp()?,
+        artifacts,
+    };
+    write_json(destination.join(MANIFEST_FILE), &manifest)?;
+    Ok(manifest)
+}
+
+/// Replaces the current config, database and artifacts with the bundle in
+/// `source`. Bundles from newer releases are refused; older ones are
+/// migrated the same way a local upgrade would be. Roots that do not exist
+/// on this machine are left for remap detection to pick up. The bundle's
+/// config is checked before anything changes, and the current config is
+/// put back if the import fails after the database was replaced.
+pub fn import_state(
+    service: &ConfigService,
+    database: &Database,
+    source: &Path,
+) -> Result<StateManifest> {
+    let manifest = read_manifest(source)?;
+
+    let imported: Value = read_json(source.join(CONFIG_FILE))?;
+    let (document, config) = service.prepare_file(|document| {
+        // Keep keys the bundle predates, so defaults for this machine win
+        // only where the bundle has nothing to say.
+        match (document, imported) {
+            (Value::Object(current), Value::Object(imported)) => current.extend(imported),
+            (document, imported) => *document = imported,
+        }
+        Ok(())
+    })?;
+    let (previous_document, previous) = service.prepare_file(|_| Ok(()))?;
+
+    database.import_from(&source.join(DATABASE_FILE))?;
+    let applied = (|| {
+        service.write_file(&document, &config)?;
+        database.configure_paths(&config);
+        for (target, name) in artifact_paths(&config) {
+            if manifest.artifacts.iter().any(|artifact| artifact == name) {
+                fs::copy(source.join(name), target)?;
+            }
+        }
+        Ok(())
+    })();
+    if let Err(err) = applied {
+        if let Err(restore) = service.write_file(&previous_document, &previous) {
+            tracing::error!(error = %restore, "failed restoring the config after a failed import");
+        }
+        database.configure_paths(&previous);
+        return Err(err);
+    }
+    Ok(manifest)
+}
+
+fn read_manifest(source: &Path) -> Result<StateManifest> {
+    let manifest: StateManifest = read_json(source.join(MANIFEST_FILE))?;
+    if manifest.format_version > BUNDLE_FORMAT_VERSION
+        || manifest.config_schema_version > SCHEMA_VERSION
+        || manifest.database_version > DB_VERSION
+    {
+        return Err(AppError::Config(format!(
+            "bundle was written by phototidy {} and is newer than this release",
+            manifest.app_version
+        )));
+    }
+    Ok(manifest)
+}
+
+fn artifact_paths(config: &AppConfig) -> [(PathBuf, &'static str); 2] {
+    [
+        (config.origin_info_path.clone(), ORIGIN_INFO_FILE),
+        (config.target_plan_path.clone(), TARGET_PLAN_FILE),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::ExclusionKind;
+    use tempfile::tempdir;
+
+    #[test]
+    fn exported_database_imports_elsewhere() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let database = Database::initialize(&config)?;
+        database.add_exclusion(ExclusionKind::Pattern, "memes/**", None)?;
+        database.set_meta("scrub_cursor", "/output/a.jpg")?;
+        fs::write(&config.origin_info_path, b"{}")?;
+
+        let bundle = tempdir()?;
+        let destination = bundle.path().join("state");
+        let manifest = export_state(&config, &database, &destination)?;
+        assert_eq!(manifest.database_version, DB_VERSION);
+        assert_eq!(manifest.artifacts, vec![ORIGIN_INFO_FILE.to_string()]);
+        assert!(export_state(&config, &database, &destination).is_err());
+
+        let other = Database::initialize(&test_config(&tempdir()?.keep()))?;
+        other.add_exclusion(ExclusionKind::Hash, "stale", None)?;
+        let exported = fs::read(destination.join(DATABASE_FILE))?;
+        other.import_from(&destination.join(DATABASE_FILE))?;
+        // Migrations run on a copy; the bundle stays as it was saved.
+        assert_eq!(fs::read(destination.join(DATABASE_FILE))?, exported);
+        let exclusions = other.exclusions()?;
+        assert_eq!(exclusions.len(), 1);
+        assert_eq!(exclusions[0].value, "memes/**");
+        assert_eq!(
+            other.get_meta("scrub_cursor")?.as_deref(),
+            Some("/output/a.jpg")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn newer_bundles_are_refused() -> Result<()> {
+        let bundle = tempdir()?;
+        let mut manifest = StateManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            app_version: "99.0.0".into(),
+            config_schema_version: SCHEMA_VERSION,
+            database_version: DB_VERSION,
+            exported_at: now_timestamp()?,
+            artifacts: Vec::new(),
+        };
+        write_json(bundle.path().join(MANIFEST_FILE), &manifest)?;
+        assert!(read_manifest(bundle.path()).is_ok());
+
+        manifest.database_version = DB_VERSION + 1;
+        write_json(bundle.path().join(MANIFEST_FILE), &manifest)?;
+        assert!(read_manifest(bundle.path()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+use crate::db::{CatalogEntry, Database};
+use crate::error::Result;
+
+/// Name prefix digiKam uses for its own bookkeeping tags.
+const DIGIKAM_INTERNAL_TAG_PREFIX: &str = "_Digikam_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatalogKind {
+    Digikam,
+    Lightroom,
+}
+
+impl CatalogKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Digikam => "digikam",
+            Self::Lightroom => "lightroom",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogImportSummary {
+    pub kind: CatalogKind,
+    pub read_entries: usize,
+    pub imported_entries: usize,
+    /// Catalog files outside the configured image root, which a scan would
+    /// never reach.
+    pub outside_root: usize,
+    /// Existing inventory rows updated in place.
+    pub seeded_records: usize,
+}
+
+/// A file as described by the source catalog, with an absolute path.
+struct CatalogFile {
+    path: PathBuf,
+    file_size: Option<u64>,
+    captured_at: Option<String>,
+    rating: Option<u8>,
+    tags: Vec<String>,
+}
+
+/// Imports capture dates, ratings and tags from a digiKam database
+/// (`digikam4.db`) or a Lightroom catalog (`.lrcat`). Files the catalog
+/// dates are scanned without an EXIF pass; neither tool keeps full-file
+/// hashes, so content is still hashed for duplicate detection.
+pub fn import_catalog(
+    config: &AppConfig,
+    database: &Database,
+    kind: CatalogKind,
+    catalog_path: &Path,
+) -> Result<CatalogImportSummary> {
+    let catalog = Connection::open_with_flags(
+        catalog_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let files = match kind {
+        CatalogKind::Digikam => read_digikam(&catalog)?,
+        CatalogKind::Lightroom => read_lightroom(&catalog)?,
+    };
+
+    let root = config
+        .sample_image_root
+        .as_ref()
+        .unwrap_or(&config.image_root);
+    let read_entries = files.len();
+    let entries: Vec<CatalogEntry> = files
+        .into_iter()
+        .filter_map(|file| {
+            let relative = file.path.strip_prefix(root).ok()?;
+            Some(CatalogEntry {
+                relative_path: relative.to_str()?.replace('\\', "/"),
+                file_size: file.file_size,
+                captured_at: file.captured_at,
+                rating: file.rating,
+                tags: file.tags,
+            })
+        })
+        .collect();
+
+    database.replace_catalog_entries(kind.as_str(), &entries)?;
+    let seeded_records = database.seed_inventory_from_catalog()?;
+
+    tracing::info!(
+        kind = kind.as_str(),
+        imported = entries.len(),
+        seeded = seeded_records,
+        "imported catalog metadata"
+    );
+    Ok(CatalogImportSummary {
+        kind,
+        read_entries,
+        imported_entries: entries.len(),
+        outside_root: read_entries - entries.len(),
+        seeded_records,
+    })
+}
+
+fn read_digikam(catalog: &Connection) -> Result<Vec<CatalogFile>> {
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    {
+        let mut stmt = catalog.prepare(
+            "SELECT it.imageid, t.name FROM ImageTags it JOIN Tags t ON t.id = it.tagid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (image, name) = row?;
+            if !name.starts_with(DIGIKAM_INTERNAL_TAG_PREFIX) {
+                tags.entry(image).or_default().push(name);
+            }
+        }
+    }
+
+    let mut stmt = catalog.prepare(
+        "SELECT i.id, r.identifier, r.specificPath, a.relativePath, i.name, i.fileSize, \
+         info.creationDate, info.rating FROM Images i \
+         JOIN Albums a ON a.id = i.album JOIN AlbumRoots r ON r.id = a.albumRoot \
+         LEFT JOIN ImageInformation info ON info.imageid = i.id WHERE i.status = 1",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<i64>>(7)?,
+        ))
+    })?;
+
+    let mut files = Vec::new();
+    for row in rows {
+        let (id, identifier, specific_path, album, name, file_size, created, rating) = row?;
+        let Some(root) = digikam_root(identifier.as_deref(), specific_path.as_deref()) else {
+            tracing::debug!(image = id, "skipping digiKam image on an unresolvable root");
+            continue;
+        };
+        let path = root.join(album.trim_start_matches('/')).join(name);
+        files.push(CatalogFile {
+            path,
+            file_size: file_size.and_then(|size| u64::try_from(size).ok()),
+            captured_at: created.as_deref().and_then(normalize_catalog_timestamp),
+            rating: rating.and_then(catalog_rating),
+            tags: tags.remove(&id).unwrap_or_default(),
+        });
+    }
+    Ok(files)
+}
+
+/// Album roots are stored as a volume identifier plus a path on that volume.
+/// Path-based identifiers (`volumeid:?path=/photos`) carry the mount point;
+/// UUID-based ones are resolved as if the volume were mounted at `/`.
+fn digikam_root(identifier: Option<&str>, specific_path: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = identifier
+        .and_then(|value| value.split_once("path="))
+        .map(|(_, path)| path.split('&').next().unwrap_or(path))
+    {
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+    specific_path.map(PathBuf::from)
+}
+
+fn read_lightroom(catalog: &Connection) -> Result<Vec<CatalogFile>> {
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    {
+        let mut stmt = catalog.prepare(
+            "SELECT ki.image, k.name FROM AgLibraryKeywordImage ki \
+             JOIN AgLibraryKeyword k ON k.id_local = ki.tag WHERE k.name IS NOT NULL",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (image, name) = row?;
+            tags.entry(image).or_default().push(name);
+        }
+    }
+
+    let mut stmt = catalog.prepare(
+        "SELECT img.id_local, root.absolutePath, folder.pathFromRoot, file.baseName, \
+         file.extension, img.captureTime, img.rating FROM Adobe_images img \
+         JOIN AgLibraryFile file ON file.id_local = img.rootFile \
+         JOIN AgLibraryFolder folder ON folder.id_local = file.folder \
+         JOIN AgLibraryRootFolder root ON root.id_local = folder.rootFolder",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<f64>>(6)?,
+        ))
+    })?;
+
+    let mut files = Vec::new();
+    for row in rows {
+        let (id, root, folder, base_name, extension, captured, rating) = row?;
+        let file_name = match extension.filter(|ext| !ext.is_empty()) {
+            Some(ext) => format!("{base_name}.{ext}"),
+            None => base_name,
+        };
+        files.push(CatalogFile {
+            path: PathBuf::from(format!("{root}{folder}{file_name}")),
+            file_size: None,
+            captured_at: captured.as_deref().and_then(normalize_catalog_timestamp),
+            rating: rating.and_then(|value| catalog_rating(value.round() as i64)),
+            tags: tags.remove(&id).unwrap_or_default(),
+        });
+    }
+    Ok(files)
+}
+
+/// Both catalogs use ISO-8601 (`2024-06-14T10:15:30`, sometimes with
+/// fractional seconds or an offset); the local wall-clock part is kept, as
+/// with EXIF dates.
+fn normalize_catalog_timestamp(raw: &str) -> Option<String> {
+    let date_time = raw.get(..19)?;
+    let bytes = date_time.as_bytes();
+    if bytes[4] != b'-' || bytes[7] != b'-' || !matches!(bytes[10], b'T' | b' ') {
+        return None;
+    }
+    let (date, time) = (&date_time[..10], &date_time[11..]);
+    if !date.replace('-', "").bytes().all(|b| b.is_ascii_digit())
+        || !time.replace(':', "").bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(format!("{date}_{}", time.replace(':', "-")))
+}
+
+/// Ratings outside 0-5 (digiKam stores -1 for "no rating") are dropped.
+fn catalog_rating(value: i64) -> Option<u8> {
+    u8::try_from(value).ok().filter(|rating| *rating <= 5)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            if let Some(byte) = value
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::InventoryRecord;
+    use crate::media::MediaKind;
+    use crate::utils::path::to_posix_string;
+    use tempfile::tempdir;
+
+    #[test]
+    fn digikam_metadata_seeds_the_inventory() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let database = Database::initialize(&config)?;
+        database.replace_inventory(&[InventoryRecord {
+            id: None,
+            file_hash: "md5".into(),
+            blake3_hash: None,
+            file_size: 10,
+            file_name: "a.jpg".into(),
+            relative_path: "trip/a.jpg".into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }])?;
+
+        let catalog_path = config.home_dir.join("digikam4.db");
+        let catalog = Connection::open(&catalog_path)?;
+        let images = to_posix_string(&config.image_root).into_owned();
+        catalog.execute_batch(&format!(
+            "CREATE TABLE AlbumRoots (id INTEGER, identifier TEXT, specificPath TEXT);
+             CREATE TABLE Albums (id INTEGER, albumRoot INTEGER, relativePath TEXT);
+             CREATE TABLE Images (id INTEGER, album INTEGER, name TEXT, status INTEGER, fileSize INTEGER);
+             CREATE TABLE ImageInformation (imageid INTEGER, rating INTEGER, creationDate TEXT);
+             CREATE TABLE Tags (id INTEGER, pid INTEGER, name TEXT);
+             CREATE TABLE ImageTags (imageid INTEGER, tagid INTEGER);
+             INSERT INTO AlbumRoots VALUES (1, 'volumeid:?path={images}', '/');
+             INSERT INTO AlbumRoots VALUES (2, 'volumeid:?path=/elsewhere', '/');
+             INSERT INTO Albums VALUES (1, 1, '/trip'), (2, 2, '/');
+             INSERT INTO Images VALUES (1, 1, 'a.jpg', 1, 10), (2, 2, 'b.jpg', 1, 5);
+             INSERT INTO ImageInformation VALUES (1, 4, '2019-07-04T18:30:00.000'), (2, -1, NULL);
+             INSERT INTO Tags VALUES (1, 0, 'Holiday'), (2, 0, '_Digikam_Internal_Tags_');
+             INSERT INTO ImageTags VALUES (1, 1), (1, 2);"
+        ))?;
+        drop(catalog);
+
+        let summary = import_catalog(&config, &database, CatalogKind::Digikam, &catalog_path)?;
+        assert_eq!(summary.read_entries, 2);
+        assert_eq!(summary.imported_entries, 1);
+        assert_eq!(summary.outside_root, 1);
+        assert_eq!(summary.seeded_records, 1);
+
+        let record = &database.inventory_snapshot()?[0];
+        assert_eq!(record.captured_at.as_deref(), Some("2019-07-04_18-30-00"));
+        assert_eq!(record.rating, Some(4));
+        assert_eq!(record.tags, vec!["Holiday".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn lightroom_catalog_entries_are_read() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let database = Database::initialize(&config)?;
+
+        let catalog_path = config.home_dir.join("catalog.lrcat");
+        let catalog = Connection::open(&catalog_path)?;
+        let images = to_posix_string(&config.image_root).into_owned();
+        catalog.execute_batch(&format!(
+            "CREATE TABLE AgLibraryRootFolder (id_local INTEGER, absolutePath TEXT);
+             CREATE TABLE AgLibraryFolder (id_local INTEGER, rootFolder INTEGER, pathFromRoot TEXT);
+             CREATE TABLE AgLibraryFile (id_local INTEGER, folder INTEGER, baseName TEXT, extension TEXT);
+             CREATE TABLE Adobe_images (id_local INTEGER, rootFile INTEGER, captureTime TEXT, rating REAL);
+             CREATE TABLE AgLibraryKeyword (id_local INTEGER, name TEXT);
+             CREATE TABLE AgLibraryKeywordImage (image INTEGER, tag INTEGER);
+             INSERT INTO AgLibraryRootFolder VALUES (1, '{images}/');
+             INSERT INTO AgLibraryFolder VALUES (1, 1, '2020/');
+             INSERT INTO AgLibraryFile VALUES (1, 1, 'IMG_0001', 'JPG');
+             INSERT INTO Adobe_images VALUES (1, 1, '2020-02-03T04:05:06+01:00', 5.0);
+             INSERT INTO AgLibraryKeyword VALUES (1, 'Family');
+             INSERT INTO AgLibraryKeywordImage VALUES (1, 1);"
+        ))?;
+        drop(catalog);
+
+        let summary = import_catalog(&config, &database, CatalogKind::Lightroom, &catalog_path)?;
+        assert_eq!(summary.imported_entries, 1);
+        assert_eq!(
+            database.catalog_entries()?,
+            vec![CatalogEntry {
+                relative_path: "2020/IMG_0001.JPG".into(),
+                file_size: None,
+                captured_at: Some("2020-02-03_04-05-06".into()),
+                rating: Some(5),
+                tags: vec!["Family".into()],
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::utils::fs::matches_extension;
+
+/// iCloud Drive replaces evicted files with `.<name>.icloud` stubs.
+const ICLOUD_STUB_EXT: &str = ".icloud";
+
+/// A file whose contents live in the cloud rather than on disk. Hashing one
+/// as-is reads either a tiny stub or triggers an unplanned download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placeholder {
+    /// An iCloud stub standing in for `original`, which does not exist yet.
+    ICloudStub { stub: PathBuf, original: PathBuf },
+    /// A file provider entry (OneDrive Files-On-Demand, iCloud on newer
+    /// macOS) that exists at its real path but holds no data locally.
+    Dataless(PathBuf),
+}
+
+impl Placeholder {
+    /// Where the real file is, or will be once downloaded.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::ICloudStub { original, .. } => original,
+            Self::Dataless(path) => path,
+        }
+    }
+}
+
+/// Recognizes placeholders for files the scan would otherwise pick up.
+pub fn detect(
+    path: &Path,
+    metadata: &Metadata,
+    extensions: &HashSet<String>,
+) -> Option<Placeholder> {
+    if let Some(original) = icloud_original(path) {
+        return matches_extension(&original, extensions).then(|| Placeholder::ICloudStub {
+            stub: path.to_path_buf(),
+            original,
+        });
+    }
+    (is_dataless(metadata) && matches_extension(path, extensions))
+        .then(|| Placeholder::Dataless(path.to_path_buf()))
+}
+
+fn icloud_original(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let original = name.strip_prefix('.')?.strip_suffix(ICLOUD_STUB_EXT)?;
+    (!original.is_empty()).then(|| path.with_file_name(original))
+}
+
+#[cfg(windows)]
+fn is_dataless(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+    metadata.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE
+            | FILE_ATTRIBUTE_RECALL_ON_OPEN
+            | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+#[cfg(target_os = "macos")]
+fn is_dataless(metadata: &Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const SF_DATALESS: u32 = 0x4000_0000;
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn is_dataless(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Downloads the placeholder's contents and returns the real path.
+pub fn hydrate(placeholder: &Placeholder) -> io::Result<PathBuf> {
+    match placeholder {
+        // Reading the whole file makes the provider fetch it.
+        Placeholder::Dataless(path) => {
+            io::copy(&mut File::open(path)?, &mut io::sink())?;
+            Ok(path.clone())
+        }
+        Placeholder::ICloudStub { stub, original } => download_icloud(stub, original),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn download_icloud(stub: &Path, original: &Path) -> io::Result<PathBuf> {
+    use std::process::Command;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+    const DOWNLOAD_POLL: Duration = Duration::from_millis(500);
+
+    let status = Command::new("brctl").arg("download").arg(stub).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "brctl download exited with {status}"
+        )));
+    }
+    // brctl returns once the download is queued; the stub disappears when
+    // the real file lands.
+    let started = Instant::now();
+    while stub.exists() || !original.exists() {
+        if started.elapsed() >= DOWNLOAD_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "iCloud download did not finish",
+            ));
+        }
+        thread::sleep(DOWNLOAD_POLL);
+    }
+    Ok(original.to_path_buf())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn download_icloud(_stub: &Path, _original: &Path) -> io::Result<PathBuf> {
+    // Stubs synced to another OS cannot be downloaded from here.
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "iCloud stubs can only be downloaded on macOS",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn icloud_stubs_name_their_original() -> io::Result<()> {
+        let dir = tempdir()?;
+        let stub = dir.path().join(".IMG_0001.JPG.icloud");
+        fs::write(&stub, b"bplist")?;
+        let exts = HashSet::from([".jpg".to_string()]);
+
+        let placeholder = detect(&stub, &fs::metadata(&stub)?, &exts);
+        assert_eq!(
+            placeholder.as_ref().map(Placeholder::path),
+            Some(dir.path().join("IMG_0001.JPG").as_path())
+        );
+
+        let document = dir.path().join(".notes.txt.icloud");
+        fs::write(&document, b"bplist")?;
+        assert_eq!(detect(&document, &fs::metadata(&document)?, &exts), None);
+        assert_eq!(icloud_original(Path::new("/photos/.icloud")), None);
+        Ok(())
+    }
+}
@@ -1,16 +1,25 @@
 use std::collections::HashSet;
 use std::env;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use directories::BaseDirs;
 use parking_lot::RwLock;
 use serde::Deserialize;
 use serde::Serialize;
-use tracing::debug;
+use serde_json::Value;
+use tracing::{debug, warn};
 
 use crate::error::{AppError, Result};
+use crate::media::{MediaExtensions, MediaKind};
+use crate::rules::{compile_folder_template, compile_name_template, compile_targets};
+use crate::template::TemplateError;
 use crate::utils::fs::{ensure_dir, ensure_parent_dir};
-use crate::utils::path::{ensure_trailing_separator, join_and_normalize, to_posix_string};
+use crate::utils::json;
+use crate::utils::path::{
+    clean_path, ensure_trailing_separator, join_and_normalize, normalize, sanitize_component,
+    to_posix_string,
+};
+use crate::utils::time::NameTimestamp;
 
 const DEFAULT_CONFIG_JSON: &str = include_str!("../../config/config.json");
 
@@ -22,11 +31,717 @@ struct RawConfig {
     #[serde(default)]
     image_root: Option<String>,
     image_root_default_name: String,
+    /// Legacy flat extension list, only used when no typed group is present.
+    #[serde(default)]
     image_exts: Vec<String>,
+    #[serde(default)]
+    photo_exts: Option<Vec<String>>,
+    #[serde(default)]
+    video_exts: Option<Vec<String>>,
+    #[serde(default)]
+    raw_exts: Option<Vec<String>>,
+    #[serde(default)]
+    sidecar_exts: Option<Vec<String>>,
     output_root_name: String,
     origin_info_json: String,
     target_file_structure_json: String,
     folder_for_duplicates: String,
+    #[serde(flatten)]
+    plan: PlanOptions,
+    #[serde(flatten)]
+    scan: ScanOptions,
+    #[serde(default)]
+    scrub: ScrubOptions,
+    #[serde(default)]
+    path_storage: PathStorage,
+    #[serde(default)]
+    power: PowerOptions,
+    #[serde(default)]
+    io: IoOptions,
+    #[serde(default)]
+    telemetry: TelemetryOptions,
+}
+
+/// Planner settings, kept flat in `config.json` but grouped here so the
+/// planner can take them as one unit.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanOptions {
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+    #[serde(default)]
+    pub output_roots: MediaOutputRoots,
+    /// Ordered volumes that share the default output root's work. The
+    /// planner fills the first one and spills later buckets to the next.
+    #[serde(default)]
+    pub output_volumes: Vec<OutputVolume>,
+    /// Cap on files per destination folder; overflow goes to `_partN`
+    /// siblings. `None` disables splitting.
+    #[serde(default)]
+    pub max_files_per_folder: Option<usize>,
+    #[serde(default)]
+    pub folder_layout: FolderLayout,
+    /// Template for date buckets such as `{year}/{month}` or
+    /// `{camera_model}/{year}`. Set, it replaces `folder_layout` and the
+    /// granularity settings.
+    #[serde(default)]
+    pub folder_template: Option<String>,
+    #[serde(default)]
+    pub bucket_granularity: BucketGranularity,
+    /// Granularities for ranges of years, e.g. yearly folders before 2015;
+    /// the first covering a file's year wins over `bucket_granularity`.
+    #[serde(default)]
+    pub granularity_periods: Vec<GranularityPeriod>,
+    #[serde(default)]
+    pub duplicates_layout: DuplicatesLayout,
+    /// Leave file names as found, bar `_dupN` counters for clashes, instead
+    /// of prefixing the timestamp.
+    #[serde(default)]
+    pub keep_original_names: bool,
+    /// Lowercase extensions in planned names and settle variants such as
+    /// `.jpeg` on one spelling.
+    #[serde(default)]
+    pub normalize_extensions: bool,
+    /// strftime-like format of the timestamp prefixed to file names, see
+    /// `NameTimestamp`.
+    #[serde(default = "default_name_timestamp")]
+    pub name_timestamp: String,
+    /// Template for planned file names, such as `{date}_{seq}` or
+    /// `{camera}-{original}`. Unset, names are the timestamp and the
+    /// original name; `keep_original_names` wins over it.
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// Folder name used for `{artist}` when a file has no EXIF artist.
+    #[serde(default = "default_artist_fallback")]
+    pub artist_fallback: String,
+    /// Plan files the scanner flagged as thumbnails or cache artifacts.
+    #[serde(default)]
+    pub include_derivatives: bool,
+    #[serde(default)]
+    pub share: ShareOptions,
+    #[serde(default)]
+    pub transcode: TranscodeOptions,
+    #[serde(default)]
+    pub export: ExportOptions,
+}
+
+fn default_artist_fallback() -> String {
+    "Unknown Artist".into()
+}
+
+fn default_name_timestamp() -> String {
+    "%Y-%m-%d_%H-%M-%S".into()
+}
+
+impl PlanOptions {
+    /// These options with the settings in `overrides` replaced, keyed as in
+    /// `config.json`, for trying them out without saving them.
+    pub fn with_overrides(&self, overrides: &Value) -> Result<Self> {
+        let Value::Object(overrides) = overrides else {
+            return Err(AppError::Config("plan overrides must be an object".into()));
+        };
+        let mut document = serde_json::to_value(self)?;
+        if let Value::Object(settings) = &mut document {
+            for (key, value) in overrides {
+                if !settings.contains_key(key) {
+                    return Err(AppError::Config(format!("{key} is not a plan setting")));
+                }
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+        let options: Self = serde_json::from_value(document)?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Parses every template-bearing setting so mistakes surface when the
+    /// config is loaded instead of as broken paths during planning.
+    pub fn validate(&self) -> Result<()> {
+        compile_targets(&self.routing_rules)?;
+        compile_folder_template(self.folder_template.as_deref())?;
+        compile_name_template(self.name_template.as_deref())?;
+        NameTimestamp::parse(&self.name_timestamp)?;
+        if sanitize_component(&self.artist_fallback).is_empty() {
+            return Err(AppError::Template {
+                field: "artistFallback".into(),
+                source: TemplateError::EmptyResult,
+            });
+        }
+        if let Some(period) = self
+            .granularity_periods
+            .iter()
+            .find(|period| matches!((period.from, period.to), (Some(from), Some(to)) if from > to))
+        {
+            return Err(AppError::Config(format!(
+                "granularity period from {:?} to {:?} is empty",
+                period.from, period.to
+            )));
+        }
+        if self.max_files_per_folder == Some(0) {
+            return Err(AppError::Config(
+                "maxFilesPerFolder must be positive; leave it unset for no limit".into(),
+            ));
+        }
+        if !(1..=100).contains(&self.transcode.quality) {
+            return Err(AppError::Config(
+                "transcode quality must be between 1 and 100".into(),
+            ));
+        }
+        if !(1..=100).contains(&self.export.quality) || self.export.max_edge == 0 {
+            return Err(AppError::Config(
+                "export needs a quality between 1 and 100 and a positive maxEdge".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The bucket granularity for files from `year`.
+    pub fn granularity_for(&self, year: Option<i32>) -> BucketGranularity {
+        year.and_then(|year| {
+            self.granularity_periods
+                .iter()
+                .find(|period| period.covers(year))
+        })
+        .map_or(self.bucket_granularity, |period| period.granularity)
+    }
+}
+
+impl Default for PlanOptions {
+    fn default() -> Self {
+        Self {
+            routing_rules: Vec::new(),
+            output_roots: MediaOutputRoots::default(),
+            output_volumes: Vec::new(),
+            max_files_per_folder: None,
+            folder_layout: FolderLayout::default(),
+            folder_template: None,
+            bucket_granularity: BucketGranularity::default(),
+            granularity_periods: Vec::new(),
+            duplicates_layout: DuplicatesLayout::default(),
+            keep_original_names: false,
+            normalize_extensions: false,
+            name_timestamp: default_name_timestamp(),
+            name_template: None,
+            artist_fallback: default_artist_fallback(),
+            include_derivatives: false,
+            share: ShareOptions::default(),
+            transcode: TranscodeOptions::default(),
+            export: ExportOptions::default(),
+        }
+    }
+}
+
+/// A sanitized second copy of everything organized, meant for handing to
+/// other people. Originals and the main output are left untouched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareOptions {
+    /// Where share copies go, mirroring the output layout. `None` turns the
+    /// step off.
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    #[serde(default = "default_strip_gps")]
+    pub strip_gps: bool,
+}
+
+fn default_strip_gps() -> bool {
+    true
+}
+
+impl Default for ShareOptions {
+    fn default() -> Self {
+        Self {
+            root: None,
+            strip_gps: default_strip_gps(),
+        }
+    }
+}
+
+/// Optional HEIC/HEIF to JPEG conversion of organized files, for devices
+/// that cannot show HEIC.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeOptions {
+    #[serde(default)]
+    pub heic_to_jpeg: bool,
+    /// JPEG quality from 1 to 100.
+    #[serde(default = "default_transcode_quality")]
+    pub quality: u8,
+    #[serde(default)]
+    pub originals: OriginalsPolicy,
+}
+
+fn default_transcode_quality() -> u8 {
+    90
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        Self {
+            heic_to_jpeg: false,
+            quality: default_transcode_quality(),
+            originals: OriginalsPolicy::default(),
+        }
+    }
+}
+
+/// Downscaled JPEG copies of the planned library, laid out like the output
+/// under a separate root.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    /// Longest edge of an exported picture, in pixels.
+    #[serde(default = "default_export_max_edge")]
+    pub max_edge: u32,
+    #[serde(default = "default_export_quality")]
+    pub quality: u8,
+}
+
+fn default_export_max_edge() -> u32 {
+    2048
+}
+
+fn default_export_quality() -> u8 {
+    85
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            root: None,
+            max_edge: default_export_max_edge(),
+            quality: default_export_quality(),
+        }
+    }
+}
+
+/// What happens to a HEIC in the output once its JPEG exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OriginalsPolicy {
+    /// Keep the HEIC next to its JPEG.
+    #[default]
+    Keep,
+    /// Drop the HEIC from the output of a copy; the source still has it.
+    /// Moves always keep it, since it is then the only original.
+    Discard,
+}
+
+/// Source enumeration settings, kept flat in `config.json` like the planner
+/// settings.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanOptions {
+    #[serde(default)]
+    pub photos_library: PhotosLibraryMode,
+    /// Skip subfolders holding an Android-style `.nomedia` marker.
+    #[serde(default = "default_honor_nomedia")]
+    pub honor_nomedia: bool,
+    #[serde(default)]
+    pub cloud_placeholders: CloudPlaceholderMode,
+    /// Walk into symlinked folders and pick up symlinked files. Each folder
+    /// and file is taken once, however many links lead to it.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Folders nested deeper than this below the source are not walked;
+    /// zero keeps the scan to the source's own files.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Media files taken from any one folder, by name order; the rest are
+    /// left out. Guards against dumps and mirrors with huge folders.
+    #[serde(default)]
+    pub max_files_per_directory: Option<usize>,
+    /// Subfolders of the image root to scan, relative and `/`-separated;
+    /// empty scans all of it. Sample folders are always scanned whole.
+    #[serde(default)]
+    pub include_folders: Vec<String>,
+    /// More folders to scan and sort along with the image root, absolute or
+    /// relative to the home folder. Left out in sample mode.
+    #[serde(default)]
+    pub extra_image_roots: Vec<PathBuf>,
+    /// Also hash decoded pixels, so re-saved or losslessly rotated copies
+    /// of a photo count as duplicates. Slower, since every picture is
+    /// decoded.
+    #[serde(default)]
+    pub pixel_hash: bool,
+    #[serde(default)]
+    pub hash_strategy: HashStrategy,
+    #[serde(default)]
+    pub queues: ScanQueues,
+}
+
+fn default_honor_nomedia() -> bool {
+    true
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            photos_library: PhotosLibraryMode::default(),
+            honor_nomedia: default_honor_nomedia(),
+            cloud_placeholders: CloudPlaceholderMode::default(),
+            follow_symlinks: false,
+            max_depth: None,
+            max_files_per_directory: None,
+            include_folders: Vec::new(),
+            extra_image_roots: Vec::new(),
+            pixel_hash: false,
+            hash_strategy: HashStrategy::default(),
+            queues: ScanQueues::default(),
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.queues.hash == 0 {
+            return Err(AppError::Config(
+                "the hash queue must hold at least one file".into(),
+            ));
+        }
+        if self.max_files_per_directory == Some(0) {
+            return Err(AppError::Config(
+                "maxFilesPerDirectory must allow at least one file".into(),
+            ));
+        }
+        for folder in &self.include_folders {
+            let relative = Path::new(folder);
+            if relative.as_os_str().is_empty()
+                || !relative
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(AppError::Config(format!(
+                    "included folder {folder} must be a subfolder of the image root"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Capacities of the queues between scan stages. Longer queues hold more
+/// files in memory, so a stage that stalls for a moment holds back the
+/// others less.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanQueues {
+    /// Files handed to the hashing threads at a time.
+    #[serde(default = "default_hash_queue")]
+    pub hash: usize,
+    /// Hashed batches that may wait to be saved; 0 hands each batch over
+    /// directly.
+    #[serde(default = "default_persist_queue")]
+    pub persist: usize,
+}
+
+fn default_hash_queue() -> usize {
+    256
+}
+
+fn default_persist_queue() -> usize {
+    2
+}
+
+impl Default for ScanQueues {
+    fn default() -> Self {
+        Self {
+            hash: default_hash_queue(),
+            persist: default_persist_queue(),
+        }
+    }
+}
+
+/// What the scanner does with Apple Photos `.photoslibrary` bundles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PhotosLibraryMode {
+    /// Leave the bundle alone; its internals are not loose files.
+    #[default]
+    Skip,
+    /// Scan only the bundle's originals folder.
+    Originals,
+}
+
+/// Which files the scanner reads in full to hash their content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HashStrategy {
+    /// Hash every file.
+    #[default]
+    Full,
+    /// Only hash files that share their byte size with another file, since
+    /// no other file can be a duplicate. Most libraries are largely unique,
+    /// so this skips most of the reading.
+    SizeGrouped,
+}
+
+/// What the scanner does with cloud files that are not downloaded yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CloudPlaceholderMode {
+    /// Leave them out and list them in the scan summary.
+    #[default]
+    Skip,
+    /// Download them before hashing.
+    Hydrate,
+    /// Stop the scan so the user can download them first.
+    Fail,
+}
+
+/// Opt-in background integrity scrub of the organized library. Each week a
+/// slice of `1 / rotationWeeks` of the library is re-hashed, read at no more
+/// than `bytesPerHour`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrubOptions {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_scrub_bytes_per_hour")]
+    pub bytes_per_hour: u64,
+    #[serde(default = "default_scrub_rotation_weeks")]
+    pub rotation_weeks: u32,
+}
+
+fn default_scrub_bytes_per_hour() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+fn default_scrub_rotation_weeks() -> u32 {
+    4
+}
+
+/// How heavy work reacts to running on battery.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerOptions {
+    #[serde(default)]
+    pub battery_mode: BatteryMode,
+    /// Charge, in percent, below which `batteryMode` applies.
+    #[serde(default = "default_battery_threshold")]
+    pub battery_threshold: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BatteryMode {
+    /// Run at full speed regardless of the battery.
+    #[default]
+    Ignore,
+    /// Hash one file at a time.
+    Throttle,
+    /// Hold the task until the machine is back on AC.
+    Pause,
+}
+
+fn default_battery_threshold() -> u8 {
+    30
+}
+
+/// Storage type and hashing concurrency, detected per scan unless set here.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IoOptions {
+    /// `None` detects the storage type of the folders being read.
+    #[serde(default)]
+    pub storage: Option<StorageKind>,
+    /// `None` picks from the storage type.
+    #[serde(default)]
+    pub hash_threads: Option<usize>,
+    /// Compare BLAKE3 hashes, not just sizes, before a move that had to
+    /// copy deletes the original.
+    #[serde(default)]
+    pub verify_moves: bool,
+    /// Bytes always left free on each output volume; preflight fails and a
+    /// run pauses rather than eat into them.
+    #[serde(default)]
+    pub free_space_reserve: u64,
+    /// Remove folders under the image root that a move run leaves empty,
+    /// and output folders an undo leaves empty.
+    #[serde(default)]
+    pub remove_empty_folders: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageKind {
+    /// Read files in parallel.
+    Ssd,
+    /// Read one file at a time, front to back.
+    Hdd,
+    /// Read a few files at a time to keep the link busy.
+    Network,
+}
+
+/// Where run counters go, for machines nobody watches. Only builds with the
+/// `telemetry` feature export anything.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryOptions {
+    /// Prometheus text file rewritten after every run, for node_exporter's
+    /// textfile collector.
+    #[serde(default)]
+    pub prometheus_file: Option<PathBuf>,
+    /// OTLP/HTTP collector base URL such as `http://localhost:4318`.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TelemetryOptions {
+    pub fn enabled(&self) -> bool {
+        self.prometheus_file.is_some() || self.otlp_endpoint.is_some()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(endpoint) = &self.otlp_endpoint {
+            if !endpoint.starts_with("http://") {
+                return Err(AppError::Config(format!(
+                    "the OTLP endpoint must be a plain http:// URL, got {endpoint}"
+                )));
+            }
+        }
+        if self.enabled() && !cfg!(feature = "telemetry") {
+            warn!("telemetry is configured but this build has no telemetry support");
+        }
+        Ok(())
+    }
+}
+
+impl Default for PowerOptions {
+    fn default() -> Self {
+        Self {
+            battery_mode: BatteryMode::default(),
+            battery_threshold: default_battery_threshold(),
+        }
+    }
+}
+
+impl Default for ScrubOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bytes_per_hour: default_scrub_bytes_per_hour(),
+            rotation_weeks: default_scrub_rotation_weeks(),
+        }
+    }
+}
+
+/// Time span covered by one date bucket. Coarser granularities collapse
+/// sparse archives into fewer folders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BucketGranularity {
+    #[default]
+    Day,
+    /// ISO week, e.g. `2024-W24`.
+    Week,
+    Month,
+    Year,
+}
+
+/// A range of years, open-ended where a bound is missing, bucketed at its
+/// own granularity.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GranularityPeriod {
+    #[serde(default)]
+    pub from: Option<i32>,
+    /// Inclusive.
+    #[serde(default)]
+    pub to: Option<i32>,
+    pub granularity: BucketGranularity,
+}
+
+impl GranularityPeriod {
+    fn covers(&self, year: i32) -> bool {
+        self.from.is_none_or(|from| from <= year) && self.to.is_none_or(|to| year <= to)
+    }
+}
+
+/// How date buckets are laid out under the output root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FolderLayout {
+    /// `2024-06-14/`
+    #[default]
+    Flat,
+    /// `2024/06/14/`
+    YearMonthDay,
+    /// `2024/2024-06/`
+    YearMonth,
+    /// Straight into the output root, or a folder per year by `Year`;
+    /// `_dupN` counters keep clashing names apart.
+    Single,
+    /// The folder the file was found in, relative to the image root; no
+    /// dates, only duplicates pulled out and names normalized.
+    Original,
+}
+
+/// How duplicates are arranged under the duplicates folder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicatesLayout {
+    /// All side by side.
+    #[default]
+    Flat,
+    /// In the folders they were found in, relative to the image root.
+    Original,
+    /// In their date bucket, as if they were kept.
+    Bucket,
+}
+
+/// How plan and library paths are written to the database. `Portable`
+/// stores them relative to a root id so the database survives moving the
+/// source and output to another machine or OS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PathStorage {
+    #[default]
+    Absolute,
+    Portable,
+}
+
+/// One disk in a spanned library. `min_free_bytes` is left untouched on the
+/// volume when deciding where the next bucket fits.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputVolume {
+    pub root: PathBuf,
+    #[serde(default)]
+    pub min_free_bytes: u64,
+}
+
+/// Optional per-media-type output roots. Unset entries fall back to the main
+/// output root; relative paths are resolved against the home directory.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaOutputRoots {
+    #[serde(default)]
+    pub photo: Option<PathBuf>,
+    #[serde(default)]
+    pub video: Option<PathBuf>,
+    #[serde(default)]
+    pub raw: Option<PathBuf>,
+}
+
+/// A routing rule sends matching files to `target` (relative to the output
+/// root) instead of the default date bucket. Rules are evaluated in order and
+/// the first match wins; all conditions that are set must hold.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingRule {
+    pub name: String,
+    #[serde(default)]
+    pub path_contains: Option<String>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    pub target: String,
+    /// Copies routed by this rule lose their EXIF, XMP, IPTC and comment
+    /// segments. Only JPEG copies are rewritten; moves are never stripped.
+    #[serde(default)]
+    pub strip_metadata: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -44,8 +759,16 @@ pub struct AppConfig {
     pub origin_info_path: PathBuf,
     pub target_plan_path: PathBuf,
     pub image_exts: HashSet<String>,
+    pub media_exts: MediaExtensions,
     pub config_file_path: PathBuf,
     pub sample_image_root: Option<PathBuf>,
+    pub plan: PlanOptions,
+    pub scan: ScanOptions,
+    pub scrub: ScrubOptions,
+    pub path_storage: PathStorage,
+    pub power: PowerOptions,
+    pub io: IoOptions,
+    pub telemetry: TelemetryOptions,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -61,7 +784,97 @@ pub struct ConfigPayload {
     pub origin_info_json: String,
     pub target_plan_json: String,
     pub image_exts: Vec<String>,
+    pub photo_exts: Vec<String>,
+    pub video_exts: Vec<String>,
+    pub raw_exts: Vec<String>,
+    pub sidecar_exts: Vec<String>,
     pub sample_image_root: Option<String>,
+    pub routing_rules: Vec<RoutingRule>,
+    pub folder_layout: FolderLayout,
+    pub folder_template: Option<String>,
+    pub bucket_granularity: BucketGranularity,
+    pub keep_original_names: bool,
+    pub normalize_extensions: bool,
+    pub name_template: Option<String>,
+    pub max_files_per_folder: Option<usize>,
+    pub media_output_roots: Vec<MediaRootPayload>,
+    pub output_volumes: Vec<OutputVolume>,
+    pub share: ShareOptions,
+    pub transcode: TranscodeOptions,
+    pub export: ExportOptions,
+    pub photos_library: PhotosLibraryMode,
+    pub honor_nomedia: bool,
+    pub cloud_placeholders: CloudPlaceholderMode,
+    pub follow_symlinks: bool,
+    pub max_depth: Option<usize>,
+    pub max_files_per_directory: Option<usize>,
+    pub include_folders: Vec<String>,
+    pub extra_image_roots: Vec<String>,
+    pub pixel_hash: bool,
+    pub hash_strategy: HashStrategy,
+    pub scan_queues: ScanQueues,
+    pub scrub: ScrubOptions,
+    pub path_storage: PathStorage,
+    pub power: PowerOptions,
+    pub io: IoOptions,
+    pub telemetry: TelemetryOptions,
+}
+
+/// How a configured folder overlaps another one it must stay apart from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PathConflict {
+    SameFolder,
+    OutputInsideSource,
+    SourceInsideOutput,
+    DuplicatesOutsideOutput,
+    SourcesOverlap,
+}
+
+impl PathConflict {
+    /// Stable code for the UI and logs.
+    pub fn code(self) -> &'static str {
+        match self {
+            PathConflict::SameFolder => "same_folder",
+            PathConflict::OutputInsideSource => "output_inside_source",
+            PathConflict::SourceInsideOutput => "source_inside_output",
+            PathConflict::DuplicatesOutsideOutput => "duplicates_outside_output",
+            PathConflict::SourcesOverlap => "sources_overlap",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            PathConflict::SameFolder => "the output folder is the source folder",
+            PathConflict::OutputInsideSource => "the output folder is inside the source folder",
+            PathConflict::SourceInsideOutput => "the source folder is inside the output folder",
+            PathConflict::DuplicatesOutsideOutput => {
+                "the duplicates folder is outside the output folder"
+            }
+            PathConflict::SourcesOverlap => "the source folder is inside another source folder",
+        }
+    }
+}
+
+/// How output `root` overlaps `source`, if it does.
+pub fn root_conflict(source: &Path, root: &Path) -> Result<Option<PathConflict>> {
+    let source = normalize(source)?;
+    let root = normalize(root)?;
+    Ok(if root == source {
+        Some(PathConflict::SameFolder)
+    } else if root.starts_with(&source) {
+        Some(PathConflict::OutputInsideSource)
+    } else if source.starts_with(&root) {
+        Some(PathConflict::SourceInsideOutput)
+    } else {
+        None
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaRootPayload {
+    pub kind: MediaKind,
+    pub root: String,
 }
 
 pub struct ConfigService {
@@ -70,24 +883,10 @@ pub struct ConfigService {
 
 impl ConfigService {
     pub fn initialize() -> Result<Self> {
-        let raw: RawConfig = serde_json::from_str(DEFAULT_CONFIG_JSON)?;
         let config_file_path =
             locate_runtime_config().unwrap_or_else(|| PathBuf::from("config/config.json"));
-        let raw = if config_file_path.exists() {
-            match crate::utils::json::read_json::<RawConfig>(&config_file_path) {
-                Ok(cfg) => cfg,
-                Err(err) => {
-                    debug!(error = ?err, "failed to read runtime config override");
-                    raw
-                }
-            }
-        } else {
-            raw
-        };
-
-        let app_config = build_app_config(raw, config_file_path)?;
         Ok(Self {
-            inner: RwLock::new(app_config),
+            inner: RwLock::new(load_config(config_file_path)?),
         })
     }
 
@@ -98,6 +897,70 @@ impl ConfigService {
     pub fn payload(&self) -> ConfigPayload {
         ConfigPayload::from(&*self.inner.read())
     }
+
+    /// Edits the on-disk `config.json` as raw JSON, so keys this version does
+    /// not know about survive, then reloads the in-memory config from it.
+    /// Nothing is written if the edited document fails to load.
+    pub fn update_file<F>(&self, edit: F) -> Result<AppConfig>
+    where
+        F: FnOnce(&mut Value) -> Result<()>,
+    {
+        let (document, config) = self.prepare_file(edit)?;
+        self.write_file(&document, &config)?;
+        Ok(config)
+    }
+
+    /// Applies `edit` to the on-disk document and loads the result without
+    /// writing it, so callers can check a change before committing to it
+    /// with `write_file`.
+    pub fn prepare_file<F>(&self, edit: F) -> Result<(Value, AppConfig)>
+    where
+        F: FnOnce(&mut Value) -> Result<()>,
+    {
+        let config_file_path = self.inner.read().config_file_path.clone();
+        let mut document: Value = if config_file_path.exists() {
+            json::read_json(&config_file_path)?
+        } else {
+            serde_json::from_str(DEFAULT_CONFIG_JSON)?
+        };
+        edit(&mut document)?;
+
+        let raw: RawConfig = serde_json::from_value(document.clone())?;
+        let config = build_app_config(raw, config_file_path)?;
+        Ok((document, config))
+    }
+
+    /// Stores a document `prepare_file` loaded and makes its config current.
+    pub fn write_file(&self, document: &Value, config: &AppConfig) -> Result<()> {
+        json::write_json(&config.config_file_path, document)?;
+        *self.inner.write() = config.clone();
+        Ok(())
+    }
+
+    /// Turns sample mode on for `root`, or off with `None`, until the app
+    /// restarts. Unlike `imageRoot` in `config.json`, nothing is written, so
+    /// demos and tests can switch folders freely. Relative folders resolve
+    /// against the working directory, as they do in the config file.
+    pub fn set_sample_root(&self, root: Option<&Path>) -> Result<AppConfig> {
+        let root = match root {
+            Some(root) => {
+                let resolved = join_and_normalize(env::current_dir()?, root)?;
+                if !resolved.is_dir() {
+                    return Err(AppError::Config(format!(
+                        "sample folder {} does not exist",
+                        resolved.display()
+                    )));
+                }
+                Some(resolved)
+            }
+            None => None,
+        };
+        let mut config = self.inner.read().clone();
+        config.sample_image_root = root;
+        config.check_layout()?;
+        *self.inner.write() = config.clone();
+        Ok(config)
+    }
 }
 
 fn build_app_config(raw: RawConfig, config_file_path: PathBuf) -> Result<AppConfig> {
@@ -112,28 +975,42 @@ fn build_app_config(raw: RawConfig, config_file_path: PathBuf) -> Result<AppConf
     ensure_parent_dir(&database_path)?;
 
     let image_root = home_dir.join(&raw.image_root_default_name);
-    ensure_dir(&image_root)?;
+    ensure_root_dir(&image_root);
 
     let output_root = home_dir.join(&raw.output_root_name);
-    ensure_dir(&output_root)?;
+    ensure_root_dir(&output_root);
 
     let duplicates_dir = output_root.join(&raw.folder_for_duplicates);
-    ensure_dir(&duplicates_dir)?;
+    ensure_root_dir(&duplicates_dir);
 
     let origin_info_path = output_root.join(&raw.origin_info_json);
     let target_plan_path = output_root.join(&raw.target_file_structure_json);
 
-    let image_exts: HashSet<String> = raw
-        .image_exts
-        .into_iter()
-        .map(|ext| ext.to_ascii_lowercase())
-        .collect();
+    let has_typed_groups = raw.photo_exts.is_some()
+        || raw.video_exts.is_some()
+        || raw.raw_exts.is_some()
+        || raw.sidecar_exts.is_some();
+    let media_exts = if has_typed_groups {
+        MediaExtensions::from_groups(
+            raw.photo_exts.unwrap_or_default(),
+            raw.video_exts.unwrap_or_default(),
+            raw.raw_exts.unwrap_or_default(),
+            raw.sidecar_exts.unwrap_or_default(),
+        )
+    } else {
+        MediaExtensions::from_legacy(raw.image_exts)
+    };
+    let image_exts = media_exts.scannable();
+
+    raw.plan.validate()?;
+    raw.scan.validate()?;
+    raw.telemetry.validate()?;
 
     let sample_image_root = raw
         .image_root
         .and_then(|value| join_and_normalize(env::current_dir().ok()?, Path::new(&value)).ok());
 
-    Ok(AppConfig {
+    let config = AppConfig {
         schema_version: SCHEMA_VERSION,
         home_dir,
         app_data_dir,
@@ -147,9 +1024,146 @@ fn build_app_config(raw: RawConfig, config_file_path: PathBuf) -> Result<AppConf
         origin_info_path,
         target_plan_path,
         image_exts,
+        media_exts,
         config_file_path,
         sample_image_root,
-    })
+        plan: raw.plan,
+        scan: raw.scan,
+        scrub: raw.scrub,
+        path_storage: raw.path_storage,
+        power: raw.power,
+        io: raw.io,
+        telemetry: raw.telemetry,
+    };
+    config.check_layout()?;
+    Ok(config)
+}
+
+impl AppConfig {
+    /// Where the source, output roots and duplicates folder overlap, each
+    /// with the folder at fault. Output inside the source gets rescanned as
+    /// new files on the next run; duplicates outside the output are never
+    /// indexed with the library, and nested sources would be scanned twice.
+    pub fn path_conflicts(&self) -> Result<Vec<(PathConflict, PathBuf)>> {
+        let sources = self.source_roots();
+        let mut conflicts = Vec::new();
+        for (index, source) in sources.iter().enumerate() {
+            for root in self.output_roots() {
+                if let Some(conflict) = root_conflict(source, &root)? {
+                    conflicts.push((conflict, root));
+                }
+            }
+            for other in &sources[..index] {
+                if root_conflict(other, source)?.is_some()
+                    || root_conflict(source, other)?.is_some()
+                {
+                    conflicts.push((PathConflict::SourcesOverlap, source.clone()));
+                }
+            }
+        }
+        if !normalize(&self.duplicates_dir)?.starts_with(normalize(&self.output_root)?) {
+            conflicts.push((
+                PathConflict::DuplicatesOutsideOutput,
+                self.duplicates_dir.clone(),
+            ));
+        }
+        Ok(conflicts)
+    }
+
+    /// Fails with the first of `path_conflicts`.
+    pub fn check_layout(&self) -> Result<()> {
+        match self.path_conflicts()?.into_iter().next() {
+            Some((conflict, path)) => Err(AppError::PathConflict {
+                conflict,
+                path: to_posix_string(&path).into_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Output root for a media kind; sidecars travel with photos. Kinds
+    /// without their own root use the first spanned volume, if any.
+    pub fn output_root_for(&self, kind: MediaKind) -> PathBuf {
+        self.explicit_output_root(kind)
+            .or_else(|| self.volume_roots().into_iter().next())
+            .unwrap_or_else(|| self.output_root.clone())
+    }
+
+    /// The per-kind root from `outputRoots`, when one is configured.
+    pub fn explicit_output_root(&self, kind: MediaKind) -> Option<PathBuf> {
+        let roots = &self.plan.output_roots;
+        let configured = match kind {
+            MediaKind::Photo | MediaKind::Sidecar => roots.photo.as_ref(),
+            MediaKind::Video => roots.video.as_ref(),
+            MediaKind::Raw => roots.raw.as_ref(),
+        };
+        configured.map(|path| self.resolve_home_relative(path))
+    }
+
+    /// Spanned output volumes in fill order.
+    pub fn volume_roots(&self) -> Vec<PathBuf> {
+        self.plan
+            .output_volumes
+            .iter()
+            .map(|volume| self.resolve_home_relative(&volume.root))
+            .collect()
+    }
+
+    /// Root of the downscaled export, when one is configured.
+    pub fn export_root(&self) -> Option<PathBuf> {
+        self.plan
+            .export
+            .root
+            .as_deref()
+            .map(|root| self.resolve_home_relative(root))
+    }
+
+    /// Root of the share copies, when that step is turned on.
+    pub fn share_root(&self) -> Option<PathBuf> {
+        self.plan
+            .share
+            .root
+            .as_deref()
+            .map(|root| self.resolve_home_relative(root))
+    }
+
+    /// Every folder the scan reads from: the sample folder or the image
+    /// root first, then the extra image roots outside sample mode.
+    pub fn source_roots(&self) -> Vec<PathBuf> {
+        if let Some(sample) = &self.sample_image_root {
+            return vec![sample.clone()];
+        }
+        let mut roots = vec![self.image_root.clone()];
+        for root in &self.scan.extra_image_roots {
+            let root = clean_path(&self.resolve_home_relative(root));
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    /// Every distinct output root the planner may write into.
+    pub fn output_roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![self.output_root.clone()];
+        let kind_roots = [MediaKind::Photo, MediaKind::Video, MediaKind::Raw]
+            .into_iter()
+            .map(|kind| self.output_root_for(kind));
+        for root in kind_roots.chain(self.volume_roots()) {
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    fn resolve_home_relative(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.home_dir.join(path)
+        }
+    }
 }
 
 impl From<&AppConfig> for ConfigPayload {
@@ -158,8 +1172,11 @@ impl From<&AppConfig> for ConfigPayload {
         let output_root = ensure_trailing_separator(&config.output_root);
         let duplicates_dir = ensure_trailing_separator(&config.duplicates_dir);
 
-        let mut image_exts = config.image_exts.iter().cloned().collect::<Vec<_>>();
-        image_exts.sort();
+        let sorted = |exts: &HashSet<String>| {
+            let mut values = exts.iter().cloned().collect::<Vec<_>>();
+            values.sort();
+            values
+        };
 
         Self {
             schema_version: config.schema_version,
@@ -172,15 +1189,106 @@ impl From<&AppConfig> for ConfigPayload {
             duplicates_folder_name: config.duplicates_folder_name.clone(),
             origin_info_json: to_posix_string(&config.origin_info_path).into_owned(),
             target_plan_json: to_posix_string(&config.target_plan_path).into_owned(),
-            image_exts,
+            image_exts: sorted(&config.image_exts),
+            photo_exts: sorted(&config.media_exts.photo),
+            video_exts: sorted(&config.media_exts.video),
+            raw_exts: sorted(&config.media_exts.raw),
+            sidecar_exts: sorted(&config.media_exts.sidecar),
             sample_image_root: config
                 .sample_image_root
                 .as_ref()
                 .map(|path| to_posix_string(path).into_owned()),
+            routing_rules: config.plan.routing_rules.clone(),
+            folder_layout: config.plan.folder_layout,
+            folder_template: config.plan.folder_template.clone(),
+            bucket_granularity: config.plan.bucket_granularity,
+            keep_original_names: config.plan.keep_original_names,
+            normalize_extensions: config.plan.normalize_extensions,
+            name_template: config.plan.name_template.clone(),
+            max_files_per_folder: config.plan.max_files_per_folder,
+            media_output_roots: [MediaKind::Photo, MediaKind::Video, MediaKind::Raw]
+                .into_iter()
+                .map(|kind| MediaRootPayload {
+                    kind,
+                    root: to_posix_string(&ensure_trailing_separator(
+                        &config.output_root_for(kind),
+                    ))
+                    .into_owned(),
+                })
+                .collect(),
+            output_volumes: config.plan.output_volumes.clone(),
+            share: config.plan.share.clone(),
+            transcode: config.plan.transcode.clone(),
+            export: config.plan.export.clone(),
+            photos_library: config.scan.photos_library,
+            honor_nomedia: config.scan.honor_nomedia,
+            cloud_placeholders: config.scan.cloud_placeholders,
+            follow_symlinks: config.scan.follow_symlinks,
+            max_depth: config.scan.max_depth,
+            max_files_per_directory: config.scan.max_files_per_directory,
+            include_folders: config.scan.include_folders.clone(),
+            extra_image_roots: config.source_roots()[1..]
+                .iter()
+                .map(|root| to_posix_string(root).into_owned())
+                .collect(),
+            pixel_hash: config.scan.pixel_hash,
+            hash_strategy: config.scan.hash_strategy,
+            scan_queues: config.scan.queues.clone(),
+            scrub: config.scrub.clone(),
+            path_storage: config.path_storage,
+            power: config.power.clone(),
+            io: config.io.clone(),
+            telemetry: config.telemetry.clone(),
         }
     }
 }
 
+/// A config for tests with everything under `root`: images in `images/`,
+/// the library in `output/` and the app's own files at the top. Only JPEGs
+/// are media. Both roots are created.
+#[cfg(test)]
+pub fn test_config(root: &Path) -> AppConfig {
+    let image_root = root.join("images");
+    let output_root = root.join("output");
+    for folder in [&image_root, &output_root] {
+        std::fs::create_dir_all(folder).expect("create test root");
+    }
+    let media_exts = MediaExtensions::from_legacy([".jpg".to_string()]);
+    AppConfig {
+        schema_version: SCHEMA_VERSION,
+        home_dir: root.to_path_buf(),
+        app_data_dir: root.to_path_buf(),
+        database_path: root.join("phototidy.sqlite3"),
+        image_root,
+        image_root_default_name: "images".into(),
+        duplicates_dir: output_root.join("duplicates"),
+        output_root,
+        output_root_name: "output".into(),
+        duplicates_folder_name: "duplicates".into(),
+        origin_info_path: root.join("origin.json"),
+        target_plan_path: root.join("plan.json"),
+        image_exts: media_exts.scannable(),
+        media_exts,
+        config_file_path: root.join("config.json"),
+        sample_image_root: None,
+        plan: Default::default(),
+        scan: Default::default(),
+        scrub: Default::default(),
+        path_storage: Default::default(),
+        power: Default::default(),
+        io: Default::default(),
+        telemetry: Default::default(),
+    }
+}
+
+/// Roots may live on a disk that is not mounted right now. That is reported
+/// through root remapping rather than failing startup.
+fn ensure_root_dir(path: &Path) {
+    if let Err(err) = ensure_dir(path) {
+        warn!(path = %path.display(), error = %err, "configured root is unavailable");
+    }
+}
+
 fn resolve_home_dir(base_dirs: &BaseDirs) -> Result<PathBuf> {
     if let Ok(path) = env::var("PHOTOTIDY_HOME") {
         return Ok(PathBuf::from(path));
@@ -195,6 +1303,37 @@ fn resolve_data_dir(base_dirs: &BaseDirs) -> Result<PathBuf> {
     Ok(PathBuf::from(base_dirs.data_local_dir()).join("photoTidy"))
 }
 
+/// Loads `config_file_path`, falling back to the defaults when the file is
+/// missing, unreadable or holds settings that do not load.
+fn load_config(config_file_path: PathBuf) -> Result<AppConfig> {
+    let raw: RawConfig = serde_json::from_str(DEFAULT_CONFIG_JSON)?;
+    let raw = if config_file_path.exists() {
+        match crate::utils::json::read_json::<RawConfig>(&config_file_path) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                debug!(error = ?err, "failed to read runtime config override");
+                raw
+            }
+        }
+    } else {
+        raw
+    };
+
+    match build_app_config(raw, config_file_path.clone()) {
+        // Starting on the defaults beats not starting; the warning names the
+        // folder or setting to fix.
+        Err(err @ AppError::PathConflict { .. }) => {
+            warn!(error = %err, "config file has overlapping folders; using the defaults");
+            build_app_config(serde_json::from_str(DEFAULT_CONFIG_JSON)?, config_file_path)
+        }
+        Err(err @ (AppError::Config(_) | AppError::Template { .. })) => {
+            warn!(error = %err, "config file has an invalid setting; using the defaults");
+            build_app_config(serde_json::from_str(DEFAULT_CONFIG_JSON)?, config_file_path)
+        }
+        other => other,
+    }
+}
+
 fn locate_runtime_config() -> Option<PathBuf> {
     let search_paths = [
         PathBuf::from("config/config.json"),
@@ -208,9 +1347,33 @@ fn locate_runtime_config() -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
+
+    /// Held by tests that point the home and data folders elsewhere.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn legacy_image_exts_are_grouped() -> Result<()> {
+        let raw: RawConfig = serde_json::from_str(
+            r#"{
+                "imageRootDefaultName": "images",
+                "imageExts": [".jpg", ".MOV"],
+                "outputRootName": "output",
+                "originInfoJson": "origin.json",
+                "targetFileStructureJson": "plan.json",
+                "folderForDuplicates": "dup/"
+            }"#,
+        )?;
+        assert!(raw.photo_exts.is_none());
+        let groups = MediaExtensions::from_legacy(raw.image_exts);
+        assert!(groups.video.contains(".mov"));
+        assert!(groups.photo.contains(".jpg"));
+        Ok(())
+    }
 
     #[test]
     fn payload_formats_paths_with_forward_slashes() -> Result<()> {
+        let _env = ENV_LOCK.lock().unwrap();
         let home = tempfile::tempdir()?;
         let data = tempfile::tempdir()?;
         std::env::set_var("PHOTOTIDY_HOME", home.path());
@@ -220,8 +1383,65 @@ mod tests {
         let config = build_app_config(raw, PathBuf::from("config/config.json"))?;
         let payload = ConfigPayload::from(&config);
         assert!(payload.image_root.ends_with('/'));
+        assert!(payload.video_exts.contains(&".mp4".to_string()));
+        assert!(!payload.image_exts.contains(&".xmp".to_string()));
+        std::env::remove_var("PHOTOTIDY_HOME");
+        std::env::remove_var("PHOTOTIDY_DATA_DIR");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_settings_fall_back_to_the_defaults() -> Result<()> {
+        let _env = ENV_LOCK.lock().unwrap();
+        let home = tempfile::tempdir()?;
+        let data = tempfile::tempdir()?;
+        std::env::set_var("PHOTOTIDY_HOME", home.path());
+        std::env::set_var("PHOTOTIDY_DATA_DIR", data.path());
+
+        let mut document: Value = serde_json::from_str(DEFAULT_CONFIG_JSON)?;
+        document["folderTemplate"] = "{camera}/{year}".into();
+        let config_file_path = home.path().join("config.json");
+        json::write_json(&config_file_path, &document)?;
+        let raw: RawConfig = serde_json::from_value(document)?;
+        assert!(matches!(
+            build_app_config(raw, config_file_path.clone()),
+            Err(AppError::Template { .. })
+        ));
+
+        let config = load_config(config_file_path.clone())?;
+        assert_eq!(config.plan.folder_template, None);
+        assert_eq!(config.config_file_path, config_file_path);
         std::env::remove_var("PHOTOTIDY_HOME");
         std::env::remove_var("PHOTOTIDY_DATA_DIR");
         Ok(())
     }
+
+    #[test]
+    fn sample_root_switches_without_touching_the_file() -> Result<()> {
+        let home = tempfile::tempdir()?;
+        let sample = home.path().join("demo");
+        std::fs::create_dir_all(&sample)?;
+        let service = ConfigService {
+            inner: RwLock::new(test_config(home.path())),
+        };
+
+        let enabled = service.set_sample_root(Some(&sample))?;
+        assert_eq!(enabled.sample_image_root.as_deref(), Some(sample.as_path()));
+        assert!(service
+            .set_sample_root(Some(&home.path().join("missing")))
+            .is_err());
+        assert_eq!(service.snapshot().sample_image_root, Some(sample));
+        let output = home.path().join("output/demo");
+        std::fs::create_dir_all(&output)?;
+        assert!(matches!(
+            service.set_sample_root(Some(&output)),
+            Err(AppError::PathConflict {
+                conflict: PathConflict::SourceInsideOutput,
+                ..
+            })
+        ));
+        assert_eq!(service.set_sample_root(None)?.sample_image_root, None);
+        assert!(!home.path().join("config.json").exists());
+        Ok(())
+    }
 }
@@ -1,67 +1,260 @@
+use std::cmp::Ordering;
 use std::convert::TryFrom;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::config::{AppConfig, SCHEMA_VERSION};
+use crate::config::{AppConfig, BucketGranularity, FolderLayout, SCHEMA_VERSION};
 use crate::error::{AppError, Result};
-use parking_lot::{Mutex, MutexGuard};
-use rusqlite::{params, Connection};
+use crate::media::MediaKind;
+use crate::portable::PathCodec;
+use crate::utils::fs::FileIdentity;
+use crate::utils::path::to_native_path;
+use crate::utils::time::now_timestamp;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 
-const DB_VERSION: i32 = 3;
+pub const DB_VERSION: i32 = 15;
+
+/// Starts the stand-in `file_hash` of a file the scanner did not read,
+/// because no other file has its size.
+pub const UNHASHED_PREFIX: &str = "unhashed:";
+
+/// See `InventoryRecord::source_dir`.
+pub fn source_dir(source_root: Option<&str>, image_root: &Path) -> PathBuf {
+    match source_root {
+        Some(root) => to_native_path(root),
+        None => image_root.to_path_buf(),
+    }
+}
+
+/// See `InventoryRecord::source_key`.
+pub fn source_key(source_root: Option<&str>, relative_path: &str) -> String {
+    match source_root {
+        Some(root) => format!("{}/{relative_path}", root.trim_end_matches('/')),
+        None => relative_path.to_string(),
+    }
+}
+
+/// Whether a `file_hash` is a stand-in rather than an MD5 of the content.
+pub fn is_unhashed(file_hash: &str) -> bool {
+    file_hash.starts_with(UNHASHED_PREFIX)
+}
 
 #[derive(Debug, Clone)]
 pub struct InventoryRecord {
     pub id: Option<i64>,
+    /// MD5 of the content, or a stand-in, see `is_unhashed`.
     pub file_hash: String,
     pub blake3_hash: Option<String>,
     pub file_size: u64,
     pub file_name: String,
     pub relative_path: String,
+    /// The extra source root the file was found under, as a POSIX path;
+    /// `None` for the image root. `relative_path` is relative to it.
+    pub source_root: Option<String>,
+    /// Photo, video or raw, by extension at scan time.
+    pub media_type: MediaKind,
     pub captured_at: Option<String>,
     pub modified_at: String,
     pub exif_model: Option<String>,
     pub exif_make: Option<String>,
     pub exif_artist: Option<String>,
+    /// Star rating (0-5) and keywords, only known for catalog-imported files.
+    pub rating: Option<u8>,
+    pub tags: Vec<String>,
+    /// Decimal degrees from EXIF, or from a Takeout sidecar when EXIF has none.
+    pub gps_latitude: Option<f64>,
+    pub gps_longitude: Option<f64>,
+    /// Set when the file looks like a thumbnail or cache artifact rather
+    /// than an original.
+    pub derivative: Option<DerivativeReason>,
     pub is_duplicate: bool,
+    /// What backs the duplicate verdict; `None` for files that are kept.
+    pub duplicate_confirmation: Option<DuplicateConfirmation>,
+    /// BLAKE3 of the decoded pixels, when pixel hashing is on and the
+    /// format can be decoded.
+    pub pixel_hash: Option<String>,
+}
+
+impl InventoryRecord {
+    /// The folder `relative_path` is relative to, given the image root.
+    pub fn source_dir(&self, image_root: &Path) -> PathBuf {
+        source_dir(self.source_root.as_deref(), image_root)
+    }
+
+    /// Tells files apart across source roots: the relative path for the
+    /// image root, the full POSIX path under the extra ones.
+    pub fn source_key(&self) -> String {
+        source_key(self.source_root.as_deref(), &self.relative_path)
+    }
+
+    /// Capture time, falling back to the modification time.
+    pub fn timestamp(&self) -> &str {
+        self.captured_at.as_deref().unwrap_or(&self.modified_at)
+    }
+
+    /// Which of the two `timestamp` picked.
+    pub fn date_source(&self) -> DateSource {
+        if self.captured_at.is_some() {
+            DateSource::Captured
+        } else {
+            DateSource::Modified
+        }
+    }
+
+    /// Stable planning order: timestamp, then content hash. The relative path
+    /// only separates byte-identical copies, so renaming folders between scans
+    /// does not reshuffle suffixes of distinct photos.
+    pub fn plan_order(&self, other: &Self) -> Ordering {
+        self.timestamp()
+            .cmp(other.timestamp())
+            .then_with(|| self.file_hash.cmp(&other.file_hash))
+            .then_with(|| self.relative_path.cmp(&other.relative_path))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PlanRecord {
     pub id: i64,
     pub file_hash: String,
+    pub blake3_hash: Option<String>,
     pub file_size: u64,
     pub origin_file_name: String,
     pub origin_full_path: String,
     pub target_path: String,
     pub target_file_name: String,
     pub is_duplicate: bool,
+    pub volume_root: Option<String>,
+    /// Set when the entry's routing rule asks for metadata-free copies.
+    pub strip_metadata: bool,
+    pub reason: Option<PlanReason>,
     pub status: PlanStatus,
 }
 
 #[derive(Debug, Clone)]
 pub struct NewPlanEntry {
     pub file_hash: String,
+    pub blake3_hash: Option<String>,
     pub file_size: u64,
     pub origin_file_name: String,
     pub origin_full_path: String,
     pub target_path: String,
     pub target_file_name: String,
     pub is_duplicate: bool,
+    pub volume_root: Option<String>,
+    pub strip_metadata: bool,
+    pub reason: Option<PlanReason>,
+}
+
+/// Why a plan entry goes where it goes, kept with the entry so a plan can
+/// be reviewed file by file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanReason {
+    pub route: PlanRoute,
+    /// Which timestamp dated the file, for its name and its date bucket.
+    pub date_source: DateSource,
+    /// Overflow folder number once the folder held `maxFilesPerFolder`.
+    pub split_part: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum PlanRoute {
+    /// A routing rule matched and its target template named the folder.
+    Rule { name: String, template: String },
+    /// The date bucket for the configured layout, or the folder template
+    /// that named it. `skipped_rule` names a rule that matched but whose
+    /// target rendered an empty segment.
+    DateBucket {
+        layout: FolderLayout,
+        #[serde(default)]
+        template: Option<String>,
+        granularity: BucketGranularity,
+        skipped_rule: Option<String>,
+    },
+    /// Same content as a file planned before it.
+    Duplicate {
+        confirmation: Option<DuplicateConfirmation>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DateSource {
+    /// When the picture was taken, from EXIF or an imported catalog.
+    Captured,
+    /// The file's modification time, for files without a capture time.
+    Modified,
+}
+
+/// A file known to live in the output library, keyed by its absolute
+/// POSIX-style path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryEntry {
+    pub path: String,
+    pub file_size: u64,
+    pub modified_at: String,
+    pub blake3_hash: String,
+}
+
+/// Hashes of a source file as last seen, keyed by its identity so they
+/// still apply after the file is renamed or moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedHash {
+    pub identity: FileIdentity,
+    pub file_size: u64,
+    pub modified_at: String,
+    pub file_hash: String,
+    pub blake3_hash: String,
+}
+
+/// Metadata another photo manager already holds for a source file, keyed by
+/// the path relative to the image root at import time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub relative_path: String,
+    pub file_size: Option<u64>,
+    pub captured_at: Option<String>,
+    pub rating: Option<u8>,
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct NewOperationLog {
-    pub plan_entry_id: i64,
+    /// `None` for operations outside the plan, which name their file in
+    /// `path` instead.
+    pub plan_entry_id: Option<i64>,
+    pub path: Option<String>,
     pub operation: String,
     pub status: String,
     pub error: Option<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct OperationLogRecord {
+    pub id: i64,
+    pub plan_entry_id: Option<i64>,
+    pub path: Option<String>,
+    pub operation: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlanStatus {
     Pending,
     Copied,
     Moved,
     Failed,
+    /// The target already held byte-identical content, so nothing was done.
+    AlreadyPresent,
 }
 
 impl PlanStatus {
@@ -71,6 +264,7 @@ impl PlanStatus {
             Self::Copied => "copied",
             Self::Moved => "moved",
             Self::Failed => "failed",
+            Self::AlreadyPresent => "already_present",
         }
     }
 }
@@ -84,6 +278,7 @@ impl TryFrom<&str> for PlanStatus {
             "copied" => Ok(Self::Copied),
             "moved" => Ok(Self::Moved),
             "failed" => Ok(Self::Failed),
+            "already_present" => Ok(Self::AlreadyPresent),
             other => Err(AppError::internal(format!(
                 "unsupported plan status: {other}"
             ))),
@@ -91,8 +286,250 @@ impl TryFrom<&str> for PlanStatus {
     }
 }
 
+/// Why a file was taken for a derivative rather than an original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DerivativeReason {
+    /// Lives under a known thumbnail or cache folder.
+    CachePath,
+    /// Too small to be a camera original.
+    TinyDimensions,
+    /// Small, next to a thumbnail database such as `Picasa.ini`.
+    ThumbnailMarker,
+}
+
+impl DerivativeReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CachePath => "cache_path",
+            Self::TinyDimensions => "tiny_dimensions",
+            Self::ThumbnailMarker => "thumbnail_marker",
+        }
+    }
+}
+
+impl TryFrom<&str> for DerivativeReason {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "cache_path" => Ok(Self::CachePath),
+            "tiny_dimensions" => Ok(Self::TinyDimensions),
+            "thumbnail_marker" => Ok(Self::ThumbnailMarker),
+            other => Err(AppError::internal(format!(
+                "unsupported derivative reason: {other}"
+            ))),
+        }
+    }
+}
+
+/// How a duplicate verdict was confirmed. An MD5 match alone never flags a
+/// duplicate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateConfirmation {
+    /// Size and MD5 agree, but one of the files has no BLAKE3 to compare.
+    Size,
+    /// Size, MD5 and BLAKE3 all agree.
+    Blake3,
+    /// Only the decoded pixels agree; the files differ in metadata or
+    /// orientation.
+    Pixels,
+}
+
+impl DuplicateConfirmation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Size => "size",
+            Self::Blake3 => "blake3",
+            Self::Pixels => "pixels",
+        }
+    }
+}
+
+impl TryFrom<&str> for DuplicateConfirmation {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "size" => Ok(Self::Size),
+            "blake3" => Ok(Self::Blake3),
+            "pixels" => Ok(Self::Pixels),
+            other => Err(AppError::internal(format!(
+                "unsupported duplicate confirmation: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExclusionKind {
+    Hash,
+    Pattern,
+}
+
+impl ExclusionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hash => "hash",
+            Self::Pattern => "pattern",
+        }
+    }
+}
+
+impl TryFrom<&str> for ExclusionKind {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "hash" => Ok(Self::Hash),
+            "pattern" => Ok(Self::Pattern),
+            other => Err(AppError::internal(format!(
+                "unsupported exclusion kind: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExclusionRecord {
+    pub id: i64,
+    pub kind: ExclusionKind,
+    pub value: String,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+/// What the user decided for a group of duplicates, see `resolution`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum DuplicateDecision {
+    /// Keep this file, by path below the image root; the rest are
+    /// duplicates.
+    Keep { keeper: String },
+    /// Keep every copy as a file of its own.
+    KeepAll,
+    /// Leave the whole group where it is until decided.
+    Later,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateDecisionRecord {
+    pub group_id: String,
+    pub decision: DuplicateDecision,
+    pub decided_at: String,
+}
+
+/// How a file differs from the previous scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Same path, different content.
+    Changed,
+    /// Same content under a new path.
+    Moved,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+            Self::Moved => "moved",
+        }
+    }
+}
+
+impl TryFrom<&str> for ChangeKind {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "added" => Ok(Self::Added),
+            "removed" => Ok(Self::Removed),
+            "changed" => Ok(Self::Changed),
+            "moved" => Ok(Self::Moved),
+            other => Err(AppError::internal(format!(
+                "unsupported change kind: {other}"
+            ))),
+        }
+    }
+}
+
+/// One file's change in one scan, by path relative to the scanned root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventoryChange {
+    pub kind: ChangeKind,
+    pub relative_path: String,
+    /// The old path of a moved file.
+    pub previous_path: Option<String>,
+    pub file_hash: String,
+}
+
+/// Aggregates kept for every completed scan.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanStats {
+    pub total_files: usize,
+    pub total_bytes: u64,
+    pub photos: usize,
+    pub videos: usize,
+    pub raw: usize,
+    pub duplicate_files: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanHistoryEntry {
+    pub id: i64,
+    pub scanned_at: String,
+    pub root: String,
+    #[serde(flatten)]
+    pub stats: ScanStats,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub moved: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChangeRecord {
+    pub scan_id: i64,
+    pub scanned_at: String,
+    pub kind: ChangeKind,
+    pub relative_path: String,
+    pub previous_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbStats {
+    pub version: i32,
+    pub size_bytes: u64,
+    pub tables: Vec<TableStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableStats {
+    pub name: String,
+    pub rows: u64,
+}
+
 pub struct Database {
     connection: Mutex<Connection>,
+    paths: RwLock<PathCodec>,
 }
 
 impl Database {
@@ -103,109 +540,709 @@ impl Database {
         apply_migrations(&mut connection)?;
         Ok(Self {
             connection: Mutex::new(connection),
+            paths: RwLock::new(PathCodec::from_config(config)),
+        })
+    }
+
+    /// Re-reads the roots used to encode and decode stored paths. Call this
+    /// whenever the configured roots change.
+    pub fn configure_paths(&self, config: &AppConfig) {
+        *self.paths.write() = PathCodec::from_config(config);
+    }
+
+    pub fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock()
+    }
+
+    /// Writes a consistent copy of the whole database to `path`, which must
+    /// not exist yet.
+    pub fn export_to(&self, path: &Path) -> Result<()> {
+        let conn = self.conn();
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Schema version, size and row counts, for diagnostics.
+    pub fn stats(&self) -> Result<DbStats> {
+        let conn = self.conn();
+        let version = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let page_count: u64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+        let page_size: u64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        let names: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let rows = conn.query_row(&format!("SELECT COUNT(*) FROM \"{name}\""), [], |row| {
+                row.get(0)
+            })?;
+            tables.push(TableStats { name, rows });
+        }
+        Ok(DbStats {
+            version,
+            size_bytes: page_count * page_size,
+            tables,
         })
     }
 
-    pub fn conn(&self) -> MutexGuard<'_, Connection> {
-        self.connection.lock()
+    /// Replaces this database's contents with the database at `path`. The
+    /// source is migrated to the current version first, so an export from an
+    /// older release loses only what a local upgrade would lose. The
+    /// migration runs on a temporary copy, so the file at `path` is left as
+    /// it was.
+    pub fn import_from(&self, path: &Path) -> Result<()> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let staged = std::env::temp_dir().join(format!(
+            "phototidy-import-{}-{nanos}.sqlite3",
+            std::process::id()
+        ));
+        std::fs::copy(path, &staged)?;
+        let imported = self.import_staged(&staged);
+        if let Err(err) = std::fs::remove_file(&staged) {
+            tracing::debug!(path = %staged.display(), error = %err, "failed removing staged import");
+        }
+        imported
+    }
+
+    fn import_staged(&self, path: &Path) -> Result<()> {
+        let version: i32 = {
+            let mut source = Connection::open(path)?;
+            let version = source.pragma_query_value(None, "user_version", |row| row.get(0))?;
+            if version > DB_VERSION {
+                return Err(AppError::Config(format!(
+                    "database version {version} is newer than this release supports ({DB_VERSION})"
+                )));
+            }
+            apply_migrations(&mut source)?;
+            version
+        };
+
+        let mut conn = self.conn();
+        conn.execute(
+            "ATTACH DATABASE ?1 AS imported",
+            params![path.to_string_lossy()],
+        )?;
+        let copied = copy_attached_tables(&mut conn);
+        conn.execute("DETACH DATABASE imported", [])?;
+        copied?;
+
+        tracing::info!(from_version = version, "imported database");
+        Ok(())
+    }
+
+    /// Runs SQLite's quick integrity check and returns the problems it
+    /// found; an empty list means the database is healthy.
+    pub fn quick_check(&self) -> Result<Vec<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("PRAGMA quick_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut problems = Vec::new();
+        for row in rows {
+            let row = row?;
+            if row != "ok" {
+                problems.push(row);
+            }
+        }
+        Ok(problems)
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT OR REPLACE INTO app_meta (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn();
+        let value = conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn inventory_snapshot(&self) -> Result<Vec<InventoryRecord>> {
+        read_inventory(&self.conn(), "media_inventory")
+    }
+
+    pub fn replace_inventory(&self, records: &[InventoryRecord]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM media_inventory", [])?;
+        for record in records {
+            insert_inventory(&tx, "media_inventory", record)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records hashed by a scan that has not finished yet. They survive a
+    /// restart, so the next scan of the same root picks up where it stopped.
+    pub fn scan_checkpoint(&self) -> Result<Vec<InventoryRecord>> {
+        read_inventory(&self.conn(), "scan_checkpoint")
+    }
+
+    /// Commits one hashed batch to the checkpoint.
+    pub fn append_scan_checkpoint(&self, records: &[InventoryRecord]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for record in records {
+            insert_inventory(&tx, "scan_checkpoint", record)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn hash_cache(&self) -> Result<Vec<CachedHash>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT volume_id, file_id, file_size, modified_at, file_hash, blake3_hash \
+             FROM hash_cache",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(CachedHash {
+                // Ids are stored bit for bit in sqlite's signed integers.
+                identity: FileIdentity {
+                    volume_id: row.get::<_, i64>(0)? as u64,
+                    file_id: row.get::<_, i64>(1)? as u64,
+                },
+                file_size: row.get::<_, i64>(2)? as u64,
+                modified_at: row.get(3)?,
+                file_hash: row.get(4)?,
+                blake3_hash: row.get(5)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    }
+
+    /// Stores hashes by file identity; an identity seen before takes the
+    /// newer hashes.
+    pub fn upsert_hash_cache(&self, entries: &[CachedHash]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for entry in entries {
+            let file_size = i64::try_from(entry.file_size)
+                .map_err(|_| AppError::internal("file size exceeds sqlite limits"))?;
+            tx.execute(
+                "INSERT OR REPLACE INTO hash_cache (volume_id, file_id, file_size, \
+                 modified_at, file_hash, blake3_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    entry.identity.volume_id as i64,
+                    entry.identity.file_id as i64,
+                    file_size,
+                    entry.modified_at,
+                    entry.file_hash,
+                    entry.blake3_hash,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn clear_scan_checkpoint(&self) -> Result<()> {
+        let conn = self.conn();
+        conn.execute("DELETE FROM scan_checkpoint", [])?;
+        conn.execute(
+            "DELETE FROM app_meta WHERE key = 'scan_checkpoint_root'",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Appends a completed scan and its changes to the history. Returns the
+    /// new scan id.
+    pub fn record_scan(
+        &self,
+        root: &str,
+        stats: &ScanStats,
+        changes: &[InventoryChange],
+    ) -> Result<i64> {
+        let count = |kind: ChangeKind| changes.iter().filter(|change| change.kind == kind).count();
+        let total_bytes = i64::try_from(stats.total_bytes)
+            .map_err(|_| AppError::internal("library size exceeds sqlite limits"))?;
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO scan_history (scanned_at, root, total_files, total_bytes, photos, videos, \
+             raw, duplicate_files, added, removed, changed, moved) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                now_timestamp()?,
+                root,
+                stats.total_files,
+                total_bytes,
+                stats.photos,
+                stats.videos,
+                stats.raw,
+                stats.duplicate_files,
+                count(ChangeKind::Added),
+                count(ChangeKind::Removed),
+                count(ChangeKind::Changed),
+                count(ChangeKind::Moved),
+            ],
+        )?;
+        let scan_id = tx.last_insert_rowid();
+        for change in changes {
+            tx.execute(
+                "INSERT INTO scan_changes (scan_id, kind, relative_path, previous_path, file_hash) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    scan_id,
+                    change.kind.as_str(),
+                    change.relative_path,
+                    change.previous_path,
+                    change.file_hash
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(scan_id)
+    }
+
+    /// Completed scans, newest first.
+    pub fn scan_history(&self) -> Result<Vec<ScanHistoryEntry>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, scanned_at, root, total_files, total_bytes, photos, videos, raw, \
+             duplicate_files, added, removed, changed, moved FROM scan_history ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ScanHistoryEntry {
+                id: row.get(0)?,
+                scanned_at: row.get(1)?,
+                root: row.get(2)?,
+                stats: ScanStats {
+                    total_files: row.get(3)?,
+                    total_bytes: row.get::<_, i64>(4)?.max(0) as u64,
+                    photos: row.get(5)?,
+                    videos: row.get(6)?,
+                    raw: row.get(7)?,
+                    duplicate_files: row.get(8)?,
+                },
+                added: row.get(9)?,
+                removed: row.get(10)?,
+                changed: row.get(11)?,
+                moved: row.get(12)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Every recorded change touching `relative_path`, oldest first, which
+    /// tells when the file first appeared and when it went away.
+    pub fn file_history(&self, relative_path: &str) -> Result<Vec<FileChangeRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT c.scan_id, h.scanned_at, c.kind, c.relative_path, c.previous_path \
+             FROM scan_changes AS c JOIN scan_history AS h ON h.id = c.scan_id \
+             WHERE c.relative_path = ?1 OR c.previous_path = ?1 ORDER BY c.scan_id, c.id",
+        )?;
+        let rows = stmt.query_map(params![relative_path], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (scan_id, scanned_at, kind, relative_path, previous_path) = row?;
+            records.push(FileChangeRecord {
+                scan_id,
+                scanned_at,
+                kind: ChangeKind::try_from(kind.as_str())?,
+                relative_path,
+                previous_path,
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn replace_plan_entries(&self, entries: &[NewPlanEntry]) -> Result<()> {
+        let paths = self.paths.read();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM operation_logs", [])?;
+        tx.execute("DELETE FROM plan_entries", [])?;
+        for entry in entries {
+            let file_size = i64::try_from(entry.file_size)
+                .map_err(|_| AppError::internal("file size exceeds sqlite limits"))?;
+            tx.execute(
+                "INSERT INTO plan_entries (file_hash, blake3_hash, file_size, origin_file_name, \
+                 origin_full_path, target_path, target_file_name, is_duplicate, volume_root, \
+                 strip_metadata, reason, status, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, \
+                 ?5, ?6, ?7, ?8, ?9, ?10, ?11, 'pending', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                params![
+                    entry.file_hash,
+                    entry.blake3_hash,
+                    file_size,
+                    entry.origin_file_name,
+                    paths.encode(&entry.origin_full_path),
+                    paths.encode(&entry.target_path),
+                    entry.target_file_name,
+                    if entry.is_duplicate { 1 } else { 0 },
+                    entry.volume_root.as_deref().map(|root| paths.encode(root)),
+                    entry.strip_metadata,
+                    entry
+                        .reason
+                        .as_ref()
+                        .map(serde_json::to_string)
+                        .transpose()?,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn plan_entries(&self) -> Result<Vec<PlanRecord>> {
+        let paths = self.paths.read();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, file_hash, blake3_hash, file_size, origin_file_name, origin_full_path, \
+             target_path, target_file_name, is_duplicate, volume_root, strip_metadata, reason, \
+             status FROM plan_entries ORDER BY id",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, i64>(8)?,
+                row.get::<_, Option<String>>(9)?,
+                row.get::<_, bool>(10)?,
+                row.get::<_, Option<String>>(11)?,
+                row.get::<_, String>(12)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (
+                id,
+                file_hash,
+                blake3_hash,
+                file_size,
+                origin_file_name,
+                origin_full_path,
+                target_path,
+                target_file_name,
+                is_duplicate,
+                volume_root,
+                strip_metadata,
+                reason,
+                status,
+            ) = row?;
+
+            let status = PlanStatus::try_from(status.as_str())?;
+            let file_size = u64::try_from(file_size)
+                .map_err(|_| AppError::internal("negative file size in plan entry"))?;
+
+            records.push(PlanRecord {
+                id,
+                file_hash,
+                blake3_hash,
+                file_size,
+                origin_file_name,
+                origin_full_path: paths.decode(&origin_full_path),
+                target_path: paths.decode(&target_path),
+                target_file_name,
+                is_duplicate: is_duplicate != 0,
+                volume_root: volume_root.map(|root| paths.decode(&root)),
+                strip_metadata,
+                reason: reason
+                    .map(|reason| serde_json::from_str(&reason))
+                    .transpose()?,
+                status,
+            });
+        }
+
+        Ok(records)
+    }
+
+    pub fn plan_entries_with_status(&self, statuses: &[PlanStatus]) -> Result<Vec<PlanRecord>> {
+        if statuses.is_empty() {
+            return self.plan_entries();
+        }
+
+        let entries = self.plan_entries()?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| statuses.contains(&entry.status))
+            .collect())
+    }
+
+    pub fn update_plan_status(&self, id: i64, status: PlanStatus) -> Result<()> {
+        let conn = self.conn();
+        conn.execute(
+            "UPDATE plan_entries SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+            params![status.as_str(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn append_operation_log(&self, log: NewOperationLog) -> Result<()> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO operation_logs (plan_entry_id, path, operation, status, error) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                log.plan_entry_id,
+                log.path,
+                log.operation,
+                log.status,
+                log.error
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Id of the newest logged operation, 0 when there is none.
+    pub fn last_operation_log_id(&self) -> Result<i64> {
+        let conn = self.conn();
+        Ok(conn.query_row(
+            "SELECT COALESCE(MAX(id), 0) FROM operation_logs",
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Logged operations with an id above `after`, oldest first.
+    pub fn operation_logs_after(&self, after: i64) -> Result<Vec<OperationLogRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, plan_entry_id, path, operation, status, error, created_at \
+             FROM operation_logs WHERE id > ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![after], |row| {
+            Ok(OperationLogRecord {
+                id: row.get(0)?,
+                plan_entry_id: row.get(1)?,
+                path: row.get(2)?,
+                operation: row.get(3)?,
+                status: row.get(4)?,
+                error: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn clear_operation_logs(&self) -> Result<()> {
+        let conn = self.conn();
+        conn.execute("DELETE FROM operation_logs", [])?;
+        Ok(())
+    }
+
+    pub fn add_exclusion(
+        &self,
+        kind: ExclusionKind,
+        value: &str,
+        note: Option<&str>,
+    ) -> Result<ExclusionRecord> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO exclusions (kind, value, note) VALUES (?1, ?2, ?3) \
+             ON CONFLICT(kind, value) DO UPDATE SET note = COALESCE(excluded.note, exclusions.note)",
+            params![kind.as_str(), value, note],
+        )?;
+        let record = conn.query_row(
+            "SELECT id, note, created_at FROM exclusions WHERE kind = ?1 AND value = ?2",
+            params![kind.as_str(), value],
+            |row| {
+                Ok(ExclusionRecord {
+                    id: row.get(0)?,
+                    kind,
+                    value: value.to_string(),
+                    note: row.get(1)?,
+                    created_at: row.get(2)?,
+                })
+            },
+        )?;
+        Ok(record)
+    }
+
+    pub fn exclusions(&self) -> Result<Vec<ExclusionRecord>> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT id, kind, value, note, created_at FROM exclusions ORDER BY id")?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (id, kind, value, note, created_at) = row?;
+            records.push(ExclusionRecord {
+                id,
+                kind: ExclusionKind::try_from(kind.as_str())?,
+                value,
+                note,
+                created_at,
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn remove_exclusion(&self, id: i64) -> Result<bool> {
+        let conn = self.conn();
+        let removed = conn.execute("DELETE FROM exclusions WHERE id = ?1", params![id])?;
+        Ok(removed > 0)
+    }
+
+    /// Drops files from the inventory by `InventoryRecord::source_key`, for
+    /// files removed outside a scan.
+    pub fn remove_inventory_records(&self, source_keys: &[String]) -> Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut removed = 0usize;
+        for key in source_keys {
+            removed += tx.execute(
+                "DELETE FROM media_inventory WHERE CASE source_root WHEN '' THEN relative_path \
+                 ELSE rtrim(source_root, '/') || '/' || relative_path END = ?1",
+                params![key],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    pub fn set_duplicate_decision(
+        &self,
+        group_id: &str,
+        decision: &DuplicateDecision,
+    ) -> Result<DuplicateDecisionRecord> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO duplicate_decisions (group_id, decision) VALUES (?1, ?2) \
+             ON CONFLICT(group_id) DO UPDATE SET decision = excluded.decision, \
+             decided_at = CURRENT_TIMESTAMP",
+            params![group_id, serde_json::to_string(decision)?],
+        )?;
+        let decided_at = conn.query_row(
+            "SELECT decided_at FROM duplicate_decisions WHERE group_id = ?1",
+            params![group_id],
+            |row| row.get(0),
+        )?;
+        Ok(DuplicateDecisionRecord {
+            group_id: group_id.to_string(),
+            decision: decision.clone(),
+            decided_at,
+        })
+    }
+
+    pub fn duplicate_decisions(&self) -> Result<Vec<DuplicateDecisionRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT group_id, decision, decided_at FROM duplicate_decisions ORDER BY group_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (group_id, decision, decided_at) = row?;
+            records.push(DuplicateDecisionRecord {
+                group_id,
+                decision: serde_json::from_str(&decision)?,
+                decided_at,
+            });
+        }
+        Ok(records)
     }
 
-    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+    pub fn clear_duplicate_decision(&self, group_id: &str) -> Result<bool> {
         let conn = self.conn();
-        conn.execute(
-            "INSERT OR REPLACE INTO app_meta (key, value) VALUES (?1, ?2)",
-            params![key, value],
+        let removed = conn.execute(
+            "DELETE FROM duplicate_decisions WHERE group_id = ?1",
+            params![group_id],
         )?;
-        Ok(())
+        Ok(removed > 0)
     }
 
-    pub fn inventory_snapshot(&self) -> Result<Vec<InventoryRecord>> {
+    pub fn library_entries(&self) -> Result<Vec<LibraryEntry>> {
+        let paths = self.paths.read();
         let conn = self.conn();
         let mut stmt = conn.prepare(
-            "SELECT id, file_hash, blake3_hash, file_size, file_name, relative_path, captured_at, \
-             modified_at, exif_model, exif_make, exif_artist, is_duplicate FROM media_inventory",
+            "SELECT path, file_size, modified_at, blake3_hash FROM library_index ORDER BY path",
         )?;
 
         let rows = stmt.query_map([], |row| {
             Ok((
-                row.get::<_, Option<i64>>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, Option<String>>(2)?,
-                row.get::<_, i64>(3)?,
-                row.get::<_, String>(4)?,
-                row.get::<_, String>(5)?,
-                row.get::<_, Option<String>>(6)?,
-                row.get::<_, String>(7)?,
-                row.get::<_, Option<String>>(8)?,
-                row.get::<_, Option<String>>(9)?,
-                row.get::<_, Option<String>>(10)?,
-                row.get::<_, i64>(11)?,
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
             ))
         })?;
 
-        let mut records = Vec::new();
+        let mut entries = Vec::new();
         for row in rows {
-            let (
-                id,
-                file_hash,
-                blake3_hash,
-                file_size,
-                file_name,
-                relative_path,
-                captured_at,
-                modified_at,
-                exif_model,
-                exif_make,
-                exif_artist,
-                is_duplicate,
-            ) = row?;
-
+            let (path, file_size, modified_at, blake3_hash) = row?;
             let file_size = u64::try_from(file_size)
-                .map_err(|_| AppError::internal("negative file size in inventory"))?;
-
-            records.push(InventoryRecord {
-                id,
-                file_hash,
-                blake3_hash,
+                .map_err(|_| AppError::internal("negative file size in library index"))?;
+            entries.push(LibraryEntry {
+                path: paths.decode(&path),
                 file_size,
-                file_name,
-                relative_path,
-                captured_at,
                 modified_at,
-                exif_model,
-                exif_make,
-                exif_artist,
-                is_duplicate: is_duplicate != 0,
+                blake3_hash,
             });
         }
-        Ok(records)
+        // Portable rows sort by root id in SQL; callers expect path order.
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
     }
 
-    pub fn replace_inventory(&self, records: &[InventoryRecord]) -> Result<()> {
+    pub fn upsert_library_entries(&self, entries: &[LibraryEntry]) -> Result<()> {
+        let paths = self.paths.read();
         let mut conn = self.conn();
         let tx = conn.transaction()?;
-        tx.execute("DELETE FROM media_inventory", [])?;
-        for record in records {
-            let file_size = i64::try_from(record.file_size)
+        for entry in entries {
+            let file_size = i64::try_from(entry.file_size)
                 .map_err(|_| AppError::internal("file size exceeds sqlite limits"))?;
             tx.execute(
-                "INSERT INTO media_inventory (file_hash, blake3_hash, file_size, file_name, \
-                 relative_path, captured_at, modified_at, exif_model, exif_make, exif_artist, \
-                 is_duplicate, hash_algo, created_at, updated_at) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                "INSERT INTO library_index (path, file_size, modified_at, blake3_hash, indexed_at) \
+                 VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP) \
+                 ON CONFLICT(path) DO UPDATE SET file_size = excluded.file_size, \
+                 modified_at = excluded.modified_at, blake3_hash = excluded.blake3_hash, \
+                 indexed_at = CURRENT_TIMESTAMP",
                 params![
-                    record.file_hash,
-                    record.blake3_hash,
+                    paths.encode(&entry.path),
                     file_size,
-                    record.file_name,
-                    record.relative_path,
-                    record.captured_at,
-                    record.modified_at,
-                    record.exif_model,
-                    record.exif_make,
-                    record.exif_artist,
-                    if record.is_duplicate { 1 } else { 0 },
-                    "md5",
+                    entry.modified_at,
+                    entry.blake3_hash
                 ],
             )?;
         }
@@ -213,26 +1250,123 @@ impl Database {
         Ok(())
     }
 
-    pub fn replace_plan_entries(&self, entries: &[NewPlanEntry]) -> Result<()> {
+    /// Rewrites every stored output path under `old_prefix` to live under
+    /// `new_prefix`, returning the number of plan and library rows touched.
+    /// Logged operation paths move with them, so the history shows the new
+    /// root.
+    /// Both prefixes must be POSIX strings ending in `/`. Portable rows are
+    /// only rewritten while `old_prefix` is still a configured root; once the
+    /// config points at the new root they follow it without any rewrite.
+    pub fn relocate_output_paths(
+        &self,
+        old_prefix: &str,
+        new_prefix: &str,
+    ) -> Result<(usize, usize)> {
+        let prefixes = self.stored_prefixes(old_prefix, new_prefix);
         let mut conn = self.conn();
         let tx = conn.transaction()?;
-        tx.execute("DELETE FROM operation_logs", [])?;
-        tx.execute("DELETE FROM plan_entries", [])?;
+        let mut plan_rows = 0;
+        let mut library_rows = 0;
+        for (old_prefix, new_prefix) in &prefixes {
+            plan_rows += tx.execute(
+                "UPDATE plan_entries SET target_path = ?2 || substr(target_path, length(?1) + 1), \
+                 updated_at = CURRENT_TIMESTAMP WHERE substr(target_path, 1, length(?1)) = ?1",
+                params![old_prefix, new_prefix],
+            )?;
+            // Volume roots are stored without the trailing separator.
+            tx.execute(
+                "UPDATE plan_entries SET volume_root = ?2 || substr(volume_root, length(?1) + 1) \
+                 WHERE volume_root = ?1 OR substr(volume_root, 1, length(?3)) = ?3",
+                params![
+                    old_prefix.trim_end_matches('/'),
+                    new_prefix.trim_end_matches('/'),
+                    old_prefix
+                ],
+            )?;
+            library_rows += tx.execute(
+                "UPDATE OR REPLACE library_index SET path = ?2 || substr(path, length(?1) + 1) \
+                 WHERE substr(path, 1, length(?1)) = ?1",
+                params![old_prefix, new_prefix],
+            )?;
+            tx.execute(
+                "UPDATE operation_logs SET path = ?2 || substr(path, length(?1) + 1) \
+                 WHERE substr(path, 1, length(?1)) = ?1",
+                params![old_prefix, new_prefix],
+            )?;
+        }
+        tx.commit()?;
+        Ok((plan_rows, library_rows))
+    }
+
+    /// Rewrites plan origins under `old_prefix` (a POSIX string ending in
+    /// `/`) to `new_prefix`. Inventory paths are relative and need no change.
+    pub fn relocate_origin_paths(&self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        let prefixes = self.stored_prefixes(old_prefix, new_prefix);
+        let conn = self.conn();
+        let mut rows = 0;
+        for (old_prefix, new_prefix) in &prefixes {
+            rows += conn.execute(
+                "UPDATE plan_entries SET origin_full_path = ?2 || substr(origin_full_path, length(?1) + 1), \
+                 updated_at = CURRENT_TIMESTAMP WHERE substr(origin_full_path, 1, length(?1)) = ?1",
+                params![old_prefix, new_prefix],
+            )?;
+        }
+        Ok(rows)
+    }
+
+    /// The stored forms of a prefix rewrite: the absolute prefix, plus its
+    /// root-relative form while `old_prefix` is a configured root.
+    fn stored_prefixes(&self, old_prefix: &str, new_prefix: &str) -> Vec<(String, String)> {
+        let paths = self.paths.read();
+        let new_stored = paths.encode(new_prefix);
+        let mut prefixes = vec![(old_prefix.to_string(), new_stored.clone())];
+        let old_stored = paths.encode(old_prefix);
+        if old_stored != old_prefix {
+            prefixes.push((old_stored, new_stored));
+        }
+        prefixes
+    }
+
+    pub fn remove_library_entries(&self, paths: &[String]) -> Result<usize> {
+        let codec = self.paths.read();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut removed = 0usize;
+        for path in paths {
+            removed += tx.execute(
+                "DELETE FROM library_index WHERE path = ?1",
+                params![codec.encode(path)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Replaces every catalog entry previously imported from `source`.
+    pub fn replace_catalog_entries(&self, source: &str, entries: &[CatalogEntry]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM catalog_entries WHERE source = ?1",
+            params![source],
+        )?;
         for entry in entries {
-            let file_size = i64::try_from(entry.file_size)
+            let file_size = entry
+                .file_size
+                .map(i64::try_from)
+                .transpose()
                 .map_err(|_| AppError::internal("file size exceeds sqlite limits"))?;
             tx.execute(
-                "INSERT INTO plan_entries (file_hash, file_size, origin_file_name, origin_full_path, \
-                 target_path, target_file_name, is_duplicate, status, created_at, updated_at) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'pending', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                "INSERT OR REPLACE INTO catalog_entries (relative_path, source, file_size, \
+                 captured_at, rating, tags, imported_at) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)",
                 params![
-                    entry.file_hash,
+                    entry.relative_path,
+                    source,
                     file_size,
-                    entry.origin_file_name,
-                    entry.origin_full_path,
-                    entry.target_path,
-                    entry.target_file_name,
-                    if entry.is_duplicate { 1 } else { 0 },
+                    entry.captured_at,
+                    entry.rating,
+                    serde_json::to_string(&entry.tags)?,
                 ],
             )?;
         }
@@ -240,96 +1374,230 @@ impl Database {
         Ok(())
     }
 
-    pub fn plan_entries(&self) -> Result<Vec<PlanRecord>> {
+    pub fn catalog_entries(&self) -> Result<Vec<CatalogEntry>> {
         let conn = self.conn();
         let mut stmt = conn.prepare(
-            "SELECT id, file_hash, file_size, origin_file_name, origin_full_path, target_path, \
-             target_file_name, is_duplicate, status FROM plan_entries ORDER BY id",
+            "SELECT relative_path, file_size, captured_at, rating, tags FROM catalog_entries",
         )?;
 
         let rows = stmt.query_map([], |row| {
             Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, i64>(2)?,
-                row.get::<_, String>(3)?,
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<u8>>(3)?,
                 row.get::<_, String>(4)?,
-                row.get::<_, String>(5)?,
-                row.get::<_, String>(6)?,
-                row.get::<_, i64>(7)?,
-                row.get::<_, String>(8)?,
             ))
         })?;
 
-        let mut records = Vec::new();
+        let mut entries = Vec::new();
         for row in rows {
-            let (
-                id,
-                file_hash,
-                file_size,
-                origin_file_name,
-                origin_full_path,
-                target_path,
-                target_file_name,
-                is_duplicate,
-                status,
-            ) = row?;
-
-            let status = PlanStatus::try_from(status.as_str())?;
-            let file_size = u64::try_from(file_size)
-                .map_err(|_| AppError::internal("negative file size in plan entry"))?;
-
-            records.push(PlanRecord {
-                id,
-                file_hash,
-                file_size,
-                origin_file_name,
-                origin_full_path,
-                target_path,
-                target_file_name,
-                is_duplicate: is_duplicate != 0,
-                status,
+            let (relative_path, file_size, captured_at, rating, tags) = row?;
+            entries.push(CatalogEntry {
+                relative_path,
+                file_size: file_size.and_then(|size| u64::try_from(size).ok()),
+                captured_at,
+                rating,
+                tags: serde_json::from_str(&tags)?,
             });
         }
-
-        Ok(records)
-    }
-
-    pub fn plan_entries_with_status(&self, statuses: &[PlanStatus]) -> Result<Vec<PlanRecord>> {
-        if statuses.is_empty() {
-            return self.plan_entries();
-        }
-
-        let entries = self.plan_entries()?;
-        Ok(entries
-            .into_iter()
-            .filter(|entry| statuses.contains(&entry.status))
-            .collect())
+        Ok(entries)
     }
 
-    pub fn update_plan_status(&self, id: i64, status: PlanStatus) -> Result<()> {
+    /// Copies catalog dates, ratings and tags onto inventory rows at the same
+    /// relative path (and size, when the catalog knows it). Returns the
+    /// number of rows updated.
+    pub fn seed_inventory_from_catalog(&self) -> Result<usize> {
         let conn = self.conn();
-        conn.execute(
-            "UPDATE plan_entries SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
-            params![status.as_str(), id],
+        let rows = conn.execute(
+            "UPDATE media_inventory SET \
+             captured_at = COALESCE(c.captured_at, media_inventory.captured_at), \
+             rating = COALESCE(c.rating, media_inventory.rating), tags = c.tags, \
+             updated_at = CURRENT_TIMESTAMP \
+             FROM catalog_entries AS c WHERE c.relative_path = media_inventory.relative_path \
+             AND (c.file_size IS NULL OR c.file_size = media_inventory.file_size)",
+            [],
         )?;
-        Ok(())
+        Ok(rows)
     }
+}
 
-    pub fn append_operation_log(&self, log: NewOperationLog) -> Result<()> {
-        let conn = self.conn();
-        conn.execute(
-            "INSERT INTO operation_logs (plan_entry_id, operation, status, error) VALUES (?1, ?2, ?3, ?4)",
-            params![log.plan_entry_id, log.operation, log.status, log.error],
-        )?;
-        Ok(())
+/// Reads `media_inventory` or the `scan_checkpoint` table, which share
+/// their columns.
+fn read_inventory(conn: &Connection, table: &str) -> Result<Vec<InventoryRecord>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, file_hash, blake3_hash, file_size, file_name, relative_path, captured_at, \
+         modified_at, exif_model, exif_make, exif_artist, rating, tags, gps_latitude, \
+         gps_longitude, derivative, is_duplicate, duplicate_confirmation, pixel_hash, \
+         source_root, media_type FROM {table}"
+    ))?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Option<i64>>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, String>(7)?,
+            row.get::<_, Option<String>>(8)?,
+            row.get::<_, Option<String>>(9)?,
+            row.get::<_, Option<String>>(10)?,
+            row.get::<_, Option<u8>>(11)?,
+            row.get::<_, String>(12)?,
+            row.get::<_, Option<f64>>(13)?,
+            row.get::<_, Option<f64>>(14)?,
+            row.get::<_, Option<String>>(15)?,
+            row.get::<_, i64>(16)?,
+            row.get::<_, Option<String>>(17)?,
+            row.get::<_, Option<String>>(18)?,
+            row.get::<_, String>(19)?,
+            row.get::<_, String>(20)?,
+        ))
+    })?;
+
+    let mut records = Vec::new();
+    for row in rows {
+        let (
+            id,
+            file_hash,
+            blake3_hash,
+            file_size,
+            file_name,
+            relative_path,
+            captured_at,
+            modified_at,
+            exif_model,
+            exif_make,
+            exif_artist,
+            rating,
+            tags,
+            gps_latitude,
+            gps_longitude,
+            derivative,
+            is_duplicate,
+            duplicate_confirmation,
+            pixel_hash,
+            source_root,
+            media_type,
+        ) = row?;
+
+        let file_size = u64::try_from(file_size)
+            .map_err(|_| AppError::internal("negative file size in inventory"))?;
+
+        records.push(InventoryRecord {
+            id,
+            file_hash,
+            blake3_hash,
+            file_size,
+            file_name,
+            relative_path,
+            // The image root is stored as '' so it can be part of a key.
+            source_root: (!source_root.is_empty()).then_some(source_root),
+            media_type: MediaKind::try_from(media_type.as_str())?,
+            captured_at,
+            modified_at,
+            exif_model,
+            exif_make,
+            exif_artist,
+            rating,
+            tags: serde_json::from_str(&tags)?,
+            gps_latitude,
+            gps_longitude,
+            derivative: derivative
+                .as_deref()
+                .map(DerivativeReason::try_from)
+                .transpose()?,
+            is_duplicate: is_duplicate != 0,
+            duplicate_confirmation: duplicate_confirmation
+                .as_deref()
+                .map(DuplicateConfirmation::try_from)
+                .transpose()?,
+            pixel_hash,
+        });
     }
+    Ok(records)
+}
 
-    pub fn clear_operation_logs(&self) -> Result<()> {
-        let conn = self.conn();
-        conn.execute("DELETE FROM operation_logs", [])?;
-        Ok(())
+fn insert_inventory(conn: &Connection, table: &str, record: &InventoryRecord) -> Result<()> {
+    let file_size = i64::try_from(record.file_size)
+        .map_err(|_| AppError::internal("file size exceeds sqlite limits"))?;
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {table} (file_hash, blake3_hash, file_size, file_name, \
+             relative_path, captured_at, modified_at, exif_model, exif_make, exif_artist, \
+             rating, tags, gps_latitude, gps_longitude, derivative, is_duplicate, \
+             duplicate_confirmation, pixel_hash, hash_algo, source_root, media_type, created_at, \
+             updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, \
+             ?15, ?16, ?17, ?18, ?19, ?20, ?21, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
+        ),
+        params![
+            record.file_hash,
+            record.blake3_hash,
+            file_size,
+            record.file_name,
+            record.relative_path,
+            record.captured_at,
+            record.modified_at,
+            record.exif_model,
+            record.exif_make,
+            record.exif_artist,
+            record.rating,
+            serde_json::to_string(&record.tags)?,
+            record.gps_latitude,
+            record.gps_longitude,
+            record.derivative.map(DerivativeReason::as_str),
+            if record.is_duplicate { 1 } else { 0 },
+            record
+                .duplicate_confirmation
+                .map(DuplicateConfirmation::as_str),
+            record.pixel_hash,
+            "md5",
+            record.source_root.as_deref().unwrap_or_default(),
+            record.media_type.as_str(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Tables copied on import, children after their parents.
+const IMPORTED_TABLES: [&str; 9] = [
+    "media_inventory",
+    "plan_entries",
+    "operation_logs",
+    "exclusions",
+    "duplicate_decisions",
+    "library_index",
+    "catalog_entries",
+    "scan_history",
+    "scan_changes",
+];
+
+fn copy_attached_tables(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction()?;
+    for table in IMPORTED_TABLES.iter().rev() {
+        tx.execute(&format!("DELETE FROM main.{table}"), [])?;
+    }
+    for table in IMPORTED_TABLES {
+        let columns = {
+            let mut stmt = tx.prepare(&format!("PRAGMA main.table_info({table})"))?;
+            let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+            names.collect::<rusqlite::Result<Vec<_>>>()?.join(", ")
+        };
+        tx.execute(
+            &format!("INSERT INTO main.{table} ({columns}) SELECT {columns} FROM imported.{table}"),
+            [],
+        )?;
     }
+    tx.execute(
+        "INSERT OR REPLACE INTO main.app_meta (key, value) \
+         SELECT key, value FROM imported.app_meta WHERE key != 'schema_version'",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(())
 }
 
 fn apply_migrations(connection: &mut Connection) -> Result<()> {
@@ -342,6 +1610,7 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
         tx.execute("DROP TABLE IF EXISTS media_inventory", [])?;
         tx.execute("DROP TABLE IF EXISTS plan_entries", [])?;
         tx.execute("DROP TABLE IF EXISTS operation_logs", [])?;
+        tx.execute("DROP TABLE IF EXISTS scan_checkpoint", [])?;
     }
 
     tx.execute_batch(
@@ -363,8 +1632,17 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
             exif_model TEXT,
             exif_make TEXT,
             exif_artist TEXT,
+            rating INTEGER,
+            tags TEXT NOT NULL DEFAULT '[]',
+            gps_latitude REAL,
+            gps_longitude REAL,
+            derivative TEXT,
             is_duplicate INTEGER NOT NULL DEFAULT 0,
+            duplicate_confirmation TEXT,
+            pixel_hash TEXT,
             hash_algo TEXT NOT NULL DEFAULT 'md5',
+            source_root TEXT NOT NULL DEFAULT '',
+            media_type TEXT NOT NULL DEFAULT 'photo',
             created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
             updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
         );
@@ -372,12 +1650,16 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
         CREATE TABLE IF NOT EXISTS plan_entries (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             file_hash TEXT NOT NULL,
+            blake3_hash TEXT,
             file_size INTEGER NOT NULL,
             origin_file_name TEXT NOT NULL,
             origin_full_path TEXT NOT NULL,
             target_path TEXT NOT NULL,
             target_file_name TEXT NOT NULL,
             is_duplicate INTEGER NOT NULL DEFAULT 0,
+            volume_root TEXT,
+            strip_metadata INTEGER NOT NULL DEFAULT 0,
+            reason TEXT,
             status TEXT NOT NULL DEFAULT 'pending',
             created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
             updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
@@ -385,7 +1667,8 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
 
         CREATE TABLE IF NOT EXISTS operation_logs (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            plan_entry_id INTEGER NOT NULL,
+            plan_entry_id INTEGER,
+            path TEXT,
             operation TEXT NOT NULL,
             status TEXT NOT NULL,
             error TEXT,
@@ -393,6 +1676,106 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
             FOREIGN KEY(plan_entry_id) REFERENCES plan_entries(id)
         );
 
+        CREATE TABLE IF NOT EXISTS exclusions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            note TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(kind, value)
+        );
+
+        CREATE TABLE IF NOT EXISTS duplicate_decisions (
+            group_id TEXT PRIMARY KEY,
+            decision TEXT NOT NULL,
+            decided_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS library_index (
+            path TEXT PRIMARY KEY,
+            file_size INTEGER NOT NULL,
+            modified_at TEXT NOT NULL,
+            blake3_hash TEXT NOT NULL,
+            indexed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS catalog_entries (
+            relative_path TEXT PRIMARY KEY,
+            source TEXT NOT NULL,
+            file_size INTEGER,
+            captured_at TEXT,
+            rating INTEGER,
+            tags TEXT NOT NULL DEFAULT '[]',
+            imported_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS scan_checkpoint (
+            id INTEGER,
+            file_hash TEXT NOT NULL,
+            blake3_hash TEXT,
+            file_size INTEGER NOT NULL,
+            file_name TEXT NOT NULL,
+            relative_path TEXT NOT NULL,
+            captured_at TEXT,
+            modified_at TEXT NOT NULL,
+            exif_model TEXT,
+            exif_make TEXT,
+            exif_artist TEXT,
+            rating INTEGER,
+            tags TEXT NOT NULL DEFAULT '[]',
+            gps_latitude REAL,
+            gps_longitude REAL,
+            derivative TEXT,
+            is_duplicate INTEGER NOT NULL DEFAULT 0,
+            duplicate_confirmation TEXT,
+            pixel_hash TEXT,
+            hash_algo TEXT NOT NULL DEFAULT 'md5',
+            source_root TEXT NOT NULL DEFAULT '',
+            media_type TEXT NOT NULL DEFAULT 'photo',
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (source_root, relative_path)
+        );
+
+        CREATE TABLE IF NOT EXISTS hash_cache (
+            volume_id INTEGER NOT NULL,
+            file_id INTEGER NOT NULL,
+            file_size INTEGER NOT NULL,
+            modified_at TEXT NOT NULL,
+            file_hash TEXT NOT NULL,
+            blake3_hash TEXT NOT NULL,
+            PRIMARY KEY (volume_id, file_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS scan_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            scanned_at TEXT NOT NULL,
+            root TEXT NOT NULL,
+            total_files INTEGER NOT NULL,
+            total_bytes INTEGER NOT NULL,
+            photos INTEGER NOT NULL,
+            videos INTEGER NOT NULL,
+            raw INTEGER NOT NULL,
+            duplicate_files INTEGER NOT NULL,
+            added INTEGER NOT NULL,
+            removed INTEGER NOT NULL,
+            changed INTEGER NOT NULL,
+            moved INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS scan_changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            scan_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            relative_path TEXT NOT NULL,
+            previous_path TEXT,
+            file_hash TEXT NOT NULL,
+            FOREIGN KEY(scan_id) REFERENCES scan_history(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_scan_changes_path ON scan_changes(relative_path);
+        CREATE INDEX IF NOT EXISTS idx_scan_changes_previous_path ON scan_changes(previous_path);
+        CREATE INDEX IF NOT EXISTS idx_library_index_hash ON library_index(blake3_hash);
         CREATE INDEX IF NOT EXISTS idx_media_inventory_hash ON media_inventory(file_hash);
         CREATE INDEX IF NOT EXISTS idx_media_inventory_relative_path ON media_inventory(relative_path);
         CREATE INDEX IF NOT EXISTS idx_plan_entries_status ON plan_entries(status);
@@ -413,14 +1796,14 @@ fn apply_migrations(connection: &mut Connection) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashSet;
+    use crate::config::test_config;
+    use crate::utils::path::to_posix_string;
     use std::path::PathBuf;
-    use tempfile::{tempdir, NamedTempFile};
+    use tempfile::tempdir;
 
     #[test]
     fn database_initializes_and_sets_schema_version() -> Result<()> {
-        let temp = NamedTempFile::new()?;
-        let config = temp_config(temp.path().to_path_buf());
+        let config = test_config(&tempdir()?.keep());
 
         let db = Database::initialize(&config)?;
         let value: String = db.conn().query_row(
@@ -434,9 +1817,7 @@ mod tests {
 
     #[test]
     fn inventory_round_trip() -> Result<()> {
-        let temp_dir = tempdir()?;
-        let db_path = temp_dir.path().join("db.sqlite3");
-        let config = temp_config(db_path.clone());
+        let config = test_config(&tempdir()?.keep());
         let db = Database::initialize(&config)?;
 
         let record = InventoryRecord {
@@ -446,15 +1827,24 @@ mod tests {
             file_size: 42,
             file_name: "image.jpg".into(),
             relative_path: "2024/01/image.jpg".into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
             captured_at: Some("2024-01-01_10-00-00".into()),
             modified_at: "2024-01-01_10-00-00".into(),
             exif_model: Some("Cam".into()),
             exif_make: Some("Make".into()),
             exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
             is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
         };
 
-        db.replace_inventory(&[record.clone()])?;
+        db.replace_inventory(std::slice::from_ref(&record))?;
         let snapshot = db.inventory_snapshot()?;
         assert_eq!(snapshot.len(), 1);
         assert_eq!(snapshot[0].file_hash, record.file_hash);
@@ -464,26 +1854,39 @@ mod tests {
 
     #[test]
     fn plan_entries_round_trip() -> Result<()> {
-        let temp_dir = tempdir()?;
-        let db_path = temp_dir.path().join("db.sqlite3");
-        let config = temp_config(db_path.clone());
+        let config = test_config(&tempdir()?.keep());
         let db = Database::initialize(&config)?;
 
         let entry = NewPlanEntry {
             file_hash: "hash".into(),
+            blake3_hash: Some("blake3".into()),
             file_size: 64,
             origin_file_name: "IMG_0001.JPG".into(),
             origin_full_path: "/origin/IMG_0001.JPG".into(),
             target_path: "/target/2024-01-01/".into(),
             target_file_name: "2024-01-01_00-00-00.IMG_0001.JPG".into(),
             is_duplicate: false,
+            volume_root: None,
+            strip_metadata: false,
+            reason: Some(PlanReason {
+                route: PlanRoute::DateBucket {
+                    layout: FolderLayout::Flat,
+                    template: None,
+                    granularity: BucketGranularity::Day,
+                    skipped_rule: None,
+                },
+                date_source: DateSource::Captured,
+                split_part: None,
+            }),
         };
 
-        db.replace_plan_entries(&[entry.clone()])?;
+        db.replace_plan_entries(std::slice::from_ref(&entry))?;
         let stored = db.plan_entries()?;
         assert_eq!(stored.len(), 1);
         assert_eq!(stored[0].status, PlanStatus::Pending);
         assert_eq!(stored[0].target_file_name, entry.target_file_name);
+        assert_eq!(stored[0].blake3_hash, entry.blake3_hash);
+        assert_eq!(stored[0].reason, entry.reason);
 
         db.update_plan_status(stored[0].id, PlanStatus::Copied)?;
         let copied = db.plan_entries()?;
@@ -494,8 +1897,18 @@ mod tests {
         let pending_only = db.plan_entries_with_status(&[PlanStatus::Pending])?;
         assert!(pending_only.is_empty());
 
+        assert_eq!(
+            db.relocate_output_paths("/target/", "/mnt/library/")?,
+            (1, 0)
+        );
+        assert_eq!(
+            db.plan_entries()?[0].target_path,
+            "/mnt/library/2024-01-01/"
+        );
+
         db.append_operation_log(NewOperationLog {
-            plan_entry_id: copied[0].id,
+            plan_entry_id: Some(copied[0].id),
+            path: None,
             operation: "copy".into(),
             status: "success".into(),
             error: None,
@@ -519,29 +1932,106 @@ mod tests {
         Ok(())
     }
 
-    #[allow(deprecated)]
-    fn temp_config(db_path: PathBuf) -> AppConfig {
-        let temp_root = tempdir().expect("tempdir").into_path();
-        let output_root = tempdir().expect("output").into_path();
-        let duplicates_dir = output_root.join("duplicates");
-        std::fs::create_dir_all(&duplicates_dir).unwrap();
-
-        AppConfig {
-            schema_version: SCHEMA_VERSION,
-            home_dir: temp_root.clone(),
-            app_data_dir: temp_root.clone(),
-            database_path: db_path,
-            image_root: temp_root.clone(),
-            image_root_default_name: "images".into(),
-            output_root: output_root.clone(),
-            output_root_name: "output".into(),
-            duplicates_dir,
-            duplicates_folder_name: "duplicates".into(),
-            origin_info_path: temp_root.join("origin.json"),
-            target_plan_path: temp_root.join("plan.json"),
-            image_exts: HashSet::from([".jpg".into()]),
-            config_file_path: PathBuf::from("config/config.json"),
-            sample_image_root: None,
-        }
+    #[test]
+    fn portable_storage_survives_moved_roots() -> Result<()> {
+        let mut config = test_config(&tempdir()?.keep());
+        config.path_storage = crate::config::PathStorage::Portable;
+        let db = Database::initialize(&config)?;
+
+        let output = to_posix_string(&config.output_root).into_owned();
+        let target_path = format!("{output}/2024-01-01/");
+        db.replace_plan_entries(&[NewPlanEntry {
+            file_hash: "hash".into(),
+            blake3_hash: None,
+            file_size: 1,
+            origin_file_name: "IMG_0001.JPG".into(),
+            origin_full_path: "/elsewhere/IMG_0001.JPG".into(),
+            target_path: target_path.clone(),
+            target_file_name: "IMG_0001.JPG".into(),
+            is_duplicate: false,
+            volume_root: Some(output.clone()),
+            strip_metadata: false,
+            reason: None,
+        }])?;
+
+        let (stored_target, stored_volume): (String, String) = db.conn().query_row(
+            "SELECT target_path, volume_root FROM plan_entries",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(stored_target, "@output/2024-01-01/");
+        assert_eq!(stored_volume, "@output");
+        assert_eq!(db.plan_entries()?[0].target_path, target_path);
+        assert_eq!(
+            db.plan_entries()?[0].origin_full_path,
+            "/elsewhere/IMG_0001.JPG"
+        );
+
+        config.output_root = PathBuf::from("/mnt/library");
+        db.configure_paths(&config);
+        let moved = &db.plan_entries()?[0];
+        assert_eq!(moved.target_path, "/mnt/library/2024-01-01/");
+        assert_eq!(moved.volume_root.as_deref(), Some("/mnt/library"));
+        Ok(())
+    }
+
+    #[test]
+    fn exclusions_round_trip() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let db = Database::initialize(&config)?;
+
+        let first = db.add_exclusion(ExclusionKind::Pattern, "memes/**", None)?;
+        let again = db.add_exclusion(ExclusionKind::Pattern, "memes/**", Some("memes"))?;
+        assert_eq!(first.id, again.id);
+        assert_eq!(again.note.as_deref(), Some("memes"));
+
+        db.add_exclusion(ExclusionKind::Hash, "abc", None)?;
+        assert_eq!(db.exclusions()?.len(), 2);
+
+        assert!(db.remove_exclusion(first.id)?);
+        let remaining = db.exclusions()?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].kind, ExclusionKind::Hash);
+        Ok(())
+    }
+
+    #[test]
+    fn scan_history_tracks_a_file_across_scans() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let db = Database::initialize(&config)?;
+
+        let change = |kind, path: &str, previous_path: Option<&str>| InventoryChange {
+            kind,
+            relative_path: path.into(),
+            previous_path: previous_path.map(Into::into),
+            file_hash: "abc".into(),
+        };
+        let stats = ScanStats {
+            total_files: 1,
+            total_bytes: 10,
+            photos: 1,
+            ..ScanStats::default()
+        };
+        let first = db.record_scan(
+            "/photos",
+            &stats,
+            &[change(ChangeKind::Added, "a.jpg", None)],
+        )?;
+        db.record_scan(
+            "/photos",
+            &stats,
+            &[change(ChangeKind::Moved, "2021/a.jpg", Some("a.jpg"))],
+        )?;
+
+        let history = db.scan_history()?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].id, first);
+        assert_eq!(history[1].added, 1);
+        assert_eq!(history[0].moved, 1);
+
+        let trail = db.file_history("a.jpg")?;
+        let kinds: Vec<ChangeKind> = trail.iter().map(|record| record.kind).collect();
+        assert_eq!(kinds, vec![ChangeKind::Added, ChangeKind::Moved]);
+        Ok(())
     }
 }
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::db::DerivativeReason;
+
+/// Folder names that only ever hold generated previews.
+const CACHE_DIRS: [&str; 6] = [
+    ".thumbnails",
+    ".thumbs",
+    "@eaDir",
+    ".@__thumb",
+    ".AppleDouble",
+    ".picasa3",
+];
+
+/// Files that sit next to photos in folders a viewer has thumbnailed.
+const THUMBNAIL_MARKERS: [&str; 4] = ["Picasa.ini", ".picasa.ini", "Thumbs.db", "ZbThumbnail.info"];
+
+/// Longest side, in pixels, at or below which an image is never an original.
+const TINY_MAX_SIDE: u32 = 256;
+/// Longest side below which a file next to a thumbnail marker is suspect.
+const MARKED_MAX_SIDE: u32 = 640;
+
+/// How many header bytes to read when looking for image dimensions.
+const HEADER_LIMIT: u64 = 256 * 1024;
+
+/// Decides whether `path` (inside `root`) looks like a thumbnail or cache
+/// artifact. Dimensions come from the image header, so files without a
+/// recognised header are only judged by their location.
+pub fn classify(root: &Path, path: &Path) -> Option<DerivativeReason> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let in_cache_dir = relative.parent().is_some_and(|parent| {
+        parent
+            .components()
+            .any(|component| CACHE_DIRS.iter().any(|dir| component.as_os_str() == *dir))
+    });
+    if in_cache_dir {
+        return Some(DerivativeReason::CachePath);
+    }
+
+    let (width, height) = image_dimensions(path)?;
+    let longest = width.max(height);
+    if longest <= TINY_MAX_SIDE {
+        return Some(DerivativeReason::TinyDimensions);
+    }
+    let marked = path.parent().is_some_and(|dir| {
+        THUMBNAIL_MARKERS
+            .iter()
+            .any(|marker| dir.join(marker).is_file())
+    });
+    (marked && longest < MARKED_MAX_SIDE).then_some(DerivativeReason::ThumbnailMarker)
+}
+
+/// Reads width and height from a JPEG, PNG or GIF header.
+fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let file = File::open(path).ok()?;
+    let mut header = Vec::new();
+    BufReader::new(file)
+        .take(HEADER_LIMIT)
+        .read_to_end(&mut header)
+        .ok()?;
+
+    match header.as_slice() {
+        [0x89, b'P', b'N', b'G', ..] if header.len() >= 24 => Some((
+            u32::from_be_bytes(header[16..20].try_into().ok()?),
+            u32::from_be_bytes(header[20..24].try_into().ok()?),
+        )),
+        [b'G', b'I', b'F', ..] if header.len() >= 10 => Some((
+            u32::from(u16::from_le_bytes([header[6], header[7]])),
+            u32::from(u16::from_le_bytes([header[8], header[9]])),
+        )),
+        [0xFF, 0xD8, ..] => jpeg_dimensions(&header),
+        _ => None,
+    }
+}
+
+/// Walks JPEG segments up to the first start-of-frame marker.
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let length = usize::from(u16::from_be_bytes([data[pos + 2], data[pos + 3]]));
+        let is_frame = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_frame {
+            let frame = data.get(pos + 5..pos + 9)?;
+            let height = u16::from_be_bytes([frame[0], frame[1]]);
+            let width = u16::from_be_bytes([frame[2], frame[3]]);
+            return Some((u32::from(width), u32::from(height)));
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        data.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn thumbnails_are_recognised() -> std::io::Result<()> {
+        let root = tempdir()?;
+        let cache = root.path().join("DCIM/.thumbnails");
+        let album = root.path().join("album");
+        fs::create_dir_all(&cache)?;
+        fs::create_dir_all(&album)?;
+
+        fs::write(cache.join("1.jpg"), jpeg(4000, 3000))?;
+        fs::write(album.join("icon.png"), png(160, 120))?;
+        fs::write(album.join("photo.jpg"), jpeg(4032, 3024))?;
+        fs::write(album.join("preview.jpg"), jpeg(480, 360))?;
+
+        let classify_file = |path: &Path| classify(root.path(), path);
+        assert_eq!(
+            classify_file(&cache.join("1.jpg")),
+            Some(DerivativeReason::CachePath)
+        );
+        assert_eq!(
+            classify_file(&album.join("icon.png")),
+            Some(DerivativeReason::TinyDimensions)
+        );
+        assert_eq!(classify_file(&album.join("photo.jpg")), None);
+        assert_eq!(classify_file(&album.join("preview.jpg")), None);
+
+        fs::write(album.join("Picasa.ini"), b"[Picasa]")?;
+        assert_eq!(
+            classify_file(&album.join("preview.jpg")),
+            Some(DerivativeReason::ThumbnailMarker)
+        );
+        assert_eq!(classify_file(&album.join("photo.jpg")), None);
+        Ok(())
+    }
+}
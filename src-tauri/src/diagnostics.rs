@@ -0,0 +1,243 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::config::{AppConfig, ConfigPayload};
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::logging::recent_logs;
+use crate::profile;
+use crate::utils::fs::ensure_parent_dir;
+use crate::utils::path::to_posix_string;
+use crate::utils::time::now_timestamp;
+use crate::utils::zip::ZipWriter;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const CONFIG_FILE: &str = "config.json";
+const DB_STATS_FILE: &str = "db-stats.json";
+const SUMMARIES_FILE: &str = "summaries.json";
+const PROFILE_FILE: &str = "profile.json";
+const LOG_FILE: &str = "recent.log";
+
+/// What went into a diagnostics bundle, also stored in it as
+/// `manifest.json`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsManifest {
+    pub path: String,
+    pub created_at: String,
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    /// Whether the configured folders were replaced with placeholders.
+    pub redacted: bool,
+    pub files: Vec<String>,
+}
+
+/// Zips what a bug report needs into `destination`: recent logs, the
+/// config, database statistics, the last task summaries and the last run's
+/// timing profile. With `redact_paths`, the configured folders and the
+/// home folder are replaced with placeholders throughout.
+pub fn create_bundle(
+    config: &AppConfig,
+    database: &Database,
+    summaries: Map<String, Value>,
+    destination: &Path,
+    redact_paths: bool,
+) -> Result<DiagnosticsManifest> {
+    if destination.exists() {
+        return Err(AppError::Config(format!(
+            "{} already exists",
+            destination.display()
+        )));
+    }
+    let redactor = Redactor::new(config, redact_paths);
+
+    let mut files: Vec<(&str, Vec<u8>)> = vec![
+        (
+            CONFIG_FILE,
+            redactor.json(serde_json::to_value(ConfigPayload::from(config))?)?,
+        ),
+        (
+            DB_STATS_FILE,
+            redactor.json(serde_json::to_value(database.stats()?)?)?,
+        ),
+        (SUMMARIES_FILE, redactor.json(Value::Object(summaries))?),
+    ];
+    if let Some(profile) = profile::last_run() {
+        files.push((PROFILE_FILE, redactor.json(serde_json::to_value(profile)?)?));
+    }
+    let logs: Vec<String> = recent_logs()
+        .iter()
+        .map(|line| redactor.text(line))
+        .collect();
+    files.push((LOG_FILE, (logs.join("\n") + "\n").into_bytes()));
+
+    let mut names: Vec<String> = files.iter().map(|(name, _)| name.to_string()).collect();
+    names.insert(0, MANIFEST_FILE.to_string());
+    let manifest = DiagnosticsManifest {
+        path: to_posix_string(destination).into_owned(),
+        created_at: now_timestamp()?,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        redacted: redact_paths,
+        files: names,
+    };
+
+    ensure_parent_dir(destination)?;
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(destination)?));
+    let mut bundled = manifest.clone();
+    bundled.path = redactor.text(&manifest.path);
+    zip.add(MANIFEST_FILE, &serde_json::to_vec_pretty(&bundled)?)?;
+    for (name, data) in &files {
+        zip.add(name, data)?;
+    }
+    zip.finish()?;
+    Ok(manifest)
+}
+
+/// Replaces the configured folders with placeholders. Longer folders go
+/// first, so an output root inside the home folder becomes `<output>`
+/// rather than `~/...`.
+struct Redactor {
+    replacements: Vec<(String, &'static str)>,
+}
+
+impl Redactor {
+    fn new(config: &AppConfig, enabled: bool) -> Self {
+        let mut folders = Vec::new();
+        if enabled {
+            folders.push((config.home_dir.clone(), "~"));
+            folders.push((config.app_data_dir.clone(), "<app-data>"));
+            folders.push((config.image_root.clone(), "<source>"));
+            if let Some(sample) = &config.sample_image_root {
+                folders.push((sample.clone(), "<source>"));
+            }
+            for root in config.output_roots() {
+                folders.push((root, "<output>"));
+            }
+            if let Some(share) = config.share_root() {
+                folders.push((share, "<share>"));
+            }
+            if let Some(export) = config.export_root() {
+                folders.push((export, "<export>"));
+            }
+        }
+
+        let mut replacements = Vec::new();
+        for (folder, placeholder) in folders {
+            let native = folder.to_string_lossy().into_owned();
+            let posix = to_posix_string(&folder).into_owned();
+            // Debug-formatted log fields escape backslashes.
+            let escaped = native.replace('\\', "\\\\");
+            for form in [native, posix, escaped] {
+                let form = form.trim_end_matches(['/', '\\']).to_string();
+                // A bare root would swallow every path.
+                if form.len() > 3 {
+                    replacements.push((form, placeholder));
+                }
+            }
+        }
+        replacements.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        replacements.dedup_by(|a, b| a.0 == b.0);
+        Self { replacements }
+    }
+
+    fn text(&self, text: &str) -> String {
+        self.replacements
+            .iter()
+            .fold(text.to_string(), |text, (folder, placeholder)| {
+                text.replace(folder.as_str(), placeholder)
+            })
+    }
+
+    fn value(&self, value: Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.text(&text)),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|item| self.value(item)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (self.text(&key), self.value(value)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    fn json(&self, value: Value) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(&self.value(value))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    use flate2::read::DeflateDecoder;
+
+    use crate::config::test_config;
+
+    /// Inflates one entry from an archive written by `ZipWriter`.
+    fn read_entry(archive: &[u8], name: &str) -> Option<String> {
+        let mut at = 0;
+        while archive.get(at..at + 4)? == [0x50, 0x4b, 0x03, 0x04] {
+            let field = |offset: usize, len: usize| {
+                archive[at + offset..at + offset + len]
+                    .iter()
+                    .rev()
+                    .fold(0usize, |acc, byte| (acc << 8) | *byte as usize)
+            };
+            let compressed = field(18, 4);
+            let name_len = field(26, 2);
+            let data_at = at + 30 + name_len + field(28, 2);
+            if &archive[at + 30..at + 30 + name_len] == name.as_bytes() {
+                let mut text = String::new();
+                DeflateDecoder::new(&archive[data_at..data_at + compressed])
+                    .read_to_string(&mut text)
+                    .ok()?;
+                return Some(text);
+            }
+            at = data_at + compressed;
+        }
+        None
+    }
+
+    #[test]
+    fn bundle_redacts_configured_folders() -> Result<()> {
+        let dir = tempdir()?;
+        let config = test_config(dir.path());
+        let database = Database::initialize(&config)?;
+        let mut summaries = Map::new();
+        summaries.insert(
+            "scan".into(),
+            serde_json::json!({ "cloudPlaceholders": [to_posix_string(&config.image_root.join("a.jpg"))] }),
+        );
+        let out_dir = tempdir()?;
+        let destination = out_dir.path().join("bundle.zip");
+
+        let manifest = create_bundle(&config, &database, summaries, &destination, true)?;
+        assert!(manifest.files.contains(&DB_STATS_FILE.to_string()));
+
+        let archive = std::fs::read(&destination)?;
+        let config_json = read_entry(&archive, CONFIG_FILE).expect("config in bundle");
+        let home = to_posix_string(&config.home_dir).into_owned();
+        assert!(!config_json.contains(&home), "{config_json}");
+        assert!(config_json.contains("<output>/"));
+        let summaries_json = read_entry(&archive, SUMMARIES_FILE).expect("summaries in bundle");
+        assert!(summaries_json.contains("<source>/a.jpg"));
+        let stats = read_entry(&archive, DB_STATS_FILE).expect("stats in bundle");
+        assert!(stats.contains("media_inventory"));
+
+        assert!(create_bundle(&config, &database, Map::new(), &destination, false).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::{is_unhashed, ChangeKind, InventoryChange, InventoryRecord};
+
+/// Paths listed per group; the counts stay exact. A first scan would
+/// otherwise list the whole library as added.
+pub const DIFF_LIST_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffGroup<T> {
+    pub count: usize,
+    /// The first `DIFF_LIST_LIMIT` entries, in path order.
+    pub items: Vec<T>,
+}
+
+impl<T> Default for DiffGroup<T> {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<T> DiffGroup<T> {
+    fn push(&mut self, item: T) {
+        self.count += 1;
+        if self.items.len() < DIFF_LIST_LIMIT {
+            self.items.push(item);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedFile {
+    pub from: String,
+    pub to: String,
+}
+
+/// What changed in the source since the previous scan, by relative path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanDiff {
+    pub added: DiffGroup<String>,
+    pub removed: DiffGroup<String>,
+    /// Same path, different content.
+    pub changed: DiffGroup<String>,
+    /// Same content under a new path.
+    pub moved: DiffGroup<MovedFile>,
+}
+
+impl ScanDiff {
+    pub fn from_changes(changes: &[InventoryChange]) -> Self {
+        let mut diff = Self::default();
+        for change in changes {
+            let path = change.relative_path.clone();
+            match change.kind {
+                ChangeKind::Added => diff.added.push(path),
+                ChangeKind::Removed => diff.removed.push(path),
+                ChangeKind::Changed => diff.changed.push(path),
+                ChangeKind::Moved => diff.moved.push(MovedFile {
+                    from: change.previous_path.clone().unwrap_or_default(),
+                    to: path,
+                }),
+            }
+        }
+        diff
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.added.count + self.removed.count + self.changed.count + self.moved.count == 0
+    }
+}
+
+/// Where one file goes in a plan, as its full target path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedTarget {
+    pub origin: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetargetedFile {
+    pub origin: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// How a second plan differs from a first, by origin path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanDiff {
+    /// Planned only in the second plan.
+    pub added: DiffGroup<PlannedTarget>,
+    /// Planned only in the first plan.
+    pub removed: DiffGroup<PlannedTarget>,
+    /// Planned in both, to different targets.
+    pub changed: DiffGroup<RetargetedFile>,
+    pub unchanged: usize,
+}
+
+impl PlanDiff {
+    pub fn between(first: Vec<PlannedTarget>, second: Vec<PlannedTarget>) -> Self {
+        let mut before: BTreeMap<String, String> = first
+            .into_iter()
+            .map(|planned| (planned.origin, planned.target))
+            .collect();
+        let after: BTreeMap<String, String> = second
+            .into_iter()
+            .map(|planned| (planned.origin, planned.target))
+            .collect();
+
+        let mut diff = Self::default();
+        for (origin, target) in after {
+            match before.remove(&origin) {
+                Some(previous) if previous == target => diff.unchanged += 1,
+                Some(previous) => diff.changed.push(RetargetedFile {
+                    origin,
+                    from: previous,
+                    to: target,
+                }),
+                None => diff.added.push(PlannedTarget { origin, target }),
+            }
+        }
+        for (origin, target) in before {
+            diff.removed.push(PlannedTarget { origin, target });
+        }
+        diff
+    }
+}
+
+/// Every change between two inventories, in path order. Files under an
+/// extra source root are named by their full path, see `source_key`.
+pub fn inventory_changes(
+    previous: &[InventoryRecord],
+    current: &[InventoryRecord],
+) -> Vec<InventoryChange> {
+    let before: HashMap<String, &str> = previous
+        .iter()
+        .map(|record| (record.source_key(), record.file_hash.as_str()))
+        .collect();
+    let after: HashMap<String, &str> = current
+        .iter()
+        .map(|record| (record.source_key(), record.file_hash.as_str()))
+        .collect();
+
+    let change = |kind, path: &str, previous_path: Option<&str>, hash: &str| InventoryChange {
+        kind,
+        relative_path: path.to_string(),
+        previous_path: previous_path.map(str::to_string),
+        file_hash: hash.to_string(),
+    };
+    let mut changes = Vec::new();
+
+    // Paths that disappeared, by content, so a new path with the same
+    // content counts as a move rather than an add and a remove.
+    let mut gone: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut gone_paths: Vec<&str> = before
+        .keys()
+        .map(String::as_str)
+        .filter(|path| !after.contains_key(*path))
+        .collect();
+    gone_paths.sort_unstable();
+    for path in gone_paths.iter().rev() {
+        gone.entry(before[*path]).or_default().push(path);
+    }
+
+    let mut current_paths: Vec<&str> = after.keys().map(String::as_str).collect();
+    current_paths.sort_unstable();
+    for path in current_paths {
+        let hash = after[path];
+        match before.get(path) {
+            // Swapping between a stand-in and a real hash says nothing
+            // about the content.
+            Some(old_hash) if *old_hash != hash && is_unhashed(old_hash) == is_unhashed(hash) => {
+                changes.push(change(ChangeKind::Changed, path, None, hash))
+            }
+            Some(_) => {}
+            None => match gone.get_mut(hash).and_then(Vec::pop) {
+                Some(from) => changes.push(change(ChangeKind::Moved, path, Some(from), hash)),
+                None => changes.push(change(ChangeKind::Added, path, None, hash)),
+            },
+        }
+    }
+
+    let mut removed: Vec<&str> = gone.into_values().flatten().collect();
+    removed.sort_unstable();
+    for path in removed {
+        changes.push(change(ChangeKind::Removed, path, None, before[path]));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MediaKind;
+
+    fn record(path: &str, hash: &str) -> InventoryRecord {
+        InventoryRecord {
+            id: None,
+            file_hash: hash.into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: path.rsplit('/').next().unwrap_or(path).into(),
+            relative_path: path.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01T00:00:00Z".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }
+    }
+
+    #[test]
+    fn diff_classifies_every_kind_of_change() {
+        let previous = [
+            record("a.jpg", "1"),
+            record("b.jpg", "2"),
+            record("old/c.jpg", "3"),
+            record("d.jpg", "4"),
+        ];
+        let current = [
+            record("a.jpg", "1"),
+            record("b.jpg", "22"),
+            record("new/c.jpg", "3"),
+            record("e.jpg", "5"),
+        ];
+
+        let diff = ScanDiff::from_changes(&inventory_changes(&previous, &current));
+        assert_eq!(diff.added.items, vec!["e.jpg"]);
+        assert_eq!(diff.removed.items, vec!["d.jpg"]);
+        assert_eq!(diff.changed.items, vec!["b.jpg"]);
+        assert_eq!(
+            diff.moved.items,
+            vec![MovedFile {
+                from: "old/c.jpg".into(),
+                to: "new/c.jpg".into()
+            }]
+        );
+        assert!(inventory_changes(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn plan_diff_matches_files_by_origin() {
+        let planned = |origin: &str, target: &str| PlannedTarget {
+            origin: origin.into(),
+            target: target.into(),
+        };
+        let first = vec![
+            planned("/src/a.jpg", "/out/2024-01-02/a.jpg"),
+            planned("/src/b.jpg", "/out/2024-01-03/b.jpg"),
+            planned("/src/c.jpg", "/out/2024-01-04/c.jpg"),
+        ];
+        let second = vec![
+            planned("/src/a.jpg", "/out/2024-01-02/a.jpg"),
+            planned("/src/b.jpg", "/out/2024/2024-01/b.jpg"),
+            planned("/src/d.jpg", "/out/2024-01-05/d.jpg"),
+        ];
+
+        let diff = PlanDiff::between(first, second);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(
+            diff.changed.items,
+            vec![RetargetedFile {
+                origin: "/src/b.jpg".into(),
+                from: "/out/2024-01-03/b.jpg".into(),
+                to: "/out/2024/2024-01/b.jpg".into(),
+            }]
+        );
+        assert_eq!(diff.added.items[0].origin, "/src/d.jpg");
+        assert_eq!(diff.removed.items[0].origin, "/src/c.jpg");
+    }
+}
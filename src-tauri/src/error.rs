@@ -3,6 +3,9 @@ use std::fmt::Display;
 use rusqlite::Error as SqliteError;
 use thiserror::Error;
 
+use crate::config::PathConflict;
+use crate::template::TemplateError;
+
 pub type Result<T> = std::result::Result<T, AppError>;
 
 #[derive(Debug, Error)]
@@ -17,6 +20,26 @@ pub enum AppError {
     Time(String),
     #[error("config error: {0}")]
     Config(String),
+    #[error("invalid template in {field}: {source}")]
+    Template {
+        field: String,
+        source: TemplateError,
+    },
+    #[error("{}: {} ({path})", .conflict.code(), .conflict.describe())]
+    PathConflict {
+        conflict: PathConflict,
+        path: String,
+    },
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("file_locked: {0} is in use by another app")]
+    FileLocked(String),
+    #[error("{count} files are cloud placeholders that are not downloaded, e.g. {example}")]
+    CloudPlaceholders { count: usize, example: String },
+    #[error("task was cancelled")]
+    Cancelled,
+    #[error("a {0} is already running")]
+    Busy(String),
     #[error("internal error: {0}")]
     Internal(String),
 }
@@ -4,3 +4,6 @@ pub const EVENT_CONFIG_UPDATED: &str = "config://updated";
 pub const EVENT_SCAN_PROGRESS: &str = "scan://progress";
 pub const EVENT_PLAN_PROGRESS: &str = "plan://progress";
 pub const EVENT_EXECUTION_PROGRESS: &str = "execute://progress";
+pub const EVENT_INDEX_PROGRESS: &str = "library://index-progress";
+pub const EVENT_VERIFY_PROGRESS: &str = "library://verify-progress";
+pub const EVENT_SCRUB_ERROR: &str = "library://scrub-error";
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+use crate::db::{source_key, Database, ExclusionKind, ExclusionRecord, InventoryRecord};
+use crate::error::{AppError, Result};
+use crate::scan::source_of;
+use crate::utils::path::normalize;
+use crate::utils::pattern::path_pattern_match;
+
+/// Exclusions loaded from the database, checked against inventory records at
+/// plan time. Excluded records stay in the inventory (so duplicate detection
+/// still sees them) but never receive a plan entry.
+#[derive(Debug, Default)]
+pub struct ExclusionSet {
+    hashes: HashSet<String>,
+    patterns: Vec<String>,
+}
+
+impl ExclusionSet {
+    pub fn load(database: &Database) -> Result<Self> {
+        Ok(Self::from_records(&database.exclusions()?))
+    }
+
+    pub fn from_records(records: &[ExclusionRecord]) -> Self {
+        let mut set = Self::default();
+        for record in records {
+            match record.kind {
+                ExclusionKind::Hash => {
+                    set.hashes.insert(record.value.clone());
+                }
+                ExclusionKind::Pattern => set.patterns.push(record.value.clone()),
+            }
+        }
+        set
+    }
+
+    pub fn is_excluded(&self, record: &InventoryRecord) -> bool {
+        if self.hashes.contains(&record.file_hash) {
+            return true;
+        }
+        if let Some(blake3) = &record.blake3_hash {
+            if self.hashes.contains(blake3) {
+                return true;
+            }
+        }
+        // Anchored patterns name a folder under one root, so they are matched
+        // against the source key; bare names match a folder in any root.
+        let key = record.source_key();
+        self.patterns.iter().any(|pattern| {
+            let anchored = pattern.trim_end_matches('/').contains('/');
+            path_pattern_match(
+                pattern,
+                if anchored {
+                    &key
+                } else {
+                    &record.relative_path
+                },
+            )
+        })
+    }
+}
+
+/// Excludes a single file by content hash. `path` may be absolute, under the
+/// image root or an extra root, or relative to the image root; the file must
+/// already be in the inventory.
+pub fn exclude_file(
+    config: &AppConfig,
+    database: &Database,
+    path: &str,
+) -> Result<ExclusionRecord> {
+    let key = key_for(config, path)?;
+
+    let record = database
+        .inventory_snapshot()?
+        .into_iter()
+        .find(|record| record.source_key() == key)
+        .ok_or_else(|| {
+            AppError::Config(format!("{key} is not in the inventory; run a scan first"))
+        })?;
+
+    database.add_exclusion(ExclusionKind::Hash, &record.file_hash, Some(&key))
+}
+
+pub fn exclude_pattern(database: &Database, pattern: &str) -> Result<ExclusionRecord> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(AppError::Config(
+            "exclusion pattern must not be empty".into(),
+        ));
+    }
+    database.add_exclusion(ExclusionKind::Pattern, &pattern.replace('\\', "/"), None)
+}
+
+/// The source key of `path`, resolved against the source roots the way the
+/// scan records it.
+fn key_for(config: &AppConfig, path: &str) -> Result<String> {
+    let candidate = Path::new(path);
+    if !candidate.is_absolute() {
+        return Ok(path.replace('\\', "/").trim_start_matches("./").to_string());
+    }
+
+    let absolute = normalize(candidate)?;
+    let roots = config
+        .source_roots()
+        .into_iter()
+        .map(normalize)
+        .collect::<Result<Vec<_>>>()?;
+    let roots: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    source_of(&roots, &absolute)
+        .filter(|(_, relative)| !relative.starts_with(".."))
+        .map(|(root, relative)| source_key(root.as_deref(), &relative))
+        .ok_or_else(|| {
+            AppError::Config(format!(
+                "{} is outside of the image roots",
+                absolute.display()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    use crate::config::test_config;
+    use crate::media::MediaKind;
+    use crate::utils::path::to_posix_string;
+
+    fn record(relative_path: &str, hash: &str) -> InventoryRecord {
+        InventoryRecord {
+            id: None,
+            file_hash: hash.into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: relative_path.rsplit('/').next().unwrap_or_default().into(),
+            relative_path: relative_path.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }
+    }
+
+    #[test]
+    fn matches_hashes_and_patterns() {
+        let set = ExclusionSet::from_records(&[
+            ExclusionRecord {
+                id: 1,
+                kind: ExclusionKind::Hash,
+                value: "h1".into(),
+                note: None,
+                created_at: String::new(),
+            },
+            ExclusionRecord {
+                id: 2,
+                kind: ExclusionKind::Pattern,
+                value: "memes".into(),
+                note: None,
+                created_at: String::new(),
+            },
+        ]);
+
+        assert!(set.is_excluded(&record("a/b.jpg", "h1")));
+        assert!(set.is_excluded(&record("chat/memes/cat.jpg", "h2")));
+        assert!(!set.is_excluded(&record("trip/cat.jpg", "h2")));
+    }
+
+    #[test]
+    fn anchored_patterns_stay_in_their_root() {
+        let set = ExclusionSet::from_records(&[pattern("trip/**")]);
+        let mut extra = record("trip/cat.jpg", "h1");
+        extra.source_root = Some("/mnt/phone".into());
+
+        assert!(set.is_excluded(&record("trip/cat.jpg", "h1")));
+        assert!(!set.is_excluded(&extra));
+
+        let set = ExclusionSet::from_records(&[pattern("/mnt/phone/trip")]);
+        assert!(set.is_excluded(&extra));
+        assert!(!set.is_excluded(&record("trip/cat.jpg", "h1")));
+    }
+
+    fn pattern(value: &str) -> ExclusionRecord {
+        ExclusionRecord {
+            id: 1,
+            kind: ExclusionKind::Pattern,
+            value: value.into(),
+            note: None,
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn files_under_an_extra_root_can_be_excluded() -> Result<()> {
+        let dir = tempdir()?;
+        let extra_dir = tempdir()?;
+        let mut config = test_config(dir.path());
+        config.scan.extra_image_roots = vec![extra_dir.path().to_path_buf()];
+        let database = Database::initialize(&config)?;
+        let extra_root = to_posix_string(extra_dir.path()).into_owned();
+        let mut extra = record("a.jpg", "h2");
+        extra.source_root = Some(extra_root.clone());
+        database.replace_inventory(&[record("a.jpg", "h1"), extra])?;
+
+        let excluded = exclude_file(
+            &config,
+            &database,
+            extra_dir.path().join("a.jpg").to_str().unwrap(),
+        )?;
+        assert_eq!(excluded.value, "h2");
+        assert_eq!(excluded.note, Some(format!("{extra_root}/a.jpg")));
+
+        let excluded = exclude_file(&config, &database, "a.jpg")?;
+        assert_eq!(excluded.value, "h1");
+        assert!(exclude_file(&config, &database, "/elsewhere/a.jpg").is_err());
+        Ok(())
+    }
+}
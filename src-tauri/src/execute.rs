@@ -1,19 +1,53 @@
+use std::collections::HashSet;
 use std::fs;
 #[cfg(unix)]
 use std::io::ErrorKind;
 use std::io::Result as IoResult;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
-use crate::config::AppConfig;
+use crate::config::{AppConfig, OriginalsPolicy};
 use crate::db::{Database, NewOperationLog, PlanRecord, PlanStatus};
-use crate::error::Result;
+use crate::error::{AppError, Result};
+use crate::library::{forget_library_file, record_library_file};
+use crate::metadata;
+use crate::metrics::{MetricsRecorder, RunMetrics};
+use crate::network::IoProfile;
 use crate::plan::PLAN_SCHEMA_VERSION;
+use crate::progress::{HoldReason, TaskControl};
+use crate::report::{write_session_report, RunScope};
+use crate::scan::output_folders;
+use crate::telemetry::{self, FailureKind};
+use crate::transcode;
+use crate::utils::copy::{self, copy_chunked, copy_native, ChunkProgress, CHUNKED_COPY_THRESHOLD};
+use crate::utils::fs::{is_locked, remove_empty_dirs};
+use crate::utils::hash::blake3_file;
+use crate::utils::path::{to_native_path, to_posix_string};
+use crate::utils::sparse::{copy_sparse, is_sparse};
+use crate::utils::time::now_timestamp;
 
 const EXECUTE_STAGE: &str = "execute";
 const UNDO_STAGE: &str = "undo";
+const VERIFY_STAGE: &str = "verify";
+const VERIFY_OPERATION: &str = "verify";
+const SHARE_OPERATION: &str = "share";
+const STRIP_OPERATION: &str = "strip_metadata";
+const TRANSCODE_OPERATION: &str = "transcode";
+
+/// Free space left untouched so the database and filesystem metadata never
+/// meet a full disk.
+const DISK_HEADROOM: u64 = 64 * 1024 * 1024;
+/// How often a run held on a full disk looks again.
+const DISK_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Time given to apps holding files locked before the retry pass.
+const LOCKED_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Progress events while a large file copies are at least this far apart.
+const CHUNK_REPORT_INTERVAL: Duration = Duration::from_millis(250);
 
 pub type ExecutionProgressEmitter = Arc<dyn Fn(ExecutionProgressPayload) + Send + Sync>;
 
@@ -49,7 +83,28 @@ pub struct ExecutionSummary {
     pub processed_entries: usize,
     pub succeeded: usize,
     pub failed: usize,
+    /// Entries whose target already held identical content.
+    pub already_present: usize,
     pub duplicate_entries: usize,
+    /// HEIC/HEIF files that got a JPEG rendition.
+    pub transcoded: usize,
+    /// Copies whose routing rule had their metadata removed.
+    pub metadata_stripped: usize,
+    /// Sanitized copies placed under the share root.
+    pub shared: usize,
+    /// Files another app still held locked when they were retried at the
+    /// end; counted in `failed` too.
+    pub locked: usize,
+    /// Source folders removed because the run moved everything out of them.
+    pub removed_folders: usize,
+    /// Targets re-hashed after the transfer and found to match the scan;
+    /// counted in `succeeded`. Copies that did not match are failed.
+    pub verified: usize,
+    /// The Markdown report written for the run, with a JSON twin beside it;
+    /// `None` for dry runs or when it could not be written.
+    pub report_path: Option<String>,
+    /// Bytes count the files copied into place; renames move none.
+    pub metrics: RunMetrics,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -59,6 +114,23 @@ pub struct UndoSummary {
     pub restored: usize,
     pub missing: usize,
     pub failed: usize,
+    /// Output folders removed because the undo emptied them.
+    pub removed_folders: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationSummary {
+    pub checked: usize,
+    pub matched: usize,
+    /// Targets that no longer hash to what the scan found; their entries
+    /// are now failed.
+    pub mismatched: usize,
+    /// Targets gone from the output; their entries are now failed too.
+    pub missing: usize,
+    /// Entries without a scanned hash, or whose copy was converted or
+    /// stripped on purpose.
+    pub skipped: usize,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -68,19 +140,121 @@ pub struct ExecutionProgressPayload {
     pub processed: usize,
     pub total: usize,
     pub current: Option<String>,
+    /// Set while the run waits on a hold such as a full output disk.
+    pub paused: Option<HoldReason>,
+    /// Bytes of the entries handled so far, including the part of a large
+    /// file copied already, and of all of them; only executions track
+    /// bytes.
+    pub bytes_processed: Option<u64>,
+    pub bytes_total: Option<u64>,
+    /// Time left at the bytes-per-second of the transfers so far.
+    pub eta_ms: Option<u64>,
+    /// Set on the last payload of a cancelled run.
+    pub cancelled: bool,
 }
 
+/// Execution progress by bytes as well as entries, so one large video
+/// moves the bar and the ETA the way its size says it should.
+struct ProgressTracker {
+    processed: usize,
+    total: usize,
+    bytes_done: u64,
+    total_bytes: u64,
+    /// Bytes of entries actually copied or moved. Skipped entries count as
+    /// done but say nothing about speed.
+    transferred: u64,
+    started: Instant,
+}
+
+impl ProgressTracker {
+    fn new(entries: &[PlanRecord]) -> Self {
+        Self {
+            processed: 0,
+            total: entries.len(),
+            bytes_done: 0,
+            total_bytes: entries.iter().map(|entry| entry.file_size).sum(),
+            transferred: 0,
+            started: Instant::now(),
+        }
+    }
+
+    fn finish_entry(&mut self, size: u64, transferred: bool) {
+        self.processed += 1;
+        self.bytes_done += size;
+        if transferred {
+            self.transferred += size;
+        }
+    }
+
+    /// `in_flight` is the part of the current file copied so far.
+    fn eta_ms(&self, elapsed: Duration, in_flight: u64) -> Option<u64> {
+        let transferred = self.transferred + in_flight;
+        if transferred == 0 {
+            return None;
+        }
+        let remaining = self.total_bytes.saturating_sub(self.bytes_done + in_flight) as u128;
+        Some((elapsed.as_millis() * remaining / transferred as u128) as u64)
+    }
+
+    fn payload(
+        &self,
+        current: Option<String>,
+        paused: Option<HoldReason>,
+        in_flight: u64,
+    ) -> ExecutionProgressPayload {
+        ExecutionProgressPayload {
+            stage: EXECUTE_STAGE,
+            processed: self.processed,
+            total: self.total,
+            current,
+            paused,
+            bytes_processed: Some(self.bytes_done + in_flight),
+            bytes_total: Some(self.total_bytes),
+            eta_ms: self.eta_ms(self.started.elapsed(), in_flight),
+            cancelled: false,
+        }
+    }
+
+    fn emit(&self, emitter: &ExecutionProgressEmitter, current: Option<String>) {
+        (emitter)(self.payload(current, None, 0));
+    }
+}
+
+/// Carries out the pending plan entries. A cancelled run stops between
+/// files, or mid-copy for large ones, with the partial copy removed and
+/// the entries not done left pending for the next run. With `verify`,
+/// every target is re-hashed before anything else touches it.
 pub fn run_execution(
-    _config: &AppConfig,
+    config: &AppConfig,
     database: &Database,
     mode: ExecutionMode,
     dry_run: bool,
+    verify: bool,
+    control: &TaskControl,
     emitter: ExecutionProgressEmitter,
 ) -> Result<ExecutionSummary> {
+    report_cancellation(emitter, EXECUTE_STAGE, |emitter| {
+        execute_pending(config, database, mode, dry_run, verify, control, emitter)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_pending(
+    config: &AppConfig,
+    database: &Database,
+    mode: ExecutionMode,
+    dry_run: bool,
+    verify: bool,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<ExecutionSummary> {
+    let _run = tracing::info_span!("execute").entered();
+    let metrics = MetricsRecorder::new();
     let entries = database.plan_entries_with_status(&[PlanStatus::Pending])?;
     let total = entries.len();
+    let mut tracker = ProgressTracker::new(&entries);
 
-    emit_progress(&emitter, EXECUTE_STAGE, 0, total, None);
+    tracker.emit(&emitter, None);
 
     if total == 0 {
         return Ok(ExecutionSummary {
@@ -90,112 +264,345 @@ pub fn run_execution(
             processed_entries: 0,
             succeeded: 0,
             failed: 0,
+            already_present: 0,
             duplicate_entries: 0,
+            transcoded: 0,
+            metadata_stripped: 0,
+            shared: 0,
+            locked: 0,
+            removed_folders: 0,
+            verified: 0,
+            report_path: None,
+            metrics: metrics.finish(),
         });
     }
 
+    let started_at = now_timestamp()?;
+    let logs_after = database.last_operation_log_id()?;
+    let profile = io_profile(config);
+    let reserve = config.io.free_space_reserve;
+    let share_root = config.share_root();
     let mut succeeded = 0usize;
     let mut failed = 0usize;
+    let mut already_present = 0usize;
+    let mut transcoded = 0usize;
+    let mut metadata_stripped = 0usize;
+    let mut shared = 0usize;
+    let mut still_locked = 0usize;
+    let mut verified = 0usize;
+    let mut emptied: Vec<PathBuf> = Vec::new();
+
+    // Files another app holds locked are set aside rather than failed, and
+    // tried again once the rest is done: the retry_locked pass.
+    let mut queue: Vec<&PlanRecord> = entries.iter().collect();
+    let mut locked: Vec<&PlanRecord> = Vec::new();
+    let mut retrying_locked = false;
+    loop {
+        for entry in queue.drain(..) {
+            // Entries not reached yet stay pending, so a cancelled run resumes.
+            control.checkpoint()?;
+            let origin_path = to_native_path(&entry.origin_full_path);
+            let target_dir = to_native_path(&entry.target_path);
+            let target_path = target_dir.join(&entry.target_file_name);
+            let current_path = Some(entry.origin_full_path.clone());
+
+            let origin_exists = origin_path.exists();
+            let target_exists = target_path.exists();
+
+            let identical = target_exists
+                && metrics.time("check", || {
+                    tracing::trace_span!("check")
+                        .in_scope(|| target_is_identical(entry, &target_path))
+                });
+            if identical {
+                metrics.read(entry.file_size);
+                already_present += 1;
+                if !dry_run {
+                    database.update_plan_status(entry.id, PlanStatus::AlreadyPresent)?;
+                    index_target(database, &target_path, entry.blake3_hash.as_deref());
+                    database.append_operation_log(NewOperationLog {
+                        plan_entry_id: Some(entry.id),
+                        path: None,
+                        operation: mode.as_str().into(),
+                        status: "skipped".into(),
+                        error: None,
+                    })?;
+                }
+                tracker.finish_entry(entry.file_size, false);
+                tracker.emit(&emitter, current_path);
+                continue;
+            }
 
-    for (idx, entry) in entries.iter().enumerate() {
-        let origin_path = to_native_path(&entry.origin_full_path);
-        let target_dir = to_native_path(&entry.target_path);
-        let target_path = target_dir.join(&entry.target_file_name);
-        let current_path = Some(entry.origin_full_path.clone());
+            if dry_run {
+                if !origin_exists || target_exists {
+                    failed += 1;
+                } else {
+                    succeeded += 1;
+                }
 
-        let origin_exists = origin_path.exists();
-        let target_exists = target_path.exists();
+                tracker.finish_entry(entry.file_size, false);
+                tracker.emit(&emitter, current_path);
+                continue;
+            }
 
-        if dry_run {
-            if !origin_exists || target_exists {
+            if !origin_exists {
                 failed += 1;
-            } else {
-                succeeded += 1;
+                telemetry::record_failure(FailureKind::OriginMissing);
+                record_failure(
+                    database,
+                    entry,
+                    Some(PlanStatus::Failed),
+                    mode.as_str(),
+                    "origin file missing",
+                )?;
+                tracker.finish_entry(entry.file_size, false);
+                tracker.emit(&emitter, current_path);
+                continue;
             }
 
-            emit_progress(&emitter, EXECUTE_STAGE, idx + 1, total, current_path);
-            continue;
-        }
-
-        if !origin_exists {
-            failed += 1;
-            record_failure(
-                database,
-                entry,
-                Some(PlanStatus::Failed),
-                mode.as_str(),
-                "origin file missing",
-            )?;
-            emit_progress(&emitter, EXECUTE_STAGE, idx + 1, total, current_path);
-            continue;
-        }
-
-        if target_exists {
-            failed += 1;
-            record_failure(
-                database,
-                entry,
-                Some(PlanStatus::Failed),
-                mode.as_str(),
-                "target file already exists",
-            )?;
-            emit_progress(&emitter, EXECUTE_STAGE, idx + 1, total, current_path);
-            continue;
-        }
-
-        if let Some(parent) = target_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        let op_result = match mode {
-            ExecutionMode::Copy => copy_file(&origin_path, &target_path),
-            ExecutionMode::Move => move_file(&origin_path, &target_path),
-        };
-
-        match op_result {
-            Ok(()) => {
-                succeeded += 1;
-                database.update_plan_status(entry.id, mode.success_status())?;
-                database.append_operation_log(NewOperationLog {
-                    plan_entry_id: entry.id,
-                    operation: mode.as_str().into(),
-                    status: "success".into(),
-                    error: None,
-                })?;
-            }
-            Err(err) => {
+            if target_exists {
                 failed += 1;
+                telemetry::record_failure(FailureKind::TargetExists);
                 record_failure(
                     database,
                     entry,
                     Some(PlanStatus::Failed),
                     mode.as_str(),
-                    &err.to_string(),
+                    "target file already exists",
                 )?;
+                tracker.finish_entry(entry.file_size, false);
+                tracker.emit(&emitter, current_path);
+                continue;
+            }
+
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+                if needs_space(mode, &origin_path, parent) {
+                    let needed = entry.file_size.saturating_add(reserve);
+                    wait_for_space(control, parent, needed, &emitter, &tracker)?;
+                }
             }
-        }
 
-        emit_progress(&emitter, EXECUTE_STAGE, idx + 1, total, current_path);
+            let transfer = tracing::trace_span!("transfer").entered();
+            let started = Instant::now();
+            let mut last_report = started;
+            // Large files stop between chunks when the run is cancelled, and
+            // wait there while it is paused.
+            let mut on_chunk = |copied: u64| {
+                if control.checkpoint().is_err() {
+                    return Err(copy::cancelled());
+                }
+                if last_report.elapsed() >= CHUNK_REPORT_INTERVAL {
+                    last_report = Instant::now();
+                    (emitter)(tracker.payload(current_path.clone(), None, copied));
+                }
+                Ok(())
+            };
+            let op_result = metrics.time("transfer", || match mode {
+                ExecutionMode::Copy => {
+                    profile.retry(|| copy_file(&origin_path, &target_path, &mut on_chunk))
+                }
+                ExecutionMode::Move => {
+                    profile.retry(|| move_file(&origin_path, &target_path, profile, &mut on_chunk))
+                }
+            });
+            drop(transfer);
+            if op_result.as_ref().is_err_and(copy::is_cancelled) {
+                // The partial copy is gone and the entry stays pending.
+                return Err(AppError::Cancelled);
+            }
+            telemetry::record_copy(started.elapsed());
+
+            if !retrying_locked && op_result.as_ref().is_err_and(is_locked) {
+                tracing::debug!(path = %origin_path.display(), "file is locked, retrying at the end");
+                locked.push(entry);
+                database.append_operation_log(NewOperationLog {
+                    plan_entry_id: Some(entry.id),
+                    path: None,
+                    operation: mode.as_str().into(),
+                    status: "retry".into(),
+                    error: Some(AppError::FileLocked(entry.origin_full_path.clone()).to_string()),
+                })?;
+                continue;
+            }
+
+            tracker.finish_entry(entry.file_size, op_result.is_ok());
+            match op_result {
+                Ok(copied) => {
+                    metrics.read(copied);
+                    metrics.wrote(copied);
+                    if mode == ExecutionMode::Move {
+                        emptied.extend(origin_path.parent().map(Path::to_path_buf));
+                    }
+                    if verify {
+                        let checked = metrics.time("verify", || verify_target(entry, &target_path));
+                        match checked {
+                            Ok(hashed) => {
+                                if hashed {
+                                    metrics.read(entry.file_size);
+                                    verified += 1;
+                                }
+                            }
+                            Err(message) => {
+                                failed += 1;
+                                telemetry::record_failure(FailureKind::Io);
+                                // A moved file's original is gone; it stays
+                                // moved so undo can still bring it back.
+                                let status = match mode {
+                                    ExecutionMode::Copy => PlanStatus::Failed,
+                                    ExecutionMode::Move => PlanStatus::Moved,
+                                };
+                                record_failure(
+                                    database,
+                                    entry,
+                                    Some(status),
+                                    VERIFY_OPERATION,
+                                    &message,
+                                )?;
+                                tracker.emit(&emitter, current_path);
+                                continue;
+                            }
+                        }
+                    }
+                    metrics.start("finish");
+                    let finish = tracing::trace_span!("finish").entered();
+                    succeeded += 1;
+                    database.update_plan_status(entry.id, mode.success_status())?;
+                    database.append_operation_log(NewOperationLog {
+                        plan_entry_id: Some(entry.id),
+                        path: None,
+                        operation: mode.as_str().into(),
+                        status: "success".into(),
+                        error: None,
+                    })?;
+                    let jpeg = transcode_target(config, database, entry, mode, &target_path)?;
+                    // Later steps work on the JPEG when there is one.
+                    let landed = jpeg.as_deref().unwrap_or(&target_path);
+                    let only_copy = mode == ExecutionMode::Move && jpeg.is_none();
+                    let stripped =
+                        entry.strip_metadata && strip_target(database, entry, only_copy, landed)?;
+                    if stripped {
+                        metadata_stripped += 1;
+                    }
+                    if let Some(jpeg) = &jpeg {
+                        transcoded += 1;
+                        index_target(database, jpeg, None);
+                    }
+                    if target_path.exists() {
+                        // The planned hash no longer describes a stripped copy.
+                        let known_hash = if stripped && jpeg.is_none() {
+                            None
+                        } else {
+                            entry.blake3_hash.as_deref()
+                        };
+                        index_target(database, &target_path, known_hash);
+                    }
+                    if let (Some(share_root), false) = (&share_root, entry.is_duplicate) {
+                        if share_target(config, database, entry, share_root, landed, profile)? {
+                            shared += 1;
+                        }
+                    }
+                    drop(finish);
+                    metrics.stop("finish");
+                }
+                Err(err) => {
+                    failed += 1;
+                    let message = if is_locked(&err) {
+                        still_locked += 1;
+                        telemetry::record_failure(FailureKind::Locked);
+                        AppError::FileLocked(entry.origin_full_path.clone()).to_string()
+                    } else {
+                        telemetry::record_failure(FailureKind::Io);
+                        err.to_string()
+                    };
+                    record_failure(
+                        database,
+                        entry,
+                        Some(PlanStatus::Failed),
+                        mode.as_str(),
+                        &message,
+                    )?;
+                }
+            }
+
+            tracker.emit(&emitter, current_path);
+        }
+        if locked.is_empty() || retrying_locked {
+            break;
+        }
+        tracing::info!(files = locked.len(), "retrying files locked by other apps");
+        retrying_locked = true;
+        thread::sleep(LOCKED_RETRY_DELAY);
+        queue = std::mem::take(&mut locked);
     }
 
     database.set_meta("plan_schema_version", &PLAN_SCHEMA_VERSION.to_string())?;
 
+    let removed_folders = if config.io.remove_empty_folders {
+        let source_roots: Vec<PathBuf> = config
+            .sample_image_root
+            .iter()
+            .chain([&config.image_root])
+            .cloned()
+            .chain(config.source_roots())
+            .collect();
+        remove_empty_dirs(emptied, &source_roots)
+    } else {
+        0
+    };
+
     let duplicate_entries = entries.iter().filter(|entry| entry.is_duplicate).count();
+    let metrics = metrics.finish();
+    telemetry::finish_run("execute", metrics.elapsed(), &config.telemetry);
 
-    Ok(ExecutionSummary {
+    let mut summary = ExecutionSummary {
         mode,
         dry_run,
         total_entries: total,
         processed_entries: total,
         succeeded,
         failed,
+        already_present,
         duplicate_entries,
-    })
+        transcoded,
+        metadata_stripped,
+        shared,
+        locked: still_locked,
+        removed_folders,
+        verified,
+        report_path: None,
+        metrics,
+    };
+    if !dry_run {
+        let entry_ids: HashSet<i64> = entries.iter().map(|entry| entry.id).collect();
+        let scope = RunScope {
+            started_at: &started_at,
+            logs_after,
+            entry_ids: &entry_ids,
+        };
+        match write_session_report(config, database, &summary, &scope) {
+            Ok(path) => summary.report_path = Some(to_posix_string(&path).into_owned()),
+            Err(err) => tracing::warn!(error = %err, "failed to write the run report"),
+        }
+    }
+    Ok(summary)
 }
 
 pub fn undo_moves(
-    _config: &AppConfig,
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<UndoSummary> {
+    report_cancellation(emitter, UNDO_STAGE, |emitter| {
+        restore_moves(config, database, control, emitter)
+    })
+}
+
+fn restore_moves(
+    config: &AppConfig,
     database: &Database,
+    control: &TaskControl,
     emitter: ExecutionProgressEmitter,
 ) -> Result<UndoSummary> {
     let moved_entries = database.plan_entries_with_status(&[PlanStatus::Moved])?;
@@ -209,14 +616,18 @@ pub fn undo_moves(
             restored: 0,
             missing: 0,
             failed: 0,
+            removed_folders: 0,
         });
     }
 
+    let profile = io_profile(config);
     let mut restored = 0usize;
     let mut missing = 0usize;
     let mut failed = 0usize;
+    let mut emptied: Vec<PathBuf> = Vec::new();
 
     for (idx, entry) in moved_entries.iter().enumerate() {
+        control.checkpoint()?;
         let origin_path = to_native_path(&entry.origin_full_path);
         let target_dir = to_native_path(&entry.target_path);
         let target_path = target_dir.join(&entry.target_file_name);
@@ -233,12 +644,17 @@ pub fn undo_moves(
             fs::create_dir_all(parent)?;
         }
 
-        match move_file(&target_path, &origin_path) {
-            Ok(()) => {
+        match profile.retry(|| move_file(&target_path, &origin_path, profile, &mut no_progress)) {
+            Ok(_) => {
                 restored += 1;
+                emptied.push(target_dir.clone());
                 database.update_plan_status(entry.id, PlanStatus::Pending)?;
+                if let Err(err) = forget_library_file(database, &target_path) {
+                    tracing::warn!(path = %target_path.display(), error = %err, "failed to update library index");
+                }
                 database.append_operation_log(NewOperationLog {
-                    plan_entry_id: entry.id,
+                    plan_entry_id: Some(entry.id),
+                    path: None,
                     operation: "undo".into(),
                     status: "success".into(),
                     error: None,
@@ -253,15 +669,61 @@ pub fn undo_moves(
         emit_progress(&emitter, UNDO_STAGE, idx + 1, total, current_path);
     }
 
+    let removed_folders = if config.io.remove_empty_folders {
+        remove_empty_dirs(emptied, &output_folders(config)?)
+    } else {
+        0
+    };
+
     Ok(UndoSummary {
         processed_entries: total,
         restored,
         missing,
         failed,
+        removed_folders,
     })
 }
 
-fn emit_progress(
+/// Runs `work` with an emitter that remembers the last payload, and sends
+/// it once more marked cancelled when `work` is cancelled, so listeners see
+/// the run end where it stopped.
+pub fn report_cancellation<T>(
+    emitter: ExecutionProgressEmitter,
+    stage: &'static str,
+    work: impl FnOnce(ExecutionProgressEmitter) -> Result<T>,
+) -> Result<T> {
+    let last: Arc<Mutex<Option<ExecutionProgressPayload>>> = Arc::default();
+    let tracked: ExecutionProgressEmitter = {
+        let last = Arc::clone(&last);
+        let emitter = Arc::clone(&emitter);
+        Arc::new(move |payload: ExecutionProgressPayload| {
+            *last.lock() = Some(payload.clone());
+            (emitter)(payload);
+        })
+    };
+    let result = work(tracked);
+    if matches!(result, Err(AppError::Cancelled)) {
+        let mut payload = last.lock().take().unwrap_or(ExecutionProgressPayload {
+            stage,
+            processed: 0,
+            total: 0,
+            current: None,
+            paused: None,
+            bytes_processed: None,
+            bytes_total: None,
+            eta_ms: None,
+            cancelled: false,
+        });
+        payload.current = None;
+        payload.paused = None;
+        payload.eta_ms = None;
+        payload.cancelled = true;
+        (emitter)(payload);
+    }
+    result
+}
+
+pub fn emit_progress(
     emitter: &ExecutionProgressEmitter,
     stage: &'static str,
     processed: usize,
@@ -273,27 +735,274 @@ fn emit_progress(
         processed,
         total,
         current,
+        paused: None,
+        bytes_processed: None,
+        bytes_total: None,
+        eta_ms: None,
+        cancelled: false,
     };
     (emitter)(payload);
 }
 
-fn to_native_path(path: &str) -> PathBuf {
-    PathBuf::from(path)
+/// Holds the run while `target_dir`'s volume lacks room for `needed` bytes,
+/// the configured reserve included, plus headroom, and resumes by itself
+/// once space is freed.
+fn wait_for_space(
+    control: &TaskControl,
+    target_dir: &Path,
+    needed: u64,
+    emitter: &ExecutionProgressEmitter,
+    tracker: &ProgressTracker,
+) -> Result<()> {
+    loop {
+        let available = fs2::available_space(target_dir)?;
+        if available >= needed.saturating_add(DISK_HEADROOM) {
+            if control.is_held(HoldReason::DiskFull) {
+                control.release(HoldReason::DiskFull);
+                tracing::info!(dir = %target_dir.display(), "output disk has room again, resuming");
+                tracker.emit(emitter, None);
+            }
+            return Ok(());
+        }
+        if !control.is_held(HoldReason::DiskFull) {
+            control.hold(HoldReason::DiskFull);
+            tracing::warn!(
+                dir = %target_dir.display(),
+                available,
+                needed,
+                "output disk is full, pausing"
+            );
+            (emitter)(tracker.payload(
+                Some(to_posix_string(target_dir).into_owned()),
+                Some(HoldReason::DiskFull),
+                0,
+            ));
+        }
+        if control.is_cancelled() {
+            control.release(HoldReason::DiskFull);
+            return Err(AppError::Cancelled);
+        }
+        thread::sleep(DISK_RECHECK_INTERVAL);
+    }
+}
+
+/// Whether writing `origin` into `target_dir` takes new space: copies
+/// always do, moves only when they cross volumes and fall back to a copy.
+fn needs_space(mode: ExecutionMode, origin: &Path, target_dir: &Path) -> bool {
+    match mode {
+        ExecutionMode::Copy => true,
+        ExecutionMode::Move => crosses_devices(origin, target_dir),
+    }
+}
+
+#[cfg(unix)]
+fn crosses_devices(origin: &Path, target_dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(origin), fs::metadata(target_dir)) {
+        (Ok(origin), Ok(target)) => origin.dev() != target.dev(),
+        _ => true,
+    }
+}
+
+/// Renames never fall back to a copy here, see `should_fallback_copy`.
+#[cfg(not(unix))]
+fn crosses_devices(_origin: &Path, _target_dir: &Path) -> bool {
+    false
+}
+
+/// A leftover target from an interrupted run counts as done when its size
+/// and BLAKE3 match what was planned.
+fn target_is_identical(entry: &PlanRecord, target: &Path) -> bool {
+    let Some(expected) = entry.blake3_hash.as_deref() else {
+        return false;
+    };
+    let same_size = fs::metadata(target)
+        .map(|meta| meta.len() == entry.file_size)
+        .unwrap_or(false);
+    same_size && blake3_file(target).is_ok_and(|actual| actual == expected)
+}
+
+/// Keeps the library index current after a file lands; a failure here
+/// must not fail the execution itself.
+fn index_target(database: &Database, target: &Path, known_hash: Option<&str>) {
+    if let Err(err) = record_library_file(database, target, known_hash) {
+        tracing::warn!(path = %target.display(), error = %err, "failed to update library index");
+    }
+}
+
+/// Copies an organized file into the share root, mirroring its place under
+/// the output root, and strips GPS tags from the copy when configured. A
+/// copy whose tags cannot be stripped is removed again, so nothing carrying
+/// a location ends up in the share folder. Returns whether a copy was kept.
+fn share_target(
+    config: &AppConfig,
+    database: &Database,
+    entry: &PlanRecord,
+    share_root: &Path,
+    target: &Path,
+    profile: IoProfile,
+) -> Result<bool> {
+    let share_path = mirrored_path(config, share_root, target);
+
+    let placed = (|| {
+        if let Some(parent) = share_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        profile.retry(|| copy_file(target, &share_path, &mut no_progress))?;
+        if config.plan.share.strip_gps {
+            if let Err(err) = metadata::strip_gps(&share_path) {
+                let _ = fs::remove_file(&share_path);
+                return Err(err);
+            }
+        }
+        Ok(())
+    })();
+
+    // Formats without editable EXIF are left out rather than shared with
+    // whatever location they carry.
+    log_step(database, entry, SHARE_OPERATION, &placed)?;
+    Ok(placed.is_ok())
+}
+
+/// Where `target` lands under `mirror_root` when the layout below its output
+/// root is kept. Targets outside every output root keep only their name.
+pub fn mirrored_path(config: &AppConfig, mirror_root: &Path, target: &Path) -> PathBuf {
+    let relative = config
+        .output_roots()
+        .into_iter()
+        .filter(|root| target.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .and_then(|root| target.strip_prefix(root).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| target.file_name().map(PathBuf::from).unwrap_or_default());
+    mirror_root.join(relative)
+}
+
+/// Writes a JPEG next to a HEIC/HEIF target when transcoding is on, and
+/// returns its path. Under the discard policy a copied HEIC is removed from
+/// the output afterwards; a moved one is the only original and stays.
+fn transcode_target(
+    config: &AppConfig,
+    database: &Database,
+    entry: &PlanRecord,
+    mode: ExecutionMode,
+    target: &Path,
+) -> Result<Option<PathBuf>> {
+    let options = &config.plan.transcode;
+    if !options.heic_to_jpeg || entry.is_duplicate || !transcode::is_heif(target) {
+        return Ok(None);
+    }
+    let jpeg = target.with_extension("jpg");
+    let converted = transcode::heic_to_jpeg(target, &jpeg, options.quality);
+    log_step(database, entry, TRANSCODE_OPERATION, &converted)?;
+    if converted.is_err() {
+        return Ok(None);
+    }
+    if options.originals == OriginalsPolicy::Discard && mode == ExecutionMode::Copy {
+        fs::remove_file(target)?;
+    }
+    Ok(Some(jpeg))
+}
+
+/// Removes all metadata from a fresh copy whose routing rule asks for it.
+/// A moved file is the only copy left, so it keeps its metadata.
+fn strip_target(
+    database: &Database,
+    entry: &PlanRecord,
+    only_copy: bool,
+    target: &Path,
+) -> Result<bool> {
+    let stripped = if only_copy {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "moved files keep their metadata",
+        ))
+    } else {
+        metadata::strip_all(target).map(|_| ())
+    };
+    log_step(database, entry, STRIP_OPERATION, &stripped)?;
+    Ok(stripped.is_ok())
+}
+
+/// Records an optional per-file step; unsupported inputs count as skipped.
+fn log_step(
+    database: &Database,
+    entry: &PlanRecord,
+    operation: &str,
+    outcome: &IoResult<()>,
+) -> Result<()> {
+    let (status, error) = match outcome {
+        Ok(()) => ("success", None),
+        Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
+            ("skipped", Some(err.to_string()))
+        }
+        Err(err) => ("failure", Some(err.to_string())),
+    };
+    database.append_operation_log(NewOperationLog {
+        plan_entry_id: Some(entry.id),
+        path: None,
+        operation: operation.into(),
+        status: status.into(),
+        error,
+    })?;
+    Ok(())
+}
+
+/// Network shares on either side slow the run down and avoid renames.
+fn io_profile(config: &AppConfig) -> IoProfile {
+    let sources = config.source_roots();
+    let outputs = config.output_roots();
+    let profile = IoProfile::configured(
+        sources.iter().chain(&outputs).map(PathBuf::as_path),
+        &config.io,
+    );
+    if profile.network {
+        tracing::info!("network share involved; moves copy then delete, with retries");
+    }
+    profile
 }
 
-fn copy_file(origin: &Path, target: &Path) -> IoResult<()> {
-    fs::copy(origin, target)?;
+/// Returns the bytes copied. Sparse files are copied hole by hole so
+/// the copy takes no more disk than the original, and large ones with the
+/// platform's copy API, both reporting to `on_chunk`; the rest go through
+/// `fs::copy`.
+fn copy_file(origin: &Path, target: &Path, on_chunk: ChunkProgress<'_>) -> IoResult<u64> {
+    let metadata = fs::metadata(origin)?;
+    if is_sparse(&metadata) {
+        return copy_sparse(origin, target, on_chunk);
+    }
+    if metadata.len() >= CHUNKED_COPY_THRESHOLD {
+        // The generic loop stands in where the platform has no API that
+        // reports progress.
+        return match copy_native(origin, target, &mut *on_chunk) {
+            Err(err) if err.kind() == ErrorKind::Unsupported => {
+                copy_chunked(origin, target, on_chunk)
+            }
+            result => result,
+        };
+    }
+    fs::copy(origin, target)
+}
+
+fn no_progress(_copied: u64) -> IoResult<()> {
     Ok(())
 }
 
-fn move_file(origin: &Path, target: &Path) -> IoResult<()> {
+/// Returns the bytes copied, which is none when the file was renamed.
+fn move_file(
+    origin: &Path,
+    target: &Path,
+    profile: IoProfile,
+    on_chunk: ChunkProgress<'_>,
+) -> IoResult<u64> {
+    if !profile.renames() {
+        return copy_then_delete(origin, target, profile, on_chunk);
+    }
     match fs::rename(origin, target) {
-        Ok(()) => Ok(()),
+        Ok(()) => Ok(0),
         Err(err) => {
             if should_fallback_copy(&err) {
-                fs::copy(origin, target)?;
-                fs::remove_file(origin)?;
-                Ok(())
+                copy_then_delete(origin, target, profile, on_chunk)
             } else {
                 Err(err)
             }
@@ -301,9 +1010,28 @@ fn move_file(origin: &Path, target: &Path) -> IoResult<()> {
     }
 }
 
+/// The original is only deleted once the copy is confirmed and on disk,
+/// so a crash or power loss mid-move leaves at least one whole file.
+fn copy_then_delete(
+    origin: &Path,
+    target: &Path,
+    profile: IoProfile,
+    on_chunk: ChunkProgress<'_>,
+) -> IoResult<u64> {
+    let copied = copy_file(origin, target, on_chunk)?;
+    copy::confirm_copy(origin, target, profile.verify_moves)?;
+    if let Err(err) = fs::remove_file(origin) {
+        // An original that cannot go, often because an app has it open,
+        // leaves the move undone rather than half done.
+        let _ = fs::remove_file(target);
+        return Err(err);
+    }
+    Ok(copied)
+}
+
 #[cfg(unix)]
 fn should_fallback_copy(err: &std::io::Error) -> bool {
-    err.kind() == ErrorKind::CrossDeviceLink
+    err.kind() == ErrorKind::CrossesDevices
 }
 
 #[cfg(not(unix))]
@@ -311,6 +1039,87 @@ fn should_fallback_copy(_err: &std::io::Error) -> bool {
     false
 }
 
+/// Re-hashes `target` and compares it with the BLAKE3 the scan recorded for
+/// the entry's origin. `Ok(false)` when the scan left the file unhashed, so
+/// there is nothing to compare; the error says why the target does not
+/// match.
+fn verify_target(entry: &PlanRecord, target: &Path) -> std::result::Result<bool, String> {
+    let Some(expected) = entry.blake3_hash.as_deref() else {
+        return Ok(false);
+    };
+    match blake3_file(target) {
+        Ok(actual) if actual == expected => Ok(true),
+        Ok(_) => Err("target does not match the scanned file's hash".to_string()),
+        Err(err) => Err(format!("could not hash the target: {err}")),
+    }
+}
+
+/// Re-hashes the targets of every copied or moved entry against the scan,
+/// failing the copies whose target is missing or no longer matches. Moves
+/// that fail only log it and stay moved, so undo still covers them. Copies
+/// converted to JPEG or stripped of metadata are left out, since they were
+/// changed on purpose.
+pub fn verify_execution(
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<VerificationSummary> {
+    report_cancellation(emitter, VERIFY_STAGE, |emitter| {
+        verify_entries(config, database, control, emitter)
+    })
+}
+
+fn verify_entries(
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<VerificationSummary> {
+    let _run = tracing::info_span!("verify").entered();
+    let entries = database.plan_entries_with_status(&[PlanStatus::Copied, PlanStatus::Moved])?;
+    let total = entries.len();
+    let transcoding = config.plan.transcode.heic_to_jpeg;
+    emit_progress(&emitter, VERIFY_STAGE, 0, total, None);
+
+    let mut summary = VerificationSummary::default();
+    for (idx, entry) in entries.iter().enumerate() {
+        control.checkpoint()?;
+        let target = to_native_path(&entry.target_path).join(&entry.target_file_name);
+        let current = Some(entry.origin_full_path.clone());
+        let changed_on_purpose = (entry.status == PlanStatus::Copied && entry.strip_metadata)
+            || (transcoding && !entry.is_duplicate && transcode::is_heif(&target));
+        if entry.blake3_hash.is_none() || changed_on_purpose {
+            summary.skipped += 1;
+            emit_progress(&emitter, VERIFY_STAGE, idx + 1, total, current);
+            continue;
+        }
+        summary.checked += 1;
+        let failure = if !target.exists() {
+            summary.missing += 1;
+            Some("target file missing".to_string())
+        } else if let Err(message) = verify_target(entry, &target) {
+            summary.mismatched += 1;
+            Some(message)
+        } else {
+            summary.matched += 1;
+            None
+        };
+        if let Some(message) = failure {
+            let status = (entry.status == PlanStatus::Copied).then_some(PlanStatus::Failed);
+            record_failure(database, entry, status, VERIFY_OPERATION, &message)?;
+        }
+        emit_progress(&emitter, VERIFY_STAGE, idx + 1, total, current);
+    }
+    tracing::info!(
+        checked = summary.checked,
+        mismatched = summary.mismatched,
+        missing = summary.missing,
+        "verified executed entries"
+    );
+    Ok(summary)
+}
+
 fn record_failure(
     database: &Database,
     entry: &PlanRecord,
@@ -322,7 +1131,8 @@ fn record_failure(
         database.update_plan_status(entry.id, status)?;
     }
     database.append_operation_log(NewOperationLog {
-        plan_entry_id: entry.id,
+        plan_entry_id: Some(entry.id),
+        path: None,
         operation: operation.into(),
         status: "failure".into(),
         error: Some(message.to_string()),
@@ -333,18 +1143,44 @@ fn record_failure(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::SCHEMA_VERSION;
+    use crate::config::test_config;
     use crate::db::InventoryRecord;
+    use crate::media::MediaKind;
     use crate::plan::{generate_plan, PlanProgressEmitter};
     use serde_json::Value;
-    use std::collections::HashSet;
     use tempfile::tempdir;
 
+    #[test]
+    fn eta_follows_bytes_transferred() {
+        let mut tracker = ProgressTracker {
+            processed: 0,
+            total: 3,
+            bytes_done: 0,
+            total_bytes: 1000,
+            transferred: 0,
+            started: Instant::now(),
+        };
+        // Files already in place are done, but give no speed to go by.
+        tracker.finish_entry(100, false);
+        assert_eq!(tracker.eta_ms(Duration::from_secs(1), 0), None);
+        tracker.finish_entry(300, true);
+        assert_eq!(tracker.eta_ms(Duration::from_secs(3), 0), Some(6000));
+        // Halfway through a 200-byte file, at the same speed.
+        assert_eq!(tracker.eta_ms(Duration::from_secs(4), 100), Some(5000));
+        let payload = tracker.payload(None, None, 100);
+        assert_eq!((payload.processed, payload.bytes_processed), (2, Some(500)));
+    }
+
     #[test]
     fn copy_execution_copies_files_and_updates_status() -> Result<()> {
         let setup = TestHarness::new()?;
         let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
-        generate_plan(&setup.config, &setup.database, plan_emitter)?;
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
 
         let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
         let summary = run_execution(
@@ -352,6 +1188,8 @@ mod tests {
             &setup.database,
             ExecutionMode::Copy,
             false,
+            false,
+            &TaskControl::default(),
             exec_emitter.clone(),
         )?;
 
@@ -366,7 +1204,216 @@ mod tests {
         assert!(statuses
             .iter()
             .all(|entry| entry.status == PlanStatus::Copied));
+        assert_eq!(setup.database.library_entries()?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verification_fails_targets_that_no_longer_match() -> Result<()> {
+        let setup = TestHarness::new()?;
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        let summary = run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Copy,
+            false,
+            true,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!((summary.succeeded, summary.verified), (2, 2));
 
+        fs::write(setup.target_one(), b"bitrot")?;
+        let verification = verify_execution(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(
+            (
+                verification.checked,
+                verification.matched,
+                verification.mismatched
+            ),
+            (2, 1, 1)
+        );
+        let failed = setup
+            .database
+            .plan_entries_with_status(&[PlanStatus::Failed])?;
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].origin_full_path.ends_with("A/IMG_0001.JPG"));
+        assert!(setup
+            .database
+            .operation_logs_after(0)?
+            .iter()
+            .any(|log| log.operation == VERIFY_OPERATION && log.status == "failure"));
+        Ok(())
+    }
+
+    #[test]
+    fn moves_that_fail_verification_can_still_be_undone() -> Result<()> {
+        let setup = TestHarness::new()?;
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Move,
+            false,
+            true,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert!(!setup.origin_one().exists());
+
+        fs::write(setup.target_one(), b"bitrot")?;
+        let verification = verify_execution(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(verification.mismatched, 1);
+        assert!(setup
+            .database
+            .plan_entries_with_status(&[PlanStatus::Failed])?
+            .is_empty());
+
+        let undone = undo_moves(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(undone.restored, 2);
+        // The file comes back as it is now; undo cannot repair it.
+        assert_eq!(fs::read(setup.origin_one())?, b"bitrot");
+        Ok(())
+    }
+
+    #[test]
+    fn share_copies_mirror_the_output_layout() -> Result<()> {
+        let mut setup = TestHarness::new()?;
+        let share_dir = tempdir()?;
+        setup.config.plan.share.root = Some(share_dir.path().to_path_buf());
+        let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
+
+        // The fixtures are not real JPEGs, so GPS stripping would refuse them.
+        setup.config.plan.share.strip_gps = false;
+        let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+        let summary = run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Copy,
+            false,
+            false,
+            &TaskControl::default(),
+            exec_emitter,
+        )?;
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.shared, 1);
+
+        let target = setup.target_one();
+        let relative = target.strip_prefix(&setup.config.output_root).unwrap();
+        assert_eq!(fs::read(share_dir.path().join(relative))?, b"unique");
+        assert!(setup.origin_one().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn unstrippable_files_are_not_shared() -> Result<()> {
+        let mut setup = TestHarness::new()?;
+        let share_dir = tempdir()?;
+        setup.config.plan.share.root = Some(share_dir.path().to_path_buf());
+        let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
+
+        let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+        let summary = run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Copy,
+            false,
+            false,
+            &TaskControl::default(),
+            exec_emitter,
+        )?;
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.shared, 0);
+        let target = setup.target_one();
+        let relative = target.strip_prefix(&setup.config.output_root).unwrap();
+        assert!(!share_dir.path().join(relative).exists());
+        assert!(target.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn rules_can_strip_metadata_from_copies() -> Result<()> {
+        let mut setup = TestHarness::new()?;
+        setup.config.plan.routing_rules = vec![crate::config::RoutingRule {
+            name: "private".into(),
+            path_contains: Some("A/".into()),
+            extensions: Vec::new(),
+            target: "private".into(),
+            strip_metadata: true,
+        }];
+        let comment = [0xFF, 0xFE, 0x00, 0x06, b'h', b'o', b'm', b'e'];
+        let scan = [0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+        let original = [&[0xFF, 0xD8][..], &comment, &scan].concat();
+        fs::write(setup.origin_one(), &original)?;
+        let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
+
+        let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+        let summary = run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Copy,
+            false,
+            false,
+            &TaskControl::default(),
+            exec_emitter,
+        )?;
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.metadata_stripped, 1);
+
+        let stripped = [&[0xFF, 0xD8][..], &scan].concat();
+        let target = setup.target_one();
+        assert_eq!(fs::read(&target)?, stripped);
+        assert_eq!(fs::read(setup.origin_one())?, original);
+        let indexed = setup.database.library_entries()?;
+        let entry = indexed
+            .iter()
+            .find(|entry| Path::new(&entry.path) == target)
+            .expect("indexed target");
+        assert_eq!(entry.blake3_hash, blake3::hash(&stripped).to_hex().as_str());
         Ok(())
     }
 
@@ -374,7 +1421,12 @@ mod tests {
     fn move_and_undo_restore_origins() -> Result<()> {
         let setup = TestHarness::new()?;
         let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
-        generate_plan(&setup.config, &setup.database, plan_emitter)?;
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
 
         let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
         let summary = run_execution(
@@ -382,6 +1434,8 @@ mod tests {
             &setup.database,
             ExecutionMode::Move,
             false,
+            false,
+            &TaskControl::default(),
             exec_emitter.clone(),
         )?;
         assert_eq!(summary.succeeded, 2);
@@ -389,8 +1443,19 @@ mod tests {
         assert!(!setup.origin_duplicate().exists());
         assert!(setup.target_one().exists());
         assert!(setup.duplicate_target().exists());
-
-        let undo_summary = undo_moves(&setup.config, &setup.database, exec_emitter)?;
+        let report = PathBuf::from(summary.report_path.expect("a report is written"));
+        let markdown = fs::read_to_string(&report)?;
+        assert!(markdown.contains("| Moved | 2 |") && markdown.contains("## Undo"));
+        let json: Value =
+            serde_json::from_str(&fs::read_to_string(report.with_extension("json"))?)?;
+        assert_eq!(json["summary"]["succeeded"], 2);
+
+        let undo_summary = undo_moves(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            exec_emitter,
+        )?;
         assert_eq!(undo_summary.restored, 2);
         assert!(setup.origin_one().exists());
         assert!(setup.origin_duplicate().exists());
@@ -405,6 +1470,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn identical_targets_are_marked_already_present() -> Result<()> {
+        let setup = TestHarness::new()?;
+        let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
+        generate_plan(
+            &setup.config,
+            &setup.database,
+            &TaskControl::default(),
+            plan_emitter,
+        )?;
+
+        let target = setup.target_one();
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(setup.origin_one(), &target)?;
+        let conflicting = setup.duplicate_target();
+        fs::create_dir_all(conflicting.parent().unwrap())?;
+        fs::write(&conflicting, b"different")?;
+
+        let exec_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+        let summary = run_execution(
+            &setup.config,
+            &setup.database,
+            ExecutionMode::Copy,
+            false,
+            false,
+            &TaskControl::default(),
+            exec_emitter,
+        )?;
+        assert_eq!(summary.already_present, 1);
+        assert_eq!(summary.failed, 1);
+
+        let entries = setup.database.plan_entries()?;
+        let status_of = |hash: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.file_hash == hash)
+                .map(|entry| entry.status)
+        };
+        assert_eq!(status_of("hash-unique"), Some(PlanStatus::AlreadyPresent));
+        assert_eq!(status_of("hash-dup"), Some(PlanStatus::Failed));
+        Ok(())
+    }
+
     struct TestHarness {
         config: crate::config::AppConfig,
         database: Database,
@@ -413,32 +1521,12 @@ mod tests {
     }
 
     impl TestHarness {
-        #[allow(deprecated)]
         fn new() -> Result<Self> {
-            let root_dir = tempdir()?.into_path();
-            let output_dir = tempdir()?.into_path();
-            let duplicates_dir = output_dir.join("duplicates");
+            let config = test_config(&tempdir()?.keep());
+            let root_dir = config.image_root.clone();
+            let duplicates_dir = config.duplicates_dir.clone();
             fs::create_dir_all(&duplicates_dir)?;
 
-            let db_path = output_dir.join("exec.sqlite3");
-            let config = crate::config::AppConfig {
-                schema_version: SCHEMA_VERSION,
-                home_dir: root_dir.clone(),
-                app_data_dir: output_dir.clone(),
-                database_path: db_path.clone(),
-                image_root: root_dir.clone(),
-                image_root_default_name: "images".into(),
-                output_root: output_dir.clone(),
-                output_root_name: "output".into(),
-                duplicates_dir: duplicates_dir.clone(),
-                duplicates_folder_name: "duplicates".into(),
-                origin_info_path: output_dir.join("origin.json"),
-                target_plan_path: output_dir.join("plan.json"),
-                image_exts: HashSet::from([".jpg".into()]),
-                config_file_path: root_dir.join("config.json"),
-                sample_image_root: None,
-            };
-
             let database = Database::initialize(&config)?;
 
             let unique_dir = root_dir.join("A");
@@ -455,30 +1543,48 @@ mod tests {
                 InventoryRecord {
                     id: None,
                     file_hash: "hash-unique".into(),
-                    blake3_hash: None,
+                    blake3_hash: Some(blake3::hash(b"unique").to_hex().to_string()),
                     file_size: 6,
                     file_name: "IMG_0001.JPG".into(),
                     relative_path: "A/IMG_0001.JPG".into(),
+                    source_root: None,
+                    media_type: MediaKind::Photo,
                     captured_at: Some("2024-01-02_10-00-00".into()),
                     modified_at: "2024-01-02_10-00-00".into(),
                     exif_model: None,
                     exif_make: None,
                     exif_artist: None,
+                    rating: None,
+                    tags: Vec::new(),
+                    gps_latitude: None,
+                    gps_longitude: None,
+                    derivative: None,
                     is_duplicate: false,
+                    duplicate_confirmation: None,
+                    pixel_hash: None,
                 },
                 InventoryRecord {
                     id: None,
                     file_hash: "hash-dup".into(),
-                    blake3_hash: None,
+                    blake3_hash: Some(blake3::hash(b"dup").to_hex().to_string()),
                     file_size: 3,
                     file_name: "IMG_0001.JPG".into(),
                     relative_path: "B/IMG_0001.JPG".into(),
+                    source_root: None,
+                    media_type: MediaKind::Photo,
                     captured_at: Some("2024-01-02_10-00-00".into()),
                     modified_at: "2024-01-02_10-00-00".into(),
                     exif_model: None,
                     exif_make: None,
                     exif_artist: None,
+                    rating: None,
+                    tags: Vec::new(),
+                    gps_latitude: None,
+                    gps_longitude: None,
+                    derivative: None,
                     is_duplicate: true,
+                    duplicate_confirmation: None,
+                    pixel_hash: None,
                 },
             ];
             database.replace_inventory(&records)?;
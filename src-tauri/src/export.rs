@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config::{AppConfig, ExportOptions};
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::execute::{emit_progress, mirrored_path, report_cancellation, ExecutionProgressEmitter};
+use crate::media::MediaKind;
+use crate::progress::TaskControl;
+use crate::transcode;
+use crate::utils::path::{to_native_path, to_posix_string};
+
+const EXPORT_STAGE: &str = "export";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub export_root: String,
+    pub total_entries: usize,
+    pub exported: usize,
+    /// Renditions newer than their source, left as they were.
+    pub up_to_date: usize,
+    /// Videos, RAW files and sidecars, which have no downscaled form.
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Writes a downscaled JPEG of every planned picture under the export root,
+/// in the plan's buckets. Organized files are read where they landed and
+/// pending ones from their origin. Plan statuses are left alone, so an
+/// export can run before or after organizing, and again to catch up.
+pub fn run_export(
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<ExportSummary> {
+    report_cancellation(emitter, EXPORT_STAGE, |emitter| {
+        export_entries(config, database, control, emitter)
+    })
+}
+
+fn export_entries(
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    emitter: ExecutionProgressEmitter,
+) -> Result<ExportSummary> {
+    let export_root = config
+        .export_root()
+        .ok_or_else(|| AppError::Config("no export root configured".into()))?;
+    let entries: Vec<_> = database
+        .plan_entries()?
+        .into_iter()
+        .filter(|entry| !entry.is_duplicate)
+        .collect();
+    let total = entries.len();
+    emit_progress(&emitter, EXPORT_STAGE, 0, total, None);
+
+    let mut summary = ExportSummary {
+        export_root: to_posix_string(&export_root).into_owned(),
+        total_entries: total,
+        exported: 0,
+        up_to_date: 0,
+        skipped: 0,
+        failed: 0,
+    };
+    for (idx, entry) in entries.iter().enumerate() {
+        control.checkpoint()?;
+        let target = to_native_path(&entry.target_path).join(&entry.target_file_name);
+        let current = Some(to_posix_string(&target).into_owned());
+
+        if config.media_exts.classify(&entry.target_file_name) != MediaKind::Photo {
+            summary.skipped += 1;
+        } else {
+            let source = if target.exists() {
+                target.clone()
+            } else {
+                to_native_path(&entry.origin_full_path)
+            };
+            let dest = mirrored_path(config, &export_root, &target).with_extension("jpg");
+            if is_up_to_date(&source, &dest) {
+                summary.up_to_date += 1;
+            } else {
+                match export_one(&source, &dest, &config.plan.export) {
+                    Ok(()) => summary.exported += 1,
+                    Err(err) => {
+                        summary.failed += 1;
+                        tracing::warn!(path = %source.display(), error = %err, "export failed");
+                    }
+                }
+            }
+        }
+        emit_progress(&emitter, EXPORT_STAGE, idx + 1, total, current);
+    }
+    Ok(summary)
+}
+
+fn export_one(source: &Path, dest: &Path, options: &ExportOptions) -> std::io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    transcode::resize_to_jpeg(source, dest, options.max_edge, options.quality)
+}
+
+/// Renditions carry their source's modification time, so a changed source
+/// shows up as a newer timestamp.
+fn is_up_to_date(source: &Path, dest: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match (modified(source), modified(dest)) {
+        (Some(source), Some(dest)) => dest >= source,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::NewPlanEntry;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn plan_entry(config: &AppConfig, name: &str) -> NewPlanEntry {
+        let target_path = config.output_root.join("2024-01-02/");
+        NewPlanEntry {
+            file_hash: name.into(),
+            blake3_hash: None,
+            file_size: 1,
+            origin_file_name: name.into(),
+            origin_full_path: to_posix_string(&config.image_root.join(name)).into_owned(),
+            target_path: to_posix_string(&target_path).into_owned(),
+            target_file_name: name.into(),
+            is_duplicate: false,
+            volume_root: None,
+            strip_metadata: false,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn export_mirrors_buckets_and_skips_current_renditions() -> Result<()> {
+        let mut config = test_config(&tempdir()?.keep());
+        let database = Database::initialize(&config)?;
+        database.replace_plan_entries(&[
+            plan_entry(&config, "a.JPG"),
+            plan_entry(&config, "clip.mp4"),
+        ])?;
+        let emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+        let control = TaskControl::default();
+        assert!(matches!(
+            run_export(&config, &database, &control, emitter.clone()),
+            Err(AppError::Config(_))
+        ));
+
+        fs::write(config.image_root.join("a.JPG"), b"photo")?;
+        let export_dir = tempdir()?;
+        config.plan.export.root = Some(export_dir.path().to_path_buf());
+        let rendition = export_dir.path().join("2024-01-02").join("a.jpg");
+        fs::create_dir_all(rendition.parent().unwrap())?;
+        fs::write(&rendition, b"small")?;
+
+        let summary = run_export(&config, &database, &control, emitter)?;
+        assert_eq!(summary.total_entries, 2);
+        assert_eq!(summary.up_to_date, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.exported + summary.failed, 0);
+        assert_eq!(fs::read(&rendition)?, b"small");
+        Ok(())
+    }
+}
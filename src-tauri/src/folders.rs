@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, Result};
+use crate::scan::output_folders;
+use crate::utils::fs::matches_extension;
+use crate::utils::path::{clean_path, to_posix_string};
+
+/// A subfolder with the media below it, at any depth.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderNode {
+    pub name: String,
+    pub path: String,
+    /// Relative to the image root, as `includeFolders` takes it; `None`
+    /// outside the root.
+    pub relative_path: Option<String>,
+    pub media_files: usize,
+    pub total_bytes: u64,
+    /// Children down to the requested depth; empty below it.
+    pub children: Vec<FolderNode>,
+    /// Whether the folder has subfolders, listed or not.
+    pub has_subfolders: bool,
+}
+
+#[derive(Default)]
+struct Totals {
+    media_files: usize,
+    total_bytes: u64,
+    has_subfolders: bool,
+}
+
+/// Lists the subfolders of `path`, the image root by default, `depth`
+/// levels deep, with media counts and sizes from a stat pass over the
+/// whole tree. Output folders inside it are left out, as the scan leaves
+/// them out.
+pub fn list_folders(
+    config: &AppConfig,
+    path: Option<&Path>,
+    depth: usize,
+) -> Result<Vec<FolderNode>> {
+    let root = match path {
+        Some(path) => clean_path(path),
+        None => config
+            .sample_image_root
+            .clone()
+            .unwrap_or_else(|| config.image_root.clone()),
+    };
+    if !root.is_dir() {
+        return Err(AppError::Config(format!(
+            "{} is not a folder",
+            root.display()
+        )));
+    }
+    let depth = depth.max(1);
+    let skip = output_folders(config)?;
+
+    // Keyed by the path below `root`, so children sort under their parent.
+    let mut totals: BTreeMap<PathBuf, Totals> = BTreeMap::new();
+    let walker = WalkDir::new(&root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            !(entry.file_type().is_dir() && skip.contains(&clean_path(entry.path())))
+        });
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        let Ok(relative) = entry.path().strip_prefix(&root) else {
+            continue;
+        };
+        let levels = relative.components().count();
+        if entry.file_type().is_dir() {
+            if levels <= depth {
+                totals.entry(relative.to_path_buf()).or_default();
+            }
+            if let Some(parent) = relative
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                if levels - 1 <= depth {
+                    totals
+                        .entry(parent.to_path_buf())
+                        .or_default()
+                        .has_subfolders = true;
+                }
+            }
+            continue;
+        }
+        if !entry.file_type().is_file() || !matches_extension(entry.path(), &config.image_exts) {
+            continue;
+        }
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        for folder in relative.ancestors().skip(1) {
+            let levels = folder.components().count();
+            if levels == 0 || levels > depth {
+                continue;
+            }
+            let folder_totals = totals.entry(folder.to_path_buf()).or_default();
+            folder_totals.media_files += 1;
+            folder_totals.total_bytes += size;
+        }
+    }
+
+    let image_root = clean_path(&config.image_root);
+    Ok(build_nodes(&root, &image_root, Path::new(""), &mut totals))
+}
+
+/// Takes the direct children of `parent` out of `totals`, with theirs.
+fn build_nodes(
+    root: &Path,
+    image_root: &Path,
+    parent: &Path,
+    totals: &mut BTreeMap<PathBuf, Totals>,
+) -> Vec<FolderNode> {
+    let children: Vec<PathBuf> = totals
+        .keys()
+        .filter(|path| path.parent() == Some(parent))
+        .cloned()
+        .collect();
+    let mut nodes = Vec::with_capacity(children.len());
+    for relative in children {
+        let Some(folder) = totals.remove(&relative) else {
+            continue;
+        };
+        let absolute = root.join(&relative);
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        nodes.push(FolderNode {
+            name,
+            path: to_posix_string(&absolute).into_owned(),
+            relative_path: absolute
+                .strip_prefix(image_root)
+                .ok()
+                .map(|path| to_posix_string(path).into_owned()),
+            media_files: folder.media_files,
+            total_bytes: folder.total_bytes,
+            children: build_nodes(root, image_root, &relative, totals),
+            has_subfolders: folder.has_subfolders,
+        });
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    use crate::config::test_config;
+
+    #[test]
+    fn folders_count_the_media_below_them() -> Result<()> {
+        let dir = tempdir()?;
+        let mut config = test_config(dir.path());
+        // An output folder inside the images is left out of the tree.
+        config.output_root = config.image_root.join("sorted");
+        let root = config.image_root.clone();
+        fs::create_dir_all(root.join("trips/paris/day1"))?;
+        fs::create_dir_all(root.join("sorted/2020"))?;
+        fs::create_dir_all(root.join("empty"))?;
+        fs::write(root.join("trips/a.jpg"), b"12345")?;
+        fs::write(root.join("trips/notes.txt"), b"not media")?;
+        fs::write(root.join("trips/paris/day1/b.jpg"), b"123")?;
+        fs::write(root.join("sorted/2020/c.jpg"), b"output")?;
+
+        let nodes = list_folders(&config, None, 1)?;
+        let names: Vec<_> = nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["empty", "trips"]);
+        let trips = &nodes[1];
+        assert_eq!((trips.media_files, trips.total_bytes), (2, 8));
+        assert_eq!(trips.relative_path.as_deref(), Some("trips"));
+        assert!(trips.has_subfolders && trips.children.is_empty());
+
+        let nodes = list_folders(&config, Some(&root.join("trips")), 2)?;
+        assert_eq!(nodes[0].relative_path.as_deref(), Some("trips/paris"));
+        assert_eq!(nodes[0].children[0].media_files, 1);
+        assert!(!nodes[0].children[0].has_subfolders);
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::utils::path::to_posix_string;
+use crate::utils::pattern::glob_match;
+
+/// Gitignore-style file read from the image root and any of its subfolders.
+pub const IGNORE_FILE: &str = ".phototidyignore";
+
+/// One line of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IgnoreRule {
+    pattern: String,
+    /// Patterns with a `/` (other than a trailing one) are matched against
+    /// the path below the ignore file's folder; the rest against the name.
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // `\#` and `\!` escape a literal leading character.
+        let line = line.strip_prefix('\\').unwrap_or(line);
+        let dir_only = line.ends_with('/');
+        let pattern = line.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern: pattern.to_string(),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, relative_path: &str, name: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.pattern, relative_path)
+        } else {
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+/// Ignore files met during a walk. Folders must be entered before their
+/// contents are checked, which a top-down walk does naturally. As in git,
+/// the last matching rule wins and deeper files override shallower ones;
+/// nothing below an ignored folder can be re-included.
+#[derive(Debug, Default)]
+pub struct IgnoreFiles {
+    /// `(folder, rules)`, outermost folder first.
+    folders: Vec<(PathBuf, Vec<IgnoreRule>)>,
+}
+
+impl IgnoreFiles {
+    /// Reads `dir`'s ignore file, if it has one.
+    pub fn enter(&mut self, dir: &Path) {
+        let path = dir.join(IGNORE_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    tracing::warn!(path = %path.display(), error = %err, "unreadable ignore file");
+                }
+                return;
+            }
+        };
+        let rules: Vec<IgnoreRule> = contents.lines().filter_map(IgnoreRule::parse).collect();
+        if !rules.is_empty() {
+            self.folders.push((dir.to_path_buf(), rules));
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for (folder, rules) in &self.folders {
+            let Ok(relative) = path.strip_prefix(folder) else {
+                continue;
+            };
+            let relative = to_posix_string(relative);
+            let name = relative.rsplit('/').next().unwrap_or_default();
+            for rule in rules {
+                if rule.matches(&relative, name, is_dir) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn nested_ignore_files_follow_gitignore_precedence() -> std::io::Result<()> {
+        let root = tempdir()?;
+        let album = root.path().join("album");
+        fs::create_dir_all(album.join("edits"))?;
+        fs::write(
+            root.path().join(IGNORE_FILE),
+            "# generated\n*.tmp.jpg\n/exports/\nraw/*.dng\n",
+        )?;
+        fs::write(album.join(IGNORE_FILE), "edits/\n!keep.tmp.jpg\n")?;
+
+        let mut ignores = IgnoreFiles::default();
+        ignores.enter(root.path());
+        assert!(ignores.is_ignored(&root.path().join("a.tmp.jpg"), false));
+        assert!(ignores.is_ignored(&root.path().join("exports"), true));
+        assert!(!ignores.is_ignored(&root.path().join("exports"), false));
+        assert!(!ignores.is_ignored(&album.join("exports"), true));
+        assert!(ignores.is_ignored(&root.path().join("raw/b.DNG"), false));
+        assert!(!ignores.is_ignored(&album.join("edits"), true));
+
+        ignores.enter(&album);
+        assert!(ignores.is_ignored(&album.join("edits"), true));
+        assert!(ignores.is_ignored(&album.join("c.tmp.jpg"), false));
+        assert!(!ignores.is_ignored(&album.join("keep.tmp.jpg"), false));
+        assert!(ignores.is_ignored(&root.path().join("keep.tmp.jpg"), false));
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+//! Change journals let a rescan read only the folders that changed since the
+//! last one: the USN journal on NTFS volumes and FSEvents on macOS. Other
+//! platforms, and journals that were reset or lost events since, fall back
+//! to the full walk.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Where a volume's change journal stood.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalCursor {
+    /// Identifies the journal itself; positions from a recreated journal
+    /// cannot be compared.
+    pub journal: String,
+    pub position: u64,
+}
+
+/// The current position of the journal covering `root`. Taken before a scan
+/// walks anything, so changes made while it runs are seen by the next one.
+pub fn cursor(root: &Path) -> Option<JournalCursor> {
+    imp::cursor(root)
+}
+
+/// Folders under `root` holding changes since `since`, each standing for
+/// everything below it. `None` when the journal cannot tell, in which case
+/// the whole tree has to be walked.
+pub fn changed_folders(root: &Path, since: &JournalCursor) -> Option<Vec<PathBuf>> {
+    // Journals report resolved paths; map them back onto `root` as given.
+    let canonical = root.canonicalize().ok()?;
+    let folders = imp::changed_folders(root, &canonical, since)?
+        .into_iter()
+        .filter_map(|folder| Some(root.join(folder.strip_prefix(&canonical).ok()?)))
+        .collect();
+    Some(collapse(folders))
+}
+
+/// Sorts folders and drops the ones inside another listed folder.
+fn collapse(mut folders: Vec<PathBuf>) -> Vec<PathBuf> {
+    folders.sort();
+    folders.dedup();
+    let mut kept: Vec<PathBuf> = Vec::with_capacity(folders.len());
+    for folder in folders {
+        if !kept.last().is_some_and(|parent| folder.starts_with(parent)) {
+            kept.push(folder);
+        }
+    }
+    kept
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::collections::HashSet;
+    use std::ffi::{c_void, OsStr, OsString};
+    use std::mem::{size_of, zeroed};
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_READ, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FileIdType, GetFinalPathNameByHandleW, GetVolumeNameForVolumeMountPointW,
+        GetVolumePathNameW, OpenFileById, FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_DESCRIPTOR,
+        FILE_ID_DESCRIPTOR_0, FILE_NAME_NORMALIZED, FILE_SHARE_DELETE, FILE_SHARE_READ,
+        FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0,
+        USN_JOURNAL_DATA_V0, USN_RECORD_V2,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    use super::JournalCursor;
+
+    /// Size of one journal read; records are 8-byte aligned within it.
+    const READ_BUFFER_WORDS: usize = 8 * 1024;
+
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle was opened by us and is closed once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    struct Volume {
+        /// `\\?\Volume{...}\`, which survives drive letter changes.
+        name: String,
+        handle: Handle,
+    }
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain(Some(0)).collect()
+    }
+
+    fn until_nul(buffer: &[u16]) -> Option<String> {
+        let len = buffer.iter().position(|&unit| unit == 0)?;
+        String::from_utf16(&buffer[..len]).ok()
+    }
+
+    fn open_volume(root: &Path) -> Option<Volume> {
+        let mut mount = [0u16; 1024];
+        let mut name = [0u16; 64];
+        // SAFETY: both buffers are passed with their lengths and the input
+        // strings are NUL-terminated.
+        unsafe {
+            if GetVolumePathNameW(
+                wide(root.as_os_str()).as_ptr(),
+                mount.as_mut_ptr(),
+                mount.len() as u32,
+            ) == 0
+                || GetVolumeNameForVolumeMountPointW(
+                    mount.as_ptr(),
+                    name.as_mut_ptr(),
+                    name.len() as u32,
+                ) == 0
+            {
+                return None;
+            }
+        }
+        let name = until_nul(&name)?;
+        // The volume device is opened without the trailing backslash.
+        let device = wide(OsStr::new(name.trim_end_matches('\\')));
+        // SAFETY: `device` is NUL-terminated; no security attributes or
+        // template are passed.
+        let handle = unsafe {
+            CreateFileW(
+                device.as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ptr::null(),
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        Some(Volume {
+            name,
+            handle: Handle(handle),
+        })
+    }
+
+    fn query(volume: &Volume) -> Option<USN_JOURNAL_DATA_V0> {
+        // SAFETY: the output buffer is a `USN_JOURNAL_DATA_V0` of the size
+        // passed.
+        unsafe {
+            let mut data: USN_JOURNAL_DATA_V0 = zeroed();
+            let mut returned = 0u32;
+            let ok = DeviceIoControl(
+                volume.handle.0,
+                FSCTL_QUERY_USN_JOURNAL,
+                ptr::null(),
+                0,
+                &mut data as *mut USN_JOURNAL_DATA_V0 as *mut c_void,
+                size_of::<USN_JOURNAL_DATA_V0>() as u32,
+                &mut returned,
+                ptr::null_mut(),
+            );
+            (ok != 0).then_some(data)
+        }
+    }
+
+    fn journal_id(volume: &Volume, data: &USN_JOURNAL_DATA_V0) -> String {
+        format!("{}#{:x}", volume.name, data.UsnJournalID)
+    }
+
+    pub fn cursor(root: &Path) -> Option<JournalCursor> {
+        let volume = open_volume(root)?;
+        let data = query(&volume)?;
+        Some(JournalCursor {
+            journal: journal_id(&volume, &data),
+            position: u64::try_from(data.NextUsn).ok()?,
+        })
+    }
+
+    pub fn changed_folders(
+        root: &Path,
+        _canonical: &Path,
+        since: &JournalCursor,
+    ) -> Option<Vec<PathBuf>> {
+        let volume = open_volume(root)?;
+        let data = query(&volume)?;
+        let start = i64::try_from(since.position).ok()?;
+        // Records before `FirstUsn` were purged, so changes may be missing.
+        if journal_id(&volume, &data) != since.journal || start < data.FirstUsn {
+            return None;
+        }
+
+        let mut parents = HashSet::new();
+        let mut request = READ_USN_JOURNAL_DATA_V0 {
+            StartUsn: start,
+            ReasonMask: u32::MAX,
+            ReturnOnlyOnClose: 0,
+            Timeout: 0,
+            BytesToWaitFor: 0,
+            UsnJournalID: data.UsnJournalID,
+        };
+        let mut buffer = vec![0u64; READ_BUFFER_WORDS];
+        while request.StartUsn < data.NextUsn {
+            let mut returned = 0u32;
+            // SAFETY: input and output buffers are passed with their sizes.
+            let ok = unsafe {
+                DeviceIoControl(
+                    volume.handle.0,
+                    FSCTL_READ_USN_JOURNAL,
+                    &request as *const READ_USN_JOURNAL_DATA_V0 as *const c_void,
+                    size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+                    buffer.as_mut_ptr() as *mut c_void,
+                    (buffer.len() * size_of::<u64>()) as u32,
+                    &mut returned,
+                    ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return None;
+            }
+            let returned = returned as usize;
+            if returned < size_of::<i64>() {
+                break;
+            }
+            // The reply starts with the USN to continue from.
+            let next = buffer[0] as i64;
+            let mut offset = size_of::<i64>();
+            while offset + size_of::<USN_RECORD_V2>() <= returned {
+                // SAFETY: the journal lays records out 8-byte aligned inside
+                // the returned bytes, which the loop condition bounds.
+                let record = unsafe {
+                    &*((buffer.as_ptr() as *const u8).add(offset) as *const USN_RECORD_V2)
+                };
+                if record.RecordLength == 0 {
+                    break;
+                }
+                // Later record versions carry 128-bit ids (ReFS).
+                if record.MajorVersion != 2 {
+                    return None;
+                }
+                parents.insert(record.ParentFileReferenceNumber);
+                offset += record.RecordLength as usize;
+            }
+            if next <= request.StartUsn {
+                break;
+            }
+            request.StartUsn = next;
+        }
+
+        // A parent that is gone was itself removed, which its own parent's
+        // record covers.
+        Some(
+            parents
+                .into_iter()
+                .filter_map(|id| folder_path(&volume, id))
+                .collect(),
+        )
+    }
+
+    fn folder_path(volume: &Volume, id: u64) -> Option<PathBuf> {
+        let descriptor = FILE_ID_DESCRIPTOR {
+            dwSize: size_of::<FILE_ID_DESCRIPTOR>() as u32,
+            Type: FileIdType,
+            Anonymous: FILE_ID_DESCRIPTOR_0 { FileId: id as i64 },
+        };
+        // SAFETY: the descriptor is fully initialized; no access rights are
+        // needed to read the name.
+        let handle = unsafe {
+            OpenFileById(
+                volume.handle.0,
+                &descriptor,
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                ptr::null(),
+                FILE_FLAG_BACKUP_SEMANTICS,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let handle = Handle(handle);
+        let mut buffer = vec![0u16; 512];
+        loop {
+            // SAFETY: the buffer is passed with its length.
+            let len = unsafe {
+                GetFinalPathNameByHandleW(
+                    handle.0,
+                    buffer.as_mut_ptr(),
+                    buffer.len() as u32,
+                    FILE_NAME_NORMALIZED,
+                )
+            } as usize;
+            if len == 0 {
+                return None;
+            }
+            if len < buffer.len() {
+                buffer.truncate(len);
+                break;
+            }
+            // Too small; `len` is the size needed.
+            buffer.resize(len + 1, 0);
+        }
+        // `\\?\C:\...`, the same form `canonicalize` produces.
+        Some(PathBuf::from(OsString::from_wide(&buffer)))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::cell::{Cell, RefCell};
+    use std::ffi::{c_char, c_void, CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+    use std::time::{Duration, Instant};
+
+    use super::JournalCursor;
+
+    type CFTypeRef = *const c_void;
+    type FSEventStreamRef = *mut c_void;
+    type FSEventStreamCallback =
+        extern "C" fn(FSEventStreamRef, *mut c_void, usize, *mut c_void, *const u32, *const u64);
+
+    #[repr(C)]
+    struct FSEventStreamContext {
+        version: isize,
+        info: *mut c_void,
+        retain: *const c_void,
+        release: *const c_void,
+        copy_description: *const c_void,
+    }
+
+    #[repr(C)]
+    struct CFUUIDBytes([u8; 16]);
+
+    const UTF8_ENCODING: u32 = 0x0800_0100;
+    const WATCH_ROOT: u32 = 0x4;
+    const USER_DROPPED: u32 = 0x2;
+    const KERNEL_DROPPED: u32 = 0x4;
+    const EVENT_IDS_WRAPPED: u32 = 0x8;
+    const HISTORY_DONE: u32 = 0x10;
+    const ROOT_CHANGED: u32 = 0x20;
+    /// Replaying history takes well under a second; past this the full walk
+    /// is the safer bet.
+    const REPLAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: CFTypeRef;
+        static kCFTypeArrayCallBacks: u8;
+        fn CFRunLoopGetCurrent() -> CFTypeRef;
+        fn CFRunLoopRunInMode(mode: CFTypeRef, seconds: f64, return_after_source: u8) -> i32;
+        fn CFStringCreateWithBytes(
+            allocator: CFTypeRef,
+            bytes: *const u8,
+            len: isize,
+            encoding: u32,
+            external: u8,
+        ) -> CFTypeRef;
+        fn CFArrayCreate(
+            allocator: CFTypeRef,
+            values: *const CFTypeRef,
+            count: isize,
+            callbacks: *const c_void,
+        ) -> CFTypeRef;
+        fn CFUUIDGetUUIDBytes(uuid: CFTypeRef) -> CFUUIDBytes;
+        fn CFRelease(value: CFTypeRef);
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        fn FSEventsGetCurrentEventId() -> u64;
+        fn FSEventsCopyUUIDForDevice(device: i32) -> CFTypeRef;
+        fn FSEventStreamCreate(
+            allocator: CFTypeRef,
+            callback: FSEventStreamCallback,
+            context: *const FSEventStreamContext,
+            paths: CFTypeRef,
+            since_when: u64,
+            latency: f64,
+            flags: u32,
+        ) -> FSEventStreamRef;
+        fn FSEventStreamScheduleWithRunLoop(
+            stream: FSEventStreamRef,
+            run_loop: CFTypeRef,
+            mode: CFTypeRef,
+        );
+        fn FSEventStreamStart(stream: FSEventStreamRef) -> u8;
+        fn FSEventStreamStop(stream: FSEventStreamRef);
+        fn FSEventStreamInvalidate(stream: FSEventStreamRef);
+        fn FSEventStreamRelease(stream: FSEventStreamRef);
+    }
+
+    /// The volume's event store UUID; it changes when the store is wiped.
+    fn store_uuid(root: &Path) -> Option<String> {
+        let device = std::fs::metadata(root).ok()?.dev() as i32;
+        // SAFETY: the copied UUID is released once its bytes are read.
+        let bytes = unsafe {
+            let uuid = FSEventsCopyUUIDForDevice(device);
+            // Volumes without an event store (read-only, network) have none.
+            if uuid.is_null() {
+                return None;
+            }
+            let bytes = CFUUIDGetUUIDBytes(uuid);
+            CFRelease(uuid);
+            bytes
+        };
+        Some(bytes.0.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    pub fn cursor(root: &Path) -> Option<JournalCursor> {
+        let journal = store_uuid(root)?;
+        // SAFETY: takes no arguments.
+        let position = unsafe { FSEventsGetCurrentEventId() };
+        Some(JournalCursor { journal, position })
+    }
+
+    #[derive(Default)]
+    struct Replay {
+        folders: RefCell<Vec<PathBuf>>,
+        done: Cell<bool>,
+        lost: Cell<bool>,
+    }
+
+    extern "C" fn collect(
+        _stream: FSEventStreamRef,
+        info: *mut c_void,
+        count: usize,
+        paths: *mut c_void,
+        flags: *const u32,
+        _ids: *const u64,
+    ) {
+        // SAFETY: `info` is the `Replay` on `changed_folders`' stack, which
+        // outlives the stream; the stream passes `count` C strings and flags.
+        let (replay, paths, flags) = unsafe {
+            (
+                &*(info as *const Replay),
+                std::slice::from_raw_parts(paths as *const *const c_char, count),
+                std::slice::from_raw_parts(flags, count),
+            )
+        };
+        for (&path, &flag) in paths.iter().zip(flags) {
+            if flag & HISTORY_DONE != 0 {
+                replay.done.set(true);
+                continue;
+            }
+            if flag & (USER_DROPPED | KERNEL_DROPPED | EVENT_IDS_WRAPPED | ROOT_CHANGED) != 0 {
+                replay.lost.set(true);
+            }
+            // SAFETY: event paths are NUL-terminated.
+            let path = unsafe { CStr::from_ptr(path) };
+            replay
+                .folders
+                .borrow_mut()
+                .push(PathBuf::from(OsStr::from_bytes(path.to_bytes())));
+        }
+    }
+
+    pub fn changed_folders(
+        root: &Path,
+        canonical: &Path,
+        since: &JournalCursor,
+    ) -> Option<Vec<PathBuf>> {
+        if store_uuid(root)? != since.journal {
+            return None;
+        }
+        let replay = Replay::default();
+        let context = FSEventStreamContext {
+            version: 0,
+            info: &replay as *const Replay as *mut c_void,
+            retain: ptr::null(),
+            release: ptr::null(),
+            copy_description: ptr::null(),
+        };
+        let bytes = canonical.as_os_str().as_bytes();
+        // SAFETY: every CF object created here is released before returning,
+        // and the stream is torn down before `replay` goes out of scope.
+        unsafe {
+            let path = CFStringCreateWithBytes(
+                ptr::null(),
+                bytes.as_ptr(),
+                bytes.len() as isize,
+                UTF8_ENCODING,
+                0,
+            );
+            if path.is_null() {
+                return None;
+            }
+            let paths = CFArrayCreate(
+                ptr::null(),
+                &path,
+                1,
+                ptr::addr_of!(kCFTypeArrayCallBacks).cast(),
+            );
+            CFRelease(path);
+            if paths.is_null() {
+                return None;
+            }
+            let stream = FSEventStreamCreate(
+                ptr::null(),
+                collect,
+                &context,
+                paths,
+                since.position,
+                0.0,
+                WATCH_ROOT,
+            );
+            CFRelease(paths);
+            if stream.is_null() {
+                return None;
+            }
+            FSEventStreamScheduleWithRunLoop(stream, CFRunLoopGetCurrent(), kCFRunLoopDefaultMode);
+            let started = FSEventStreamStart(stream) != 0;
+            let deadline = Instant::now() + REPLAY_TIMEOUT;
+            while started && !replay.done.get() && Instant::now() < deadline {
+                CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 1);
+            }
+            if started {
+                FSEventStreamStop(stream);
+            }
+            FSEventStreamInvalidate(stream);
+            FSEventStreamRelease(stream);
+        }
+        (replay.done.get() && !replay.lost.get()).then(|| replay.folders.take())
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use super::JournalCursor;
+
+    pub fn cursor(_root: &Path) -> Option<JournalCursor> {
+        None
+    }
+
+    pub fn changed_folders(
+        _root: &Path,
+        _canonical: &Path,
+        _since: &JournalCursor,
+    ) -> Option<Vec<PathBuf>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_folders_collapse_into_their_parent() {
+        let folders = vec![
+            PathBuf::from("/photos/2024/trip"),
+            PathBuf::from("/photos/2024"),
+            PathBuf::from("/photos/2024-edits"),
+            PathBuf::from("/photos/2024"),
+            PathBuf::from("/photos/2023/a"),
+        ];
+        assert_eq!(
+            collapse(folders),
+            vec![
+                PathBuf::from("/photos/2023/a"),
+                PathBuf::from("/photos/2024"),
+                PathBuf::from("/photos/2024-edits"),
+            ]
+        );
+    }
+}
@@ -1,37 +1,126 @@
+mod audit;
+mod bundle;
+mod catalog;
+mod cloud;
 mod config;
 mod db;
+mod derivative;
+mod diagnostics;
+mod diff;
 mod error;
 mod events;
+mod exclusion;
 mod execute;
+mod export;
+mod folders;
+mod ignore;
+mod journal;
+mod library;
 mod logging;
+mod media;
+mod metadata;
+mod metrics;
+mod network;
+mod notify;
+mod permissions;
+mod pixels;
 mod plan;
+mod portable;
+mod power;
+mod preflight;
+mod profile;
+mod progress;
+mod remap;
+mod replay;
+mod report;
+mod resolution;
+mod roots;
+mod rules;
 mod scan;
+mod scrub;
+mod selftest;
 mod system;
+mod takeout;
+mod telemetry;
+mod template;
+mod transcode;
+mod tray;
 pub mod utils;
+mod video;
+mod volumes;
 
+use std::path::Path;
 use std::sync::Arc;
 
-use tauri::{AppHandle, Emitter, Manager};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Window, WindowEvent};
 use tracing::{error, info};
 
+use crate::audit::{export_audit_log as run_export_audit_log, AuditExport};
+use crate::bundle::{export_state, import_state, StateManifest};
+use crate::catalog::{import_catalog as run_import_catalog, CatalogImportSummary, CatalogKind};
 use crate::config::{AppConfig, ConfigPayload, ConfigService, SCHEMA_VERSION};
-use crate::db::Database;
+use crate::db::{
+    Database, DuplicateDecision, DuplicateDecisionRecord, ExclusionRecord, FileChangeRecord,
+    ScanHistoryEntry,
+};
+use crate::diagnostics::{create_bundle, DiagnosticsManifest};
+use crate::diff::{PlanDiff, ScanDiff};
 use crate::events::{
-    EVENT_BOOTSTRAP_CONFIG, EVENT_EXECUTION_PROGRESS, EVENT_PLAN_PROGRESS, EVENT_SCAN_PROGRESS,
+    EVENT_BOOTSTRAP_CONFIG, EVENT_CONFIG_UPDATED, EVENT_EXECUTION_PROGRESS, EVENT_INDEX_PROGRESS,
+    EVENT_PLAN_PROGRESS, EVENT_SCAN_PROGRESS, EVENT_SCRUB_ERROR, EVENT_VERIFY_PROGRESS,
+};
+use crate::exclusion::{
+    exclude_file as add_file_exclusion, exclude_pattern as add_pattern_exclusion,
 };
 use crate::execute::{
-    run_execution, undo_moves as undo_plan_moves, ExecutionMode, ExecutionProgressEmitter,
-    ExecutionSummary, UndoSummary,
+    run_execution, undo_moves as undo_plan_moves, verify_execution as verify_executed_entries,
+    ExecutionMode, ExecutionProgressEmitter, ExecutionSummary, UndoSummary, VerificationSummary,
+};
+use crate::export::{run_export, ExportSummary};
+use crate::folders::{list_folders as run_list_folders, FolderNode};
+use crate::library::{
+    index_output as run_index_output, relocate_library as run_relocate_library,
+    verify_library as run_verify_library, IndexSummary, LibraryProgressEmitter, RelocationReport,
+    VerifyReport,
 };
 use crate::logging::init_logging;
-use crate::plan::{generate_plan, PlanProgressEmitter, PlanSummary};
-use crate::scan::{perform_scan, ProgressEmitter, ScanSummary};
+use crate::permissions::{check_permissions as run_check_permissions, PermissionReport};
+use crate::plan::{
+    diff_plans as run_diff_plans, explain_file as run_explain_file, explain_routing_rules,
+    generate_plan, preview_plan as run_preview_plan, FileExplanation, PlanPreview,
+    PlanProgressEmitter, PlanSource, PlanSummary,
+};
+use crate::power::{watch_battery, SleepGuard};
+use crate::preflight::{preflight as run_preflight, PreflightReport};
+use crate::profile::ProfileNode;
+use crate::progress::{ProgressCache, ProgressSnapshot, TaskKind};
+use crate::remap::{apply_root_remaps, detect_root_remaps, RootKind, RootRemap};
+use crate::replay::{EventReplay, ReplayedEvent};
+use crate::resolution::{
+    decide_duplicate_group as run_decide_duplicate_group,
+    delete_duplicates as run_delete_duplicates, duplicate_groups as list_duplicate_groups_in,
+    prepare_duplicate_deletion as run_prepare_duplicate_deletion, DeletionPreview, DeletionSummary,
+    DuplicateGroup,
+};
+use crate::roots::{
+    set_included_folders as run_set_included_folders, set_root, update_config as run_update_config,
+};
+use crate::rules::RuleReport;
+use crate::scan::{
+    estimate_scan as run_estimate_scan, last_scan_diff as read_last_scan_diff, perform_scan,
+    ProgressEmitter, ScanEstimate, ScanSummary,
+};
+use crate::scrub::{spawn_scrub_scheduler, ScrubReportEmitter};
+use crate::selftest::{run_self_test, SelfTestReport};
 use crate::system::{disk_status, DiskStatus};
 
 #[derive(Clone)]
 pub struct AppState {
     config: Arc<ConfigService>,
     database: Arc<Database>,
+    progress: Arc<ProgressCache>,
+    replay: Arc<EventReplay>,
 }
 
 impl AppState {
@@ -39,6 +128,8 @@ impl AppState {
         Self {
             config: Arc::new(config),
             database: Arc::new(database),
+            progress: Arc::new(ProgressCache::default()),
+            replay: Arc::new(EventReplay::default()),
         }
     }
 
@@ -50,6 +141,10 @@ impl AppState {
         self.database.as_ref()
     }
 
+    pub fn progress(&self) -> &ProgressCache {
+        self.progress.as_ref()
+    }
+
     pub fn config_arc(&self) -> Arc<ConfigService> {
         Arc::clone(&self.config)
     }
@@ -57,12 +152,40 @@ impl AppState {
     pub fn database_arc(&self) -> Arc<Database> {
         Arc::clone(&self.database)
     }
+
+    pub fn progress_arc(&self) -> Arc<ProgressCache> {
+        Arc::clone(&self.progress)
+    }
+
+    pub fn replay(&self) -> &EventReplay {
+        self.replay.as_ref()
+    }
+
+    pub fn replay_arc(&self) -> Arc<EventReplay> {
+        Arc::clone(&self.replay)
+    }
+}
+
+/// Emits a progress event to every window and keeps it for `replay_events`.
+fn emit_recorded<P: Serialize + Clone>(
+    app: &AppHandle,
+    replay: &EventReplay,
+    channel: &str,
+    payload: P,
+) -> tauri::Result<()> {
+    replay.record(channel, &payload);
+    app.emit(channel, payload)
 }
 
 #[tauri::command]
-fn bootstrap_paths(state: tauri::State<'_, AppState>, app: AppHandle) -> ConfigPayload {
+fn bootstrap_paths(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    window: Window,
+) -> ConfigPayload {
     let payload = state.config().payload();
-    if let Err(err) = app.emit(EVENT_BOOTSTRAP_CONFIG, payload.clone()) {
+    // Only the asking window is bootstrapping; the others are already set up.
+    if let Err(err) = app.emit_to(window.label(), EVENT_BOOTSTRAP_CONFIG, payload.clone()) {
         error!("failed to emit bootstrap event: {err:?}");
     }
     payload
@@ -74,6 +197,47 @@ fn check_disk_space(state: tauri::State<'_, AppState>) -> Result<DiskStatus, Str
     disk_status(&snapshot.output_root).map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+fn current_progress(state: tauri::State<'_, AppState>) -> ProgressSnapshot {
+    state.progress().snapshot()
+}
+
+#[tauri::command]
+fn cancel_task(state: tauri::State<'_, AppState>) -> Option<TaskKind> {
+    state.progress().cancel()
+}
+
+#[tauri::command]
+fn replay_events(
+    state: tauri::State<'_, AppState>,
+    channel: String,
+    since: Option<u64>,
+) -> Vec<ReplayedEvent> {
+    state.replay().replay(&channel, since)
+}
+
+/// Where the time went in the last scan, plan or execution.
+#[tauri::command]
+fn profile_last_run() -> Option<ProfileNode> {
+    profile::last_run()
+}
+
+#[tauri::command]
+fn preflight(state: tauri::State<'_, AppState>) -> Result<PreflightReport, String> {
+    let snapshot = state.config().snapshot();
+    run_preflight(&snapshot, state.database()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn check_permissions(state: tauri::State<'_, AppState>) -> PermissionReport {
+    let snapshot = state.config().snapshot();
+    let source = snapshot
+        .sample_image_root
+        .as_ref()
+        .unwrap_or(&snapshot.image_root);
+    run_check_permissions(source, &snapshot.home_dir)
+}
+
 #[tauri::command]
 async fn scan_media(
     state: tauri::State<'_, AppState>,
@@ -81,23 +245,84 @@ async fn scan_media(
 ) -> Result<ScanSummary, String> {
     let config = state.config_arc();
     let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
     let app_handle = app.clone();
 
-    tauri::async_runtime::spawn_blocking(move || {
+    let task = progress
+        .begin(TaskKind::Scan)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _awake = SleepGuard::acquire("scanning photos");
         let emitter: ProgressEmitter = Arc::new(move |payload| {
-            if let Err(err) = app_handle.emit(EVENT_SCAN_PROGRESS, payload.clone()) {
+            progress.record_scan(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Scan,
+                payload.processed,
+                payload.total.unwrap_or(0),
+            );
+            if let Err(err) =
+                emit_recorded(&app_handle, &replay, EVENT_SCAN_PROGRESS, payload.clone())
+            {
                 tracing::debug!(error = ?err, "failed emitting scan progress");
             }
         });
 
         let snapshot = config.snapshot();
-        perform_scan(&snapshot, database.as_ref(), emitter)
+        watch_battery(snapshot.power.clone(), task.shared_control());
+        let result = perform_scan(&snapshot, database.as_ref(), task.control(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await;
+    tray::clear_progress(&app);
+    if let Ok(outcome) = &result {
+        notify::task_finished(&app, TaskKind::Scan, outcome);
+    }
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn estimate_scan(state: tauri::State<'_, AppState>) -> Result<ScanEstimate, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        run_estimate_scan(&snapshot, database.as_ref())
     })
     .await
     .map_err(|err| err.to_string())?
     .map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+fn last_scan_diff(state: tauri::State<'_, AppState>) -> Result<Option<ScanDiff>, String> {
+    read_last_scan_diff(state.database()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn scan_history(state: tauri::State<'_, AppState>) -> Result<Vec<ScanHistoryEntry>, String> {
+    state
+        .database()
+        .scan_history()
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn file_history(
+    state: tauri::State<'_, AppState>,
+    relative_path: String,
+) -> Result<Vec<FileChangeRecord>, String> {
+    state
+        .database()
+        .file_history(&relative_path)
+        .map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 async fn plan_targets(
     state: tauri::State<'_, AppState>,
@@ -105,47 +330,251 @@ async fn plan_targets(
 ) -> Result<PlanSummary, String> {
     let config = state.config_arc();
     let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
     let app_handle = app.clone();
 
-    tauri::async_runtime::spawn_blocking(move || {
+    let task = progress
+        .begin(TaskKind::Plan)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
         let emitter: PlanProgressEmitter = Arc::new(move |payload| {
-            if let Err(err) = app_handle.emit(EVENT_PLAN_PROGRESS, payload.clone()) {
+            progress.record_plan(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Plan,
+                payload.processed,
+                payload.total,
+            );
+            if let Err(err) =
+                emit_recorded(&app_handle, &replay, EVENT_PLAN_PROGRESS, payload.clone())
+            {
                 tracing::debug!(error = ?err, "failed emitting plan progress");
             }
         });
 
         let snapshot = config.snapshot();
-        generate_plan(&snapshot, database.as_ref(), emitter)
+        let result = generate_plan(&snapshot, database.as_ref(), task.control(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await;
+    tray::clear_progress(&app);
+    if let Ok(outcome) = &result {
+        notify::task_finished(&app, TaskKind::Plan, outcome);
+    }
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn preview_plan(
+    state: tauri::State<'_, AppState>,
+    config_overrides: serde_json::Value,
+) -> Result<PlanPreview, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        run_preview_plan(&snapshot, database.as_ref(), &config_overrides)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn diff_plans(
+    state: tauri::State<'_, AppState>,
+    plan_a: PlanSource,
+    plan_b: PlanSource,
+) -> Result<PlanDiff, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        run_diff_plans(&snapshot, database.as_ref(), &plan_a, &plan_b)
     })
     .await
     .map_err(|err| err.to_string())?
     .map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+fn explain_rules(state: tauri::State<'_, AppState>) -> Result<Vec<RuleReport>, String> {
+    let snapshot = state.config().snapshot();
+    explain_routing_rules(&snapshot, state.database()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn explain_file(
+    state: tauri::State<'_, AppState>,
+    path_or_hash: String,
+) -> Result<FileExplanation, String> {
+    let snapshot = state.config().snapshot();
+    run_explain_file(&snapshot, state.database(), &path_or_hash).map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 async fn execute_plan(
     state: tauri::State<'_, AppState>,
     app: AppHandle,
     mode: ExecutionMode,
     dry_run: bool,
+    verify: bool,
 ) -> Result<ExecutionSummary, String> {
     let config = state.config_arc();
     let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
     let app_handle = app.clone();
 
-    tauri::async_runtime::spawn_blocking(move || {
+    let task = progress
+        .begin(TaskKind::Execute)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _awake = SleepGuard::acquire("organizing photos");
         let emitter: ExecutionProgressEmitter = Arc::new(move |payload| {
-            if let Err(err) = app_handle.emit(EVENT_EXECUTION_PROGRESS, payload.clone()) {
+            progress.record_execute(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Execute,
+                payload.processed,
+                payload.total,
+            );
+            if let Err(err) = emit_recorded(
+                &app_handle,
+                &replay,
+                EVENT_EXECUTION_PROGRESS,
+                payload.clone(),
+            ) {
                 tracing::debug!(error = ?err, "failed emitting execution progress");
             }
         });
 
         let snapshot = config.snapshot();
-        run_execution(&snapshot, database.as_ref(), mode, dry_run, emitter)
+        watch_battery(snapshot.power.clone(), task.shared_control());
+        let result = run_execution(
+            &snapshot,
+            database.as_ref(),
+            mode,
+            dry_run,
+            verify,
+            task.control(),
+            emitter,
+        );
+        task.finish(&result);
+        result
     })
-    .await
-    .map_err(|err| err.to_string())?
-    .map_err(|err| err.to_string())
+    .await;
+    tray::clear_progress(&app);
+    if let Ok(outcome) = &result {
+        notify::task_finished(&app, TaskKind::Execute, outcome);
+    }
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn verify_execution(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+) -> Result<VerificationSummary, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
+    let app_handle = app.clone();
+
+    let task = progress
+        .begin(TaskKind::Execute)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _awake = SleepGuard::acquire("verifying organized photos");
+        let emitter: ExecutionProgressEmitter = Arc::new(move |payload| {
+            progress.record_execute(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Execute,
+                payload.processed,
+                payload.total,
+            );
+            if let Err(err) = emit_recorded(
+                &app_handle,
+                &replay,
+                EVENT_EXECUTION_PROGRESS,
+                payload.clone(),
+            ) {
+                tracing::debug!(error = ?err, "failed emitting verification progress");
+            }
+        });
+
+        let snapshot = config.snapshot();
+        let result = verify_executed_entries(&snapshot, database.as_ref(), task.control(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await;
+    tray::clear_progress(&app);
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_resized(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ExportSummary, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
+    let app_handle = app.clone();
+
+    let task = progress
+        .begin(TaskKind::Export)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _awake = SleepGuard::acquire("exporting resized photos");
+        let emitter: ExecutionProgressEmitter = Arc::new(move |payload| {
+            progress.record_execute(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Export,
+                payload.processed,
+                payload.total,
+            );
+            if let Err(err) = emit_recorded(
+                &app_handle,
+                &replay,
+                EVENT_EXECUTION_PROGRESS,
+                payload.clone(),
+            ) {
+                tracing::debug!(error = ?err, "failed emitting export progress");
+            }
+        });
+
+        let snapshot = config.snapshot();
+        watch_battery(snapshot.power.clone(), task.shared_control());
+        let result = run_export(&snapshot, database.as_ref(), task.control(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await;
+    tray::clear_progress(&app);
+    if let Ok(outcome) = &result {
+        notify::task_finished(&app, TaskKind::Export, outcome);
+    }
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
@@ -155,17 +584,512 @@ async fn undo_moves(
 ) -> Result<UndoSummary, String> {
     let config = state.config_arc();
     let database = state.database_arc();
+    let progress = state.progress_arc();
+    let replay = state.replay_arc();
     let app_handle = app.clone();
 
-    tauri::async_runtime::spawn_blocking(move || {
+    let task = progress
+        .begin(TaskKind::Undo)
+        .map_err(|err| err.to_string())?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _awake = SleepGuard::acquire("restoring moved photos");
         let emitter: ExecutionProgressEmitter = Arc::new(move |payload| {
-            if let Err(err) = app_handle.emit(EVENT_EXECUTION_PROGRESS, payload.clone()) {
+            progress.record_execute(payload.clone());
+            tray::show_progress(
+                &app_handle,
+                TaskKind::Undo,
+                payload.processed,
+                payload.total,
+            );
+            if let Err(err) = emit_recorded(
+                &app_handle,
+                &replay,
+                EVENT_EXECUTION_PROGRESS,
+                payload.clone(),
+            ) {
                 tracing::debug!(error = ?err, "failed emitting undo progress");
             }
         });
 
         let snapshot = config.snapshot();
-        undo_plan_moves(&snapshot, database.as_ref(), emitter)
+        watch_battery(snapshot.power.clone(), task.shared_control());
+        let result = undo_plan_moves(&snapshot, database.as_ref(), task.control(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await;
+    tray::clear_progress(&app);
+    result
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn index_output(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+) -> Result<IndexSummary, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let replay = state.replay_arc();
+    let app_handle = app.clone();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let emitter: LibraryProgressEmitter = Arc::new(move |payload| {
+            if let Err(err) =
+                emit_recorded(&app_handle, &replay, EVENT_INDEX_PROGRESS, payload.clone())
+            {
+                tracing::debug!(error = ?err, "failed emitting index progress");
+            }
+        });
+
+        let snapshot = config.snapshot();
+        let result = run_index_output(&snapshot, database.as_ref(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn verify_library(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+) -> Result<VerifyReport, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let replay = state.replay_arc();
+    let app_handle = app.clone();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let emitter: LibraryProgressEmitter = Arc::new(move |payload| {
+            if let Err(err) =
+                emit_recorded(&app_handle, &replay, EVENT_VERIFY_PROGRESS, payload.clone())
+            {
+                tracing::debug!(error = ?err, "failed emitting verify progress");
+            }
+        });
+
+        let snapshot = config.snapshot();
+        let result = run_verify_library(&snapshot, database.as_ref(), emitter);
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn relocate_library(
+    state: tauri::State<'_, AppState>,
+    old_root: String,
+    new_root: String,
+) -> Result<RelocationReport, String> {
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = run_relocate_library(
+            database.as_ref(),
+            Path::new(&old_root),
+            Path::new(&new_root),
+        );
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn detect_remaps(state: tauri::State<'_, AppState>) -> Result<Vec<RootRemap>, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        let result = detect_root_remaps(&snapshot, database.as_ref());
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn remap_roots(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    remaps: Vec<RootRemap>,
+) -> Result<ConfigPayload, String> {
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    let result = apply_root_remaps(state.config(), state.database(), &remaps)
+        .map(|config| ConfigPayload::from(&config));
+    task.finish(&result);
+    let payload = result.map_err(|err| err.to_string())?;
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+#[tauri::command]
+async fn set_image_root(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    path: String,
+) -> Result<ConfigPayload, String> {
+    change_root(state, app, RootKind::Image, path).await
+}
+
+#[tauri::command]
+async fn set_output_root(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    path: String,
+) -> Result<ConfigPayload, String> {
+    change_root(state, app, RootKind::Output, path).await
+}
+
+#[tauri::command]
+async fn set_included_folders(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    folders: Vec<String>,
+) -> Result<ConfigPayload, String> {
+    let config = state.config_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    let payload = tauri::async_runtime::spawn_blocking(move || {
+        let result = run_set_included_folders(config.as_ref(), &folders)
+            .map(|config| ConfigPayload::from(&config));
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+#[tauri::command]
+async fn update_config(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    changes: serde_json::Map<String, serde_json::Value>,
+) -> Result<ConfigPayload, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    let payload = tauri::async_runtime::spawn_blocking(move || {
+        let result = run_update_config(config.as_ref(), database.as_ref(), &changes)
+            .map(|config| ConfigPayload::from(&config));
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+async fn change_root(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    kind: RootKind,
+    path: String,
+) -> Result<ConfigPayload, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    // Stored paths are decoded against the roots, so nothing may be running.
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    let payload = tauri::async_runtime::spawn_blocking(move || {
+        let result = set_root(config.as_ref(), database.as_ref(), kind, Path::new(&path))
+            .map(|config| ConfigPayload::from(&config));
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+#[tauri::command]
+async fn list_folders(
+    state: tauri::State<'_, AppState>,
+    path: Option<String>,
+    depth: Option<usize>,
+) -> Result<Vec<FolderNode>, String> {
+    let config = state.config_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        run_list_folders(
+            &snapshot,
+            path.as_deref().map(Path::new),
+            depth.unwrap_or(1),
+        )
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn set_sample_mode(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    root: Option<String>,
+) -> Result<ConfigPayload, String> {
+    let updated = state
+        .config()
+        .set_sample_root(root.as_deref().map(Path::new))
+        .map_err(|err| err.to_string())?;
+    state.database().configure_paths(&updated);
+    let payload = ConfigPayload::from(&updated);
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+#[tauri::command]
+async fn export_app_state(
+    state: tauri::State<'_, AppState>,
+    destination: String,
+) -> Result<StateManifest, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        export_state(&snapshot, database.as_ref(), Path::new(&destination))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn create_diagnostics_bundle(
+    state: tauri::State<'_, AppState>,
+    destination: String,
+    redact_paths: bool,
+) -> Result<DiagnosticsManifest, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let summaries = state.progress().last_summaries();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        create_bundle(
+            &snapshot,
+            database.as_ref(),
+            summaries,
+            Path::new(&destination),
+            redact_paths,
+        )
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn self_test() -> Result<SelfTestReport, String> {
+    tauri::async_runtime::spawn_blocking(run_self_test)
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_audit_log(
+    state: tauri::State<'_, AppState>,
+    destination: String,
+) -> Result<AuditExport, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        run_export_audit_log(&snapshot, database.as_ref(), Path::new(&destination))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn import_app_state(
+    state: tauri::State<'_, AppState>,
+    app: AppHandle,
+    source: String,
+) -> Result<ConfigPayload, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = import_state(config.as_ref(), database.as_ref(), Path::new(&source));
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    let payload = state.config().payload();
+    if let Err(err) = app.emit(EVENT_CONFIG_UPDATED, payload.clone()) {
+        error!("failed to emit config update: {err:?}");
+    }
+    Ok(payload)
+}
+
+#[tauri::command]
+async fn import_catalog(
+    state: tauri::State<'_, AppState>,
+    kind: CatalogKind,
+    path: String,
+) -> Result<CatalogImportSummary, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        let result = run_import_catalog(&snapshot, database.as_ref(), kind, Path::new(&path));
+        task.finish(&result);
+        result
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn exclude_file(
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<ExclusionRecord, String> {
+    let snapshot = state.config().snapshot();
+    add_file_exclusion(&snapshot, state.database(), &path).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn exclude_pattern(
+    state: tauri::State<'_, AppState>,
+    pattern: String,
+) -> Result<ExclusionRecord, String> {
+    add_pattern_exclusion(state.database(), &pattern).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn list_exclusions(state: tauri::State<'_, AppState>) -> Result<Vec<ExclusionRecord>, String> {
+    state.database().exclusions().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn remove_exclusion(state: tauri::State<'_, AppState>, id: i64) -> Result<bool, String> {
+    state
+        .database()
+        .remove_exclusion(id)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn list_duplicate_groups(state: tauri::State<'_, AppState>) -> Result<Vec<DuplicateGroup>, String> {
+    list_duplicate_groups_in(state.database()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn decide_duplicate_group(
+    state: tauri::State<'_, AppState>,
+    group_id: String,
+    decision: Option<DuplicateDecision>,
+) -> Result<Option<DuplicateDecisionRecord>, String> {
+    run_decide_duplicate_group(state.database(), &group_id, decision).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn prepare_duplicate_deletion(
+    state: tauri::State<'_, AppState>,
+    group_ids: Vec<String>,
+    to_trash: bool,
+) -> Result<DeletionPreview, String> {
+    let database = state.database_arc();
+    tauri::async_runtime::spawn_blocking(move || {
+        run_prepare_duplicate_deletion(database.as_ref(), &group_ids, to_trash)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn delete_duplicates(
+    state: tauri::State<'_, AppState>,
+    group_ids: Vec<String>,
+    to_trash: bool,
+    token: String,
+) -> Result<DeletionSummary, String> {
+    let config = state.config_arc();
+    let database = state.database_arc();
+    let task = state
+        .progress_arc()
+        .begin(TaskKind::Maintenance)
+        .map_err(|err| err.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let snapshot = config.snapshot();
+        let result =
+            run_delete_duplicates(&snapshot, database.as_ref(), &group_ids, to_trash, &token);
+        task.finish(&result);
+        result
     })
     .await
     .map_err(|err| err.to_string())?
@@ -191,22 +1115,82 @@ pub fn run() {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_opener::init())
         .manage(AppState::new(config_service, database))
         .invoke_handler(tauri::generate_handler![
             bootstrap_paths,
             check_disk_space,
+            preflight,
+            check_permissions,
+            current_progress,
+            cancel_task,
+            replay_events,
+            profile_last_run,
             scan_media,
+            estimate_scan,
+            last_scan_diff,
+            scan_history,
+            file_history,
             plan_targets,
+            preview_plan,
+            diff_plans,
+            explain_rules,
+            explain_file,
             execute_plan,
-            undo_moves
+            verify_execution,
+            undo_moves,
+            export_resized,
+            index_output,
+            verify_library,
+            relocate_library,
+            detect_remaps,
+            remap_roots,
+            set_image_root,
+            set_output_root,
+            set_included_folders,
+            update_config,
+            list_folders,
+            set_sample_mode,
+            export_app_state,
+            export_audit_log,
+            create_diagnostics_bundle,
+            self_test,
+            import_app_state,
+            import_catalog,
+            exclude_file,
+            exclude_pattern,
+            list_exclusions,
+            remove_exclusion,
+            list_duplicate_groups,
+            decide_duplicate_group,
+            prepare_duplicate_deletion,
+            delete_duplicates
         ])
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                tray::keep_running_in_tray(window, api);
+            }
+        })
         .setup(|app| {
+            if let Err(err) = tray::setup_tray(app.handle()) {
+                error!("failed to create tray icon: {err:?}");
+            }
             if let Some(state) = app.try_state::<AppState>() {
                 let payload = state.config().payload();
                 if let Err(err) = app.emit(EVENT_BOOTSTRAP_CONFIG, payload.clone()) {
                     error!("failed to emit bootstrap event from setup: {err:?}");
                 }
+
+                let app_handle = app.handle().clone();
+                let replay = state.replay_arc();
+                let on_scrub_error: ScrubReportEmitter = Arc::new(move |report| {
+                    if let Err(err) = emit_recorded(&app_handle, &replay, EVENT_SCRUB_ERROR, report)
+                    {
+                        tracing::debug!(error = ?err, "failed emitting scrub error");
+                    }
+                });
+                spawn_scrub_scheduler(state.config_arc(), state.database_arc(), on_scrub_error);
             }
             Ok(())
         })
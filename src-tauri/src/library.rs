@@ -0,0 +1,505 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::config::AppConfig;
+use crate::db::{Database, LibraryEntry, PlanStatus};
+use crate::error::{AppError, Result};
+use crate::media::MediaKind;
+use crate::resolution::trash_dir;
+use crate::utils::fs::collect_files;
+use crate::utils::hash::blake3_file;
+use crate::utils::path::{ensure_trailing_separator, to_native_path, to_posix_string};
+use crate::utils::time::{format_timestamp, now_timestamp};
+
+const INDEX_STAGE: &str = "index";
+const RELOCATION_SAMPLE_SIZE: usize = 16;
+const VERIFY_STAGE: &str = "verify";
+
+pub type LibraryProgressEmitter = Arc<dyn Fn(LibraryProgressPayload) + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryProgressPayload {
+    pub stage: &'static str,
+    pub processed: usize,
+    pub total: usize,
+    pub current: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexSummary {
+    pub indexed_at: String,
+    pub roots: Vec<String>,
+    pub indexed_files: usize,
+    pub hashed_files: usize,
+    pub unchanged_files: usize,
+    pub removed_files: usize,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifiedFile {
+    pub path: String,
+    pub expected_hash: String,
+    pub actual_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyReport {
+    pub verified_at: String,
+    pub clean: bool,
+    pub checked_files: usize,
+    pub intact_files: usize,
+    pub missing: Vec<String>,
+    pub modified: Vec<ModifiedFile>,
+    /// Files on disk that the index does not know about.
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocationReport {
+    pub old_root: String,
+    pub new_root: String,
+    pub plan_entries_updated: usize,
+    pub library_entries_updated: usize,
+    pub sampled_files: usize,
+}
+
+/// Walks every output root and brings `library_index` in line with what is on
+/// disk. Files whose size and modification time are unchanged keep their
+/// stored hash; vanished files are dropped from the index.
+pub fn index_output(
+    config: &AppConfig,
+    database: &Database,
+    emitter: LibraryProgressEmitter,
+) -> Result<IndexSummary> {
+    let roots = config.output_roots();
+    let files = library_files(config, &roots)?;
+
+    let total = files.len();
+    emit_progress(&emitter, INDEX_STAGE, 0, total, None);
+
+    let mut known: HashMap<String, LibraryEntry> = database
+        .library_entries()?
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    let mut updated = Vec::new();
+    let mut hashed_files = 0usize;
+    let mut unchanged_files = 0usize;
+    let mut total_bytes = 0u64;
+
+    for (idx, path) in files.iter().enumerate() {
+        let key = to_posix_string(path).into_owned();
+        let (file_size, modified_at) = match file_stamp(path) {
+            Ok(stamp) => stamp,
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "failed to stat library file");
+                emit_progress(&emitter, INDEX_STAGE, idx + 1, total, Some(key));
+                continue;
+            }
+        };
+        total_bytes += file_size;
+
+        match known.remove(&key) {
+            Some(entry) if entry.file_size == file_size && entry.modified_at == modified_at => {
+                unchanged_files += 1;
+            }
+            _ => match blake3_file(path) {
+                Ok(blake3_hash) => {
+                    hashed_files += 1;
+                    updated.push(LibraryEntry {
+                        path: key.clone(),
+                        file_size,
+                        modified_at,
+                        blake3_hash,
+                    });
+                }
+                Err(err) => {
+                    tracing::warn!(path = %path.display(), error = %err, "failed to hash library file");
+                }
+            },
+        }
+
+        emit_progress(&emitter, INDEX_STAGE, idx + 1, total, Some(key));
+    }
+
+    database.upsert_library_entries(&updated)?;
+    let stale: Vec<String> = known.into_keys().collect();
+    let removed_files = database.remove_library_entries(&stale)?;
+
+    let indexed_at = now_timestamp()?;
+    database.set_meta("library_indexed_at", &indexed_at)?;
+
+    Ok(IndexSummary {
+        indexed_at,
+        roots: roots
+            .iter()
+            .map(|root| to_posix_string(root).into_owned())
+            .collect(),
+        indexed_files: hashed_files + unchanged_files,
+        hashed_files,
+        unchanged_files,
+        removed_files,
+        total_bytes,
+    })
+}
+
+/// Re-hashes every indexed file and compares it with the recorded hash. The
+/// index itself is left untouched so a failed check can be inspected before
+/// re-indexing.
+pub fn verify_library(
+    config: &AppConfig,
+    database: &Database,
+    emitter: LibraryProgressEmitter,
+) -> Result<VerifyReport> {
+    let recorded = database.library_entries()?;
+    let total = recorded.len();
+    emit_progress(&emitter, VERIFY_STAGE, 0, total, None);
+
+    let mut on_disk: HashSet<String> = library_files(config, &config.output_roots())?
+        .iter()
+        .map(|path| to_posix_string(path).into_owned())
+        .collect();
+
+    let mut intact_files = 0usize;
+    let mut missing = Vec::new();
+    let mut modified = Vec::new();
+
+    for (idx, entry) in recorded.iter().enumerate() {
+        on_disk.remove(&entry.path);
+        match check_entry(entry) {
+            EntryCheck::Intact => intact_files += 1,
+            EntryCheck::Missing => missing.push(entry.path.clone()),
+            EntryCheck::Modified(file) => modified.push(file),
+        }
+        emit_progress(
+            &emitter,
+            VERIFY_STAGE,
+            idx + 1,
+            total,
+            Some(entry.path.clone()),
+        );
+    }
+
+    let mut extra: Vec<String> = on_disk.into_iter().collect();
+    extra.sort();
+
+    let verified_at = now_timestamp()?;
+    database.set_meta("library_verified_at", &verified_at)?;
+
+    Ok(VerifyReport {
+        verified_at,
+        clean: missing.is_empty() && modified.is_empty() && extra.is_empty(),
+        checked_files: total,
+        intact_files,
+        missing,
+        modified,
+        extra,
+    })
+}
+
+pub enum EntryCheck {
+    Intact,
+    Missing,
+    Modified(ModifiedFile),
+}
+
+/// Re-hashes one indexed file. Unreadable files count as missing.
+pub fn check_entry(entry: &LibraryEntry) -> EntryCheck {
+    let path = to_native_path(&entry.path);
+    if !path.is_file() {
+        return EntryCheck::Missing;
+    }
+    match blake3_file(&path) {
+        Ok(actual) if actual == entry.blake3_hash => EntryCheck::Intact,
+        Ok(actual) => EntryCheck::Modified(ModifiedFile {
+            path: entry.path.clone(),
+            expected_hash: entry.blake3_hash.clone(),
+            actual_hash: actual,
+        }),
+        Err(err) => {
+            tracing::warn!(path = %entry.path, error = %err, "failed to hash library file");
+            EntryCheck::Missing
+        }
+    }
+}
+
+/// Points stored output paths at a library that moved to `new_root`.
+///
+/// A spread-out sample of files is checked at the new location first
+/// (hash-checked when indexed, existence-checked otherwise); nothing is
+/// rewritten unless every sampled file is there. Operation logs reference
+/// plan entries by id, so they follow the rewritten entries.
+pub fn relocate_library(
+    database: &Database,
+    old_root: &Path,
+    new_root: &Path,
+) -> Result<RelocationReport> {
+    if !new_root.is_dir() {
+        return Err(AppError::Config(format!(
+            "new library root {} is not a directory",
+            new_root.display()
+        )));
+    }
+
+    let old_prefix = to_posix_string(&ensure_trailing_separator(old_root)).into_owned();
+    let new_prefix = to_posix_string(&ensure_trailing_separator(new_root)).into_owned();
+    if old_prefix == new_prefix {
+        return Err(AppError::Config(
+            "old and new library roots are the same".into(),
+        ));
+    }
+    let moved = |path: &str| {
+        path.strip_prefix(old_prefix.as_str())
+            .map(|rest| format!("{new_prefix}{rest}"))
+    };
+
+    let indexed: Vec<LibraryEntry> = database
+        .library_entries()?
+        .into_iter()
+        .filter_map(|entry| {
+            Some(LibraryEntry {
+                path: moved(&entry.path)?,
+                ..entry
+            })
+        })
+        .collect();
+
+    let mut failures = Vec::new();
+    let sampled_files = if indexed.is_empty() {
+        let placed: Vec<String> = database
+            .plan_entries_with_status(&[
+                PlanStatus::Copied,
+                PlanStatus::Moved,
+                PlanStatus::AlreadyPresent,
+            ])?
+            .into_iter()
+            .filter_map(|entry| moved(&format!("{}{}", entry.target_path, entry.target_file_name)))
+            .collect();
+        let sample = spread_sample(&placed);
+        failures.extend(
+            sample
+                .iter()
+                .filter(|path| !Path::new(path.as_str()).is_file())
+                .map(|path| path.to_string()),
+        );
+        sample.len()
+    } else {
+        let sample = spread_sample(&indexed);
+        failures.extend(
+            sample
+                .iter()
+                .filter(|entry| !matches!(check_entry(entry), EntryCheck::Intact))
+                .map(|entry| entry.path.clone()),
+        );
+        sample.len()
+    };
+
+    if !failures.is_empty() {
+        return Err(AppError::Config(format!(
+            "{} of {sampled_files} sampled files are missing or differ at the new location, e.g. {}",
+            failures.len(),
+            failures[0]
+        )));
+    }
+
+    let (plan_entries_updated, library_entries_updated) =
+        database.relocate_output_paths(&old_prefix, &new_prefix)?;
+
+    Ok(RelocationReport {
+        old_root: old_prefix,
+        new_root: new_prefix,
+        plan_entries_updated,
+        library_entries_updated,
+        sampled_files,
+    })
+}
+
+/// Up to `RELOCATION_SAMPLE_SIZE` items spread evenly over the slice.
+fn spread_sample<T>(items: &[T]) -> Vec<&T> {
+    let step = items.len().div_ceil(RELOCATION_SAMPLE_SIZE).max(1);
+    items.iter().step_by(step).collect()
+}
+
+/// Records a file that an execution just placed in the library. The hash is
+/// reused when the caller already knows it.
+pub fn record_library_file(
+    database: &Database,
+    path: &Path,
+    known_hash: Option<&str>,
+) -> Result<()> {
+    let (file_size, modified_at) = file_stamp(path)?;
+    let blake3_hash = match known_hash {
+        Some(hash) => hash.to_string(),
+        None => blake3_file(path)?,
+    };
+    database.upsert_library_entries(&[LibraryEntry {
+        path: to_posix_string(path).into_owned(),
+        file_size,
+        modified_at,
+        blake3_hash,
+    }])
+}
+
+pub fn forget_library_file(database: &Database, path: &Path) -> Result<()> {
+    database.remove_library_entries(&[to_posix_string(path).into_owned()])?;
+    Ok(())
+}
+
+/// Media and sidecar files under the given roots, sorted and de-duplicated
+/// (roots may nest). Trashed duplicates are not library files.
+fn library_files(config: &AppConfig, roots: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut exts = config.media_exts.scannable();
+    exts.extend(config.media_exts.group(MediaKind::Sidecar).iter().cloned());
+
+    let trash = trash_dir(config);
+    let mut files = Vec::new();
+    for root in roots {
+        let found = collect_files(root, &exts)?;
+        files.extend(found.into_iter().filter(|path| !path.starts_with(&trash)));
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn file_stamp(path: &Path) -> Result<(u64, String)> {
+    let metadata = fs::metadata(path)?;
+    let modified = OffsetDateTime::from(metadata.modified()?);
+    Ok((metadata.len(), format_timestamp(modified)?))
+}
+
+fn emit_progress(
+    emitter: &LibraryProgressEmitter,
+    stage: &'static str,
+    processed: usize,
+    total: usize,
+    current: Option<String>,
+) {
+    let payload = LibraryProgressPayload {
+        stage,
+        processed,
+        total,
+        current,
+    };
+    (emitter)(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::NewOperationLog;
+    use tempfile::tempdir;
+
+    #[test]
+    fn index_output_tracks_added_changed_and_removed_files() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let output_dir = config.output_root.clone();
+        let database = Database::initialize(&config)?;
+        let emitter: LibraryProgressEmitter = Arc::new(|_| {});
+
+        let bucket = output_dir.join("2024-01-02");
+        fs::create_dir_all(&bucket)?;
+        fs::write(bucket.join("a.jpg"), b"first")?;
+        fs::write(bucket.join("b.jpg"), b"second")?;
+        fs::write(output_dir.join("plan.json"), b"[]")?;
+
+        let summary = index_output(&config, &database, emitter.clone())?;
+        assert_eq!(summary.indexed_files, 2);
+        assert_eq!(summary.hashed_files, 2);
+
+        let again = index_output(&config, &database, emitter.clone())?;
+        assert_eq!(again.unchanged_files, 2);
+        assert_eq!(again.hashed_files, 0);
+
+        fs::remove_file(bucket.join("b.jpg"))?;
+        let pruned = index_output(&config, &database, emitter)?;
+        assert_eq!(pruned.removed_files, 1);
+
+        let entries = database.library_entries()?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].blake3_hash,
+            blake3::hash(b"first").to_hex().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn verify_library_reports_missing_modified_and_extra() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let output_dir = config.output_root.clone();
+        let database = Database::initialize(&config)?;
+        let emitter: LibraryProgressEmitter = Arc::new(|_| {});
+
+        fs::write(output_dir.join("kept.jpg"), b"kept")?;
+        fs::write(output_dir.join("gone.jpg"), b"gone")?;
+        fs::write(output_dir.join("changed.jpg"), b"before")?;
+        index_output(&config, &database, emitter.clone())?;
+        assert!(verify_library(&config, &database, emitter.clone())?.clean);
+
+        fs::remove_file(output_dir.join("gone.jpg"))?;
+        fs::write(output_dir.join("changed.jpg"), b"after")?;
+        fs::write(output_dir.join("new.jpg"), b"new")?;
+
+        let report = verify_library(&config, &database, emitter)?;
+        assert!(!report.clean);
+        assert_eq!(report.checked_files, 3);
+        assert_eq!(report.intact_files, 1);
+        assert_eq!(report.missing.len(), 1);
+        assert!(report.missing[0].ends_with("gone.jpg"));
+        assert_eq!(report.modified.len(), 1);
+        assert!(report.modified[0].path.ends_with("changed.jpg"));
+        assert_eq!(report.extra.len(), 1);
+        assert!(report.extra[0].ends_with("new.jpg"));
+        Ok(())
+    }
+
+    #[test]
+    fn relocate_library_rewrites_paths_after_checking_samples() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let output_dir = config.output_root.clone();
+        let database = Database::initialize(&config)?;
+        let emitter: LibraryProgressEmitter = Arc::new(|_| {});
+        fs::write(output_dir.join("a.jpg"), b"a")?;
+        index_output(&config, &database, emitter)?;
+        database.append_operation_log(NewOperationLog {
+            plan_entry_id: None,
+            path: Some(format!("{}/a.jpg", to_posix_string(&output_dir))),
+            operation: "copy".into(),
+            status: "success".into(),
+            error: None,
+        })?;
+
+        let new_root = tempdir()?.keep();
+        let err = relocate_library(&database, &output_dir, &new_root).unwrap_err();
+        assert!(matches!(err, AppError::Config(_)));
+        assert!(database.library_entries()?[0]
+            .path
+            .starts_with(to_posix_string(&output_dir).as_ref()));
+
+        fs::copy(output_dir.join("a.jpg"), new_root.join("a.jpg"))?;
+        let report = relocate_library(&database, &output_dir, &new_root)?;
+        assert_eq!(report.sampled_files, 1);
+        assert_eq!(report.library_entries_updated, 1);
+        let relocated = &database.library_entries()?[0];
+        assert_eq!(relocated.path, format!("{}a.jpg", report.new_root));
+        assert_eq!(
+            database.operation_logs_after(0)?[0].path.as_deref(),
+            Some(format!("{}a.jpg", report.new_root).as_str())
+        );
+        Ok(())
+    }
+}
@@ -1,16 +1,124 @@
-use tracing_subscriber::{fmt, EnvFilter};
+use std::collections::VecDeque;
+use std::fmt::{Debug, Write as _};
+use std::sync::{Arc, OnceLock};
+
+use parking_lot::Mutex;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+use crate::profile::ProfileLayer;
+
+/// Log lines kept in memory for diagnostics bundles.
+const RECENT_LINES: usize = 2000;
 
 pub fn init_logging() {
     if tracing::dispatcher::has_been_set() {
         return;
     }
 
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info,phototidy=debug"));
+    // The profile sees every span of ours, whatever the log level.
+    let profile = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE);
 
-    fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .compact()
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_target(false)
+                .compact()
+                .with_filter(env_filter()),
+        )
+        .with(RecentLogs::global().clone().with_filter(env_filter()))
+        .with(ProfileLayer::global().clone().with_filter(profile))
         .init();
 }
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info,phototidy=debug"))
+}
+
+/// The last log lines, oldest first.
+pub fn recent_logs() -> Vec<String> {
+    RecentLogs::global().lines.lock().iter().cloned().collect()
+}
+
+/// Keeps the last [`RECENT_LINES`] events as text.
+#[derive(Clone, Default)]
+struct RecentLogs {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl RecentLogs {
+    fn global() -> &'static RecentLogs {
+        static GLOBAL: OnceLock<RecentLogs> = OnceLock::new();
+        GLOBAL.get_or_init(RecentLogs::default)
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == RECENT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentLogs {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = format!(
+            "{} {:>5} {}:",
+            OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            metadata.level(),
+            metadata.target()
+        );
+        event.record(&mut LineVisitor(&mut line));
+        self.push(line);
+    }
+}
+
+struct LineVisitor<'a>(&'a mut String);
+
+impl Visit for LineVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_become_lines_and_old_ones_drop_off() {
+        let logs = RecentLogs::default();
+        let subscriber = tracing_subscriber::registry().with(logs.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            for idx in 0..RECENT_LINES + 1 {
+                tracing::info!(idx, path = "a b.jpg", "copied");
+            }
+        });
+
+        let lines = logs.lines.lock();
+        assert_eq!(lines.len(), RECENT_LINES);
+        assert!(lines[0].ends_with("logging::tests: copied idx=1 path=\"a b.jpg\""));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+
+const VIDEO_EXTS: &[&str] = &[".mp4", ".mov", ".avi", ".m4v", ".mkv", ".mts", ".3gp"];
+const RAW_EXTS: &[&str] = &[
+    ".dng", ".cr2", ".cr3", ".nef", ".arw", ".raf", ".orf", ".rw2", ".pef", ".srw",
+];
+const SIDECAR_EXTS: &[&str] = &[".xmp", ".aae", ".thm"];
+
+/// Broad class of a media file, used to route files to per-type output roots
+/// and to break down statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Photo,
+    Video,
+    Raw,
+    Sidecar,
+}
+
+impl MediaKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Video => "video",
+            Self::Raw => "raw",
+            Self::Sidecar => "sidecar",
+        }
+    }
+}
+
+impl TryFrom<&str> for MediaKind {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "photo" => Ok(Self::Photo),
+            "video" => Ok(Self::Video),
+            "raw" => Ok(Self::Raw),
+            "sidecar" => Ok(Self::Sidecar),
+            other => Err(AppError::internal(format!(
+                "unsupported media type: {other}"
+            ))),
+        }
+    }
+}
+
+/// Extension groups from config. Sidecars are tracked so they can be
+/// recognised next to their primaries, but are never scanned as media.
+#[derive(Debug, Clone, Default)]
+pub struct MediaExtensions {
+    pub photo: HashSet<String>,
+    pub video: HashSet<String>,
+    pub raw: HashSet<String>,
+    pub sidecar: HashSet<String>,
+}
+
+impl MediaExtensions {
+    /// Compatibility path for configs that only carry a flat `imageExts`
+    /// list: every entry is sorted into a group using the built-in tables.
+    pub fn from_legacy<I>(exts: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut groups = Self::default();
+        for ext in exts {
+            let ext = normalize_extension(&ext);
+            let kind = builtin_kind(&ext);
+            groups.group_mut(kind).insert(ext);
+        }
+        groups
+    }
+
+    pub fn from_groups(
+        photo: Vec<String>,
+        video: Vec<String>,
+        raw: Vec<String>,
+        sidecar: Vec<String>,
+    ) -> Self {
+        let collect = |exts: Vec<String>| exts.iter().map(|ext| normalize_extension(ext)).collect();
+        Self {
+            photo: collect(photo),
+            video: collect(video),
+            raw: collect(raw),
+            sidecar: collect(sidecar),
+        }
+    }
+
+    pub fn group(&self, kind: MediaKind) -> &HashSet<String> {
+        match kind {
+            MediaKind::Photo => &self.photo,
+            MediaKind::Video => &self.video,
+            MediaKind::Raw => &self.raw,
+            MediaKind::Sidecar => &self.sidecar,
+        }
+    }
+
+    fn group_mut(&mut self, kind: MediaKind) -> &mut HashSet<String> {
+        match kind {
+            MediaKind::Photo => &mut self.photo,
+            MediaKind::Video => &mut self.video,
+            MediaKind::Raw => &mut self.raw,
+            MediaKind::Sidecar => &mut self.sidecar,
+        }
+    }
+
+    /// Extensions the scanner should pick up (everything but sidecars).
+    pub fn scannable(&self) -> HashSet<String> {
+        self.photo
+            .iter()
+            .chain(&self.video)
+            .chain(&self.raw)
+            .cloned()
+            .collect()
+    }
+
+    /// Classifies by the configured groups, falling back to the built-in
+    /// tables for extensions the config does not mention.
+    pub fn classify(&self, file_name: &str) -> MediaKind {
+        let extension = extension_of(file_name);
+        [
+            MediaKind::Video,
+            MediaKind::Raw,
+            MediaKind::Sidecar,
+            MediaKind::Photo,
+        ]
+        .into_iter()
+        .find(|kind| self.group(*kind).contains(&extension))
+        .unwrap_or_else(|| builtin_kind(&extension))
+    }
+}
+
+/// Per-kind file counts reported in summaries.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaCounts {
+    pub photos: usize,
+    pub videos: usize,
+    pub raw: usize,
+}
+
+impl MediaCounts {
+    pub fn add(&mut self, kind: MediaKind) {
+        match kind {
+            MediaKind::Photo | MediaKind::Sidecar => self.photos += 1,
+            MediaKind::Video => self.videos += 1,
+            MediaKind::Raw => self.raw += 1,
+        }
+    }
+}
+
+fn builtin_kind(extension: &str) -> MediaKind {
+    if VIDEO_EXTS.contains(&extension) {
+        MediaKind::Video
+    } else if RAW_EXTS.contains(&extension) {
+        MediaKind::Raw
+    } else if SIDECAR_EXTS.contains(&extension) {
+        MediaKind::Sidecar
+    } else {
+        MediaKind::Photo
+    }
+}
+
+fn extension_of(file_name: &str) -> String {
+    Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| format!(".{}", ext.to_ascii_lowercase()))
+        .unwrap_or_default()
+}
+
+fn normalize_extension(ext: &str) -> String {
+    let lower = ext.trim().to_ascii_lowercase();
+    if lower.starts_with('.') {
+        lower
+    } else {
+        format!(".{lower}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_by_builtin_tables() {
+        let groups = MediaExtensions::default();
+        assert_eq!(groups.classify("clip.MOV"), MediaKind::Video);
+        assert_eq!(groups.classify("IMG_0001.CR3"), MediaKind::Raw);
+        assert_eq!(groups.classify("IMG_0001.xmp"), MediaKind::Sidecar);
+        assert_eq!(groups.classify("IMG_0001.jpg"), MediaKind::Photo);
+        assert_eq!(groups.classify("no_extension"), MediaKind::Photo);
+    }
+
+    #[test]
+    fn legacy_list_is_split_into_groups() {
+        let groups = MediaExtensions::from_legacy(vec![
+            ".JPG".to_string(),
+            "mp4".to_string(),
+            ".nef".to_string(),
+            ".xmp".to_string(),
+        ]);
+        assert!(groups.photo.contains(".jpg"));
+        assert!(groups.video.contains(".mp4"));
+        assert!(groups.raw.contains(".nef"));
+        assert!(groups.sidecar.contains(".xmp"));
+        assert_eq!(groups.scannable().len(), 3);
+    }
+
+    #[test]
+    fn configured_groups_override_builtin_tables() {
+        let groups = MediaExtensions::from_groups(
+            vec![".jpg".into()],
+            vec![".dat".into()],
+            Vec::new(),
+            Vec::new(),
+        );
+        assert_eq!(groups.classify("clip.DAT"), MediaKind::Video);
+        assert_eq!(groups.classify("clip.mov"), MediaKind::Video);
+        assert_eq!(groups.classify("a.jpg"), MediaKind::Photo);
+    }
+}
@@ -0,0 +1,435 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Prefix of an APP1 payload that holds EXIF; the TIFF header follows.
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const IFD_ENTRY_LEN: usize = 12;
+
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_APP0: u8 = 0xE0;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_APP1: u8 = 0xE1;
+const MARKER_APP13: u8 = 0xED;
+const MARKER_COM: u8 = 0xFE;
+
+/// Segments that describe the picture rather than encode it: EXIF and XMP
+/// (APP1), IPTC (APP13) and comments. ICC profiles and the Adobe colour
+/// transform stay, since decoders need them.
+const METADATA_MARKERS: [u8; 3] = [MARKER_APP1, MARKER_APP13, MARKER_COM];
+
+/// Removes GPS tags from a JPEG or TIFF in place. The file keeps its size
+/// and every other tag; the GPS directory and its values are zeroed and
+/// unlinked. Returns whether there was anything to remove.
+pub fn strip_gps(path: &Path) -> io::Result<bool> {
+    let mut bytes = fs::read(path)?;
+    let stripped = if bytes.starts_with(&[0xFF, MARKER_SOI]) {
+        let mut stripped = false;
+        for (start, end) in jpeg_segments(&bytes, MARKER_APP1)? {
+            let payload = &mut bytes[start..end];
+            if let Some(tiff) = payload.strip_prefix(EXIF_HEADER) {
+                let offset = start + EXIF_HEADER.len();
+                let len = tiff.len();
+                stripped |= strip_gps_ifd(&mut bytes[offset..offset + len])?;
+            }
+        }
+        stripped
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        strip_gps_ifd(&mut bytes)?
+    } else {
+        return Err(unsupported(path));
+    };
+    if stripped {
+        fs::write(path, &bytes)?;
+    }
+    Ok(stripped)
+}
+
+/// Rewrites a JPEG without its metadata segments. The image data is copied
+/// untouched. Returns whether any segment was dropped.
+pub fn strip_all(path: &Path) -> io::Result<bool> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&[0xFF, MARKER_SOI]) {
+        return Err(unsupported(path));
+    }
+    let mut dropped = Vec::new();
+    for marker in METADATA_MARKERS {
+        // Ranges cover the marker and length bytes, not just the payload.
+        dropped.extend(
+            jpeg_segments(&bytes, marker)?
+                .into_iter()
+                .map(|(start, end)| (start - 4, end)),
+        );
+    }
+    if dropped.is_empty() {
+        return Ok(false);
+    }
+    dropped.sort_unstable();
+
+    let mut kept = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    for (start, end) in dropped {
+        kept.extend_from_slice(&bytes[pos..start]);
+        pos = end;
+    }
+    kept.extend_from_slice(&bytes[pos..]);
+    fs::write(path, kept)?;
+    Ok(true)
+}
+
+/// The raw EXIF `DateTimeOriginal` of any container kamadak-exif reads,
+/// HEIF included, e.g. `2024:01:02 10:00:00`.
+pub fn capture_date(path: &Path) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .map(|value| value.trim_matches('\0').trim().to_string())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    }
+}
+
+/// Gives a JPEG without any EXIF a minimal block holding `date` as
+/// `DateTimeOriginal`. JPEGs that already carry EXIF are left alone, since
+/// rewriting their directories safely is more than this needs. Returns
+/// whether the date was written.
+pub fn insert_capture_date(path: &Path, date: &str) -> io::Result<bool> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&[0xFF, MARKER_SOI]) {
+        return Err(unsupported(path));
+    }
+    let has_exif = jpeg_segments(&bytes, MARKER_APP1)?
+        .into_iter()
+        .any(|(start, end)| bytes[start..end].starts_with(EXIF_HEADER));
+    if has_exif {
+        return Ok(false);
+    }
+
+    // JFIF requires its APP0 to stay right after SOI.
+    let insert_at = jpeg_segments(&bytes, MARKER_APP0)?
+        .first()
+        .filter(|(start, _)| *start == 6)
+        .map_or(2, |(_, end)| *end);
+    let payload = [EXIF_HEADER, &date_tiff(date)].concat();
+    let mut rewritten = Vec::with_capacity(bytes.len() + payload.len() + 4);
+    rewritten.extend_from_slice(&bytes[..insert_at]);
+    rewritten.extend([0xFF, MARKER_APP1]);
+    rewritten.extend(((payload.len() + 2) as u16).to_be_bytes());
+    rewritten.extend(payload);
+    rewritten.extend_from_slice(&bytes[insert_at..]);
+    fs::write(path, rewritten)?;
+    Ok(true)
+}
+
+/// A little-endian TIFF whose IFD0 points at an EXIF directory holding
+/// only `DateTimeOriginal`.
+fn date_tiff(date: &str) -> Vec<u8> {
+    const IFD0: u32 = 8;
+    const EXIF_IFD: u32 = IFD0 + 2 + IFD_ENTRY_LEN as u32 + 4;
+    const DATE: u32 = EXIF_IFD + 2 + IFD_ENTRY_LEN as u32 + 4;
+    let mut value = date.as_bytes().to_vec();
+    value.push(0);
+
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(IFD0.to_le_bytes());
+    for (tag, field_type, count, offset) in [
+        (TAG_EXIF_IFD, TYPE_LONG, 1, EXIF_IFD),
+        (TAG_DATE_TIME_ORIGINAL, TYPE_ASCII, value.len() as u32, DATE),
+    ] {
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(field_type.to_le_bytes());
+        tiff.extend(count.to_le_bytes());
+        tiff.extend(offset.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+    }
+    tiff.extend(value);
+    tiff
+}
+
+fn unsupported(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot edit metadata of {}", path.display()),
+    )
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed metadata")
+}
+
+/// Payload ranges of every `marker` segment before the image data.
+fn jpeg_segments(bytes: &[u8], marker: u8) -> io::Result<Vec<(usize, usize)>> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return Err(malformed());
+        }
+        let kind = bytes[pos + 1];
+        // Fill bytes before a marker.
+        if kind == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if kind == MARKER_SOS {
+            break;
+        }
+        let len = usize::from(u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]));
+        let end = pos + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return Err(malformed());
+        }
+        if kind == marker {
+            segments.push((pos + 4, end));
+        }
+        pos = end;
+    }
+    Ok(segments)
+}
+
+/// Reads TIFF integers in the file's byte order.
+struct Tiff<'a> {
+    bytes: &'a mut [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16_at(&self, pos: usize) -> io::Result<u16> {
+        let raw: [u8; 2] = self
+            .bytes
+            .get(pos..pos + 2)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or_else(malformed)?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(raw)
+        } else {
+            u16::from_be_bytes(raw)
+        })
+    }
+
+    fn u32_at(&self, pos: usize) -> io::Result<u32> {
+        let raw: [u8; 4] = self
+            .bytes
+            .get(pos..pos + 4)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or_else(malformed)?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(raw)
+        } else {
+            u32::from_be_bytes(raw)
+        })
+    }
+
+    fn write_u16(&mut self, pos: usize, value: u16) {
+        let raw = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        self.bytes[pos..pos + 2].copy_from_slice(&raw);
+    }
+
+    fn zero(&mut self, start: usize, len: usize) -> io::Result<()> {
+        self.bytes
+            .get_mut(start..start + len)
+            .ok_or_else(malformed)?
+            .fill(0);
+        Ok(())
+    }
+}
+
+fn strip_gps_ifd(bytes: &mut [u8]) -> io::Result<bool> {
+    let little_endian = match bytes.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return Err(malformed()),
+    };
+    let mut tiff = Tiff {
+        bytes,
+        little_endian,
+    };
+    let ifd0 = tiff.u32_at(4)? as usize;
+    let count = usize::from(tiff.u16_at(ifd0)?);
+    let entries = ifd0 + 2;
+
+    let mut gps = None;
+    for idx in 0..count {
+        let entry = entries + idx * IFD_ENTRY_LEN;
+        if tiff.u16_at(entry)? == TAG_GPS_IFD {
+            gps = Some((idx, tiff.u32_at(entry + 8)? as usize));
+            break;
+        }
+    }
+    let Some((gps_idx, gps_ifd)) = gps else {
+        return Ok(false);
+    };
+
+    // Zero the GPS values stored outside their entries, then the directory.
+    let gps_count = usize::from(tiff.u16_at(gps_ifd)?);
+    for idx in 0..gps_count {
+        let entry = gps_ifd + 2 + idx * IFD_ENTRY_LEN;
+        let size = type_size(tiff.u16_at(entry + 2)?) * tiff.u32_at(entry + 4)? as usize;
+        if size > 4 {
+            let value = tiff.u32_at(entry + 8)? as usize;
+            tiff.zero(value, size)?;
+        }
+    }
+    tiff.zero(gps_ifd, 2 + gps_count * IFD_ENTRY_LEN + 4)?;
+
+    // Unlink the pointer from IFD0: later entries and the next-IFD offset
+    // shift up one slot.
+    let removed = entries + gps_idx * IFD_ENTRY_LEN;
+    let table_end = entries + count * IFD_ENTRY_LEN + 4;
+    if table_end > tiff.bytes.len() {
+        return Err(malformed());
+    }
+    tiff.bytes
+        .copy_within(removed + IFD_ENTRY_LEN..table_end, removed);
+    tiff.zero(table_end - IFD_ENTRY_LEN, IFD_ENTRY_LEN)?;
+    tiff.write_u16(ifd0, (count - 1) as u16);
+    Ok(true)
+}
+
+/// Bytes per value of a TIFF field type.
+fn type_size(field_type: u16) -> usize {
+    match field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A little-endian TIFF with an orientation tag and a GPS directory
+    /// holding one rational latitude.
+    fn tiff_with_gps() -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        // IFD0 at 8: two entries, then next-IFD offset.
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend(0x0112u16.to_le_bytes());
+        tiff.extend(3u16.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(TAG_GPS_IFD.to_le_bytes());
+        tiff.extend(4u16.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(38u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        // GPS IFD at 38: latitude, 3 rationals at 56.
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend(0x0002u16.to_le_bytes());
+        tiff.extend(5u16.to_le_bytes());
+        tiff.extend(3u32.to_le_bytes());
+        tiff.extend(56u32.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        for value in [48u32, 1, 51, 1, 30, 1] {
+            tiff.extend(value.to_le_bytes());
+        }
+        tiff
+    }
+
+    const SCAN: [u8; 8] = [0xFF, MARKER_SOS, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, marker];
+        bytes.extend(((2 + payload.len()) as u16).to_be_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    fn exif_segment(tiff: &[u8]) -> Vec<u8> {
+        segment(MARKER_APP1, &[EXIF_HEADER, tiff].concat())
+    }
+
+    #[test]
+    fn gps_is_removed_from_jpeg_exif() -> io::Result<()> {
+        let tiff = tiff_with_gps();
+        let jpeg = [vec![0xFF, MARKER_SOI], exif_segment(&tiff), SCAN.to_vec()].concat();
+
+        let dir = tempdir()?;
+        let path = dir.path().join("a.jpg");
+        fs::write(&path, &jpeg)?;
+        assert!(strip_gps(&path)?);
+        let stripped = fs::read(&path)?;
+        assert_eq!(stripped.len(), jpeg.len());
+
+        let exif = exif::Reader::new()
+            .read_raw(stripped[12..12 + tiff.len()].to_vec())
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        assert!(exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .is_some());
+        assert!(exif
+            .get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY)
+            .is_none());
+        assert!(!strip_gps(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn strip_all_keeps_only_image_segments() -> io::Result<()> {
+        let jfif = segment(0xE0, b"JFIF\0\x01\x01");
+        let icc = segment(0xE2, b"ICC_PROFILE\0");
+        let jpeg = [
+            vec![0xFF, MARKER_SOI],
+            jfif.clone(),
+            exif_segment(&tiff_with_gps()),
+            segment(MARKER_APP1, b"http://ns.adobe.com/xap/1.0/\0<x/>"),
+            icc.clone(),
+            segment(MARKER_APP13, b"Photoshop 3.0\0"),
+            segment(MARKER_COM, b"taken at home"),
+            SCAN.to_vec(),
+        ]
+        .concat();
+
+        let dir = tempdir()?;
+        let path = dir.path().join("a.jpg");
+        fs::write(&path, &jpeg)?;
+        assert!(strip_all(&path)?);
+        let expected = [vec![0xFF, MARKER_SOI], jfif, icc, SCAN.to_vec()].concat();
+        assert_eq!(fs::read(&path)?, expected);
+        assert!(!strip_all(&path)?);
+
+        fs::write(&path, b"not a jpeg")?;
+        assert_eq!(
+            strip_all(&path).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn capture_date_is_added_to_bare_jpegs() -> io::Result<()> {
+        let jfif = segment(MARKER_APP0, b"JFIF\0\x01\x01");
+        let jpeg = [vec![0xFF, MARKER_SOI], jfif.clone(), SCAN.to_vec()].concat();
+        let dir = tempdir()?;
+        let path = dir.path().join("a.jpg");
+        fs::write(&path, &jpeg)?;
+
+        assert_eq!(capture_date(&path), None);
+        assert!(insert_capture_date(&path, "2024:01:02 10:00:00")?);
+        let written = fs::read(&path)?;
+        assert_eq!(&written[2..2 + jfif.len()], jfif.as_slice());
+        assert!(written.ends_with(&SCAN));
+        assert_eq!(capture_date(&path).as_deref(), Some("2024:01:02 10:00:00"));
+        assert!(!insert_capture_date(&path, "2030:01:01 00:00:00")?);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Wall time and I/O of one scan, plan or execution, so slow runs can be
+/// told apart from slow disks in bug reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunMetrics {
+    pub elapsed_ms: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Bytes per second of wall time, counting the larger of reads and
+    /// writes so a copy is not counted twice.
+    pub throughput: u64,
+    /// Stages in the order they started. Stages can overlap, so their
+    /// times need not add up to `elapsed_ms`.
+    pub stages: Vec<StageTiming>,
+}
+
+impl RunMetrics {
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub elapsed_ms: u64,
+}
+
+struct Stage {
+    name: &'static str,
+    elapsed: Duration,
+    running_since: Option<Instant>,
+}
+
+/// Collects [`RunMetrics`] while a run is under way. Shared across the
+/// hashing threads, hence the interior mutability.
+pub struct MetricsRecorder {
+    started: Instant,
+    stages: Mutex<Vec<Stage>>,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl MetricsRecorder {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            stages: Mutex::new(Vec::new()),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts the clock on `stage`; a stage already running keeps its start.
+    pub fn start(&self, stage: &'static str) {
+        let mut stages = self.stages.lock();
+        let now = Instant::now();
+        match stages.iter_mut().find(|entry| entry.name == stage) {
+            Some(entry) => {
+                entry.running_since.get_or_insert(now);
+            }
+            None => stages.push(Stage {
+                name: stage,
+                elapsed: Duration::ZERO,
+                running_since: Some(now),
+            }),
+        }
+    }
+
+    pub fn stop(&self, stage: &'static str) {
+        let mut stages = self.stages.lock();
+        if let Some(entry) = stages.iter_mut().find(|entry| entry.name == stage) {
+            if let Some(since) = entry.running_since.take() {
+                entry.elapsed += since.elapsed();
+            }
+        }
+    }
+
+    /// Runs `op`, adding its time to `stage`. Suits stages that take turns
+    /// within a loop.
+    pub fn time<T>(&self, stage: &'static str, op: impl FnOnce() -> T) -> T {
+        self.start(stage);
+        let result = op();
+        self.stop(stage);
+        result
+    }
+
+    pub fn read(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn wrote(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// The metrics so far; stages still running count up to now.
+    pub fn finish(&self) -> RunMetrics {
+        let elapsed = self.started.elapsed();
+        let stages = self
+            .stages
+            .lock()
+            .iter()
+            .map(|entry| StageTiming {
+                stage: entry.name,
+                elapsed_ms: millis(
+                    entry.elapsed
+                        + entry
+                            .running_since
+                            .map_or(Duration::ZERO, |since| since.elapsed()),
+                ),
+            })
+            .collect();
+        let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+        let bytes_written = self.bytes_written.load(Ordering::Relaxed);
+        RunMetrics {
+            elapsed_ms: millis(elapsed),
+            bytes_read,
+            bytes_written,
+            throughput: throughput(bytes_read.max(bytes_written), elapsed),
+            stages,
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> u64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        (bytes as f64 / secs) as u64
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_accumulate_and_bytes_add_up() {
+        let recorder = MetricsRecorder::new();
+        recorder.time("copy", || recorder.read(100));
+        recorder.start("index");
+        recorder.time("copy", || recorder.wrote(100));
+        recorder.stop("index");
+        recorder.read(20);
+
+        let metrics = recorder.finish();
+        let stages: Vec<_> = metrics.stages.iter().map(|entry| entry.stage).collect();
+        assert_eq!(stages, ["copy", "index"]);
+        assert_eq!(metrics.bytes_read, 120);
+        assert_eq!(metrics.bytes_written, 100);
+
+        assert_eq!(throughput(3_000, Duration::from_millis(1_500)), 2_000);
+        assert_eq!(throughput(3_000, Duration::ZERO), 0);
+    }
+}
@@ -0,0 +1,450 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{IoOptions, StorageKind};
+use crate::utils::path::{clean_path, is_unc, to_posix_string};
+
+/// Filesystem types that live on another machine.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afpfs",
+    "webdav",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "fuse.glusterfs",
+    "fuse.sshfs",
+    "fuse.davfs2",
+    "fuse.rclone",
+];
+
+/// Hashing threads used against a share; one per core saturates the link
+/// and starves the server.
+const NETWORK_HASH_THREADS: usize = 2;
+/// Attempts for a single copy or move on a share.
+const NETWORK_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How file work should be paced for the folders it touches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoProfile {
+    pub network: bool,
+    /// A spinning disk, where parallel reads make the head seek back and
+    /// forth instead of streaming.
+    pub rotational: bool,
+    /// Hashing threads set in the config.
+    threads: Option<usize>,
+    /// Hash copies made for a move before the original goes.
+    pub verify_moves: bool,
+}
+
+impl IoProfile {
+    pub fn for_paths<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let paths: Vec<&Path> = paths.into_iter().collect();
+        let network = paths.iter().any(|path| is_network_path(path));
+        Self {
+            network,
+            rotational: !network && paths.iter().any(|path| on_rotational_disk(path)),
+            threads: None,
+            verify_moves: false,
+        }
+    }
+
+    /// The detected profile with the overrides from `options` applied.
+    pub fn configured<'a>(paths: impl IntoIterator<Item = &'a Path>, options: &IoOptions) -> Self {
+        let mut profile = match options.storage {
+            None => Self::for_paths(paths),
+            Some(StorageKind::Ssd) => Self::default(),
+            Some(StorageKind::Hdd) => Self {
+                rotational: true,
+                ..Self::default()
+            },
+            Some(StorageKind::Network) => Self {
+                network: true,
+                ..Self::default()
+            },
+        };
+        profile.threads = options.hash_threads.filter(|&threads| threads > 0);
+        profile.verify_moves = options.verify_moves;
+        profile
+    }
+
+    pub fn storage(self) -> StorageKind {
+        if self.network {
+            StorageKind::Network
+        } else if self.rotational {
+            StorageKind::Hdd
+        } else {
+            StorageKind::Ssd
+        }
+    }
+
+    /// Hashing threads to use, or `None` for rayon's default. A spinning
+    /// disk is read one file at a time, front to back.
+    pub fn hash_threads(self) -> Option<usize> {
+        self.threads.or(match self.storage() {
+            StorageKind::Network => Some(NETWORK_HASH_THREADS),
+            StorageKind::Hdd => Some(1),
+            StorageKind::Ssd => None,
+        })
+    }
+
+    /// Renames touching a share can fail halfway with the file in neither
+    /// place, so moves there copy and then delete instead.
+    pub fn renames(self) -> bool {
+        !self.network
+    }
+
+    /// Runs `op`, retrying transient failures when on a share.
+    pub fn retry<T>(self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let attempts = if self.network { NETWORK_ATTEMPTS } else { 1 };
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(err) if attempt < attempts && is_transient(&err) => {
+                    tracing::debug!(attempt, error = %err, "retrying network file operation");
+                    thread::sleep(RETRY_BACKOFF * attempt);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        TimedOut
+            | Interrupted
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | UnexpectedEof
+            | NetworkDown
+            | NetworkUnreachable
+            | HostUnreachable
+    )
+}
+
+/// True for UNC paths and paths on a mounted network filesystem.
+pub fn is_network_path(path: &Path) -> bool {
+    is_unc(&to_posix_string(path)) || on_network_mount(path)
+}
+
+#[cfg(target_os = "linux")]
+fn on_network_mount(path: &Path) -> bool {
+    std::fs::read_to_string("/proc/self/mounts")
+        .map(|table| is_network_fs(mount_fs_type(path, &parse_proc_mounts(&table))))
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "macos")]
+fn on_network_mount(path: &Path) -> bool {
+    std::process::Command::new("mount")
+        .output()
+        .map(|output| {
+            let table = parse_mount_output(&String::from_utf8_lossy(&output.stdout));
+            is_network_fs(mount_fs_type(path, &table))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn on_network_mount(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Component;
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+
+    const DRIVE_REMOTE: u32 = 4;
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    let root: Vec<u16> = prefix
+        .as_os_str()
+        .encode_wide()
+        .chain("\\".encode_utf16())
+        .chain(Some(0))
+        .collect();
+    // SAFETY: `root` is a NUL-terminated UTF-16 string.
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn on_network_mount(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn on_rotational_disk(path: &Path) -> bool {
+    let Ok(table) = std::fs::read_to_string("/proc/self/mounts") else {
+        return false;
+    };
+    innermost_mount(path, &parse_proc_mounts(&table))
+        .and_then(|mount| block_device_rotational(&mount.device))
+        .unwrap_or(false)
+}
+
+/// Reads the kernel's rotational flag for a device such as `/dev/sda1`.
+#[cfg(target_os = "linux")]
+fn block_device_rotational(device: &str) -> Option<bool> {
+    use std::fs;
+
+    // `/dev/mapper/...` and `/dev/disk/by-*/...` link to the kernel name.
+    let name = fs::canonicalize(device).ok()?.file_name()?.to_owned();
+    let block = fs::canonicalize(Path::new("/sys/class/block").join(name)).ok()?;
+    // Partitions have no queue of their own; their disk is the parent.
+    [
+        block.join("queue/rotational"),
+        block.parent()?.join("queue/rotational"),
+    ]
+    .iter()
+    .find_map(|flag| fs::read_to_string(flag).ok())
+    .map(|flag| flag.trim() == "1")
+}
+
+#[cfg(target_os = "macos")]
+fn on_rotational_disk(path: &Path) -> bool {
+    use std::process::Command;
+
+    let Ok(output) = Command::new("mount").output() else {
+        return false;
+    };
+    let mounts = parse_mount_output(&String::from_utf8_lossy(&output.stdout));
+    let Some(mount) = innermost_mount(path, &mounts) else {
+        return false;
+    };
+    Command::new("diskutil")
+        .arg("info")
+        .arg(&mount.device)
+        .output()
+        .map(|output| parse_solid_state(&String::from_utf8_lossy(&output.stdout)) == Some(false))
+        .unwrap_or(false)
+}
+
+/// Reads `Solid State: Yes` from `diskutil info`.
+#[cfg(any(target_os = "macos", test))]
+fn parse_solid_state(info: &str) -> Option<bool> {
+    info.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("Solid State:")?.trim();
+        Some(value.eq_ignore_ascii_case("yes"))
+    })
+}
+
+#[cfg(windows)]
+fn on_rotational_disk(path: &Path) -> bool {
+    use std::ffi::c_void;
+    use std::mem::{size_of, zeroed};
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Component;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        PropertyStandardQuery, StorageDeviceSeekPenaltyProperty, DEVICE_SEEK_PENALTY_DESCRIPTOR,
+        IOCTL_STORAGE_QUERY_PROPERTY, STORAGE_PROPERTY_QUERY,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    // `\\.\C:` opens the volume; no access rights are needed to query it.
+    let device: Vec<u16> = "\\\\.\\"
+        .encode_utf16()
+        .chain(prefix.as_os_str().encode_wide())
+        .chain(Some(0))
+        .collect();
+    // SAFETY: `device` is NUL-terminated, the query and descriptor are
+    // passed with their sizes and the handle is closed before returning.
+    unsafe {
+        let handle = CreateFileW(
+            device.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            ptr::null(),
+            OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        let query = STORAGE_PROPERTY_QUERY {
+            PropertyId: StorageDeviceSeekPenaltyProperty,
+            QueryType: PropertyStandardQuery,
+            AdditionalParameters: [0],
+        };
+        let mut descriptor: DEVICE_SEEK_PENALTY_DESCRIPTOR = zeroed();
+        let mut returned = 0u32;
+        let ok = DeviceIoControl(
+            handle,
+            IOCTL_STORAGE_QUERY_PROPERTY,
+            &query as *const STORAGE_PROPERTY_QUERY as *const c_void,
+            size_of::<STORAGE_PROPERTY_QUERY>() as u32,
+            &mut descriptor as *mut DEVICE_SEEK_PENALTY_DESCRIPTOR as *mut c_void,
+            size_of::<DEVICE_SEEK_PENALTY_DESCRIPTOR>() as u32,
+            &mut returned,
+            ptr::null_mut(),
+        );
+        CloseHandle(handle);
+        ok != 0 && descriptor.IncursSeekPenalty != 0
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn on_rotational_disk(_path: &Path) -> bool {
+    false
+}
+
+fn is_network_fs(fs_type: Option<&str>) -> bool {
+    fs_type.is_some_and(|fs_type| NETWORK_FILESYSTEMS.contains(&fs_type))
+}
+
+/// One line of the mount table.
+#[derive(Debug)]
+struct Mount {
+    device: String,
+    point: PathBuf,
+    fs_type: String,
+}
+
+/// The innermost mount holding `path`.
+fn innermost_mount<'a>(path: &Path, mounts: &'a [Mount]) -> Option<&'a Mount> {
+    let path = clean_path(path);
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.point))
+        .max_by_key(|mount| mount.point.components().count())
+}
+
+/// The type of the innermost mount holding `path`.
+fn mount_fs_type<'a>(path: &Path, mounts: &'a [Mount]) -> Option<&'a str> {
+    innermost_mount(path, mounts).map(|mount| mount.fs_type.as_str())
+}
+
+/// Parses `/proc/self/mounts`, e.g. `nas:/photos /mnt/My\040Photos nfs4 rw 0 0`.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_mounts(table: &str) -> Vec<Mount> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            Some(Mount {
+                device: device.to_string(),
+                point: PathBuf::from(mount_point),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses macOS `mount`, e.g. `//me@nas/photos on /Volumes/photos (smbfs, nodev)`.
+#[cfg(any(target_os = "macos", test))]
+fn parse_mount_output(output: &str) -> Vec<Mount> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (device, rest) = line.split_once(" on ")?;
+            let (mount_point, options) = rest.rsplit_once(" (")?;
+            let fs_type = options.split([',', ')']).next()?.trim();
+            Some(Mount {
+                device: device.to_string(),
+                point: PathBuf::from(mount_point),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_mounts_are_matched_by_innermost_mount_point() {
+        let linux = parse_proc_mounts(
+            "/dev/sda1 / ext4 rw 0 0\nnas:/photos /mnt/My\\040Photos nfs4 rw 0 0\n",
+        );
+        assert_eq!(
+            mount_fs_type(Path::new("/mnt/My Photos/2021/a.jpg"), &linux),
+            Some("nfs4")
+        );
+        assert!(is_network_fs(mount_fs_type(
+            Path::new("/mnt/My Photos"),
+            &linux
+        )));
+        assert!(!is_network_fs(mount_fs_type(
+            Path::new("/home/me/Pictures"),
+            &linux
+        )));
+
+        let macos = parse_mount_output(
+            "/dev/disk3s1s1 on / (apfs, sealed, local, read-only)\n//me@nas/photos on /Volumes/photos (smbfs, nodev, nosuid, mounted by me)\n",
+        );
+        assert_eq!(
+            mount_fs_type(Path::new("/Volumes/photos/raw"), &macos),
+            Some("smbfs")
+        );
+        assert_eq!(mount_fs_type(Path::new("/Users/me"), &macos), Some("apfs"));
+
+        assert!(IoProfile::for_paths([Path::new(r"\\nas\photos")]).network);
+    }
+
+    #[test]
+    fn spinning_disks_hash_one_file_at_a_time() {
+        let info = "   Device Node:              /dev/disk4s2\n   Solid State:              No\n";
+        assert_eq!(parse_solid_state(info), Some(false));
+        assert_eq!(parse_solid_state("   Solid State:   Yes"), Some(true));
+
+        let hdd = IoOptions {
+            storage: Some(StorageKind::Hdd),
+            hash_threads: None,
+            ..IoOptions::default()
+        };
+        assert_eq!(IoProfile::configured([], &hdd).hash_threads(), Some(1));
+        let ssd = IoOptions {
+            storage: Some(StorageKind::Ssd),
+            hash_threads: Some(4),
+            ..IoOptions::default()
+        };
+        assert_eq!(IoProfile::configured([], &ssd).hash_threads(), Some(4));
+    }
+
+    #[test]
+    fn retries_only_transient_errors_on_shares() {
+        let network = IoProfile {
+            network: true,
+            ..IoProfile::default()
+        };
+        let mut calls = 0;
+        let result: io::Result<()> = network.retry(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result = IoProfile::default().retry(|| {
+            calls += 1;
+            Err::<(), _>(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+}
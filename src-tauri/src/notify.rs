@@ -0,0 +1,38 @@
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::error::{AppError, Result};
+use crate::progress::{Headline, TaskKind};
+
+/// Tells the user a long task ended, unless they are already looking at the
+/// app. Cancelled tasks are reported too, since the user may have cancelled
+/// from another window or the tray.
+pub fn task_finished<T: Headline>(app: &AppHandle, task: TaskKind, outcome: &Result<T>) {
+    let watching = app
+        .webview_windows()
+        .values()
+        .any(|window| window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false));
+    if watching {
+        return;
+    }
+
+    let name = match task {
+        TaskKind::Scan => "Scan",
+        TaskKind::Plan => "Planning",
+        TaskKind::Execute => "Execution",
+        TaskKind::Undo => "Undo",
+        TaskKind::Export => "Export",
+        TaskKind::Maintenance => "Maintenance",
+    };
+    let (title, body) = match outcome {
+        Ok(summary) => (format!("{name} finished"), summary.headline()),
+        Err(AppError::Cancelled) => (
+            format!("{name} cancelled"),
+            "Stopped at your request".into(),
+        ),
+        Err(err) => (format!("{name} failed"), err.to_string()),
+    };
+    if let Err(err) = app.notification().builder().title(title).body(body).show() {
+        tracing::debug!(error = ?err, "failed showing notification");
+    }
+}
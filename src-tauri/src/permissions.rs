@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::utils::path::to_posix_string;
+
+/// Folders macOS guards behind Full Disk Access or per-folder consent.
+/// Reading them without it fails with EPERM even though the Unix mode bits
+/// allow it, so the scan would silently find nothing.
+const PROTECTED_FOLDERS: [&str; 4] = ["Desktop", "Documents", "Downloads", "Pictures"];
+
+#[cfg(target_os = "macos")]
+const SETTINGS_URL: Option<&str> =
+    Some("x-apple.systempreferences:com.apple.preference.security?Privacy_AllFiles");
+#[cfg(not(target_os = "macos"))]
+const SETTINGS_URL: Option<&str> = None;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionReport {
+    pub ok: bool,
+    /// Folders that could not be listed.
+    pub denied: Vec<String>,
+    /// Where the user can grant access, when the platform has such a page.
+    pub settings_url: Option<&'static str>,
+}
+
+pub fn is_permission_denied(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied
+}
+
+/// Tries to list `source` and every protected folder it covers.
+pub fn check_permissions(source: &Path, home_dir: &Path) -> PermissionReport {
+    let mut probes = vec![source.to_path_buf()];
+    probes.extend(protected_folders_within(source, home_dir));
+
+    let denied: Vec<String> = probes
+        .iter()
+        .filter(|folder| {
+            fs::read_dir(folder)
+                .err()
+                .is_some_and(|err| is_permission_denied(&err))
+        })
+        .map(|folder| to_posix_string(folder).into_owned())
+        .collect();
+
+    PermissionReport {
+        ok: denied.is_empty(),
+        denied,
+        settings_url: SETTINGS_URL,
+    }
+}
+
+/// Protected folders a scan of `source` would walk into. A source inside
+/// one of them is covered by probing the source itself.
+fn protected_folders_within(source: &Path, home_dir: &Path) -> Vec<PathBuf> {
+    PROTECTED_FOLDERS
+        .iter()
+        .map(|name| home_dir.join(name))
+        .filter(|folder| folder != source && folder.starts_with(source))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_folders_are_probed_only_under_the_source() {
+        let home = Path::new("/Users/me");
+        assert_eq!(
+            protected_folders_within(home, home),
+            vec![
+                home.join("Desktop"),
+                home.join("Documents"),
+                home.join("Downloads"),
+                home.join("Pictures"),
+            ]
+        );
+        assert!(protected_folders_within(&home.join("Pictures"), home).is_empty());
+        assert!(protected_folders_within(Path::new("/Volumes/Photos"), home).is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageDecoder, ImageReader};
+
+/// BLAKE3 of a picture's decoded pixels as it is displayed, so copies that
+/// differ only in metadata or in a lossless rotation hash alike. Formats the
+/// decoder cannot read, and broken files, have no pixel hash.
+pub fn pixel_hash(path: &Path) -> Option<String> {
+    let decoded = (|| -> image::ImageResult<DynamicImage> {
+        let mut decoder = ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut picture = DynamicImage::from_decoder(decoder)?;
+        picture.apply_orientation(orientation);
+        Ok(picture)
+    })();
+    let picture = match decoded {
+        Ok(picture) => picture.into_rgb8(),
+        Err(err) => {
+            tracing::debug!(path = %path.display(), error = %err, "no pixel hash");
+            return None;
+        }
+    };
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&picture.width().to_le_bytes());
+    hasher.update(&picture.height().to_le_bytes());
+    hasher.update(picture.as_raw());
+    Some(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn pixel_hash_ignores_metadata_but_not_pixels() -> std::io::Result<()> {
+        let dir = tempdir()?;
+        let picture = RgbImage::from_fn(4, 3, |x, y| Rgb([x as u8 * 40, y as u8 * 60, 7]));
+        let plain = dir.path().join("plain.png");
+        picture
+            .save_with_format(&plain, ImageFormat::Png)
+            .map_err(std::io::Error::other)?;
+
+        // A text chunk before IEND changes the file but not the pixels.
+        let mut bytes = fs::read(&plain)?;
+        let iend = bytes.len() - 12;
+        let text = b"Commentedited";
+        let mut chunk = (text.len() as u32).to_be_bytes().to_vec();
+        chunk.extend(b"tEXt");
+        chunk.extend(text);
+        let mut crc_input = b"tEXt".to_vec();
+        crc_input.extend(text);
+        chunk.extend(crc32(&crc_input).to_be_bytes());
+        bytes.splice(iend..iend, chunk);
+        let tagged = dir.path().join("tagged.png");
+        fs::write(&tagged, &bytes)?;
+
+        let mut edited = picture.clone();
+        edited.put_pixel(0, 0, Rgb([255, 255, 255]));
+        let changed = dir.path().join("changed.png");
+        edited
+            .save_with_format(&changed, ImageFormat::Png)
+            .map_err(std::io::Error::other)?;
+
+        let hash = pixel_hash(&plain).expect("decodable");
+        assert_ne!(fs::read(&plain)?, fs::read(&tagged)?);
+        assert_eq!(pixel_hash(&tagged).as_deref(), Some(hash.as_str()));
+        assert_ne!(pixel_hash(&changed).as_deref(), Some(hash.as_str()));
+
+        fs::write(dir.path().join("broken.png"), b"not a png")?;
+        assert_eq!(pixel_hash(&dir.path().join("broken.png")), None);
+        Ok(())
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for byte in bytes {
+            crc ^= u32::from(*byte);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+}
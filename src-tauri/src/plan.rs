@@ -1,15 +1,31 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::config::AppConfig;
-use crate::db::{Database, NewPlanEntry};
-use crate::error::Result;
+use crate::config::{AppConfig, BucketGranularity, DuplicatesLayout, FolderLayout};
+use crate::db::{Database, DateSource, InventoryRecord, NewPlanEntry, PlanReason, PlanRoute};
+use crate::diff::{PlanDiff, PlannedTarget};
+use crate::error::{AppError, Result};
+use crate::exclusion::ExclusionSet;
+use crate::metrics::{MetricsRecorder, RunMetrics};
+use crate::progress::TaskControl;
+use crate::resolution::DuplicateDecisions;
+use crate::rules::{
+    compile_folder_template, compile_name_template, compile_targets, match_rule, RuleReport,
+    RuleTracker,
+};
+use crate::telemetry;
+use crate::template::{NameContext, NameTemplate, Template, TemplateContext};
 use crate::utils::json;
-use crate::utils::path::{ensure_trailing_separator, to_posix_string};
-use crate::utils::time::now_timestamp;
+use crate::utils::path::{
+    clean_path, ensure_trailing_separator, strip_copy_suffix, to_posix_string,
+};
+use crate::utils::time::{now_timestamp, NameTimestamp};
+use crate::volumes::VolumeAllocator;
 
 const PLAN_STAGE: &str = "plan";
 pub const PLAN_SCHEMA_VERSION: i32 = 1;
@@ -23,6 +39,8 @@ pub struct PlanProgressPayload {
     pub processed: usize,
     pub total: usize,
     pub current: Option<String>,
+    /// Set on the last payload of a cancelled run.
+    pub cancelled: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -35,6 +53,9 @@ pub struct PlanItem {
     pub new_file_name: String,
     pub new_path: String,
     pub is_duplicate: bool,
+    /// Spanned volume root the entry was assigned to, if volumes are in use.
+    pub volume: Option<String>,
+    pub reason: PlanReason,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -45,13 +66,94 @@ pub struct PlanSummary {
     pub duplicate_entries: usize,
     pub unique_entries: usize,
     pub destination_buckets: usize,
+    pub excluded_entries: usize,
+    /// Thumbnails and cache artifacts left out of the plan.
+    pub derivative_entries: usize,
+    /// Duplicates held back until their group is decided.
+    pub undecided_entries: usize,
     pub total_bytes: u64,
     pub plan_json_path: String,
+    pub rule_reports: Vec<RuleReport>,
     pub entries: Vec<PlanItem>,
+    pub metrics: RunMetrics,
 }
 
+/// A plan worked out under hypothetical settings and thrown away again.
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
+pub struct PlanPreview {
+    pub total_entries: usize,
+    pub duplicate_entries: usize,
+    pub unique_entries: usize,
+    pub destination_buckets: usize,
+    pub excluded_entries: usize,
+    pub derivative_entries: usize,
+    pub undecided_entries: usize,
+    pub total_bytes: u64,
+    pub rule_reports: Vec<RuleReport>,
+    /// The destination folders under each output root.
+    pub tree: Vec<BucketNode>,
+    pub entries: Vec<PlanItem>,
+}
+
+/// A destination folder and the files planned anywhere below it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketNode {
+    pub name: String,
+    /// POSIX-style, with a trailing separator like `PlanItem::new_path`.
+    pub path: String,
+    pub files: usize,
+    pub total_bytes: u64,
+    pub children: Vec<BucketNode>,
+}
+
+/// One decision the planner made for a file, in the order it made them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanStep {
+    /// `date`, `bucket`, `duplicate`, `rule`, `output`, `split`, `name` or
+    /// `collision`.
+    pub step: &'static str,
+    pub detail: String,
+}
+
+/// How the planner would place one inventory file, step by step.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileExplanation {
+    pub relative_path: String,
+    pub origin_full_path: String,
+    /// Why the file would be left out of the plan, if it would be.
+    pub left_out: Option<LeftOut>,
+    pub new_path: Option<String>,
+    pub new_file_name: Option<String>,
+    pub reason: Option<PlanReason>,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LeftOut {
+    Excluded,
+    /// A thumbnail or cache artifact, while derivatives are not planned.
+    Derivative,
+    /// In a duplicate group left to decide later.
+    Undecided,
+}
+
+/// A plan to compare: the stored one, the one it replaced, or a preview
+/// with the given plan settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PlanSource {
+    Current,
+    Previous,
+    Preview { overrides: Value },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct LegacyPlanItem {
     pub file_hash: String,
     pub file_size: u64,
@@ -61,17 +163,32 @@ struct LegacyPlanItem {
     pub new_path: String,
 }
 
+/// Routes the inventory and stores the plan. Nothing is stored until
+/// every file is routed, so a cancelled run keeps the previous plan.
 pub fn generate_plan(
     config: &AppConfig,
     database: &Database,
+    control: &TaskControl,
     emitter: PlanProgressEmitter,
 ) -> Result<PlanSummary> {
-    let inventory = database.inventory_snapshot()?;
+    let _run = tracing::info_span!("plan").entered();
+    let metrics = MetricsRecorder::new();
+    metrics.start("load");
+    let load = tracing::debug_span!("load").entered();
+    let mut inventory = database.inventory_snapshot()?;
+    inventory.sort_by(InventoryRecord::plan_order);
+    let undecided_entries = DuplicateDecisions::load(database)?
+        .apply(&mut inventory)
+        .len();
     let total = inventory.len();
 
     emit_progress(&emitter, 0, total, None);
 
     if inventory.is_empty() {
+        drop(load);
+        metrics.stop("load");
+        metrics.start("persist");
+        let _persist = tracing::debug_span!("persist").entered();
         database.replace_plan_entries(&[])?;
         database.set_meta("plan_entry_count", "0")?;
         database.set_meta("plan_schema_version", &PLAN_SCHEMA_VERSION.to_string())?;
@@ -79,7 +196,11 @@ pub fn generate_plan(
 
         let generated_at = now_timestamp()?;
         let plan_json_path = to_posix_string(&config.target_plan_path).into_owned();
-        json::write_json(&config.target_plan_path, &Vec::<LegacyPlanItem>::new())?;
+        write_plan_json(config, &[])?;
+        metrics.wrote(written_size(&config.target_plan_path));
+        metrics.stop("persist");
+        let metrics = metrics.finish();
+        telemetry::finish_run("plan", metrics.elapsed(), &config.telemetry);
 
         return Ok(PlanSummary {
             generated_at,
@@ -87,40 +208,146 @@ pub fn generate_plan(
             duplicate_entries: 0,
             unique_entries: 0,
             destination_buckets: 0,
+            excluded_entries: 0,
+            derivative_entries: 0,
+            undecided_entries,
             total_bytes: 0,
             plan_json_path,
+            rule_reports: RuleTracker::new(&config.plan.routing_rules).into_reports(),
             entries: Vec::new(),
+            metrics,
         });
     }
 
+    let exclusions = ExclusionSet::load(database)?;
+    drop(load);
+    metrics.stop("load");
+    metrics.start("route");
+    let route = tracing::debug_span!("route").entered();
+    let Routing {
+        items: plan_items,
+        entries: db_entries,
+        destination_buckets,
+        excluded_entries,
+        derivative_entries,
+        rule_reports,
+    } = route_inventory(config, &inventory, &exclusions, control, &emitter)?;
+
+    drop(route);
+    metrics.stop("route");
+    metrics.start("persist");
+    let persist = tracing::debug_span!("persist").entered();
+    database.replace_plan_entries(&db_entries)?;
+
+    let total_bytes: u64 = plan_items.iter().map(|item| item.file_size).sum();
+
+    let generated_at = now_timestamp()?;
+    database.set_meta("plan_generated_at", &generated_at)?;
+    database.set_meta("plan_entry_count", &plan_items.len().to_string())?;
+    database.set_meta("plan_schema_version", &PLAN_SCHEMA_VERSION.to_string())?;
+    database.set_meta("plan_total_bytes", &total_bytes.to_string())?;
+
+    let legacy: Vec<LegacyPlanItem> = plan_items
+        .iter()
+        .map(|item| LegacyPlanItem {
+            file_hash: item.file_hash.clone(),
+            file_size: item.file_size,
+            origin_file_name: item.origin_file_name.clone(),
+            origin_full_path: item.origin_full_path.clone(),
+            new_file_name: item.new_file_name.clone(),
+            new_path: item.new_path.clone(),
+        })
+        .collect();
+    write_plan_json(config, &legacy)?;
+    metrics.wrote(written_size(&config.target_plan_path));
+    metrics.stop("persist");
+    drop(persist);
+
+    let duplicate_entries = plan_items.iter().filter(|item| item.is_duplicate).count();
+    let plan_json_path = to_posix_string(&config.target_plan_path).into_owned();
+    let metrics = metrics.finish();
+    telemetry::finish_run("plan", metrics.elapsed(), &config.telemetry);
+
+    Ok(PlanSummary {
+        generated_at,
+        total_entries: plan_items.len(),
+        duplicate_entries,
+        unique_entries: plan_items.len().saturating_sub(duplicate_entries),
+        destination_buckets,
+        excluded_entries,
+        derivative_entries,
+        undecided_entries,
+        total_bytes,
+        plan_json_path,
+        rule_reports,
+        entries: plan_items,
+        metrics,
+    })
+}
+
+/// A plan routed in memory, before anything is stored.
+struct Routing {
+    items: Vec<PlanItem>,
+    entries: Vec<NewPlanEntry>,
+    destination_buckets: usize,
+    excluded_entries: usize,
+    derivative_entries: usize,
+    rule_reports: Vec<RuleReport>,
+}
+
+/// Routes `inventory`, already in plan order, without touching the
+/// database or the plan JSON.
+fn route_inventory(
+    config: &AppConfig,
+    inventory: &[InventoryRecord],
+    exclusions: &ExclusionSet,
+    control: &TaskControl,
+    emitter: &PlanProgressEmitter,
+) -> Result<Routing> {
     let root_dir = config
         .sample_image_root
         .as_ref()
         .unwrap_or(&config.image_root);
 
-    let mut used_targets: HashSet<String> = HashSet::new();
+    let mut excluded_entries = 0usize;
+    let mut derivative_entries = 0usize;
+    let mut rule_tracker = RuleTracker::new(&config.plan.routing_rules);
+    let total = inventory.len();
+    let mut router = Router::new(config)?;
+
     let mut destinations: HashSet<String> = HashSet::new();
     let mut plan_items = Vec::with_capacity(total);
     let mut db_entries = Vec::with_capacity(total);
 
     for (idx, record) in inventory.iter().enumerate() {
-        let timestamp = record.captured_at.as_deref().unwrap_or(&record.modified_at);
-        let date_bucket = bucket_from_timestamp(timestamp);
-
-        let mut target_dir = if record.is_duplicate {
-            config.duplicates_dir.clone()
-        } else {
-            config.output_root.join(date_bucket)
-        };
-        target_dir = ensure_trailing_separator(&target_dir);
-        let target_path_string = to_posix_string(&target_dir).into_owned();
-        destinations.insert(target_path_string.clone());
+        if let Err(err) = control.checkpoint() {
+            (emitter)(PlanProgressPayload {
+                stage: PLAN_STAGE,
+                processed: idx,
+                total,
+                current: None,
+                cancelled: true,
+            });
+            return Err(err);
+        }
+        if let Some(reason) = left_out(config, exclusions, record) {
+            match reason {
+                LeftOut::Excluded => excluded_entries += 1,
+                LeftOut::Derivative => derivative_entries += 1,
+                // Held back before routing, see `DuplicateDecisions`.
+                LeftOut::Undecided => {}
+            }
+            emit_progress(emitter, idx + 1, total, None);
+            continue;
+        }
 
-        let base_file_name = format!("{timestamp}.{}", record.file_name);
-        let unique_file_name =
-            reserve_target_name(&mut used_targets, &target_path_string, &base_file_name);
+        let routed = router.route(record, &mut Trace::off());
+        destinations.insert(routed.target_path.clone());
+        if let Some(order) = routed.rule {
+            rule_tracker.record(order, &record.relative_path, &routed.target_path);
+        }
 
-        let origin_full_path = join_origin(root_dir, &record.relative_path);
+        let origin_full_path = join_origin(&record.source_dir(root_dir), &record.relative_path);
         let origin_full_path_string = to_posix_string(&origin_full_path).into_owned();
 
         plan_items.push(PlanItem {
@@ -128,70 +355,687 @@ pub fn generate_plan(
             file_size: record.file_size,
             origin_file_name: record.file_name.clone(),
             origin_full_path: origin_full_path_string.clone(),
-            new_file_name: unique_file_name.clone(),
-            new_path: target_path_string.clone(),
+            new_file_name: routed.file_name.clone(),
+            new_path: routed.target_path.clone(),
             is_duplicate: record.is_duplicate,
+            volume: routed.volume.clone(),
+            reason: routed.reason.clone(),
         });
 
         db_entries.push(NewPlanEntry {
             file_hash: record.file_hash.clone(),
+            blake3_hash: record.blake3_hash.clone(),
             file_size: record.file_size,
             origin_file_name: record.file_name.clone(),
             origin_full_path: origin_full_path_string,
-            target_path: target_path_string.clone(),
-            target_file_name: unique_file_name,
+            target_path: routed.target_path,
+            target_file_name: routed.file_name,
             is_duplicate: record.is_duplicate,
+            volume_root: routed.volume,
+            strip_metadata: routed
+                .rule
+                .is_some_and(|order| config.plan.routing_rules[order].strip_metadata),
+            reason: Some(routed.reason),
         });
 
         emit_progress(
-            &emitter,
+            emitter,
             idx + 1,
             total,
             Some(to_posix_string(&origin_full_path).into_owned()),
         );
     }
 
-    database.replace_plan_entries(&db_entries)?;
+    Ok(Routing {
+        items: plan_items,
+        entries: db_entries,
+        destination_buckets: destinations.len(),
+        excluded_entries,
+        derivative_entries,
+        rule_reports: rule_tracker.into_reports(),
+    })
+}
 
-    let total_bytes: u64 = plan_items.iter().map(|item| item.file_size).sum();
+/// Plans the current inventory with `overrides` applied to the plan
+/// settings, keyed as in `config.json`, so layouts can be compared before
+/// one is saved. The stored plan and the plan JSON are left alone.
+pub fn preview_plan(
+    config: &AppConfig,
+    database: &Database,
+    overrides: &Value,
+) -> Result<PlanPreview> {
+    let mut config = config.clone();
+    config.plan = config.plan.with_overrides(overrides)?;
 
-    let generated_at = now_timestamp()?;
-    database.set_meta("plan_generated_at", &generated_at)?;
-    database.set_meta("plan_entry_count", &plan_items.len().to_string())?;
-    database.set_meta("plan_schema_version", &PLAN_SCHEMA_VERSION.to_string())?;
-    database.set_meta("plan_total_bytes", &total_bytes.to_string())?;
+    let mut inventory = database.inventory_snapshot()?;
+    inventory.sort_by(InventoryRecord::plan_order);
+    let undecided_entries = DuplicateDecisions::load(database)?
+        .apply(&mut inventory)
+        .len();
+    let exclusions = ExclusionSet::load(database)?;
+    let emitter: PlanProgressEmitter = Arc::new(|_| {});
+    let routing = route_inventory(
+        &config,
+        &inventory,
+        &exclusions,
+        &TaskControl::default(),
+        &emitter,
+    )?;
 
-    let legacy: Vec<LegacyPlanItem> = plan_items
+    let duplicate_entries = routing
+        .items
         .iter()
-        .map(|item| LegacyPlanItem {
-            file_hash: item.file_hash.clone(),
-            file_size: item.file_size,
-            origin_file_name: item.origin_file_name.clone(),
-            origin_full_path: item.origin_full_path.clone(),
-            new_file_name: item.new_file_name.clone(),
-            new_path: item.new_path.clone(),
-        })
-        .collect();
-    json::write_json(&config.target_plan_path, &legacy)?;
+        .filter(|item| item.is_duplicate)
+        .count();
+    Ok(PlanPreview {
+        total_entries: routing.items.len(),
+        duplicate_entries,
+        unique_entries: routing.items.len().saturating_sub(duplicate_entries),
+        destination_buckets: routing.destination_buckets,
+        excluded_entries: routing.excluded_entries,
+        derivative_entries: routing.derivative_entries,
+        undecided_entries,
+        total_bytes: routing.items.iter().map(|item| item.file_size).sum(),
+        rule_reports: routing.rule_reports,
+        tree: bucket_tree(&config, &routing.items),
+        entries: routing.items,
+    })
+}
+
+#[derive(Default)]
+struct TreeCounts {
+    files: usize,
+    total_bytes: u64,
+    children: BTreeMap<String, TreeCounts>,
+}
 
-    let duplicate_entries = inventory
+impl TreeCounts {
+    fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.total_bytes += size;
+    }
+
+    fn into_nodes(self, parent: &str) -> Vec<BucketNode> {
+        self.children
+            .into_iter()
+            .map(|(name, counts)| {
+                let path = format!("{parent}{name}/");
+                BucketNode {
+                    files: counts.files,
+                    total_bytes: counts.total_bytes,
+                    children: counts.into_nodes(&path),
+                    name,
+                    path,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Groups planned folders under the output root each one lies in; the
+/// roots are the top-level nodes.
+fn bucket_tree(config: &AppConfig, items: &[PlanItem]) -> Vec<BucketNode> {
+    let roots: Vec<String> = config
+        .output_roots()
         .iter()
-        .filter(|record| record.is_duplicate)
-        .count();
-    let plan_json_path = to_posix_string(&config.target_plan_path).into_owned();
+        .map(|root| to_posix_string(&ensure_trailing_separator(root)).into_owned())
+        .collect();
+    let mut tops: BTreeMap<String, TreeCounts> = BTreeMap::new();
+    for item in items {
+        let root = roots
+            .iter()
+            .filter(|root| item.new_path.starts_with(root.as_str()))
+            .max_by_key(|root| root.len())
+            .map_or("/", String::as_str);
+        let top = tops.entry(root.to_string()).or_default();
+        top.add(item.file_size);
+        let mut node = top;
+        for part in item.new_path[root.len()..]
+            .split('/')
+            .filter(|part| !part.is_empty())
+        {
+            node = node.children.entry(part.to_string()).or_default();
+            node.add(item.file_size);
+        }
+    }
+    tops.into_iter()
+        .map(|(path, counts)| BucketNode {
+            name: path.clone(),
+            files: counts.files,
+            total_bytes: counts.total_bytes,
+            children: counts.into_nodes(&path),
+            path,
+        })
+        .collect()
+}
 
-    Ok(PlanSummary {
-        generated_at,
-        total_entries: plan_items.len(),
-        duplicate_entries,
-        unique_entries: plan_items.len().saturating_sub(duplicate_entries),
-        destination_buckets: destinations.len(),
-        total_bytes,
-        plan_json_path,
-        entries: plan_items,
+/// Writes the plan JSON, keeping the one it replaces for `diff_plans`.
+fn write_plan_json(config: &AppConfig, items: &[LegacyPlanItem]) -> Result<()> {
+    if config.target_plan_path.exists() {
+        fs::rename(&config.target_plan_path, previous_plan_path(config))?;
+    }
+    json::write_json(&config.target_plan_path, &items)
+}
+
+/// `targetFileStructure.json` becomes `targetFileStructure.previous.json`.
+fn previous_plan_path(config: &AppConfig) -> PathBuf {
+    config.target_plan_path.with_extension("previous.json")
+}
+
+/// Compares where two plans send each file, so the effect of a settings
+/// change on an existing library shows before it is executed.
+pub fn diff_plans(
+    config: &AppConfig,
+    database: &Database,
+    plan_a: &PlanSource,
+    plan_b: &PlanSource,
+) -> Result<PlanDiff> {
+    Ok(PlanDiff::between(
+        planned_targets(config, database, plan_a)?,
+        planned_targets(config, database, plan_b)?,
+    ))
+}
+
+fn planned_targets(
+    config: &AppConfig,
+    database: &Database,
+    source: &PlanSource,
+) -> Result<Vec<PlannedTarget>> {
+    let planned = |origin: &str, dir: &str, name: &str| PlannedTarget {
+        origin: origin.to_string(),
+        target: format!("{dir}{name}"),
+    };
+    Ok(match source {
+        PlanSource::Current => database
+            .plan_entries()?
+            .iter()
+            .map(|entry| {
+                planned(
+                    &entry.origin_full_path,
+                    &entry.target_path,
+                    &entry.target_file_name,
+                )
+            })
+            .collect(),
+        PlanSource::Previous => {
+            let path = previous_plan_path(config);
+            if !path.exists() {
+                return Err(AppError::Config(
+                    "there is no previous plan to compare with yet".into(),
+                ));
+            }
+            let items: Vec<LegacyPlanItem> = json::read_json(&path)?;
+            items
+                .iter()
+                .map(|item| planned(&item.origin_full_path, &item.new_path, &item.new_file_name))
+                .collect()
+        }
+        PlanSource::Preview { overrides } => preview_plan(config, database, overrides)?
+            .entries
+            .iter()
+            .map(|item| planned(&item.origin_full_path, &item.new_path, &item.new_file_name))
+            .collect(),
     })
 }
 
+/// Size of the plan JSON just written; the database writes are not counted.
+fn written_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+fn left_out(
+    config: &AppConfig,
+    exclusions: &ExclusionSet,
+    record: &InventoryRecord,
+) -> Option<LeftOut> {
+    if exclusions.is_excluded(record) {
+        Some(LeftOut::Excluded)
+    } else if record.derivative.is_some() && !config.plan.include_derivatives {
+        Some(LeftOut::Derivative)
+    } else {
+        None
+    }
+}
+
+/// Routing state carried from one record to the next: the names taken so
+/// far, how full each folder is and which volume still has room.
+struct Router<'a> {
+    config: &'a AppConfig,
+    rule_targets: Vec<Template>,
+    folder_template: Option<Template>,
+    volumes: Option<VolumeAllocator>,
+    name_timestamp: NameTimestamp,
+    name_template: Option<NameTemplate>,
+    used_targets: HashSet<String>,
+    /// Files named into each target folder so far, for `{seq}`.
+    folder_seq: HashMap<String, usize>,
+    bucket_fill: HashMap<PathBuf, usize>,
+}
+
+/// Where one record goes.
+struct Routed {
+    /// Output folder, POSIX-style with a trailing separator.
+    target_path: String,
+    file_name: String,
+    volume: Option<String>,
+    /// Order of the routing rule that placed the record.
+    rule: Option<usize>,
+    reason: PlanReason,
+}
+
+/// Collects the steps of a routing decision when explaining one file, and
+/// nothing while a whole plan is generated.
+struct Trace(Option<Vec<PlanStep>>);
+
+impl Trace {
+    fn off() -> Self {
+        Self(None)
+    }
+
+    fn on() -> Self {
+        Self(Some(Vec::new()))
+    }
+
+    fn note(&mut self, step: &'static str, detail: impl FnOnce() -> String) {
+        if let Some(steps) = &mut self.0 {
+            steps.push(PlanStep {
+                step,
+                detail: detail(),
+            });
+        }
+    }
+}
+
+impl<'a> Router<'a> {
+    fn new(config: &'a AppConfig) -> Result<Self> {
+        Ok(Self {
+            config,
+            rule_targets: compile_targets(&config.plan.routing_rules)?,
+            folder_template: compile_folder_template(config.plan.folder_template.as_deref())?,
+            volumes: VolumeAllocator::from_config(config)?,
+            name_timestamp: NameTimestamp::parse(&config.plan.name_timestamp)?,
+            name_template: compile_name_template(config.plan.name_template.as_deref())?,
+            used_targets: HashSet::new(),
+            folder_seq: HashMap::new(),
+            bucket_fill: HashMap::new(),
+        })
+    }
+
+    /// Places `record` after everything routed before it.
+    fn route(&mut self, record: &InventoryRecord, trace: &mut Trace) -> Routed {
+        let config = self.config;
+        let rules = &config.plan.routing_rules;
+        let timestamp = record.timestamp();
+        let date_source = record.date_source();
+        trace.note("date", || match date_source {
+            DateSource::Captured => format!("{timestamp} from the capture time"),
+            DateSource::Modified => {
+                format!("{timestamp} from the modification time; there is no capture time")
+            }
+        });
+        let granularity = config
+            .plan
+            .granularity_for(timestamp.get(..4).and_then(|year| year.parse().ok()));
+        let templated = self
+            .folder_template
+            .as_ref()
+            .and_then(|template| render_template(template, record, config));
+        let date_bucket = match (&templated, config.plan.folder_layout) {
+            (Some(dir), _) => dir.clone(),
+            (None, FolderLayout::Original) => original_folder(record),
+            (None, layout) => bucket_from_timestamp(timestamp, layout, granularity),
+        };
+        trace.note("bucket", || {
+            let dir = to_posix_string(&date_bucket);
+            let by_layout = match config.plan.folder_layout {
+                FolderLayout::Original => format!("{dir} where it was found"),
+                layout => format!("{dir} for the {layout:?} layout by {granularity:?}"),
+            };
+            match &self.folder_template {
+                Some(template) if templated.is_some() => {
+                    format!("{dir} from the folder template {template}")
+                }
+                Some(template) => format!("{by_layout}; {template} rendered an empty segment"),
+                None => by_layout,
+            }
+        });
+
+        let matched = if record.is_duplicate {
+            trace.note("duplicate", || match record.duplicate_confirmation {
+                Some(confirmation) => format!(
+                    "same content as an earlier file, confirmed by {}; rules are not applied",
+                    confirmation.as_str()
+                ),
+                None => "same content as an earlier file; rules are not applied".into(),
+            });
+            None
+        } else {
+            match_rule(rules, record).map(|(order, _)| order)
+        };
+        let matched_rule = matched.and_then(|order| {
+            render_template(&self.rule_targets[order], record, config).map(|dir| (order, dir))
+        });
+        let route = if record.is_duplicate {
+            PlanRoute::Duplicate {
+                confirmation: record.duplicate_confirmation,
+            }
+        } else if let Some((order, dir)) = &matched_rule {
+            let rule = &rules[*order];
+            trace.note("rule", || {
+                format!(
+                    "rule \"{}\" matched; {} renders {}",
+                    rule.name,
+                    rule.target,
+                    to_posix_string(dir)
+                )
+            });
+            PlanRoute::Rule {
+                name: rule.name.clone(),
+                template: rule.target.clone(),
+            }
+        } else {
+            match matched {
+                Some(order) => trace.note("rule", || {
+                    format!(
+                        "rule \"{}\" matched but {} rendered an empty segment; the date bucket is used",
+                        rules[order].name, rules[order].target
+                    )
+                }),
+                None if !rules.is_empty() => {
+                    trace.note("rule", || "no routing rule matched".into())
+                }
+                None => {}
+            }
+            PlanRoute::DateBucket {
+                layout: config.plan.folder_layout,
+                template: templated
+                    .is_some()
+                    .then(|| config.plan.folder_template.clone())
+                    .flatten(),
+                granularity,
+                skipped_rule: matched.map(|order| rules[order].name.clone()),
+            }
+        };
+
+        let relative_dir = match &matched_rule {
+            Some((_, rule_dir)) => rule_dir.clone(),
+            None => date_bucket,
+        };
+        let kind = record.media_type;
+        let mut volume = None;
+        // The root files go under and their folder below it, kept apart
+        // so overflow folders stay under the root.
+        let (bucket_root, bucket) = if record.is_duplicate {
+            let subfolder =
+                duplicate_subfolder(config.plan.duplicates_layout, record, &relative_dir);
+            trace.note("output", || {
+                format!(
+                    "duplicates folder {}",
+                    to_posix_string(&config.duplicates_dir.join(&subfolder))
+                )
+            });
+            (config.duplicates_dir.clone(), subfolder)
+        } else if let (None, Some(allocator)) =
+            (config.explicit_output_root(kind), self.volumes.as_mut())
+        {
+            let idx = allocator.assign(&relative_dir, record.file_size);
+            let root = allocator.root(idx);
+            trace.note("output", || {
+                format!("volume {} has room for it", to_posix_string(root))
+            });
+            volume = Some(to_posix_string(root).into_owned());
+            (root.to_path_buf(), relative_dir)
+        } else {
+            let root = config.output_root_for(kind);
+            trace.note("output", || {
+                format!(
+                    "output root {} for {} files",
+                    to_posix_string(&root),
+                    kind.as_str()
+                )
+            });
+            (root, relative_dir)
+        };
+        let mut target_dir = bucket_root.join(&bucket);
+        let mut split_part = None;
+        if let Some(limit) = config.plan.max_files_per_folder.filter(|limit| *limit > 0) {
+            let filled = self.bucket_fill.entry(target_dir.clone()).or_insert(0);
+            let part = *filled / limit;
+            *filled += 1;
+            if part > 0 {
+                let held = part * limit;
+                trace.note("split", || {
+                    format!(
+                        "{} already holds {held} files, over the limit of {limit}; part {}",
+                        to_posix_string(&target_dir),
+                        part + 1
+                    )
+                });
+                target_dir = bucket_root.join(split_bucket(&bucket, part + 1));
+                split_part = Some(part + 1);
+            }
+        }
+        target_dir = ensure_trailing_separator(&target_dir);
+        let target_path = to_posix_string(&target_dir).into_owned();
+
+        let base_file_name = if config.plan.keep_original_names {
+            trace.note("name", || format!("{}, kept as found", record.file_name));
+            record.file_name.clone()
+        } else {
+            let stripped = strip_copy_suffix(&record.file_name);
+            let origin_name = stripped.clone().unwrap_or_else(|| record.file_name.clone());
+            let name_timestamp = self.name_timestamp.render(timestamp);
+            let templated = self.name_template.as_ref().and_then(|template| {
+                let seq = self.folder_seq.entry(target_path.clone()).or_insert(0);
+                *seq += 1;
+                let context = NameContext {
+                    record,
+                    original: &origin_name,
+                    timestamp: &name_timestamp,
+                    seq: *seq,
+                };
+                match template.render(&context) {
+                    Ok(name) => Some(name),
+                    Err(err) => {
+                        tracing::warn!(template = %template, path = %record.relative_path, error = %err, "name template rendered an empty name");
+                        None
+                    }
+                }
+            });
+            let base_file_name =
+                templated.unwrap_or_else(|| format!("{name_timestamp}.{origin_name}"));
+            trace.note("name", || {
+                let named = match &self.name_template {
+                    Some(template) => format!("{base_file_name} from the name template {template}"),
+                    None => base_file_name.clone(),
+                };
+                match &stripped {
+                    Some(_) => format!(
+                        "{named}, dropping the copy suffix from {}",
+                        record.file_name
+                    ),
+                    None => named,
+                }
+            });
+            base_file_name
+        };
+        let base_file_name = if config.plan.normalize_extensions {
+            let normalized = normalize_extension(&base_file_name);
+            if normalized != base_file_name {
+                trace.note("name", || {
+                    format!("{normalized}, with the extension normalized")
+                });
+            }
+            normalized
+        } else {
+            base_file_name
+        };
+        let file_name = reserve_target_name(&mut self.used_targets, &target_path, &base_file_name);
+        if file_name != base_file_name {
+            trace.note("collision", || {
+                format!("{base_file_name} is taken by an earlier file; {file_name} instead")
+            });
+        }
+
+        Routed {
+            target_path,
+            file_name,
+            volume,
+            rule: matched_rule.map(|(order, _)| order),
+            reason: PlanReason {
+                route,
+                date_source,
+                split_part,
+            },
+        }
+    }
+}
+
+/// Where a duplicate goes below the duplicates folder; `relative_dir` is
+/// its date bucket, as duplicates skip the rules.
+fn duplicate_subfolder(
+    layout: DuplicatesLayout,
+    record: &InventoryRecord,
+    relative_dir: &Path,
+) -> PathBuf {
+    match layout {
+        DuplicatesLayout::Flat => PathBuf::new(),
+        DuplicatesLayout::Original => original_folder(record),
+        DuplicatesLayout::Bucket => relative_dir.to_path_buf(),
+    }
+}
+
+/// The folder `record` was found in, relative to the image root.
+fn original_folder(record: &InventoryRecord) -> PathBuf {
+    Path::new(&record.relative_path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// Runs the planner up to one file, found by its path (absolute or below
+/// the image root) or by its MD5 or BLAKE3 hash, and reports each decision
+/// made for it. Files before it are routed too, since they take names and
+/// folder space first; nothing is stored. A hash shared by duplicates
+/// finds the first of them.
+pub fn explain_file(
+    config: &AppConfig,
+    database: &Database,
+    path_or_hash: &str,
+) -> Result<FileExplanation> {
+    let root_dir = config
+        .sample_image_root
+        .as_ref()
+        .unwrap_or(&config.image_root);
+    let query = path_or_hash.trim();
+    let query_path = to_posix_string(&clean_path(&root_dir.join(query))).into_owned();
+
+    let mut inventory = database.inventory_snapshot()?;
+    inventory.sort_by(InventoryRecord::plan_order);
+    let held = DuplicateDecisions::load(database)?.apply(&mut inventory);
+    let matches = |record: &InventoryRecord| {
+        record.file_hash == query
+            || record.blake3_hash.as_deref() == Some(query)
+            || record.relative_path == query
+            || to_posix_string(&join_origin(
+                &record.source_dir(root_dir),
+                &record.relative_path,
+            )) == query_path
+    };
+    let position = inventory.iter().position(matches);
+    let record = match position {
+        Some(position) => &inventory[position],
+        None => held.iter().find(|record| matches(record)).ok_or_else(|| {
+            AppError::Config(format!("{query} is not in the inventory; run a scan first"))
+        })?,
+    };
+    let mut explanation = FileExplanation {
+        relative_path: record.relative_path.clone(),
+        origin_full_path: to_posix_string(&join_origin(
+            &record.source_dir(root_dir),
+            &record.relative_path,
+        ))
+        .into_owned(),
+        left_out: None,
+        new_path: None,
+        new_file_name: None,
+        reason: None,
+        steps: Vec::new(),
+    };
+
+    let Some(position) = position else {
+        explanation.left_out = Some(LeftOut::Undecided);
+        return Ok(explanation);
+    };
+    let exclusions = ExclusionSet::load(database)?;
+    if let Some(reason) = left_out(config, &exclusions, record) {
+        explanation.left_out = Some(reason);
+        return Ok(explanation);
+    }
+
+    let mut router = Router::new(config)?;
+    for earlier in &inventory[..position] {
+        if left_out(config, &exclusions, earlier).is_none() {
+            router.route(earlier, &mut Trace::off());
+        }
+    }
+    let mut trace = Trace::on();
+    let routed = router.route(record, &mut trace);
+    explanation.new_path = Some(routed.target_path);
+    explanation.new_file_name = Some(routed.file_name);
+    explanation.reason = Some(routed.reason);
+    explanation.steps = trace.0.unwrap_or_default();
+    Ok(explanation)
+}
+
+/// Evaluates the routing rules against the current inventory without touching
+/// the stored plan, so rule edits can be checked before re-planning.
+pub fn explain_routing_rules(config: &AppConfig, database: &Database) -> Result<Vec<RuleReport>> {
+    let exclusions = ExclusionSet::load(database)?;
+    let mut tracker = RuleTracker::new(&config.plan.routing_rules);
+    let rule_targets = compile_targets(&config.plan.routing_rules)?;
+
+    let mut inventory = database.inventory_snapshot()?;
+    inventory.sort_by(InventoryRecord::plan_order);
+
+    for record in inventory {
+        if record.is_duplicate || exclusions.is_excluded(&record) {
+            continue;
+        }
+        let Some((order, _)) = match_rule(&config.plan.routing_rules, &record) else {
+            continue;
+        };
+        if let Some(rule_dir) = render_template(&rule_targets[order], &record, config) {
+            let output_root = config.output_root_for(record.media_type);
+            let target_dir = ensure_trailing_separator(&output_root.join(rule_dir));
+            tracker.record(order, &record.relative_path, &to_posix_string(&target_dir));
+        }
+    }
+
+    Ok(tracker.into_reports())
+}
+
+/// Renders a matched rule's target or the folder template. A record whose
+/// metadata renders an empty segment falls back to its date bucket rather
+/// than a broken path.
+fn render_template(
+    template: &Template,
+    record: &InventoryRecord,
+    config: &AppConfig,
+) -> Option<PathBuf> {
+    let context = TemplateContext {
+        record,
+        artist_fallback: &config.plan.artist_fallback,
+    };
+    match template.render(&context) {
+        Ok(dir) => Some(dir),
+        Err(err) => {
+            tracing::warn!(template = %template, path = %record.relative_path, error = %err, "template rendered an empty segment");
+            None
+        }
+    }
+}
+
 fn emit_progress(
     emitter: &PlanProgressEmitter,
     processed: usize,
@@ -203,12 +1047,66 @@ fn emit_progress(
         processed,
         total,
         current,
+        cancelled: false,
     };
     (emitter)(payload);
 }
 
-fn bucket_from_timestamp(timestamp: &str) -> &str {
-    timestamp.split('_').next().unwrap_or(timestamp)
+fn bucket_from_timestamp(
+    timestamp: &str,
+    layout: FolderLayout,
+    granularity: BucketGranularity,
+) -> PathBuf {
+    let date = timestamp.split('_').next().unwrap_or(timestamp);
+    let Some(parsed) = parse_bucket_date(date) else {
+        return PathBuf::from(date);
+    };
+    let year = format!("{:04}", parsed.year());
+    let month = format!("{:02}", u8::from(parsed.month()));
+    let day = format!("{:02}", parsed.day());
+    let (week_year, week, _) = parsed.to_iso_week_date();
+    let week_year = format!("{week_year:04}");
+    let week = format!("W{week:02}");
+
+    match (layout, granularity) {
+        // Placed by `original_folder` instead; the day keeps it harmless.
+        (FolderLayout::Original, _) => PathBuf::from(date),
+        (_, BucketGranularity::Year) => PathBuf::from(year),
+        (FolderLayout::Single, _) => PathBuf::new(),
+        (FolderLayout::Flat, BucketGranularity::Day) => PathBuf::from(date),
+        (FolderLayout::Flat, BucketGranularity::Week) => {
+            PathBuf::from(format!("{week_year}-{week}"))
+        }
+        (FolderLayout::Flat, BucketGranularity::Month) => PathBuf::from(format!("{year}-{month}")),
+        (FolderLayout::YearMonthDay, BucketGranularity::Day) => [year, month, day].iter().collect(),
+        (FolderLayout::YearMonthDay, BucketGranularity::Week) => {
+            PathBuf::from(week_year).join(week)
+        }
+        (FolderLayout::YearMonthDay, BucketGranularity::Month) => PathBuf::from(year).join(month),
+        (FolderLayout::YearMonth, BucketGranularity::Week) => {
+            PathBuf::from(&week_year).join(format!("{week_year}-{week}"))
+        }
+        (FolderLayout::YearMonth, BucketGranularity::Day | BucketGranularity::Month) => {
+            PathBuf::from(&year).join(format!("{year}-{month}"))
+        }
+    }
+}
+
+fn parse_bucket_date(date: &str) -> Option<time::Date> {
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = time::Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    let day = parts.next()?.parse().ok()?;
+    time::Date::from_calendar_date(year, month, day).ok()
+}
+
+/// Names the overflow folder for a bucket: `2024-06-14` → `2024-06-14_part2`,
+/// or `part2` for files that go straight into the root.
+fn split_bucket(dir: &Path, part: usize) -> PathBuf {
+    match dir.file_name().and_then(|name| name.to_str()) {
+        Some(name) => dir.with_file_name(format!("{name}_part{part}")),
+        None => dir.join(format!("part{part}")),
+    }
 }
 
 fn join_origin(root: &Path, relative: &str) -> PathBuf {
@@ -232,6 +1130,22 @@ fn reserve_target_name(used: &mut HashSet<String>, path: &str, base_name: &str)
     }
 }
 
+/// Lowercases the extension of `name` and settles spelling variants on one,
+/// so `.JPEG` and `.jpg` files end up alike.
+fn normalize_extension(name: &str) -> String {
+    let Some((stem, ext)) = name.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()) else {
+        return name.to_string();
+    };
+    let ext = ext.to_lowercase();
+    let ext = match ext.as_str() {
+        "jpeg" | "jpe" => "jpg",
+        "tiff" => "tif",
+        "mpeg" => "mpg",
+        other => other,
+    };
+    format!("{stem}.{ext}")
+}
+
 fn add_duplicate_suffix(name: &str, attempt: usize) -> String {
     let suffix = format!("_dup{attempt}");
     match name.rsplit_once('.') {
@@ -243,79 +1157,112 @@ fn add_duplicate_suffix(name: &str, attempt: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::SCHEMA_VERSION;
-    use crate::db::{InventoryRecord, PlanStatus};
+    use crate::config::test_config;
+    use crate::db::PlanStatus;
+    use crate::media::MediaKind;
+    use parking_lot::Mutex;
     use std::collections::HashSet as StdHashSet;
     use std::fs;
     use tempfile::tempdir;
 
-    #[allow(deprecated)]
-    #[test]
-    fn generate_plan_builds_entries_and_persists_json() -> Result<()> {
-        let root_dir = tempdir()?.into_path();
-        let output_dir = tempdir()?.into_path();
-        let duplicates_dir = output_dir.join("duplicates");
-        fs::create_dir_all(&duplicates_dir)?;
-
-        let db_path = output_dir.join("plan.sqlite3");
-        let config = crate::config::AppConfig {
-            schema_version: SCHEMA_VERSION,
-            home_dir: root_dir.clone(),
-            app_data_dir: output_dir.clone(),
-            database_path: db_path.clone(),
-            image_root: root_dir.clone(),
-            image_root_default_name: "images".into(),
-            output_root: output_dir.clone(),
-            output_root_name: "output".into(),
-            duplicates_dir: duplicates_dir.clone(),
-            duplicates_folder_name: "duplicates".into(),
-            origin_info_path: output_dir.join("origin.json"),
-            target_plan_path: output_dir.join("plan.json"),
-            image_exts: StdHashSet::from([".jpg".into()]),
-            config_file_path: root_dir.join("config.json"),
-            sample_image_root: None,
-        };
+    struct Fixture {
+        config: AppConfig,
+        database: Database,
+        records: Vec<InventoryRecord>,
+    }
 
-        let database = Database::initialize(&config)?;
-        let records = vec![
-            InventoryRecord {
-                id: None,
-                file_hash: "hash-1".into(),
-                blake3_hash: None,
-                file_size: 100,
-                file_name: "IMG_0001.JPG".into(),
-                relative_path: "A/IMG_0001.JPG".into(),
-                captured_at: Some("2024-01-02_10-00-00".into()),
-                modified_at: "2024-01-02_10-00-00".into(),
-                exif_model: None,
-                exif_make: None,
-                exif_artist: None,
-                is_duplicate: false,
-            },
+    impl Fixture {
+        fn new() -> Result<Self> {
+            let config = test_config(&tempdir()?.keep());
+            fs::create_dir_all(&config.duplicates_dir)?;
+
+            let database = Database::initialize(&config)?;
+            let records = vec![
+                InventoryRecord {
+                    id: None,
+                    file_hash: "hash-1".into(),
+                    blake3_hash: None,
+                    file_size: 100,
+                    file_name: "IMG_0001.JPG".into(),
+                    relative_path: "A/IMG_0001.JPG".into(),
+                    source_root: None,
+                    media_type: MediaKind::Photo,
+                    captured_at: Some("2024-01-02_10-00-00".into()),
+                    modified_at: "2024-01-02_10-00-00".into(),
+                    exif_model: None,
+                    exif_make: None,
+                    exif_artist: None,
+                    rating: None,
+                    tags: Vec::new(),
+                    gps_latitude: None,
+                    gps_longitude: None,
+                    derivative: None,
+                    is_duplicate: false,
+                    duplicate_confirmation: None,
+                    pixel_hash: None,
+                },
+                InventoryRecord {
+                    id: None,
+                    file_hash: "hash-2".into(),
+                    blake3_hash: None,
+                    file_size: 100,
+                    file_name: "IMG_0001.JPG".into(),
+                    relative_path: "B/IMG_0001.JPG".into(),
+                    source_root: None,
+                    media_type: MediaKind::Photo,
+                    captured_at: Some("2024-01-02_10-00-00".into()),
+                    modified_at: "2024-01-02_10-00-00".into(),
+                    exif_model: None,
+                    exif_make: None,
+                    exif_artist: None,
+                    rating: None,
+                    tags: Vec::new(),
+                    gps_latitude: None,
+                    gps_longitude: None,
+                    derivative: None,
+                    is_duplicate: true,
+                    duplicate_confirmation: None,
+                    pixel_hash: None,
+                },
+            ];
+            database.replace_inventory(&records)?;
+
+            Ok(Self {
+                config,
+                database,
+                records,
+            })
+        }
+
+        fn unique_record(&self, hash: &str, relative_path: &str) -> InventoryRecord {
             InventoryRecord {
-                id: None,
-                file_hash: "hash-2".into(),
-                blake3_hash: None,
-                file_size: 100,
-                file_name: "IMG_0001.JPG".into(),
-                relative_path: "B/IMG_0001.JPG".into(),
-                captured_at: Some("2024-01-02_10-00-00".into()),
-                modified_at: "2024-01-02_10-00-00".into(),
-                exif_model: None,
-                exif_make: None,
-                exif_artist: None,
-                is_duplicate: true,
-            },
-        ];
-        database.replace_inventory(&records)?;
+                file_hash: hash.into(),
+                file_name: relative_path.rsplit('/').next().unwrap_or_default().into(),
+                relative_path: relative_path.into(),
+                source_root: None,
+                media_type: self.config.media_exts.classify(relative_path),
+                ..self.records[0].clone()
+            }
+        }
 
-        let emitter: PlanProgressEmitter = Arc::new(|_| {});
-        let summary = generate_plan(&config, &database, emitter)?;
+        fn plan(&self, config: &AppConfig) -> Result<PlanSummary> {
+            let emitter: PlanProgressEmitter = Arc::new(|_| {});
+            generate_plan(config, &self.database, &TaskControl::default(), emitter)
+        }
+    }
+
+    #[test]
+    fn generate_plan_builds_entries_and_persists_json() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let config = &fixture.config;
+        let database = &fixture.database;
+
+        let summary = fixture.plan(config)?;
         assert_eq!(summary.total_bytes, 200);
 
         assert_eq!(summary.total_entries, 2);
         assert_eq!(summary.duplicate_entries, 1);
-        assert_eq!(summary.destination_buckets >= 1, true);
+        assert!(summary.destination_buckets >= 1);
         assert!(summary.entries.iter().any(|item| item.is_duplicate));
 
         let stored = database.plan_entries()?;
@@ -329,4 +1276,614 @@ mod tests {
         assert!(json_contents.contains("2024-01-02"));
         Ok(())
     }
+
+    #[test]
+    fn cancelled_plans_keep_the_previous_plan() -> Result<()> {
+        let fixture = Fixture::new()?;
+        fixture.plan(&fixture.config)?;
+
+        let control = TaskControl::default();
+        control.cancel();
+        let payloads = Arc::new(Mutex::new(Vec::new()));
+        let emitter: PlanProgressEmitter = {
+            let payloads = Arc::clone(&payloads);
+            Arc::new(move |payload| payloads.lock().push(payload))
+        };
+        let result = generate_plan(&fixture.config, &fixture.database, &control, emitter);
+        assert!(matches!(result, Err(AppError::Cancelled)));
+        assert!(payloads
+            .lock()
+            .last()
+            .is_some_and(|payload| payload.cancelled));
+        assert_eq!(fixture.database.plan_entries()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn excluded_records_are_not_planned() -> Result<()> {
+        let fixture = Fixture::new()?;
+        fixture
+            .database
+            .add_exclusion(crate::db::ExclusionKind::Pattern, "B/**", None)?;
+
+        let summary = fixture.plan(&fixture.config)?;
+        assert_eq!(summary.total_entries, 1);
+        assert_eq!(summary.excluded_entries, 1);
+        assert_eq!(fixture.database.inventory_snapshot()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn derivatives_are_left_out_unless_included() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut records = fixture.records.clone();
+        records[0].derivative = Some(crate::db::DerivativeReason::CachePath);
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&fixture.config)?;
+        assert_eq!(summary.total_entries, 1);
+        assert_eq!(summary.derivative_entries, 1);
+
+        let mut config = fixture.config.clone();
+        config.plan.include_derivatives = true;
+        let summary = fixture.plan(&config)?;
+        assert_eq!(summary.total_entries, 2);
+        assert_eq!(summary.derivative_entries, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn routing_rules_override_date_buckets() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.routing_rules = vec![crate::config::RoutingRule {
+            name: "a-folder".into(),
+            path_contains: Some("A/".into()),
+            extensions: Vec::new(),
+            target: "from-a/{year}".into(),
+            strip_metadata: false,
+        }];
+
+        let summary = fixture.plan(&config)?;
+        assert_eq!(summary.rule_reports[0].matched, 1);
+        let routed = summary
+            .entries
+            .iter()
+            .find(|item| item.new_path.ends_with("from-a/2024/"))
+            .expect("routed entry");
+        assert_eq!(
+            routed.reason.route,
+            PlanRoute::Rule {
+                name: "a-folder".into(),
+                template: "from-a/{year}".into(),
+            }
+        );
+        assert_eq!(routed.reason.date_source, crate::db::DateSource::Captured);
+
+        let stored = fixture.database.plan_entries()?;
+        let duplicate = stored.iter().find(|entry| entry.is_duplicate).unwrap();
+        assert!(matches!(
+            duplicate.reason.as_ref().map(|reason| &reason.route),
+            Some(PlanRoute::Duplicate { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn explaining_a_file_replays_earlier_collisions() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/IMG_0001.JPG"));
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&fixture.config)?;
+        let planned = summary
+            .entries
+            .iter()
+            .find(|item| item.file_hash == "hash-3")
+            .expect("planned entry");
+        fixture.database.replace_plan_entries(&[])?;
+
+        let explanation = explain_file(&fixture.config, &fixture.database, "C/IMG_0001.JPG")?;
+        assert_eq!(explanation.new_path.as_ref(), Some(&planned.new_path));
+        assert_eq!(
+            explanation.new_file_name.as_ref(),
+            Some(&planned.new_file_name)
+        );
+        let steps: Vec<_> = explanation.steps.iter().map(|step| step.step).collect();
+        assert_eq!(steps, ["date", "bucket", "output", "name", "collision"]);
+        assert!(fixture.database.plan_entries()?.is_empty());
+
+        let by_hash = explain_file(&fixture.config, &fixture.database, "hash-2")?;
+        assert!(by_hash.steps.iter().any(|step| step.step == "duplicate"));
+        assert!(explain_file(&fixture.config, &fixture.database, "missing.jpg").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn previews_apply_overrides_without_storing_a_plan() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let stored = fixture.plan(&fixture.config)?;
+
+        let overrides = serde_json::json!({
+            "folderLayout": "yearMonth",
+            "bucketGranularity": "month",
+        });
+        let preview = preview_plan(&fixture.config, &fixture.database, &overrides)?;
+        assert_eq!(preview.total_entries, stored.total_entries);
+        let unique = preview
+            .entries
+            .iter()
+            .find(|item| !item.is_duplicate)
+            .expect("unique entry");
+        assert!(unique.new_path.ends_with("/2024/2024-01/"));
+
+        let output = preview
+            .tree
+            .iter()
+            .find(|node| unique.new_path.starts_with(&node.path))
+            .expect("output root");
+        assert_eq!(output.files, 2);
+        let year = output
+            .children
+            .iter()
+            .find(|node| node.name == "2024")
+            .expect("year folder");
+        assert_eq!((year.files, year.total_bytes), (1, 100));
+        assert_eq!(year.children[0].path, unique.new_path);
+
+        // The stored plan still has the day buckets.
+        let entries = fixture.database.plan_entries()?;
+        assert!(entries
+            .iter()
+            .any(|entry| entry.target_path.ends_with("/2024-01-02/")));
+        assert!(preview_plan(
+            &fixture.config,
+            &fixture.database,
+            &serde_json::json!({ "noSuchSetting": 1 })
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn plans_diff_against_the_previous_plan_and_previews() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let config = &fixture.config;
+        assert!(diff_plans(
+            config,
+            &fixture.database,
+            &PlanSource::Previous,
+            &PlanSource::Current
+        )
+        .is_err());
+
+        fixture.plan(config)?;
+        let mut monthly = config.clone();
+        monthly.plan.bucket_granularity = BucketGranularity::Month;
+        fixture.plan(&monthly)?;
+
+        let diff = diff_plans(
+            config,
+            &fixture.database,
+            &PlanSource::Previous,
+            &PlanSource::Current,
+        )?;
+        // The duplicate stays in the duplicates folder.
+        assert_eq!((diff.changed.count, diff.unchanged), (1, 1));
+        assert!(diff.changed.items[0].from.contains("/2024-01-02/"));
+        assert!(diff.changed.items[0].to.contains("/2024-01/"));
+
+        let preview = PlanSource::Preview {
+            overrides: serde_json::json!({ "bucketGranularity": "month" }),
+        };
+        let diff = diff_plans(config, &fixture.database, &PlanSource::Current, &preview)?;
+        assert_eq!((diff.changed.count, diff.unchanged), (0, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_can_keep_their_folders_or_buckets() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        let duplicate_dir = |summary: PlanSummary| {
+            summary
+                .entries
+                .into_iter()
+                .find(|item| item.is_duplicate)
+                .map(|item| item.new_path)
+                .unwrap_or_default()
+        };
+        let root = to_posix_string(&config.duplicates_dir).into_owned();
+
+        assert_eq!(duplicate_dir(fixture.plan(&config)?), format!("{root}/"));
+        config.plan.duplicates_layout = DuplicatesLayout::Original;
+        assert_eq!(duplicate_dir(fixture.plan(&config)?), format!("{root}/B/"));
+        config.plan.duplicates_layout = DuplicatesLayout::Bucket;
+        assert_eq!(
+            duplicate_dir(fixture.plan(&config)?),
+            format!("{root}/2024-01-02/")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn original_layout_keeps_found_folders() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.folder_layout = FolderLayout::Original;
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/trip/IMG_0002.JPG"));
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        let output = to_posix_string(&config.output_root).into_owned();
+        let paths: StdHashSet<_> = summary
+            .entries
+            .iter()
+            .filter(|item| !item.is_duplicate)
+            .map(|item| item.new_path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            StdHashSet::from([
+                format!("{output}/A/").as_str(),
+                &format!("{output}/C/trip/")
+            ])
+        );
+        assert!(summary
+            .entries
+            .iter()
+            .all(|item| item.new_file_name.starts_with("2024-01-02_10-00-00.")));
+        Ok(())
+    }
+
+    #[test]
+    fn folder_template_names_date_buckets() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.folder_template = Some("{camera_model}/{year}-{month}".into());
+        let mut records = fixture.records.clone();
+        let mut canon = fixture.unique_record("hash-3", "C/IMG_0003.JPG");
+        canon.exif_model = Some("EOS R6".into());
+        records.push(canon);
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        let output = to_posix_string(&config.output_root).into_owned();
+        let path_of = |name: &str| {
+            summary
+                .entries
+                .iter()
+                .find(|item| item.origin_file_name == name)
+                .map(|item| item.new_path.clone())
+        };
+        assert_eq!(
+            path_of("IMG_0003.JPG"),
+            Some(format!("{output}/EOS R6/2024-01/"))
+        );
+        assert_eq!(
+            path_of("IMG_0001.JPG"),
+            Some(format!("{output}/Unknown Camera/2024-01/"))
+        );
+
+        config.plan.folder_template = Some("{camera}/{year}".into());
+        assert!(matches!(
+            config.plan.validate(),
+            Err(AppError::Template { ref field, .. }) if field == "folderTemplate"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn videos_use_their_own_output_root() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.output_roots.video = Some(config.output_root.join("videos"));
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/clip.mp4"));
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        let video_item = summary
+            .entries
+            .iter()
+            .find(|item| item.origin_file_name == "clip.mp4")
+            .expect("video entry");
+        assert!(video_item.new_path.contains("/videos/2024-01-02"));
+
+        let report = crate::preflight::preflight(&config, &fixture.database)?;
+        assert_eq!(report.volumes.len(), 2);
+        assert!(report
+            .volumes
+            .iter()
+            .any(|volume| volume.required_bytes == 100));
+        Ok(())
+    }
+
+    #[test]
+    fn bucket_layouts() {
+        let ts = "2024-06-14_10-00-00";
+        let day = BucketGranularity::Day;
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::Flat, day),
+            PathBuf::from("2024-06-14")
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::YearMonthDay, day),
+            PathBuf::from("2024").join("06").join("14")
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::YearMonth, day),
+            PathBuf::from("2024").join("2024-06")
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::Single, day),
+            PathBuf::new()
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::Single, BucketGranularity::Year),
+            PathBuf::from("2024")
+        );
+    }
+
+    #[test]
+    fn older_years_can_use_coarser_buckets() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.granularity_periods = vec![crate::config::GranularityPeriod {
+            from: None,
+            to: Some(2023),
+            granularity: BucketGranularity::Year,
+        }];
+        let mut records = fixture.records.clone();
+        records.push(InventoryRecord {
+            captured_at: Some("2015-03-04_10-00-00".into()),
+            ..fixture.unique_record("hash-3", "C/old.jpg")
+        });
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        let path_of = |name: &str| {
+            summary
+                .entries
+                .iter()
+                .find(|item| item.new_file_name.ends_with(name))
+                .map(|item| item.new_path.clone())
+                .unwrap_or_default()
+        };
+        assert!(path_of("old.jpg").ends_with("/2015/"));
+        assert!(path_of("IMG_0001.JPG").ends_with("/2024-01-02/"));
+        Ok(())
+    }
+
+    #[test]
+    fn name_timestamps_follow_the_configured_format() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.name_timestamp = "%Y%m%dT%H%M".into();
+        let summary = fixture.plan(&config)?;
+        assert!(summary
+            .entries
+            .iter()
+            .all(|item| item.new_file_name.starts_with("20240102T1000.")));
+        Ok(())
+    }
+
+    #[test]
+    fn name_templates_keep_collision_handling() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.name_template = Some("{date}_{seq}".into());
+        config.plan.folder_layout = FolderLayout::Single;
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/IMG_0003.JPG"));
+        fixture.database.replace_inventory(&records)?;
+
+        let mut names: Vec<_> = fixture
+            .plan(&config)?
+            .entries
+            .into_iter()
+            .filter(|item| !item.is_duplicate)
+            .map(|item| item.new_file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["2024-01-02_0001.JPG", "2024-01-02_0002.JPG"]);
+
+        config.plan.name_template = Some("{date}".into());
+        let mut names: Vec<_> = fixture
+            .plan(&config)?
+            .entries
+            .into_iter()
+            .filter(|item| !item.is_duplicate)
+            .map(|item| item.new_file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["2024-01-02.JPG", "2024-01-02_dup1.JPG"]);
+
+        config.plan.name_template = Some("{date}/{seq}".into());
+        assert!(matches!(
+            config.plan.validate(),
+            Err(AppError::Template { ref field, .. }) if field == "nameTemplate"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn original_names_can_be_kept() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.keep_original_names = true;
+        config.plan.folder_layout = FolderLayout::Single;
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/IMG_0001.JPG"));
+        fixture.database.replace_inventory(&records)?;
+
+        let mut names: Vec<_> = fixture
+            .plan(&config)?
+            .entries
+            .into_iter()
+            .filter(|item| !item.is_duplicate)
+            .map(|item| item.new_file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["IMG_0001.JPG", "IMG_0001_dup1.JPG"]);
+        Ok(())
+    }
+
+    #[test]
+    fn extensions_normalize_before_collisions_are_checked() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.keep_original_names = true;
+        config.plan.normalize_extensions = true;
+        config.plan.folder_layout = FolderLayout::Single;
+        let mut records = fixture.records.clone();
+        records.push(fixture.unique_record("hash-3", "C/IMG_0001.jpeg"));
+        fixture.database.replace_inventory(&records)?;
+
+        let mut names: Vec<_> = fixture
+            .plan(&config)?
+            .entries
+            .into_iter()
+            .filter(|item| !item.is_duplicate)
+            .map(|item| item.new_file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["IMG_0001.jpg", "IMG_0001_dup1.jpg"]);
+        assert_eq!(normalize_extension(".hidden"), ".hidden");
+        assert_eq!(normalize_extension("scan.TIFF"), "scan.tif");
+        Ok(())
+    }
+
+    #[test]
+    fn bucket_granularities() {
+        let ts = "2024-06-14_10-00-00";
+        let flat = FolderLayout::Flat;
+        assert_eq!(
+            bucket_from_timestamp(ts, flat, BucketGranularity::Week),
+            PathBuf::from("2024-W24")
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, flat, BucketGranularity::Month),
+            PathBuf::from("2024-06")
+        );
+        assert_eq!(
+            bucket_from_timestamp(ts, FolderLayout::YearMonthDay, BucketGranularity::Year),
+            PathBuf::from("2024")
+        );
+        // ISO weeks belong to the week-year, not the calendar year.
+        assert_eq!(
+            bucket_from_timestamp("2024-12-30_00-00-00", flat, BucketGranularity::Week),
+            PathBuf::from("2025-W01")
+        );
+    }
+
+    #[test]
+    fn full_buckets_spill_into_part_folders() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.max_files_per_folder = Some(1);
+        let records = vec![
+            fixture.records[0].clone(),
+            fixture.unique_record("hash-4", "A/IMG_0002.JPG"),
+        ];
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        assert_eq!(summary.destination_buckets, 2);
+        let spilled = summary
+            .entries
+            .iter()
+            .find(|item| item.new_path.ends_with("2024-01-02_part2/"))
+            .expect("spilled entry");
+        assert_eq!(spilled.reason.split_part, Some(2));
+
+        config.plan.max_files_per_folder = Some(0);
+        assert!(config.plan.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn single_folder_overflow_stays_under_the_output_root() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut config = fixture.config.clone();
+        config.plan.folder_layout = FolderLayout::Single;
+        config.plan.max_files_per_folder = Some(1);
+        let records = vec![
+            fixture.records[0].clone(),
+            fixture.unique_record("hash-4", "A/IMG_0002.JPG"),
+        ];
+        fixture.database.replace_inventory(&records)?;
+
+        let summary = fixture.plan(&config)?;
+        let output = to_posix_string(&config.output_root).into_owned();
+        let mut paths: Vec<_> = summary
+            .entries
+            .iter()
+            .map(|item| item.new_path.as_str())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, [format!("{output}/"), format!("{output}/part2/")]);
+        Ok(())
+    }
+
+    #[test]
+    fn suffixes_follow_hash_not_scan_order() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let first = fixture.unique_record("hash-a", "Z/IMG_0001.JPG");
+        let second = fixture.unique_record("hash-b", "A/IMG_0001.JPG");
+
+        let name_of = |summary: &PlanSummary, hash: &str| {
+            summary
+                .entries
+                .iter()
+                .find(|item| item.file_hash == hash)
+                .map(|item| item.new_file_name.clone())
+                .expect("planned entry")
+        };
+
+        fixture
+            .database
+            .replace_inventory(&[second.clone(), first.clone()])?;
+        let summary = fixture.plan(&fixture.config)?;
+        assert!(!name_of(&summary, "hash-a").contains("_dup"));
+        assert!(name_of(&summary, "hash-b").contains("_dup1"));
+
+        fixture.database.replace_inventory(&[first, second])?;
+        let replanned = fixture.plan(&fixture.config)?;
+        assert_eq!(name_of(&summary, "hash-a"), name_of(&replanned, "hash-a"));
+        assert_eq!(name_of(&summary, "hash-b"), name_of(&replanned, "hash-b"));
+        Ok(())
+    }
+
+    #[test]
+    fn full_volumes_spill_onto_the_next() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let full = tempdir()?.keep();
+        let spare = tempdir()?.keep();
+        let mut config = fixture.config.clone();
+        config.plan.output_volumes = vec![
+            crate::config::OutputVolume {
+                root: full,
+                min_free_bytes: u64::MAX,
+            },
+            crate::config::OutputVolume {
+                root: spare.clone(),
+                min_free_bytes: 0,
+            },
+        ];
+        fixture.database.replace_inventory(&fixture.records[..1])?;
+
+        let summary = fixture.plan(&config)?;
+        let entry = &summary.entries[0];
+        let spare_root = to_posix_string(&spare).into_owned();
+        assert_eq!(entry.volume.as_deref(), Some(spare_root.as_str()));
+        assert!(entry.new_path.starts_with(&spare_root));
+        assert_eq!(
+            fixture.database.plan_entries()?[0].volume_root.as_deref(),
+            Some(spare_root.as_str())
+        );
+        Ok(())
+    }
 }
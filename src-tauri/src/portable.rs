@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use crate::config::{AppConfig, PathStorage};
+use crate::media::MediaKind;
+use crate::utils::path::{ensure_trailing_separator, to_posix_string};
+
+const ROOT_MARKER: char = '@';
+
+/// Translates absolute paths to and from their stored form. In portable mode
+/// a path under a configured root is stored as `@<root id>/<relative>`, so
+/// the database keeps working after the roots move to another machine or OS.
+/// Decoding always understands both forms, which makes switching modes safe.
+#[derive(Debug, Clone)]
+pub struct PathCodec {
+    storage: PathStorage,
+    /// `(id, POSIX prefix ending in '/')`, longest prefix first.
+    roots: Vec<(String, String)>,
+}
+
+impl PathCodec {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let source = config
+            .sample_image_root
+            .as_ref()
+            .unwrap_or(&config.image_root);
+        let mut roots = vec![
+            ("source".to_string(), prefix_of(source)),
+            ("output".to_string(), prefix_of(&config.output_root)),
+        ];
+        for (kind, id) in [
+            (MediaKind::Photo, "photo"),
+            (MediaKind::Video, "video"),
+            (MediaKind::Raw, "raw"),
+        ] {
+            if let Some(root) = config.explicit_output_root(kind) {
+                roots.push((id.to_string(), prefix_of(&root)));
+            }
+        }
+        for (idx, root) in config.volume_roots().iter().enumerate() {
+            roots.push((format!("volume{}", idx + 1), prefix_of(root)));
+        }
+        // Stable sort keeps the earlier id when two roots share a prefix.
+        roots.sort_by_key(|(_, prefix)| std::cmp::Reverse(prefix.len()));
+
+        Self {
+            storage: config.path_storage,
+            roots,
+        }
+    }
+
+    pub fn encode(&self, path: &str) -> String {
+        if self.storage == PathStorage::Absolute {
+            return path.to_string();
+        }
+        for (id, prefix) in &self.roots {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                return format!("{ROOT_MARKER}{id}/{rest}");
+            }
+            if path == prefix.trim_end_matches('/') {
+                return format!("{ROOT_MARKER}{id}");
+            }
+        }
+        path.to_string()
+    }
+
+    pub fn decode(&self, stored: &str) -> String {
+        let Some(tagged) = stored.strip_prefix(ROOT_MARKER) else {
+            return stored.to_string();
+        };
+        let (id, rest) = match tagged.split_once('/') {
+            Some((id, rest)) => (id, Some(rest)),
+            None => (tagged, None),
+        };
+        match self.roots.iter().find(|(root_id, _)| root_id == id) {
+            Some((_, prefix)) => match rest {
+                Some(rest) => format!("{prefix}{rest}"),
+                None => prefix.trim_end_matches('/').to_string(),
+            },
+            None => {
+                tracing::warn!(root = id, "stored path references an unconfigured root");
+                stored.to_string()
+            }
+        }
+    }
+}
+
+fn prefix_of(root: &Path) -> String {
+    to_posix_string(&ensure_trailing_separator(root)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn config(source: &str, output: &str, storage: PathStorage) -> AppConfig {
+        let dir = tempdir().unwrap();
+        let mut config = test_config(dir.path());
+        config.image_root = PathBuf::from(source);
+        config.output_root = PathBuf::from(output);
+        config.path_storage = storage;
+        config
+    }
+
+    #[test]
+    fn portable_paths_follow_the_roots() {
+        let old = PathCodec::from_config(&config("/src", "/lib", PathStorage::Portable));
+        let stored_target = old.encode("/lib/2024-01-02/");
+        let stored_origin = old.encode("/src/A/IMG_0001.JPG");
+        assert_eq!(stored_target, "@output/2024-01-02/");
+        assert_eq!(stored_origin, "@source/A/IMG_0001.JPG");
+        assert_eq!(old.encode("/lib"), "@output");
+        assert_eq!(old.encode("/elsewhere/x.jpg"), "/elsewhere/x.jpg");
+
+        let moved = PathCodec::from_config(&config("D:/src", "E:/lib", PathStorage::Absolute));
+        assert_eq!(moved.decode(&stored_target), "E:/lib/2024-01-02/");
+        assert_eq!(moved.decode(&stored_origin), "D:/src/A/IMG_0001.JPG");
+        assert_eq!(moved.decode("@output"), "E:/lib");
+        assert_eq!(moved.decode("/abs/path"), "/abs/path");
+        assert_eq!(moved.encode("E:/lib/a.jpg"), "E:/lib/a.jpg");
+    }
+}
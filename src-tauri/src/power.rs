@@ -0,0 +1,281 @@
+use std::marker::PhantomData;
+#[cfg(not(windows))]
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{BatteryMode, PowerOptions};
+use crate::progress::{HoldReason, TaskControl};
+
+/// How often the battery is re-read while a task runs.
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the watcher notices that its task has ended.
+const WATCH_TICK: Duration = Duration::from_secs(1);
+
+/// Keeps the machine from sleeping while held, so an overnight copy is not
+/// suspended halfway. Idle display sleep is still allowed. Failing to take
+/// the assertion is logged and otherwise ignored.
+///
+/// Windows ties the assertion to the calling thread, so the guard must be
+/// dropped on the thread that acquired it.
+pub struct SleepGuard {
+    #[cfg(not(windows))]
+    child: Option<Child>,
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl SleepGuard {
+    pub fn acquire(reason: &str) -> Self {
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::System::Power::{
+                SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+            };
+            // SAFETY: only sets flags for the current thread.
+            if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } == 0 {
+                tracing::warn!(reason, "failed to prevent system sleep");
+            }
+            Self {
+                _thread_bound: PhantomData,
+            }
+        }
+
+        #[cfg(not(windows))]
+        {
+            let child = match inhibitor_command(reason).spawn() {
+                Ok(child) => Some(child),
+                Err(err) => {
+                    tracing::warn!(reason, error = %err, "failed to prevent system sleep");
+                    None
+                }
+            };
+            Self {
+                child,
+                _thread_bound: PhantomData,
+            }
+        }
+    }
+}
+
+/// A helper process that holds the assertion until it is killed.
+#[cfg(not(windows))]
+fn inhibitor_command(reason: &str) -> Command {
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let _ = reason;
+        let mut command = Command::new("caffeinate");
+        // `-w` also ends the assertion if phototidy itself dies.
+        command
+            .arg("-i")
+            .arg("-w")
+            .arg(std::process::id().to_string());
+        command
+    };
+
+    #[cfg(not(target_os = "macos"))]
+    let mut command = {
+        let mut command = Command::new("systemd-inhibit");
+        command.args([
+            "--what=sleep:idle",
+            "--who=phototidy",
+            &format!("--why={reason}"),
+            "--mode=block",
+            "cat",
+        ]);
+        command
+    };
+
+    // Closing stdin ends `cat`, and with it the inhibitor.
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::System::Power::{SetThreadExecutionState, ES_CONTINUOUS};
+            // SAFETY: clears the flags this thread set in `acquire`.
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+
+        #[cfg(not(windows))]
+        if let Some(mut child) = self.child.take() {
+            drop(child.stdin.take());
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    pub on_battery: bool,
+    pub percent: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryAction {
+    FullSpeed,
+    Throttle,
+    Pause,
+}
+
+/// What `options` asks for under `status`. Machines without a battery, or
+/// whose charge is unknown, always run at full speed.
+pub fn battery_action(options: &PowerOptions, status: Option<BatteryStatus>) -> BatteryAction {
+    let low = status.is_some_and(|status| {
+        status.on_battery
+            && status
+                .percent
+                .is_some_and(|percent| percent < options.battery_threshold)
+    });
+    match (low, options.battery_mode) {
+        (false, _) | (true, BatteryMode::Ignore) => BatteryAction::FullSpeed,
+        (true, BatteryMode::Throttle) => BatteryAction::Throttle,
+        (true, BatteryMode::Pause) => BatteryAction::Pause,
+    }
+}
+
+/// Applies the battery policy to `control` until its task ends.
+pub fn watch_battery(options: PowerOptions, control: Arc<TaskControl>) {
+    if options.battery_mode == BatteryMode::Ignore {
+        return;
+    }
+    thread::spawn(move || {
+        let mut last_check: Option<Instant> = None;
+        while !control.is_finished() {
+            if last_check.is_none_or(|at| at.elapsed() >= BATTERY_POLL_INTERVAL) {
+                last_check = Some(Instant::now());
+                let action = battery_action(&options, battery_status());
+                control.set_throttled(action == BatteryAction::Throttle);
+                if action == BatteryAction::Pause {
+                    control.hold(HoldReason::Battery);
+                } else {
+                    control.release(HoldReason::Battery);
+                }
+            }
+            thread::sleep(WATCH_TICK);
+        }
+    });
+}
+
+/// Reads the battery state, or `None` on machines without one.
+#[cfg(target_os = "linux")]
+pub fn battery_status() -> Option<BatteryStatus> {
+    use std::fs;
+
+    let mut on_battery = None;
+    let mut percent = None;
+    for entry in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let path = entry.path();
+        let read = |name: &str| {
+            fs::read_to_string(path.join(name))
+                .ok()
+                .map(|value| value.trim().to_string())
+        };
+        match read("type").as_deref() {
+            Some("Battery") => {
+                percent = percent.or_else(|| read("capacity").and_then(|value| value.parse().ok()));
+                if read("status").as_deref() == Some("Discharging") {
+                    on_battery = Some(true);
+                }
+            }
+            Some("Mains") if read("online").as_deref() == Some("1") => on_battery = Some(false),
+            _ => {}
+        }
+    }
+    // No battery reported a charge, so this is not a battery machine.
+    let percent: u8 = percent?;
+    Some(BatteryStatus {
+        on_battery: on_battery.unwrap_or(false),
+        percent: Some(percent),
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn battery_status() -> Option<BatteryStatus> {
+    let output = Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+    parse_pmset(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(windows)]
+pub fn battery_status() -> Option<BatteryStatus> {
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    // SAFETY: the struct is plain data filled in by the call.
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return None;
+    }
+    const NO_BATTERY: u8 = 128;
+    const UNKNOWN: u8 = 255;
+    if status.BatteryFlag & NO_BATTERY != 0 {
+        return None;
+    }
+    Some(BatteryStatus {
+        on_battery: status.ACLineStatus == 0,
+        percent: (status.BatteryLifePercent != UNKNOWN).then_some(status.BatteryLifePercent),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn battery_status() -> Option<BatteryStatus> {
+    None
+}
+
+/// Parses `pmset -g batt`, e.g.
+/// `Now drawing from 'Battery Power'` then `-InternalBattery-0 (id=1)\t85%; discharging`.
+#[cfg(any(target_os = "macos", test))]
+fn parse_pmset(output: &str) -> Option<BatteryStatus> {
+    let percent = output
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .find_map(|token| token.strip_suffix('%'))
+        .and_then(|value| value.parse().ok())?;
+    Some(BatteryStatus {
+        on_battery: output.contains("'Battery Power'"),
+        percent: Some(percent),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_battery_applies_the_configured_mode() {
+        let on_battery = parse_pmset(
+            "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=4653155)\t18%; discharging; 1:02 remaining present: true\n",
+        );
+        assert_eq!(
+            on_battery,
+            Some(BatteryStatus {
+                on_battery: true,
+                percent: Some(18)
+            })
+        );
+        let on_ac = parse_pmset(
+            "Now drawing from 'AC Power'\n -InternalBattery-0 (id=1)\t18%; charging;\n",
+        );
+
+        let mut options = PowerOptions::default();
+        assert_eq!(
+            battery_action(&options, on_battery),
+            BatteryAction::FullSpeed
+        );
+        options.battery_mode = BatteryMode::Pause;
+        assert_eq!(battery_action(&options, on_battery), BatteryAction::Pause);
+        assert_eq!(battery_action(&options, on_ac), BatteryAction::FullSpeed);
+        assert_eq!(battery_action(&options, None), BatteryAction::FullSpeed);
+        options.battery_mode = BatteryMode::Throttle;
+        options.battery_threshold = 10;
+        assert_eq!(
+            battery_action(&options, on_battery),
+            BatteryAction::FullSpeed
+        );
+    }
+}
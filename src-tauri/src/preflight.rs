@@ -0,0 +1,362 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::{root_conflict, AppConfig, PathConflict};
+use crate::db::{Database, PlanRecord, PlanStatus};
+use crate::error::Result;
+use crate::plan::PLAN_SCHEMA_VERSION;
+use crate::system::disk_status;
+use crate::utils::path::{ensure_trailing_separator, normalize, to_posix_string};
+use crate::utils::sparse::{allocated_size, is_sparse};
+
+const WRITE_PROBE_FILE: &str = ".phototidy-write-probe";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeCheck {
+    pub root: String,
+    pub required_bytes: u64,
+    /// Free space that must be left over, see `IoOptions::free_space_reserve`.
+    pub reserve_bytes: u64,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub ok: bool,
+}
+
+/// The individual checks, in the order the pipeline depends on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckKind {
+    SourceReadable,
+    OutputWritable,
+    OutputOutsideSource,
+    DuplicatesInsideOutput,
+    DiskSpace,
+    DatabaseHealthy,
+    PlanFresh,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightCheck {
+    pub kind: CheckKind,
+    pub ok: bool,
+    /// What was found, worded for the checklist whether it passed or not.
+    pub detail: String,
+    /// Set for folder overlaps, see `PathConflict::code`.
+    pub code: Option<&'static str>,
+}
+
+impl PreflightCheck {
+    fn new(kind: CheckKind, ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            ok,
+            detail: detail.into(),
+            code: None,
+        }
+    }
+
+    fn conflict(kind: CheckKind, conflict: PathConflict, path: &Path) -> Self {
+        Self {
+            code: Some(conflict.code()),
+            ..Self::new(
+                kind,
+                false,
+                format!("{}: {}", conflict.describe(), to_posix_string(path)),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightReport {
+    pub ok: bool,
+    pub checks: Vec<PreflightCheck>,
+    pub volumes: Vec<VolumeCheck>,
+}
+
+/// Validates the whole pipeline before a run. Every check is reported even
+/// when an earlier one fails, so the UI can show the full checklist.
+pub fn preflight(config: &AppConfig, database: &Database) -> Result<PreflightReport> {
+    let source = config
+        .sample_image_root
+        .as_ref()
+        .unwrap_or(&config.image_root);
+    let volumes = check_volumes(config, database)?;
+
+    let short_volumes: Vec<&str> = volumes
+        .iter()
+        .filter(|volume| !volume.ok)
+        .map(|volume| volume.root.as_str())
+        .collect();
+    let disk_space = if short_volumes.is_empty() {
+        PreflightCheck::new(CheckKind::DiskSpace, true, "enough free space for the plan")
+    } else {
+        let reserve = match config.io.free_space_reserve {
+            0 => String::new(),
+            bytes => format!(" while keeping {bytes} bytes free"),
+        };
+        PreflightCheck::new(
+            CheckKind::DiskSpace,
+            false,
+            format!(
+                "not enough free space on {}{reserve}",
+                short_volumes.join(", ")
+            ),
+        )
+    };
+
+    let checks = vec![
+        check_source(source),
+        check_output_writable(&config.output_roots()),
+        check_nesting(source, &config.output_roots())?,
+        check_duplicates(&config.output_root, &config.duplicates_dir)?,
+        disk_space,
+        check_database(database)?,
+        check_plan(database)?,
+    ];
+
+    Ok(PreflightReport {
+        ok: checks.iter().all(|check| check.ok),
+        checks,
+        volumes,
+    })
+}
+
+/// Checks the pending plan against every configured output root, so a video
+/// root on a bulk disk and a photo root on an SSD are checked on their own
+/// free space rather than against one shared number.
+fn check_volumes(config: &AppConfig, database: &Database) -> Result<Vec<VolumeCheck>> {
+    let roots: Vec<(PathBuf, String)> = config
+        .output_roots()
+        .into_iter()
+        .map(|root| {
+            let prefix = to_posix_string(&ensure_trailing_separator(&root)).into_owned();
+            (root, prefix)
+        })
+        .collect();
+
+    let mut required = vec![0u64; roots.len()];
+    for entry in database.plan_entries_with_status(&[PlanStatus::Pending])? {
+        let owner = roots
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, prefix))| entry.target_path.starts_with(prefix.as_str()))
+            .max_by_key(|(_, (_, prefix))| prefix.len())
+            .map(|(idx, _)| idx);
+        if let Some(idx) = owner {
+            required[idx] += bytes_needed(&entry);
+        }
+    }
+
+    let reserve_bytes = config.io.free_space_reserve;
+    let mut volumes = Vec::with_capacity(roots.len());
+    for ((root, _), required_bytes) in roots.iter().zip(required) {
+        let status = disk_status(root)?;
+        volumes.push(VolumeCheck {
+            root: status.path,
+            required_bytes,
+            reserve_bytes,
+            available_bytes: status.available_bytes,
+            total_bytes: status.total_bytes,
+            ok: required_bytes.saturating_add(reserve_bytes) <= status.available_bytes,
+        });
+    }
+    Ok(volumes)
+}
+
+/// Disk a copy of the entry's origin takes: its length, or what it
+/// occupies when it is sparse, since copies keep the holes.
+fn bytes_needed(entry: &PlanRecord) -> u64 {
+    match fs::metadata(&entry.origin_full_path) {
+        Ok(metadata) if is_sparse(&metadata) => allocated_size(&metadata),
+        _ => entry.file_size,
+    }
+}
+
+fn check_source(source: &Path) -> PreflightCheck {
+    let display = to_posix_string(source);
+    match fs::read_dir(source) {
+        Ok(_) => PreflightCheck::new(
+            CheckKind::SourceReadable,
+            true,
+            format!("{display} is readable"),
+        ),
+        Err(err) => PreflightCheck::new(
+            CheckKind::SourceReadable,
+            false,
+            format!("cannot read {display}: {err}"),
+        ),
+    }
+}
+
+fn check_output_writable(roots: &[PathBuf]) -> PreflightCheck {
+    for root in roots {
+        let probe = root.join(WRITE_PROBE_FILE);
+        let written = fs::create_dir_all(root).and_then(|()| fs::write(&probe, b""));
+        let _ = fs::remove_file(&probe);
+        if let Err(err) = written {
+            return PreflightCheck::new(
+                CheckKind::OutputWritable,
+                false,
+                format!("cannot write to {}: {err}", to_posix_string(root)),
+            );
+        }
+    }
+    PreflightCheck::new(CheckKind::OutputWritable, true, "output roots are writable")
+}
+
+/// An output root inside the source would be rescanned and reorganized on
+/// the next run; a source inside an output root would be reorganized into
+/// itself.
+fn check_nesting(source: &Path, roots: &[PathBuf]) -> Result<PreflightCheck> {
+    for root in roots {
+        if let Some(conflict) = root_conflict(source, root)? {
+            return Ok(PreflightCheck::conflict(
+                CheckKind::OutputOutsideSource,
+                conflict,
+                root,
+            ));
+        }
+    }
+    Ok(PreflightCheck::new(
+        CheckKind::OutputOutsideSource,
+        true,
+        "output roots are outside the source folder",
+    ))
+}
+
+fn check_duplicates(output_root: &Path, duplicates_dir: &Path) -> Result<PreflightCheck> {
+    Ok(
+        if normalize(duplicates_dir)?.starts_with(normalize(output_root)?) {
+            PreflightCheck::new(
+                CheckKind::DuplicatesInsideOutput,
+                true,
+                "duplicates folder is inside the output folder",
+            )
+        } else {
+            PreflightCheck::conflict(
+                CheckKind::DuplicatesInsideOutput,
+                PathConflict::DuplicatesOutsideOutput,
+                duplicates_dir,
+            )
+        },
+    )
+}
+
+fn check_database(database: &Database) -> Result<PreflightCheck> {
+    let problems = database.quick_check()?;
+    Ok(if problems.is_empty() {
+        PreflightCheck::new(CheckKind::DatabaseHealthy, true, "database is healthy")
+    } else {
+        PreflightCheck::new(
+            CheckKind::DatabaseHealthy,
+            false,
+            format!("database is damaged: {}", problems.join("; ")),
+        )
+    })
+}
+
+/// A plan goes stale when it was written by another planner version or its
+/// origin files changed since planning.
+fn check_plan(database: &Database) -> Result<PreflightCheck> {
+    let schema = database.get_meta("plan_schema_version")?;
+    if schema.is_some_and(|version| version != PLAN_SCHEMA_VERSION.to_string()) {
+        return Ok(PreflightCheck::new(
+            CheckKind::PlanFresh,
+            false,
+            "plan was made by another version; plan again",
+        ));
+    }
+
+    let pending = database.plan_entries_with_status(&[PlanStatus::Pending])?;
+    let changed = pending
+        .iter()
+        .filter(|entry| {
+            fs::metadata(&entry.origin_full_path)
+                .map(|metadata| metadata.len() != entry.file_size)
+                .unwrap_or(true)
+        })
+        .count();
+    Ok(if changed == 0 {
+        PreflightCheck::new(
+            CheckKind::PlanFresh,
+            true,
+            format!("{} pending entries match their files", pending.len()),
+        )
+    } else {
+        PreflightCheck::new(
+            CheckKind::PlanFresh,
+            false,
+            format!(
+                "{changed} origin files changed or disappeared since planning; scan and plan again"
+            ),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use tempfile::tempdir;
+
+    #[test]
+    fn nested_and_missing_roots_fail_their_checks() -> Result<()> {
+        let dir = tempdir()?;
+        let source = dir.path().join("photos");
+        fs::create_dir_all(&source)?;
+
+        assert!(check_source(&source).ok);
+        assert!(!check_source(&dir.path().join("missing")).ok);
+
+        let outside = vec![dir.path().join("library")];
+        assert!(check_output_writable(&outside).ok);
+        assert!(!outside[0].join(WRITE_PROBE_FILE).exists());
+        assert!(check_nesting(&source, &outside)?.ok);
+
+        let nested = vec![source.join("library")];
+        let check = check_nesting(&source, &nested)?;
+        assert_eq!(check.kind, CheckKind::OutputOutsideSource);
+        assert!(!check.ok);
+
+        let around = vec![dir.path().to_path_buf()];
+        let check = check_nesting(&source, &around)?;
+        assert_eq!(check.code, Some("source_inside_output"));
+
+        let library = dir.path().join("library");
+        assert!(check_duplicates(&library, &library.join("dups"))?.ok);
+        let check = check_duplicates(&library, &library.join("../dups"))?;
+        assert_eq!(check.code, Some("duplicates_outside_output"));
+        Ok(())
+    }
+
+    #[test]
+    fn the_free_space_reserve_counts_against_every_volume() -> Result<()> {
+        let dir = tempdir()?;
+        let mut config = test_config(dir.path());
+        let database = Database::initialize(&config)?;
+        assert!(preflight(&config, &database)?
+            .volumes
+            .iter()
+            .all(|volume| volume.ok && volume.reserve_bytes == 0));
+
+        // A reserve no disk can keep fails the space check everywhere.
+        config.io.free_space_reserve = u64::MAX / 2;
+        let report = preflight(&config, &database)?;
+        assert!(report.volumes.iter().all(|volume| !volume.ok));
+        let disk_space = report
+            .checks
+            .iter()
+            .find(|check| check.kind == CheckKind::DiskSpace)
+            .expect("disk space check");
+        assert!(!disk_space.ok);
+        assert!(disk_space.detail.contains("while keeping"));
+        assert!(!report.ok);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Top-level spans whose timings are kept for `profile_last_run`.
+const RUN_SPANS: &[&str] = &["scan", "plan", "execute"];
+
+/// Timing of one span and everything under it. Spans of the same name
+/// under one parent are merged, so per-file work shows up as one node.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileNode {
+    pub name: &'static str,
+    /// Summed over all calls; calls on parallel threads overlap, so this can
+    /// exceed the parent's time.
+    pub elapsed_ms: f64,
+    pub calls: u64,
+    pub children: Vec<ProfileNode>,
+}
+
+impl ProfileNode {
+    fn absorb(&mut self, other: ProfileNode) {
+        self.elapsed_ms += other.elapsed_ms;
+        self.calls += other.calls;
+        for child in other.children {
+            add_child(&mut self.children, child);
+        }
+    }
+}
+
+fn add_child(children: &mut Vec<ProfileNode>, node: ProfileNode) {
+    match children.iter_mut().find(|child| child.name == node.name) {
+        Some(existing) => existing.absorb(node),
+        None => children.push(node),
+    }
+}
+
+/// Kept in a span's extensions while it is open.
+struct OpenSpan {
+    started: Instant,
+    children: Vec<ProfileNode>,
+}
+
+/// Builds a timing tree from the spans of the last scan, plan or execution.
+#[derive(Clone, Default)]
+pub struct ProfileLayer {
+    last: Arc<Mutex<Option<ProfileNode>>>,
+}
+
+impl ProfileLayer {
+    /// The layer installed by `init_logging`.
+    pub fn global() -> &'static ProfileLayer {
+        static GLOBAL: OnceLock<ProfileLayer> = OnceLock::new();
+        GLOBAL.get_or_init(ProfileLayer::default)
+    }
+
+    pub fn last_run(&self) -> Option<ProfileNode> {
+        self.last.lock().clone()
+    }
+}
+
+impl<S> Layer<S> for ProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(OpenSpan {
+                started: Instant::now(),
+                children: Vec::new(),
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        let node = ProfileNode {
+            name: span.name(),
+            elapsed_ms: open.started.elapsed().as_secs_f64() * 1000.0,
+            calls: 1,
+            children: open.children,
+        };
+        match span.parent() {
+            Some(parent) => {
+                if let Some(parent) = parent.extensions_mut().get_mut::<OpenSpan>() {
+                    add_child(&mut parent.children, node);
+                }
+            }
+            None if RUN_SPANS.contains(&node.name) => *self.last.lock() = Some(node),
+            None => {}
+        }
+    }
+}
+
+/// Timings of the last scan, plan or execution since the app started.
+pub fn last_run() -> Option<ProfileNode> {
+    ProfileLayer::global().last_run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn sibling_spans_merge_under_the_run() {
+        let layer = ProfileLayer::default();
+        let subscriber = tracing_subscriber::registry().with(layer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let run = tracing::info_span!("scan").entered();
+            for _ in 0..3 {
+                let _file = tracing::trace_span!("hash_file").entered();
+            }
+            let persist = tracing::debug_span!("persist").entered();
+            tracing::trace_span!("db_write").in_scope(|| {});
+            drop(persist);
+            drop(run);
+            // Spans outside a run do not replace its profile.
+            tracing::info_span!("scrub").in_scope(|| {});
+        });
+
+        let profile = layer.last_run().expect("scan profile");
+        assert_eq!(profile.name, "scan");
+        let children: Vec<_> = profile
+            .children
+            .iter()
+            .map(|child| (child.name, child.calls))
+            .collect();
+        assert_eq!(children, [("hash_file", 3), ("persist", 1)]);
+        assert_eq!(profile.children[1].children[0].name, "db_write");
+    }
+}
@@ -0,0 +1,523 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::{Mutex, MutexGuard};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::error::{AppError, Result};
+use crate::execute::{ExecutionMode, ExecutionProgressPayload, ExecutionSummary};
+use crate::export::ExportSummary;
+use crate::plan::{PlanProgressPayload, PlanSummary};
+use crate::scan::{ScanProgressPayload, ScanSummary};
+
+/// Long-running tasks whose progress is cached for reconnecting windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskKind {
+    Scan,
+    Plan,
+    Execute,
+    Undo,
+    Export,
+    /// Short jobs that rewrite the inventory, plan or library on disk, such
+    /// as deleting duplicates or importing a catalog. They report no
+    /// progress but must not overlap the tasks above.
+    Maintenance,
+}
+
+impl TaskKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskKind::Scan => "scan",
+            TaskKind::Plan => "plan",
+            TaskKind::Execute => "execute",
+            TaskKind::Undo => "undo",
+            TaskKind::Export => "export",
+            TaskKind::Maintenance => "maintenance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskState {
+    #[default]
+    Idle,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// The latest known progress, so a reloaded frontend can pick up where the
+/// fire-and-forget events left it.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressSnapshot {
+    pub task: Option<TaskKind>,
+    pub state: TaskState,
+    pub paused: bool,
+    /// Automatic holds keeping the task waiting, besides a user pause.
+    pub held: Vec<HoldReason>,
+    pub throttled: bool,
+    pub error: Option<String>,
+    pub scan: Option<ScanProgressPayload>,
+    pub plan: Option<PlanProgressPayload>,
+    /// Shared by execution and undo, which report on the same channel.
+    pub execute: Option<ExecutionProgressPayload>,
+}
+
+/// How often a paused task wakes up to look for resume or cancel.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Why a task was held without the user asking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HoldReason {
+    Battery,
+    /// The output volume would fill up with the next write.
+    DiskFull,
+}
+
+impl HoldReason {
+    const ALL: [HoldReason; 2] = [HoldReason::Battery, HoldReason::DiskFull];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Pause and cancel requests for a running task. Workers call
+/// `checkpoint` between files, so requests take effect at file boundaries.
+/// Holds are pauses placed by monitors rather than the user; each is
+/// released independently of the others and of a user pause.
+#[derive(Debug, Default)]
+pub struct TaskControl {
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    holds: AtomicU8,
+    throttled: AtomicBool,
+    /// Taken around each file's heavy work while throttled.
+    lane: Mutex<()>,
+}
+
+impl TaskControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// True once the task has ended; monitors stop watching then.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    pub fn hold(&self, reason: HoldReason) {
+        self.holds.fetch_or(reason.bit(), Ordering::SeqCst);
+    }
+
+    pub fn release(&self, reason: HoldReason) {
+        self.holds.fetch_and(!reason.bit(), Ordering::SeqCst);
+    }
+
+    pub fn is_held(&self, reason: HoldReason) -> bool {
+        self.holds.load(Ordering::SeqCst) & reason.bit() != 0
+    }
+
+    pub fn holds(&self) -> Vec<HoldReason> {
+        let holds = self.holds.load(Ordering::SeqCst);
+        HoldReason::ALL
+            .into_iter()
+            .filter(|reason| holds & reason.bit() != 0)
+            .collect()
+    }
+
+    pub fn set_throttled(&self, throttled: bool) {
+        self.throttled.store(throttled, Ordering::SeqCst);
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttled.load(Ordering::SeqCst)
+    }
+
+    /// While throttled, serializes parallel workers: hold the returned guard
+    /// for the duration of one file's work.
+    pub fn lane(&self) -> Option<MutexGuard<'_, ()>> {
+        self.is_throttled().then(|| self.lane.lock())
+    }
+
+    /// Waits while the task is paused or held and fails once it is cancelled.
+    pub fn checkpoint(&self) -> Result<()> {
+        while (self.is_paused() || self.holds.load(Ordering::SeqCst) != 0) && !self.is_cancelled() {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+        if self.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Lists in kept summaries are cut to this many items.
+const SUMMARY_LIST_LIMIT: usize = 20;
+
+#[derive(Debug, Default)]
+struct CacheState {
+    snapshot: ProgressSnapshot,
+    control: Option<Arc<TaskControl>>,
+    /// The last successful summary of each task, for diagnostics.
+    summaries: Vec<(TaskKind, Value)>,
+}
+
+/// Shared by every window. Only one task runs at a time, so a window that
+/// opens mid-task sees that task instead of starting a competing one.
+#[derive(Debug, Default)]
+pub struct ProgressCache {
+    inner: Mutex<CacheState>,
+}
+
+impl ProgressCache {
+    /// Marks `task` as running and drops the progress it is about to replace.
+    /// Fails while another task is running.
+    pub fn begin(self: &Arc<Self>, task: TaskKind) -> Result<TaskGuard> {
+        let mut state = self.inner.lock();
+        if let (TaskState::Running, Some(running)) = (state.snapshot.state, state.snapshot.task) {
+            return Err(AppError::Busy(running.as_str().to_string()));
+        }
+        let control = Arc::new(TaskControl::default());
+        state.control = Some(Arc::clone(&control));
+        let snapshot = &mut state.snapshot;
+        snapshot.task = Some(task);
+        snapshot.state = TaskState::Running;
+        snapshot.error = None;
+        match task {
+            TaskKind::Scan => snapshot.scan = None,
+            TaskKind::Plan => snapshot.plan = None,
+            TaskKind::Execute | TaskKind::Undo | TaskKind::Export => snapshot.execute = None,
+            TaskKind::Maintenance => {}
+        }
+        Ok(TaskGuard {
+            cache: Arc::clone(self),
+            task,
+            control,
+            finished: false,
+        })
+    }
+
+    pub fn record_scan(&self, payload: ScanProgressPayload) {
+        self.inner.lock().snapshot.scan = Some(payload);
+    }
+
+    pub fn record_plan(&self, payload: PlanProgressPayload) {
+        self.inner.lock().snapshot.plan = Some(payload);
+    }
+
+    pub fn record_execute(&self, payload: ExecutionProgressPayload) {
+        self.inner.lock().snapshot.execute = Some(payload);
+    }
+
+    /// Pause and cancel handle of the running task, if there is one.
+    pub fn control(&self) -> Option<Arc<TaskControl>> {
+        self.inner.lock().control.clone()
+    }
+
+    /// Asks the running task to stop at its next checkpoint and returns
+    /// which task that is, or `None` when nothing is running.
+    pub fn cancel(&self) -> Option<TaskKind> {
+        let state = self.inner.lock();
+        state.control.as_ref()?.cancel();
+        state.snapshot.task
+    }
+
+    fn finish(&self, task: TaskKind, outcome: std::result::Result<Value, String>) {
+        let mut state = self.inner.lock();
+        state.control = None;
+        let error = match outcome {
+            Ok(summary) => {
+                state.summaries.retain(|(kept, _)| *kept != task);
+                state.summaries.push((task, summary));
+                None
+            }
+            Err(error) => Some(error),
+        };
+        state.snapshot.state = if error.is_some() {
+            TaskState::Failed
+        } else {
+            TaskState::Succeeded
+        };
+        state.snapshot.error = error;
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let state = self.inner.lock();
+        let mut snapshot = state.snapshot.clone();
+        if let Some(control) = &state.control {
+            snapshot.paused = control.is_paused();
+            snapshot.held = control.holds();
+            snapshot.throttled = control.is_throttled();
+        }
+        snapshot
+    }
+
+    /// The last successful summary of each task, keyed by task name, with
+    /// long lists cut short.
+    pub fn last_summaries(&self) -> Map<String, Value> {
+        self.inner
+            .lock()
+            .summaries
+            .iter()
+            .map(|(task, summary)| (task.as_str().to_string(), summary.clone()))
+            .collect()
+    }
+}
+
+/// Cuts every list below `value` to [`SUMMARY_LIST_LIMIT`] items, noting
+/// how many were left out, so a plan summary does not keep every entry.
+fn excerpt(value: Value) -> Value {
+    match value {
+        Value::Array(items) => {
+            let total = items.len();
+            let mut kept: Vec<Value> = items
+                .into_iter()
+                .take(SUMMARY_LIST_LIMIT)
+                .map(excerpt)
+                .collect();
+            if total > SUMMARY_LIST_LIMIT {
+                kept.push(Value::String(format!(
+                    "... {} more",
+                    total - SUMMARY_LIST_LIMIT
+                )));
+            }
+            Value::Array(kept)
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, excerpt(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Held for the lifetime of a task. Dropping it without `finish`, e.g. when
+/// the task panics, records the task as failed so it cannot block others.
+pub struct TaskGuard {
+    cache: Arc<ProgressCache>,
+    task: TaskKind,
+    control: Arc<TaskControl>,
+    finished: bool,
+}
+
+impl TaskGuard {
+    pub fn control(&self) -> &TaskControl {
+        &self.control
+    }
+
+    /// A handle for monitors that outlive the borrow of the guard.
+    pub fn shared_control(&self) -> Arc<TaskControl> {
+        Arc::clone(&self.control)
+    }
+
+    /// Records how the task ended.
+    pub fn finish<T: Serialize, E: ToString>(mut self, result: &std::result::Result<T, E>) {
+        self.finished = true;
+        self.control.finished.store(true, Ordering::SeqCst);
+        let outcome = match result {
+            Ok(summary) => Ok(serde_json::to_value(summary).map_or(Value::Null, excerpt)),
+            Err(err) => Err(err.to_string()),
+        };
+        self.cache.finish(self.task, outcome);
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.control.finished.store(true, Ordering::SeqCst);
+            self.cache
+                .finish(self.task, Err("task ended unexpectedly".into()));
+        }
+    }
+}
+
+/// The numbers worth putting in a completion notification.
+pub trait Headline {
+    fn headline(&self) -> String;
+}
+
+impl Headline for ScanSummary {
+    fn headline(&self) -> String {
+        format!(
+            "{} files found, {} hashed, {} duplicates",
+            self.total_files, self.hashed_files, self.duplicate_files
+        )
+    }
+}
+
+impl Headline for PlanSummary {
+    fn headline(&self) -> String {
+        format!(
+            "{} files planned into {} folders, {} duplicates",
+            self.total_entries, self.destination_buckets, self.duplicate_entries
+        )
+    }
+}
+
+impl Headline for ExecutionSummary {
+    fn headline(&self) -> String {
+        let headline = format!(
+            "{} of {} files {}, {} failed",
+            self.succeeded + self.already_present,
+            self.total_entries,
+            match self.mode {
+                ExecutionMode::Copy => "copied",
+                ExecutionMode::Move => "moved",
+            },
+            self.failed
+        );
+        if self.dry_run {
+            format!("Dry run: {headline}")
+        } else {
+            headline
+        }
+    }
+}
+
+impl Headline for ExportSummary {
+    fn headline(&self) -> String {
+        format!(
+            "{} pictures exported, {} up to date, {} failed",
+            self.exported, self.up_to_date, self.failed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::ScanStage;
+
+    #[test]
+    fn snapshot_tracks_the_latest_task() -> Result<()> {
+        let cache = Arc::new(ProgressCache::default());
+        assert_eq!(cache.snapshot().state, TaskState::Idle);
+
+        let scan = cache.begin(TaskKind::Scan)?;
+        cache.record_scan(ScanProgressPayload {
+            stage: ScanStage::FullHash,
+            processed: 3,
+            total: Some(10),
+            current: Some("a.jpg".into()),
+            stages: Vec::new(),
+            cancelled: false,
+        });
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.task, Some(TaskKind::Scan));
+        assert_eq!(snapshot.state, TaskState::Running);
+        assert_eq!(snapshot.scan.map(|scan| scan.processed), Some(3));
+
+        assert!(matches!(
+            cache.begin(TaskKind::Execute),
+            Err(AppError::Busy(running)) if running == "scan"
+        ));
+
+        scan.finish(&Err::<(), _>("disk gone"));
+        assert_eq!(cache.snapshot().error.as_deref(), Some("disk gone"));
+        let plan = cache.begin(TaskKind::Plan)?;
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.state, TaskState::Running);
+        assert!(snapshot.error.is_none());
+        assert!(snapshot.scan.is_some());
+
+        plan.finish(&Ok::<(), String>(()));
+        assert_eq!(cache.snapshot().state, TaskState::Succeeded);
+
+        drop(cache.begin(TaskKind::Undo)?);
+        assert_eq!(cache.snapshot().state, TaskState::Failed);
+        Ok(())
+    }
+
+    #[test]
+    fn finished_tasks_keep_a_short_summary() -> Result<()> {
+        let cache = Arc::new(ProgressCache::default());
+        let entries: Vec<u32> = (0..25).collect();
+        cache
+            .begin(TaskKind::Plan)?
+            .finish(&Ok::<_, String>(serde_json::json!({ "entries": entries })));
+        cache
+            .begin(TaskKind::Scan)?
+            .finish(&Err::<(), _>("disk gone"));
+
+        let summaries = cache.last_summaries();
+        assert_eq!(summaries.len(), 1);
+        let kept = summaries["plan"]["entries"].as_array().unwrap();
+        assert_eq!(kept.len(), SUMMARY_LIST_LIMIT + 1);
+        assert_eq!(kept[SUMMARY_LIST_LIMIT], "... 5 more");
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_tasks_stop_at_the_next_checkpoint() -> Result<()> {
+        let cache = Arc::new(ProgressCache::default());
+        assert!(cache.control().is_none());
+        let task = cache.begin(TaskKind::Execute)?;
+        task.control().checkpoint()?;
+
+        let control = cache.control().expect("running task has a control");
+        control.pause();
+        assert!(cache.snapshot().paused);
+        assert_eq!(cache.cancel(), Some(TaskKind::Execute));
+        assert!(matches!(
+            task.control().checkpoint(),
+            Err(AppError::Cancelled)
+        ));
+
+        task.finish(&Err::<(), _>(AppError::Cancelled));
+        assert!(cache.control().is_none());
+        assert_eq!(cache.cancel(), None);
+        assert!(!cache.snapshot().paused);
+        Ok(())
+    }
+
+    #[test]
+    fn execution_headline_counts_files_already_in_place() {
+        let summary = ExecutionSummary {
+            mode: ExecutionMode::Move,
+            dry_run: true,
+            total_entries: 10,
+            processed_entries: 10,
+            succeeded: 7,
+            failed: 1,
+            already_present: 2,
+            duplicate_entries: 0,
+            transcoded: 0,
+            metadata_stripped: 0,
+            shared: 0,
+            locked: 0,
+            removed_folders: 0,
+            verified: 0,
+            report_path: None,
+            metrics: Default::default(),
+        };
+        assert_eq!(summary.headline(), "Dry run: 9 of 10 files moved, 1 failed");
+    }
+}
@@ -0,0 +1,411 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{AppConfig, ConfigService};
+use crate::db::{Database, PlanStatus};
+use crate::error::{AppError, Result};
+use crate::media::MediaKind;
+use crate::utils::path::{ensure_trailing_separator, to_posix_string};
+
+const FINGERPRINT_SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RootKind {
+    Image,
+    Output,
+}
+
+/// A configured root that went missing and the place the same files turned
+/// up, e.g. after a drive letter or `/Volumes` name changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootRemap {
+    pub kind: RootKind,
+    pub old_root: String,
+    pub new_root: String,
+    pub matched_samples: usize,
+}
+
+/// A file expected under a root, used to recognise the same volume under a
+/// different mount point.
+#[derive(Clone)]
+struct Fingerprint {
+    relative_path: String,
+    file_size: u64,
+}
+
+/// Looks for missing image or output roots whose files are present, with the
+/// same sizes, under another mount point.
+pub fn detect_root_remaps(config: &AppConfig, database: &Database) -> Result<Vec<RootRemap>> {
+    detect_with_mounts(config, database, &mount_points())
+}
+
+fn detect_with_mounts(
+    config: &AppConfig,
+    database: &Database,
+    mounts: &[PathBuf],
+) -> Result<Vec<RootRemap>> {
+    let image_root = config
+        .sample_image_root
+        .clone()
+        .unwrap_or_else(|| config.image_root.clone());
+    let mut roots = vec![(RootKind::Image, image_root)];
+    roots.extend(
+        config
+            .output_roots()
+            .into_iter()
+            .map(|root| (RootKind::Output, root)),
+    );
+
+    let mut remaps = Vec::new();
+    for (kind, root) in roots {
+        if root.exists() {
+            continue;
+        }
+        let samples = match kind {
+            RootKind::Image => image_fingerprints(database)?,
+            RootKind::Output => output_fingerprints(database, &root)?,
+        };
+        if samples.is_empty() {
+            continue;
+        }
+        if let Some(new_root) = find_relocated_root(&root, &samples, mounts) {
+            remaps.push(RootRemap {
+                kind,
+                old_root: to_posix_string(&root).into_owned(),
+                new_root: to_posix_string(&new_root).into_owned(),
+                matched_samples: samples.len(),
+            });
+        }
+    }
+    Ok(remaps)
+}
+
+/// Applies confirmed remaps: the config file is pointed at the new roots and
+/// every stored absolute path is rewritten, so config and database agree.
+pub fn apply_root_remaps(
+    service: &ConfigService,
+    database: &Database,
+    remaps: &[RootRemap],
+) -> Result<AppConfig> {
+    let config = service.snapshot();
+    for remap in remaps {
+        if !Path::new(&remap.new_root).is_dir() {
+            return Err(AppError::Config(format!(
+                "remap target {} is not a directory",
+                remap.new_root
+            )));
+        }
+    }
+
+    let updated = service.update_file(|document| {
+        for remap in remaps {
+            let old_root = PathBuf::from(&remap.old_root);
+            let keys = config_keys_for(&config, remap.kind, &old_root);
+            if keys.is_empty() {
+                return Err(AppError::Config(format!(
+                    "{} is not a configured {} root",
+                    remap.old_root,
+                    match remap.kind {
+                        RootKind::Image => "image",
+                        RootKind::Output => "output",
+                    }
+                )));
+            }
+            for key in keys {
+                set_config_value(document, &key, Value::String(remap.new_root.clone()));
+            }
+        }
+        Ok(())
+    })?;
+    // Portable rows follow the new roots once the codec knows them; only
+    // absolute rows still need rewriting below.
+    database.configure_paths(&updated);
+
+    for remap in remaps {
+        let old_prefix = prefix_of(Path::new(&remap.old_root));
+        let new_prefix = prefix_of(Path::new(&remap.new_root));
+        match remap.kind {
+            RootKind::Image => {
+                database.relocate_origin_paths(&old_prefix, &new_prefix)?;
+            }
+            RootKind::Output => {
+                database.relocate_output_paths(&old_prefix, &new_prefix)?;
+            }
+        }
+    }
+    Ok(updated)
+}
+
+/// JSON paths (as key segments) of every config value that resolves to
+/// `root`.
+fn config_keys_for(config: &AppConfig, kind: RootKind, root: &Path) -> Vec<Vec<String>> {
+    let key = |parts: &[&str]| parts.iter().map(|part| part.to_string()).collect();
+    let mut keys = Vec::new();
+    match kind {
+        RootKind::Image => {
+            if config.sample_image_root.as_deref() == Some(root) {
+                keys.push(key(&["imageRoot"]));
+            } else if config.image_root == root {
+                keys.push(key(&["imageRootDefaultName"]));
+            }
+        }
+        RootKind::Output => {
+            if config.output_root == root {
+                keys.push(key(&["outputRootName"]));
+            }
+            for (kind, name) in [
+                (MediaKind::Photo, "photo"),
+                (MediaKind::Video, "video"),
+                (MediaKind::Raw, "raw"),
+            ] {
+                if config.explicit_output_root(kind).as_deref() == Some(root) {
+                    keys.push(key(&["outputRoots", name]));
+                }
+            }
+            for (idx, volume_root) in config.volume_roots().iter().enumerate() {
+                if volume_root == root {
+                    let idx = idx.to_string();
+                    keys.push(key(&["outputVolumes", &idx, "root"]));
+                }
+            }
+        }
+    }
+    keys
+}
+
+pub fn set_config_value(document: &mut Value, key: &[String], value: Value) {
+    let mut current = document;
+    for part in &key[..key.len() - 1] {
+        current = match current {
+            Value::Array(items) => {
+                match part.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                    Some(item) => item,
+                    None => return,
+                }
+            }
+            Value::Object(map) => map
+                .entry(part.clone())
+                .or_insert_with(|| Value::Object(Default::default())),
+            _ => return,
+        };
+    }
+    if let Value::Object(map) = current {
+        map.insert(key[key.len() - 1].clone(), value);
+    }
+}
+
+fn image_fingerprints(database: &Database) -> Result<Vec<Fingerprint>> {
+    let mut records = database.inventory_snapshot()?;
+    // Only the image root is remapped; extra roots are changed in the config.
+    records.retain(|record| record.source_root.is_none());
+    Ok(spread(&records)
+        .into_iter()
+        .map(|record| Fingerprint {
+            relative_path: record.relative_path.clone(),
+            file_size: record.file_size,
+        })
+        .collect())
+}
+
+fn output_fingerprints(database: &Database, root: &Path) -> Result<Vec<Fingerprint>> {
+    let prefix = prefix_of(root);
+    let relative = |path: &str| path.strip_prefix(prefix.as_str()).map(str::to_string);
+
+    let mut files: Vec<Fingerprint> = database
+        .library_entries()?
+        .into_iter()
+        .filter_map(|entry| {
+            Some(Fingerprint {
+                relative_path: relative(&entry.path)?,
+                file_size: entry.file_size,
+            })
+        })
+        .collect();
+    if files.is_empty() {
+        files = database
+            .plan_entries_with_status(&[
+                PlanStatus::Copied,
+                PlanStatus::Moved,
+                PlanStatus::AlreadyPresent,
+            ])?
+            .into_iter()
+            .filter_map(|entry| {
+                Some(Fingerprint {
+                    relative_path: relative(&format!(
+                        "{}{}",
+                        entry.target_path, entry.target_file_name
+                    ))?,
+                    file_size: entry.file_size,
+                })
+            })
+            .collect();
+    }
+    Ok(spread(&files).into_iter().cloned().collect())
+}
+
+fn spread<T>(items: &[T]) -> Vec<&T> {
+    let step = items.len().div_ceil(FINGERPRINT_SAMPLES).max(1);
+    items.iter().step_by(step).collect()
+}
+
+/// Tries the tail of the old root's path under every mount point, longest
+/// tail first, and returns the first candidate holding all samples.
+fn find_relocated_root(
+    old_root: &Path,
+    samples: &[Fingerprint],
+    mounts: &[PathBuf],
+) -> Option<PathBuf> {
+    let components: Vec<&std::ffi::OsStr> = old_root
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+
+    for start in 0..components.len() {
+        let tail: PathBuf = components[start..].iter().collect();
+        for mount in mounts {
+            let candidate = mount.join(&tail);
+            if candidate != old_root && holds_samples(&candidate, samples) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+fn holds_samples(candidate: &Path, samples: &[Fingerprint]) -> bool {
+    candidate.is_dir()
+        && samples.iter().all(|sample| {
+            fs::metadata(candidate.join(&sample.relative_path))
+                .map(|meta| meta.is_file() && meta.len() == sample.file_size)
+                .unwrap_or(false)
+        })
+}
+
+fn prefix_of(root: &Path) -> String {
+    to_posix_string(&ensure_trailing_separator(root)).into_owned()
+}
+
+#[cfg(windows)]
+fn mount_points() -> Vec<PathBuf> {
+    ('A'..='Z')
+        .map(|letter| PathBuf::from(format!("{letter}:\\")))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Where removable and network volumes usually appear: `/Volumes` on macOS,
+/// `/media`, `/run/media` and `/mnt` (one or two levels deep) elsewhere.
+#[cfg(not(windows))]
+fn mount_points() -> Vec<PathBuf> {
+    let mut mounts = Vec::new();
+    for base in ["/Volumes", "/media", "/run/media", "/mnt"] {
+        for child in child_dirs(Path::new(base)) {
+            mounts.extend(child_dirs(&child));
+            mounts.push(child);
+        }
+    }
+    mounts.sort();
+    mounts.push(PathBuf::from("/"));
+    mounts
+}
+
+#[cfg(not(windows))]
+fn child_dirs(path: &Path) -> Vec<PathBuf> {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::db::InventoryRecord;
+    use tempfile::tempdir;
+
+    #[test]
+    fn detects_image_root_under_a_new_mount() -> Result<()> {
+        let old_mount = tempdir()?.keep();
+        let new_mount = tempdir()?.keep();
+        let data_dir = tempdir()?.keep();
+        let missing_root = old_mount.join("Card").join("DCIM");
+
+        let mut config = test_config(&data_dir);
+        config.sample_image_root = Some(missing_root.clone());
+        let database = Database::initialize(&config)?;
+        database.replace_inventory(&[InventoryRecord {
+            id: None,
+            file_hash: "hash".into(),
+            blake3_hash: None,
+            file_size: 4,
+            file_name: "a.jpg".into(),
+            relative_path: "100/a.jpg".into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }])?;
+
+        let relocated = new_mount.join("Card").join("DCIM");
+        fs::create_dir_all(relocated.join("100"))?;
+        fs::write(relocated.join("100").join("a.jpg"), b"four")?;
+
+        let mounts = vec![new_mount.clone()];
+        let remaps = detect_with_mounts(&config, &database, &mounts)?;
+        assert_eq!(remaps.len(), 1);
+        assert_eq!(remaps[0].kind, RootKind::Image);
+        assert_eq!(remaps[0].new_root, to_posix_string(&relocated));
+
+        // A file of the wrong size is not the same volume.
+        fs::write(relocated.join("100").join("a.jpg"), b"changed")?;
+        assert!(detect_with_mounts(&config, &database, &mounts)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn set_config_value_walks_objects_and_arrays() {
+        let mut document: Value =
+            serde_json::from_str(r#"{"outputVolumes": [{"root": "/a", "minFreeBytes": 1}]}"#)
+                .unwrap();
+        let key = |parts: &[&str]| {
+            parts
+                .iter()
+                .map(|part| part.to_string())
+                .collect::<Vec<_>>()
+        };
+        set_config_value(
+            &mut document,
+            &key(&["outputVolumes", "0", "root"]),
+            "/b".into(),
+        );
+        set_config_value(&mut document, &key(&["outputRoots", "video"]), "/v".into());
+        assert_eq!(document["outputVolumes"][0]["root"], "/b");
+        assert_eq!(document["outputVolumes"][0]["minFreeBytes"], 1);
+        assert_eq!(document["outputRoots"]["video"], "/v");
+    }
+}
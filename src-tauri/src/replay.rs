@@ -0,0 +1,110 @@
+use std::collections::{HashMap, VecDeque};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Events kept per channel; older ones are dropped first.
+pub const REPLAY_CAPACITY: usize = 512;
+
+/// An emitted event as remembered for replay. Sequence numbers increase
+/// across all channels, so a client can merge several channels in order.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayedEvent {
+    pub sequence: u64,
+    pub channel: String,
+    pub payload: Value,
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    next_sequence: u64,
+    channels: HashMap<String, VecDeque<ReplayedEvent>>,
+}
+
+/// Bounded history of emitted events, so windows that missed them can
+/// rebuild the progress timeline.
+#[derive(Debug)]
+pub struct EventReplay {
+    capacity: usize,
+    state: Mutex<ReplayState>,
+}
+
+impl Default for EventReplay {
+    fn default() -> Self {
+        Self::with_capacity(REPLAY_CAPACITY)
+    }
+}
+
+impl EventReplay {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(ReplayState::default()),
+        }
+    }
+
+    pub fn record<P: Serialize>(&self, channel: &str, payload: &P) {
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::debug!(channel, error = %err, "event payload not recorded for replay");
+                return;
+            }
+        };
+        let mut state = self.state.lock();
+        state.next_sequence += 1;
+        let event = ReplayedEvent {
+            sequence: state.next_sequence,
+            channel: channel.to_string(),
+            payload,
+        };
+        let buffer = state.channels.entry(channel.to_string()).or_default();
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(event);
+    }
+
+    /// Events on `channel` with a sequence number above `since`, oldest first.
+    pub fn replay(&self, channel: &str, since: Option<u64>) -> Vec<ReplayedEvent> {
+        let since = since.unwrap_or(0);
+        self.state
+            .lock()
+            .channels
+            .get(channel)
+            .map(|buffer| {
+                buffer
+                    .iter()
+                    .filter(|event| event.sequence > since)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replay_is_bounded_per_channel() {
+        let replay = EventReplay::with_capacity(2);
+        replay.record("scan", &json!({ "processed": 1 }));
+        replay.record("plan", &json!({ "processed": 1 }));
+        replay.record("scan", &json!({ "processed": 2 }));
+        replay.record("scan", &json!({ "processed": 3 }));
+
+        let scan = replay.replay("scan", None);
+        let sequences: Vec<u64> = scan.iter().map(|event| event.sequence).collect();
+        assert_eq!(sequences, vec![3, 4]);
+        assert_eq!(scan[1].payload["processed"], 3);
+
+        assert_eq!(replay.replay("scan", Some(3)).len(), 1);
+        assert_eq!(replay.replay("plan", None).len(), 1);
+        assert!(replay.replay("execute", None).is_empty());
+    }
+}
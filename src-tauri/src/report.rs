@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::db::{Database, PlanRecord, PlanStatus};
+use crate::error::Result;
+use crate::execute::{ExecutionMode, ExecutionSummary};
+use crate::progress::Headline;
+use crate::utils::fs::ensure_dir;
+use crate::utils::path::{to_native_path, to_posix_string};
+use crate::utils::time::now_timestamp;
+
+/// Folder under the output root holding a report for every run.
+const REPORTS_FOLDER: &str = "phototidy-reports";
+
+/// What one execution covered: when it started, the operations it logged
+/// and the plan entries it worked through.
+pub struct RunScope<'a> {
+    pub started_at: &'a str,
+    /// Operations logged with a higher id belong to the run.
+    pub logs_after: i64,
+    pub entry_ids: &'a HashSet<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionReport<'a> {
+    app_version: &'static str,
+    headline: String,
+    started_at: &'a str,
+    finished_at: String,
+    image_root: String,
+    output_root: String,
+    summary: &'a ExecutionSummary,
+    verification: Verification,
+    failures: Vec<ReportFailure>,
+    /// Target folders, relative to the output root where inside it.
+    buckets: BTreeMap<String, Vec<ReportFile>>,
+    undo: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Verification {
+    /// Targets found already holding the same content, left as they were.
+    identical_targets: usize,
+    /// Targets re-hashed after the transfer and matching the scan.
+    hash_verified: usize,
+    /// How files a move had to copy were checked before the original went:
+    /// `"size"`, `"blake3"`, or `None` for copy runs, which delete nothing.
+    moved_copies_checked_by: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportFile {
+    origin: String,
+    name: String,
+    status: &'static str,
+    duplicate: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportFailure {
+    origin: String,
+    reason: String,
+}
+
+/// Writes a Markdown report of the run and the same content as JSON into
+/// the output root's report folder, both named after the start time.
+/// Returns the Markdown file's path.
+pub fn write_session_report(
+    config: &AppConfig,
+    database: &Database,
+    summary: &ExecutionSummary,
+    scope: &RunScope<'_>,
+) -> Result<PathBuf> {
+    let entries: Vec<PlanRecord> = database
+        .plan_entries()?
+        .into_iter()
+        .filter(|entry| scope.entry_ids.contains(&entry.id))
+        .collect();
+    let mut reasons: HashMap<i64, String> = HashMap::new();
+    for log in database.operation_logs_after(scope.logs_after)? {
+        if let (Some(id), "failure", Some(error)) =
+            (log.plan_entry_id, log.status.as_str(), log.error)
+        {
+            reasons.insert(id, error);
+        }
+    }
+
+    let mut buckets: BTreeMap<String, Vec<ReportFile>> = BTreeMap::new();
+    let mut failures = Vec::new();
+    for entry in &entries {
+        if entry.status == PlanStatus::Failed {
+            failures.push(ReportFailure {
+                origin: entry.origin_full_path.clone(),
+                reason: reasons
+                    .get(&entry.id)
+                    .cloned()
+                    .unwrap_or_else(|| "unknown".to_string()),
+            });
+        }
+        buckets
+            .entry(bucket_name(&config.output_root, &entry.target_path))
+            .or_default()
+            .push(ReportFile {
+                origin: entry.origin_full_path.clone(),
+                name: entry.target_file_name.clone(),
+                status: entry.status.as_str(),
+                duplicate: entry.is_duplicate,
+            });
+    }
+
+    let report = SessionReport {
+        app_version: env!("CARGO_PKG_VERSION"),
+        headline: summary.headline(),
+        started_at: scope.started_at,
+        finished_at: now_timestamp()?,
+        image_root: to_posix_string(&config.image_root).into_owned(),
+        output_root: to_posix_string(&config.output_root).into_owned(),
+        summary,
+        verification: Verification {
+            identical_targets: summary.already_present,
+            hash_verified: summary.verified,
+            moved_copies_checked_by: match summary.mode {
+                ExecutionMode::Copy => None,
+                ExecutionMode::Move if config.io.verify_moves => Some("blake3"),
+                ExecutionMode::Move => Some("size"),
+            },
+        },
+        failures,
+        buckets,
+        undo: undo_instructions(summary),
+    };
+
+    let folder = config.output_root.join(REPORTS_FOLDER);
+    ensure_dir(&folder)?;
+    let stem = format!("{}-{}", scope.started_at, summary.mode.as_str());
+    fs::write(
+        folder.join(format!("{stem}.json")),
+        serde_json::to_string_pretty(&report)?,
+    )?;
+    let markdown = folder.join(format!("{stem}.md"));
+    fs::write(&markdown, render_markdown(&report))?;
+    Ok(markdown)
+}
+
+fn bucket_name(output_root: &Path, target_path: &str) -> String {
+    let target = to_native_path(target_path);
+    match target.strip_prefix(output_root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => to_posix_string(relative).into_owned(),
+        Err(_) => to_posix_string(&target).into_owned(),
+    }
+}
+
+fn undo_instructions(summary: &ExecutionSummary) -> String {
+    match summary.mode {
+        ExecutionMode::Move => format!(
+            "Undo in photoTidy moves the {} files this run moved back where they were, \
+             as long as the plan has not been regenerated.",
+            summary.succeeded
+        ),
+        ExecutionMode::Copy => "The originals were left in place. Deleting the copied files \
+                                from the output folder undoes this run."
+            .to_string(),
+    }
+}
+
+fn render_markdown(report: &SessionReport<'_>) -> String {
+    let summary = report.summary;
+    let mut out = String::new();
+    let _ = writeln!(out, "# photoTidy run report\n");
+    let _ = writeln!(out, "{}\n", report.headline);
+    let _ = writeln!(out, "- Started: {}", report.started_at);
+    let _ = writeln!(out, "- Finished: {}", report.finished_at);
+    let _ = writeln!(out, "- Images: `{}`", report.image_root);
+    let _ = writeln!(out, "- Output: `{}`", report.output_root);
+    let _ = writeln!(out, "- photoTidy {}\n", report.app_version);
+
+    let _ = writeln!(out, "## Totals\n");
+    let _ = writeln!(out, "| | Files |\n|---|---|");
+    let done = match summary.mode {
+        ExecutionMode::Copy => "Copied",
+        ExecutionMode::Move => "Moved",
+    };
+    for (label, count) in [
+        ("Planned", summary.total_entries),
+        (done, summary.succeeded),
+        ("Already in place", summary.already_present),
+        ("Failed", summary.failed),
+        ("Duplicates", summary.duplicate_entries),
+        ("Converted to JPEG", summary.transcoded),
+        ("Metadata stripped", summary.metadata_stripped),
+        ("Shared copies", summary.shared),
+        ("Empty folders removed", summary.removed_folders),
+    ] {
+        let _ = writeln!(out, "| {label} | {count} |");
+    }
+    let _ = writeln!(
+        out,
+        "\nTook {:.1} s, read {} bytes, wrote {} bytes.\n",
+        summary.metrics.elapsed_ms as f64 / 1000.0,
+        summary.metrics.bytes_read,
+        summary.metrics.bytes_written
+    );
+
+    let _ = writeln!(out, "## Verification\n");
+    let _ = writeln!(
+        out,
+        "- Targets already holding the same content: {}",
+        report.verification.identical_targets
+    );
+    if report.verification.hash_verified > 0 {
+        let _ = writeln!(
+            out,
+            "- Targets re-hashed after the transfer and matching the scan: {}",
+            report.verification.hash_verified
+        );
+    }
+    let checked = match report.verification.moved_copies_checked_by {
+        Some("blake3") => {
+            "Moves that had to copy compared sizes and BLAKE3 hashes before deleting originals."
+        }
+        Some(_) => "Moves that had to copy compared sizes before deleting originals.",
+        None => "Copies leave the originals in place.",
+    };
+    let _ = writeln!(out, "- {checked}\n");
+
+    let _ = writeln!(out, "## Failures\n");
+    if report.failures.is_empty() {
+        let _ = writeln!(out, "None.\n");
+    } else {
+        for failure in &report.failures {
+            let _ = writeln!(out, "- `{}`: {}", failure.origin, failure.reason);
+        }
+        let _ = writeln!(out);
+    }
+
+    let _ = writeln!(out, "## Folders\n");
+    for (bucket, files) in &report.buckets {
+        let _ = writeln!(out, "### {bucket} ({} files)\n", files.len());
+        for file in files {
+            let duplicate = if file.duplicate { ", duplicate" } else { "" };
+            let _ = writeln!(
+                out,
+                "- `{}` → `{}` ({}{duplicate})",
+                file.origin, file.name, file.status
+            );
+        }
+        let _ = writeln!(out);
+    }
+
+    let _ = writeln!(out, "## Undo\n");
+    let _ = writeln!(out, "{}", report.undo);
+    out
+}
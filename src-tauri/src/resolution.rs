@@ -0,0 +1,632 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::db::{
+    Database, DuplicateConfirmation, DuplicateDecision, DuplicateDecisionRecord, InventoryRecord,
+    NewOperationLog,
+};
+use crate::error::{AppError, Result};
+use crate::utils::fs::ensure_parent_dir;
+use crate::utils::hash::blake3_file;
+use crate::utils::path::{to_native_path, to_posix_string};
+
+/// Folder under the output root that trashed duplicates are moved to.
+const TRASH_FOLDER: &str = ".trash";
+
+/// Copies of one content: byte-identical files, and files whose pixels
+/// match the kept one. The id is the kept file's hash at scan time.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub id: String,
+    pub files: Vec<GroupFile>,
+    /// Which file the scan kept, by path below the image root or, under an
+    /// extra root, full path.
+    pub automatic_keeper: String,
+    pub decision: Option<DuplicateDecisionRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupFile {
+    pub relative_path: String,
+    pub file_size: u64,
+    pub captured_at: Option<String>,
+    /// What backs the verdict for a file the scan flagged; `None` for the
+    /// kept one.
+    pub confirmation: Option<DuplicateConfirmation>,
+}
+
+/// Every duplicate group in the inventory with its decision, undecided
+/// groups first.
+pub fn duplicate_groups(database: &Database) -> Result<Vec<DuplicateGroup>> {
+    let inventory = database.inventory_snapshot()?;
+    let mut decisions = decisions_by_group(database)?;
+    let mut groups: Vec<DuplicateGroup> = group_members(&inventory)
+        .into_iter()
+        .map(|(id, members)| {
+            let automatic_keeper = members
+                .iter()
+                .map(|&idx| &inventory[idx])
+                .find(|record| !record.is_duplicate)
+                .map(|record| record.source_key())
+                .unwrap_or_default();
+            let files = members
+                .iter()
+                .map(|&idx| {
+                    let record = &inventory[idx];
+                    GroupFile {
+                        relative_path: record.source_key(),
+                        file_size: record.file_size,
+                        captured_at: record.captured_at.clone(),
+                        confirmation: record.duplicate_confirmation,
+                    }
+                })
+                .collect();
+            DuplicateGroup {
+                decision: decisions.remove(&id),
+                id,
+                files,
+                automatic_keeper,
+            }
+        })
+        .collect();
+    groups.sort_by_key(|group| group.decision.is_some());
+    Ok(groups)
+}
+
+/// Stores `decision` for group `group_id`, or clears it with `None` so the
+/// scan's choice applies again. A kept file must belong to the group.
+pub fn decide_duplicate_group(
+    database: &Database,
+    group_id: &str,
+    decision: Option<DuplicateDecision>,
+) -> Result<Option<DuplicateDecisionRecord>> {
+    let Some(decision) = decision else {
+        database.clear_duplicate_decision(group_id)?;
+        return Ok(None);
+    };
+    let inventory = database.inventory_snapshot()?;
+    let groups = group_members(&inventory);
+    let Some(members) = groups.get(group_id) else {
+        return Err(AppError::Config(format!(
+            "{group_id} is not a duplicate group; run a scan first"
+        )));
+    };
+    if let DuplicateDecision::Keep { keeper } = &decision {
+        if !members
+            .iter()
+            .any(|&idx| inventory[idx].source_key() == *keeper)
+        {
+            return Err(AppError::Config(format!(
+                "{keeper} is not in duplicate group {group_id}"
+            )));
+        }
+    }
+    database
+        .set_duplicate_decision(group_id, &decision)
+        .map(Some)
+}
+
+/// Decisions loaded from the database, applied to the inventory at plan
+/// time in place of the scan's choices.
+#[derive(Debug, Default)]
+pub struct DuplicateDecisions(HashMap<String, DuplicateDecision>);
+
+impl DuplicateDecisions {
+    pub fn load(database: &Database) -> Result<Self> {
+        Ok(Self(
+            database
+                .duplicate_decisions()?
+                .into_iter()
+                .map(|record| (record.group_id, record.decision))
+                .collect(),
+        ))
+    }
+
+    /// Re-marks decided groups in `inventory` and takes out the ones left
+    /// for later, returning those.
+    pub fn apply(&self, inventory: &mut Vec<InventoryRecord>) -> Vec<InventoryRecord> {
+        if self.0.is_empty() {
+            return Vec::new();
+        }
+        let mut held = vec![false; inventory.len()];
+        for (id, members) in group_members(inventory) {
+            let Some(decision) = self.0.get(&id) else {
+                continue;
+            };
+            match decision {
+                DuplicateDecision::Keep { keeper } => {
+                    let Some(&kept) = members
+                        .iter()
+                        .find(|&&idx| inventory[idx].source_key() == *keeper)
+                    else {
+                        tracing::warn!(group = %id, %keeper, "kept file is gone; the scan's choice applies");
+                        continue;
+                    };
+                    let confirmation = inventory[kept]
+                        .duplicate_confirmation
+                        .unwrap_or(DuplicateConfirmation::Size);
+                    for idx in members {
+                        let record = &mut inventory[idx];
+                        record.is_duplicate = idx != kept;
+                        record.duplicate_confirmation = match record.duplicate_confirmation {
+                            _ if idx == kept => None,
+                            Some(existing) => Some(existing),
+                            None => Some(confirmation),
+                        };
+                    }
+                }
+                DuplicateDecision::KeepAll => {
+                    for idx in members {
+                        inventory[idx].is_duplicate = false;
+                        inventory[idx].duplicate_confirmation = None;
+                    }
+                }
+                DuplicateDecision::Later => {
+                    for idx in members {
+                        held[idx] = true;
+                    }
+                }
+            }
+        }
+        let (kept, held_back) = std::mem::take(inventory)
+            .into_iter()
+            .zip(held)
+            .partition::<Vec<_>, _>(|(_, held)| !held);
+        *inventory = kept.into_iter().map(|(record, _)| record).collect();
+        held_back.into_iter().map(|(record, _)| record).collect()
+    }
+}
+
+/// What `delete_duplicates` would remove, and the token that confirms it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionPreview {
+    /// Pass back to `delete_duplicates`; it changes with the files, so a
+    /// rescan in between invalidates it.
+    pub token: String,
+    pub files: Vec<DeletionCandidate>,
+    pub total_bytes: u64,
+    pub skipped: Vec<SkippedGroup>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionCandidate {
+    pub group_id: String,
+    pub relative_path: String,
+    /// The copy that stays, which must still match at deletion time.
+    pub keeper: String,
+    pub file_size: u64,
+    #[serde(skip)]
+    file_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedGroup {
+    pub group_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionSummary {
+    pub deleted: usize,
+    pub freed_bytes: u64,
+    /// Where trashed files went, when they were trashed.
+    pub trash_dir: Option<String>,
+    pub failed: Vec<FailedDeletion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedDeletion {
+    pub relative_path: String,
+    pub error: String,
+}
+
+/// Lists the duplicates in `group_ids` that deleting them would remove,
+/// after the groups' decisions. Groups left for later, kept whole or only
+/// matching by pixels are skipped: only byte-identical copies are deleted.
+pub fn prepare_duplicate_deletion(
+    database: &Database,
+    group_ids: &[String],
+    to_trash: bool,
+) -> Result<DeletionPreview> {
+    let mut inventory = database.inventory_snapshot()?;
+    let groups: BTreeMap<String, Vec<String>> = group_members(&inventory)
+        .into_iter()
+        .map(|(id, members)| {
+            let paths = members
+                .iter()
+                .map(|&idx| inventory[idx].source_key())
+                .collect();
+            (id, paths)
+        })
+        .collect();
+    for id in group_ids {
+        if !groups.contains_key(id) {
+            return Err(AppError::Config(format!(
+                "{id} is not a duplicate group; run a scan first"
+            )));
+        }
+    }
+    let held: HashSet<String> = DuplicateDecisions::load(database)?
+        .apply(&mut inventory)
+        .into_iter()
+        .map(|record| record.source_key())
+        .collect();
+    let decided = group_members(&inventory);
+
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    for id in group_ids {
+        let skip = |reason: &str| SkippedGroup {
+            group_id: id.clone(),
+            reason: reason.into(),
+        };
+        let Some(members) = decided.get(id) else {
+            let left = groups[id].iter().any(|path| held.contains(path));
+            skipped.push(skip(if left {
+                "left to decide later"
+            } else {
+                "every copy is kept"
+            }));
+            continue;
+        };
+        let Some(keeper) = members
+            .iter()
+            .map(|&idx| &inventory[idx])
+            .find(|record| !record.is_duplicate)
+        else {
+            skipped.push(skip("no copy is kept"));
+            continue;
+        };
+        let before = files.len();
+        for &idx in members {
+            let record = &inventory[idx];
+            if !record.is_duplicate {
+                continue;
+            }
+            if record.file_hash != keeper.file_hash
+                || record.duplicate_confirmation == Some(DuplicateConfirmation::Pixels)
+            {
+                skipped.push(skip(&format!(
+                    "{} only matches by pixels",
+                    record.source_key()
+                )));
+                continue;
+            }
+            files.push(DeletionCandidate {
+                group_id: id.clone(),
+                relative_path: record.source_key(),
+                keeper: keeper.source_key(),
+                file_size: record.file_size,
+                file_hash: record.file_hash.clone(),
+            });
+        }
+        if files.len() == before && skipped.last().map(|last| &last.group_id) != Some(id) {
+            skipped.push(skip("nothing to delete"));
+        }
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[u8::from(to_trash)]);
+    for file in &files {
+        for part in [
+            &file.group_id,
+            &file.relative_path,
+            &file.keeper,
+            &file.file_hash,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update(&[0]);
+        }
+    }
+    Ok(DeletionPreview {
+        token: hasher.finalize().to_hex().to_string(),
+        total_bytes: files.iter().map(|file| file.file_size).sum(),
+        files,
+        skipped,
+    })
+}
+
+/// Where trashed duplicates go: inside the output root, so trashing is a
+/// rename on the same disk, but left out of the library and the scan.
+pub fn trash_dir(config: &AppConfig) -> PathBuf {
+    config.output_root.join(TRASH_FOLDER)
+}
+
+/// Deletes the duplicates `prepare_duplicate_deletion` listed for the same
+/// arguments, or moves them to the trash folder under the output root,
+/// once `token` shows the user confirmed that list. Each file is hashed
+/// against its kept copy first and left alone unless they still match.
+/// A file that cannot be checked or removed is reported and the rest go on.
+/// Every file removed or not is logged as an operation.
+pub fn delete_duplicates(
+    config: &AppConfig,
+    database: &Database,
+    group_ids: &[String],
+    to_trash: bool,
+    token: &str,
+) -> Result<DeletionSummary> {
+    let preview = prepare_duplicate_deletion(database, group_ids, to_trash)?;
+    if preview.token != token {
+        return Err(AppError::Config(
+            "the duplicates changed since they were confirmed; review them again".into(),
+        ));
+    }
+    let root_dir = config
+        .sample_image_root
+        .as_ref()
+        .unwrap_or(&config.image_root);
+    let trash_dir = trash_dir(config);
+    let operation = if to_trash {
+        "trash_duplicate"
+    } else {
+        "delete_duplicate"
+    };
+
+    let mut summary = DeletionSummary {
+        trash_dir: to_trash.then(|| to_posix_string(&trash_dir).into_owned()),
+        ..DeletionSummary::default()
+    };
+    let mut removed = Vec::new();
+    for file in preview.files {
+        // Files under extra roots go by full paths, which `join` keeps.
+        let origin = root_dir.join(to_native_path(&file.relative_path));
+        let keeper = root_dir.join(to_native_path(&file.keeper));
+        let outcome = match (blake3_file(&origin), blake3_file(&keeper)) {
+            (Err(err), _) => Err(format!("could not hash the duplicate: {err}")),
+            (_, Err(err)) => Err(format!("could not hash the kept copy: {err}")),
+            (Ok(duplicate), Ok(kept)) if duplicate != kept => {
+                Err("no longer matches the kept copy".to_string())
+            }
+            _ => Ok(()),
+        };
+        let outcome = outcome.and_then(|()| {
+            if to_trash {
+                let inside: PathBuf = Path::new(&file.relative_path)
+                    .components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect();
+                move_to_trash(&origin, &trash_dir.join(inside)).map_err(|err| err.to_string())
+            } else {
+                fs::remove_file(&origin).map_err(|err| err.to_string())
+            }
+        });
+        let logged = database.append_operation_log(NewOperationLog {
+            plan_entry_id: None,
+            path: Some(to_posix_string(&origin).into_owned()),
+            operation: operation.into(),
+            status: if outcome.is_ok() {
+                "success"
+            } else {
+                "failure"
+            }
+            .into(),
+            error: outcome.clone().err(),
+        });
+        if let Err(err) = logged {
+            tracing::warn!(path = %origin.display(), error = %err, "failed logging duplicate deletion");
+        }
+        match outcome {
+            Ok(()) => {
+                summary.deleted += 1;
+                summary.freed_bytes += file.file_size;
+                removed.push(file.relative_path);
+            }
+            Err(error) => summary.failed.push(FailedDeletion {
+                relative_path: file.relative_path,
+                error,
+            }),
+        }
+    }
+    database.remove_inventory_records(&removed)?;
+    Ok(summary)
+}
+
+/// Moves `origin` to `target`, copying when they are on different volumes.
+fn move_to_trash(origin: &Path, target: &Path) -> io::Result<()> {
+    let target = free_name(target);
+    ensure_parent_dir(&target).map_err(io::Error::other)?;
+    if fs::rename(origin, &target).is_ok() {
+        return Ok(());
+    }
+    fs::copy(origin, &target)?;
+    if let Err(err) = fs::remove_file(origin) {
+        let _ = fs::remove_file(&target);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// `path`, or the first `name (n).ext` beside it not taken yet.
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|ext| ext.to_string_lossy());
+    let mut candidate = path.to_path_buf();
+    let mut attempt = 1;
+    while candidate.exists() {
+        let name = match &ext {
+            Some(ext) => format!("{stem} ({attempt}).{ext}"),
+            None => format!("{stem} ({attempt})"),
+        };
+        candidate = path.with_file_name(name);
+        attempt += 1;
+    }
+    candidate
+}
+
+fn decisions_by_group(database: &Database) -> Result<HashMap<String, DuplicateDecisionRecord>> {
+    Ok(database
+        .duplicate_decisions()?
+        .into_iter()
+        .map(|record| (record.group_id.clone(), record))
+        .collect())
+}
+
+/// Indexes of the records in each duplicate group, keyed by group id.
+/// Pixel duplicates join the group of the file whose pixels they match.
+fn group_members(inventory: &[InventoryRecord]) -> BTreeMap<String, Vec<usize>> {
+    let kept_pixels: HashMap<&str, &str> = inventory
+        .iter()
+        .filter(|record| !record.is_duplicate)
+        .filter_map(|record| Some((record.pixel_hash.as_deref()?, record.file_hash.as_str())))
+        .collect();
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (idx, record) in inventory.iter().enumerate() {
+        let id = match (record.duplicate_confirmation, &record.pixel_hash) {
+            (Some(DuplicateConfirmation::Pixels), Some(pixels)) => kept_pixels
+                .get(pixels.as_str())
+                .copied()
+                .unwrap_or(&record.file_hash),
+            _ => &record.file_hash,
+        };
+        groups.entry(id.to_string()).or_default().push(idx);
+    }
+    groups.retain(|_, members| {
+        members.len() > 1 && members.iter().any(|&idx| inventory[idx].is_duplicate)
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::library::{index_output, verify_library, LibraryProgressEmitter};
+    use crate::media::MediaKind;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn record(relative_path: &str, hash: &str, is_duplicate: bool) -> InventoryRecord {
+        InventoryRecord {
+            id: None,
+            file_hash: hash.into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: relative_path.rsplit('/').next().unwrap_or_default().into(),
+            relative_path: relative_path.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate,
+            duplicate_confirmation: is_duplicate.then_some(DuplicateConfirmation::Size),
+            pixel_hash: None,
+        }
+    }
+
+    #[test]
+    fn decisions_remark_or_hold_back_groups() {
+        let inventory = vec![
+            record("a/one.jpg", "h1", false),
+            record("b/one.jpg", "h1", true),
+            record("a/two.jpg", "h2", false),
+            record("b/two.jpg", "h2", true),
+            record("a/three.jpg", "h3", false),
+            record("b/three.jpg", "h3", true),
+            record("solo.jpg", "h4", false),
+        ];
+        assert_eq!(group_members(&inventory).len(), 3);
+
+        let decisions = DuplicateDecisions(HashMap::from([
+            (
+                "h1".to_string(),
+                DuplicateDecision::Keep {
+                    keeper: "b/one.jpg".into(),
+                },
+            ),
+            ("h2".to_string(), DuplicateDecision::KeepAll),
+            ("h3".to_string(), DuplicateDecision::Later),
+        ]));
+        let mut applied = inventory.clone();
+        assert_eq!(decisions.apply(&mut applied).len(), 2);
+        let duplicates: Vec<&str> = applied
+            .iter()
+            .filter(|record| record.is_duplicate)
+            .map(|record| record.relative_path.as_str())
+            .collect();
+        assert_eq!(duplicates, ["a/one.jpg"]);
+        assert_eq!(applied.len(), 5);
+        assert!(applied
+            .iter()
+            .all(|record| !record.relative_path.contains("three")));
+    }
+
+    #[test]
+    fn confirmed_duplicates_are_trashed_after_a_rehash() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let root_dir = config.image_root.clone();
+        let output_dir = config.output_root.clone();
+        let database = Database::initialize(&config)?;
+        for dir in ["a", "b", "c"] {
+            fs::create_dir_all(root_dir.join(dir))?;
+        }
+        fs::write(root_dir.join("a/one.jpg"), b"same")?;
+        fs::write(root_dir.join("b/one.jpg"), b"same")?;
+        fs::write(root_dir.join("a/two.jpg"), b"kept")?;
+        fs::write(root_dir.join("b/two.jpg"), b"edited since the scan")?;
+        // The kept copy of h4 is gone, so it cannot be checked.
+        fs::write(root_dir.join("b/four.jpg"), b"orphan")?;
+        database.replace_inventory(&[
+            record("a/one.jpg", "h1", false),
+            record("b/one.jpg", "h1", true),
+            record("a/two.jpg", "h2", false),
+            record("b/two.jpg", "h2", true),
+            record("c/three.jpg", "h3", false),
+            record("c/three (1).jpg", "h3", true),
+            record("a/four.jpg", "h4", false),
+            record("b/four.jpg", "h4", true),
+        ])?;
+        database.set_duplicate_decision("h3", &DuplicateDecision::Later)?;
+
+        let groups: Vec<String> = ["h1", "h2", "h3", "h4"].map(String::from).to_vec();
+        let preview = prepare_duplicate_deletion(&database, &groups, true)?;
+        assert_eq!(preview.files.len(), 3);
+        assert_eq!(preview.skipped[0].reason, "left to decide later");
+        assert!(delete_duplicates(&config, &database, &groups, false, &preview.token).is_err());
+
+        let summary = delete_duplicates(&config, &database, &groups, true, &preview.token)?;
+        assert_eq!((summary.deleted, summary.failed.len()), (1, 2));
+        let mut failed: Vec<&str> = summary
+            .failed
+            .iter()
+            .map(|failure| failure.relative_path.as_str())
+            .collect();
+        failed.sort();
+        assert_eq!(failed, ["b/four.jpg", "b/two.jpg"]);
+        assert!(!root_dir.join("b/one.jpg").exists());
+        assert!(output_dir.join(TRASH_FOLDER).join("b/one.jpg").exists());
+        assert!(root_dir.join("b/two.jpg").exists());
+        assert!(root_dir.join("b/four.jpg").exists());
+        assert_eq!(database.inventory_snapshot()?.len(), 7);
+        assert_eq!(database.operation_logs_after(0)?.len(), 3);
+
+        // The trash sits in the output root but is not part of the library.
+        let emitter: LibraryProgressEmitter = Arc::new(|_| {});
+        index_output(&config, &database, emitter.clone())?;
+        assert!(database
+            .library_entries()?
+            .iter()
+            .all(|entry| !entry.path.contains(TRASH_FOLDER)));
+        assert!(verify_library(&config, &database, emitter)?.clean);
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::config::{AppConfig, ConfigService};
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::permissions::is_permission_denied;
+use crate::remap::{set_config_value, RootKind};
+use crate::utils::path::{join_and_normalize, to_posix_string};
+
+/// Written and removed again to prove an output folder takes new files.
+const WRITE_PROBE: &str = ".phototidy-write-probe";
+
+/// Points the image or output root at `path`, usually a folder the user
+/// just picked. The folder is checked first, then stored in `config.json`;
+/// the duplicates folder and plan files follow the output root on reload.
+pub fn set_root(
+    service: &ConfigService,
+    database: &Database,
+    kind: RootKind,
+    path: &Path,
+) -> Result<AppConfig> {
+    let config = service.snapshot();
+    let root = join_and_normalize(env::current_dir()?, path)?;
+    validate_root(&config, kind, &root)?;
+
+    let key = match kind {
+        RootKind::Image => "imageRootDefaultName",
+        RootKind::Output => "outputRootName",
+    };
+    let value = path_value(&root);
+    let updated = service.update_file(|document| {
+        set_config_value(document, &[key.to_string()], value);
+        Ok(())
+    })?;
+    database.configure_paths(&updated);
+    Ok(updated)
+}
+
+/// Limits scans to `folders` inside the image root, or lifts the limit
+/// when empty. Folders may be given absolute or relative to the root; they
+/// are stored relative, without any that another one already covers.
+pub fn set_included_folders(service: &ConfigService, folders: &[String]) -> Result<AppConfig> {
+    let config = service.snapshot();
+    let kept = included_folders(&config.image_root, folders)?;
+    service.update_file(|document| {
+        set_config_value(document, &["includeFolders".to_string()], kept);
+        Ok(())
+    })
+}
+
+/// `ConfigPayload` fields stored under another key in `config.json`.
+const RENAMED_SETTINGS: &[(&str, &str)] = &[
+    ("duplicatesFolderName", "folderForDuplicates"),
+    ("targetPlanJson", "targetFileStructureJson"),
+    ("scanQueues", "queues"),
+];
+
+/// `ConfigPayload` fields stored as they are sent.
+const PLAIN_SETTINGS: &[&str] = &[
+    "photoExts",
+    "videoExts",
+    "rawExts",
+    "sidecarExts",
+    "originInfoJson",
+    "routingRules",
+    "folderLayout",
+    "folderTemplate",
+    "bucketGranularity",
+    "keepOriginalNames",
+    "normalizeExtensions",
+    "nameTemplate",
+    "maxFilesPerFolder",
+    "outputVolumes",
+    "share",
+    "transcode",
+    "export",
+    "photosLibrary",
+    "honorNomedia",
+    "cloudPlaceholders",
+    "followSymlinks",
+    "maxDepth",
+    "maxFilesPerDirectory",
+    "pixelHash",
+    "hashStrategy",
+    "scrub",
+    "pathStorage",
+    "power",
+    "io",
+    "telemetry",
+];
+
+/// Applies `changes`, keyed by `ConfigPayload`'s field names in camelCase
+/// (`photoExts`, not `photo_exts`) and holding only the fields to change,
+/// to `config.json` and the running config. Roots are
+/// checked as `set_root` checks them, against each other's new value when
+/// both change, and included folders are stored as `set_included_folders`
+/// stores them. Nothing is written unless the result loads as a config.
+pub fn update_config(
+    service: &ConfigService,
+    database: &Database,
+    changes: &Map<String, Value>,
+) -> Result<AppConfig> {
+    let edits = config_edits(&service.snapshot(), changes)?;
+    let updated = service.update_file(|document| {
+        for (key, value) in edits {
+            set_config_value(document, &[key], value);
+        }
+        Ok(())
+    })?;
+    database.configure_paths(&updated);
+    Ok(updated)
+}
+
+/// Turns `changes` into `config.json` keys and values.
+fn config_edits(config: &AppConfig, changes: &Map<String, Value>) -> Result<Vec<(String, Value)>> {
+    let cwd = env::current_dir()?;
+    let root = |key: &str| -> Result<Option<PathBuf>> {
+        match changes.get(key) {
+            None => Ok(None),
+            Some(Value::String(path)) => Ok(Some(join_and_normalize(&cwd, Path::new(path))?)),
+            Some(_) => Err(AppError::Config(format!("{key} must be a folder path"))),
+        }
+    };
+    let image_root = root("imageRoot")?;
+    let output_root = root("outputRoot")?;
+    let mut layout = config.clone();
+    if let Some(root) = &image_root {
+        layout.image_root = root.clone();
+    }
+    if let Some(root) = &output_root {
+        layout.output_root = root.clone();
+    }
+    if let Some(root) = &image_root {
+        validate_root(&layout, RootKind::Image, root)?;
+    }
+    if let Some(root) = &output_root {
+        validate_root(&layout, RootKind::Output, root)?;
+    }
+
+    let mut edits = Vec::with_capacity(changes.len());
+    for (key, value) in changes {
+        let edit = match key.as_str() {
+            "imageRoot" => ("imageRootDefaultName", path_value(&layout.image_root)),
+            "outputRoot" => ("outputRootName", path_value(&layout.output_root)),
+            "includeFolders" => {
+                let folders: Vec<String> = serde_json::from_value(value.clone())?;
+                (
+                    "includeFolders",
+                    included_folders(&layout.image_root, &folders)?,
+                )
+            }
+            "extraImageRoots" => {
+                let roots: Vec<String> = serde_json::from_value(value.clone())?;
+                ("extraImageRoots", extra_image_roots(&layout, &cwd, &roots)?)
+            }
+            key => {
+                let renamed = RENAMED_SETTINGS
+                    .iter()
+                    .find(|(field, _)| *field == key)
+                    .map(|(_, stored)| *stored);
+                match renamed.or_else(|| PLAIN_SETTINGS.iter().copied().find(|field| *field == key))
+                {
+                    Some(stored) => (stored, value.clone()),
+                    None => {
+                        return Err(AppError::Config(format!(
+                            "{key} cannot be changed with a config update"
+                        )))
+                    }
+                }
+            }
+        };
+        edits.push((edit.0.to_string(), edit.1));
+    }
+    Ok(edits)
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(to_posix_string(path).into_owned())
+}
+
+/// `folders` relative to `image_root`, without any another one covers.
+fn included_folders(image_root: &Path, folders: &[String]) -> Result<Value> {
+    let mut relative = Vec::with_capacity(folders.len());
+    for folder in folders {
+        relative.push(included_folder(image_root, Path::new(folder))?);
+    }
+    relative.sort();
+    relative.dedup();
+    let kept = relative
+        .iter()
+        .filter(|folder| {
+            !relative
+                .iter()
+                .any(|other| other != *folder && folder.starts_with(other))
+        })
+        .map(|folder| path_value(folder))
+        .collect();
+    Ok(Value::Array(kept))
+}
+
+/// `roots` as absolute folders, each checked like a picked image root.
+fn extra_image_roots(config: &AppConfig, cwd: &Path, roots: &[String]) -> Result<Value> {
+    let mut absolute = Vec::with_capacity(roots.len());
+    for root in roots {
+        let root = join_and_normalize(cwd, Path::new(root))?;
+        validate_root(config, RootKind::Image, &root)?;
+        absolute.push(path_value(&root));
+    }
+    Ok(Value::Array(absolute))
+}
+
+fn included_folder(image_root: &Path, folder: &Path) -> Result<PathBuf> {
+    let absolute = join_and_normalize(image_root, folder)?;
+    let relative = match absolute.strip_prefix(image_root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+        _ => {
+            return Err(AppError::Config(format!(
+                "{} is not a subfolder of the image folder",
+                absolute.display()
+            )))
+        }
+    };
+    if !absolute.is_dir() {
+        return Err(AppError::Config(format!(
+            "{} is not a folder",
+            absolute.display()
+        )));
+    }
+    Ok(relative)
+}
+
+fn validate_root(config: &AppConfig, kind: RootKind, root: &Path) -> Result<()> {
+    if !root.is_dir() {
+        return Err(AppError::Config(format!(
+            "{} is not a folder",
+            root.display()
+        )));
+    }
+    if let Err(err) = fs::read_dir(root) {
+        return Err(if is_permission_denied(&err) {
+            AppError::PermissionDenied(to_posix_string(root).into_owned())
+        } else {
+            err.into()
+        });
+    }
+
+    let (other, other_name) = match kind {
+        RootKind::Image => (&config.output_root, "output folder"),
+        RootKind::Output => (&config.image_root, "image folder"),
+    };
+    if root == other {
+        return Err(AppError::Config(format!(
+            "{} is already the {other_name}",
+            root.display()
+        )));
+    }
+    if root.starts_with(other) || other.starts_with(root) {
+        return Err(AppError::Config(format!(
+            "{} and the {other_name} {} must not contain each other",
+            root.display(),
+            other.display()
+        )));
+    }
+
+    if kind == RootKind::Output {
+        probe_writable(root)?;
+    }
+    Ok(())
+}
+
+fn probe_writable(root: &Path) -> Result<()> {
+    let probe = root.join(WRITE_PROBE);
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            fs::remove_file(&probe)?;
+            Ok(())
+        }
+        Err(err) if is_permission_denied(&err) => Err(AppError::PermissionDenied(
+            to_posix_string(root).into_owned(),
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    use crate::config::test_config;
+
+    #[test]
+    fn picked_roots_must_exist_and_stay_apart() -> Result<()> {
+        let dir = tempdir()?;
+        let images = dir.path().join("images");
+        let output = dir.path().join("output");
+        let elsewhere = dir.path().join("elsewhere");
+        for folder in [&images, &output, &elsewhere, &images.join("sorted")] {
+            fs::create_dir_all(folder)?;
+        }
+        let config = test_config(dir.path());
+
+        validate_root(&config, RootKind::Output, &elsewhere)?;
+        assert!(!elsewhere.join(WRITE_PROBE).exists());
+        validate_root(&config, RootKind::Image, &elsewhere)?;
+        assert!(validate_root(&config, RootKind::Output, &dir.path().join("missing")).is_err());
+        assert!(validate_root(&config, RootKind::Output, &images).is_err());
+        assert!(validate_root(&config, RootKind::Output, &images.join("sorted")).is_err());
+        assert!(validate_root(&config, RootKind::Image, dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn config_updates_map_payload_fields_to_file_keys() -> Result<()> {
+        let dir = tempdir()?;
+        let images = dir.path().join("images");
+        let output = dir.path().join("output");
+        let moved = dir.path().join("moved");
+        for folder in [&images, &output, &moved, &moved.join("trips")] {
+            fs::create_dir_all(folder)?;
+        }
+        let config = test_config(dir.path());
+        let changes = |value: Value| value.as_object().cloned().unwrap_or_default();
+
+        let edits = config_edits(
+            &config,
+            &changes(serde_json::json!({
+                "imageRoot": moved.to_string_lossy(),
+                "includeFolders": ["trips"],
+                "duplicatesFolderName": "dupes",
+                "photoExts": [".jpg"],
+                "folderTemplate": "{camera_model}/{year}",
+                "maxFilesPerFolder": 500,
+            })),
+        )?;
+        let edits: HashMap<_, _> = edits.into_iter().collect();
+        assert_eq!(edits["imageRootDefaultName"], path_value(&moved));
+        assert_eq!(edits["includeFolders"], serde_json::json!(["trips"]));
+        assert_eq!(edits["folderForDuplicates"], "dupes");
+        assert_eq!(edits["photoExts"], serde_json::json!([".jpg"]));
+        assert_eq!(edits["folderTemplate"], "{camera_model}/{year}");
+        assert_eq!(edits["maxFilesPerFolder"], 500);
+
+        // Swapping the roots is checked against the new values.
+        let swapped = changes(serde_json::json!({
+            "imageRoot": output.to_string_lossy(),
+            "outputRoot": images.to_string_lossy(),
+        }));
+        assert_eq!(config_edits(&config, &swapped)?.len(), 2);
+        let clash = changes(serde_json::json!({ "imageRoot": output.to_string_lossy() }));
+        assert!(config_edits(&config, &clash).is_err());
+        let extra = changes(serde_json::json!({ "extraImageRoots": [moved.to_string_lossy()] }));
+        assert_eq!(
+            config_edits(&config, &extra)?,
+            [(
+                "extraImageRoots".to_string(),
+                serde_json::json!([path_value(&moved)])
+            )]
+        );
+        let missing = changes(serde_json::json!({
+            "extraImageRoots": [dir.path().join("missing").to_string_lossy()],
+        }));
+        assert!(config_edits(&config, &missing).is_err());
+        let derived = changes(serde_json::json!({ "databasePath": "elsewhere.sqlite3" }));
+        assert!(config_edits(&config, &derived).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn included_folders_must_be_inside_the_image_root() -> Result<()> {
+        let dir = tempdir()?;
+        let images = dir.path().join("images");
+        fs::create_dir_all(images.join("trips/paris"))?;
+
+        assert_eq!(
+            included_folder(&images, Path::new("trips/paris"))?,
+            Path::new("trips/paris")
+        );
+        assert_eq!(
+            included_folder(&images, &images.join("trips"))?,
+            Path::new("trips")
+        );
+        assert!(included_folder(&images, Path::new("missing")).is_err());
+        assert!(included_folder(&images, Path::new("../images")).is_err());
+        assert!(included_folder(&images, dir.path()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config::RoutingRule;
+use crate::db::InventoryRecord;
+use crate::error::{AppError, Result};
+use crate::template::{NameTemplate, Template};
+
+const SAMPLE_LIMIT: usize = 5;
+
+/// Per-rule outcome of a planning pass, used as the dry-run explanation of
+/// how the configured rules routed the inventory.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleReport {
+    pub order: usize,
+    pub name: String,
+    pub target: String,
+    pub matched: usize,
+    pub samples: Vec<RuleSample>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSample {
+    pub relative_path: String,
+    pub target_dir: String,
+}
+
+pub struct RuleTracker {
+    reports: Vec<RuleReport>,
+}
+
+impl RuleTracker {
+    pub fn new(rules: &[RoutingRule]) -> Self {
+        let reports = rules
+            .iter()
+            .enumerate()
+            .map(|(order, rule)| RuleReport {
+                order,
+                name: rule.name.clone(),
+                target: rule.target.clone(),
+                matched: 0,
+                samples: Vec::new(),
+            })
+            .collect();
+        Self { reports }
+    }
+
+    pub fn record(&mut self, order: usize, relative_path: &str, target_dir: &str) {
+        if let Some(report) = self.reports.get_mut(order) {
+            report.matched += 1;
+            if report.samples.len() < SAMPLE_LIMIT {
+                report.samples.push(RuleSample {
+                    relative_path: relative_path.to_string(),
+                    target_dir: target_dir.to_string(),
+                });
+            }
+        }
+    }
+
+    pub fn into_reports(self) -> Vec<RuleReport> {
+        self.reports
+    }
+}
+
+/// Returns the first rule (and its position) matching the record.
+pub fn match_rule<'a>(
+    rules: &'a [RoutingRule],
+    record: &InventoryRecord,
+) -> Option<(usize, &'a RoutingRule)> {
+    rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule_matches(rule, record))
+}
+
+fn rule_matches(rule: &RoutingRule, record: &InventoryRecord) -> bool {
+    if let Some(needle) = &rule.path_contains {
+        let haystack = record.relative_path.to_lowercase();
+        if !haystack.contains(&needle.replace('\\', "/").to_lowercase()) {
+            return false;
+        }
+    }
+
+    if !rule.extensions.is_empty() {
+        let extension = Path::new(&record.file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| format!(".{}", ext.to_ascii_lowercase()));
+        let Some(extension) = extension else {
+            return false;
+        };
+        let matched = rule.extensions.iter().any(|candidate| {
+            let candidate = candidate.trim().to_ascii_lowercase();
+            candidate.trim_start_matches('.') == extension.trim_start_matches('.')
+        });
+        if !matched {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Compiles every rule target once per run; targets were already validated
+/// at config load, so failures here only surface hand-built configs.
+pub fn compile_targets(rules: &[RoutingRule]) -> Result<Vec<Template>> {
+    rules
+        .iter()
+        .map(|rule| {
+            Template::parse(&rule.target).map_err(|source| AppError::Template {
+                field: format!("routingRules.{}.target", rule.name),
+                source,
+            })
+        })
+        .collect()
+}
+
+/// Parses the date bucket template, if one is set.
+pub fn compile_folder_template(template: Option<&str>) -> Result<Option<Template>> {
+    template
+        .map(|template| {
+            Template::parse(template).map_err(|source| AppError::Template {
+                field: "folderTemplate".into(),
+                source,
+            })
+        })
+        .transpose()
+}
+
+/// Parses the file name template, if one is set.
+pub fn compile_name_template(template: Option<&str>) -> Result<Option<NameTemplate>> {
+    template
+        .map(|template| {
+            NameTemplate::parse(template).map_err(|source| AppError::Template {
+                field: "nameTemplate".into(),
+                source,
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MediaKind;
+
+    fn record(relative_path: &str) -> InventoryRecord {
+        InventoryRecord {
+            id: None,
+            file_hash: "hash".into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: relative_path.rsplit('/').next().unwrap_or_default().into(),
+            relative_path: relative_path.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-06-14_10-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }
+    }
+
+    fn rules() -> Vec<RoutingRule> {
+        vec![
+            RoutingRule {
+                name: "screenshots".into(),
+                path_contains: Some("Screenshots".into()),
+                extensions: Vec::new(),
+                target: "screenshots/{year}".into(),
+                strip_metadata: false,
+            },
+            RoutingRule {
+                name: "videos".into(),
+                path_contains: None,
+                extensions: vec![".mp4".into()],
+                target: "videos/{year}/{month}".into(),
+                strip_metadata: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = rules();
+        let (order, rule) = match_rule(&rules, &record("phone/Screenshots/clip.MP4")).unwrap();
+        assert_eq!(order, 0);
+        assert_eq!(rule.name, "screenshots");
+
+        let (order, _) = match_rule(&rules, &record("trip/clip.MP4")).unwrap();
+        assert_eq!(order, 1);
+        assert!(match_rule(&rules, &record("trip/photo.jpg")).is_none());
+    }
+
+    #[test]
+    fn compile_targets_reports_the_offending_rule() {
+        assert_eq!(compile_targets(&rules()).unwrap().len(), 2);
+
+        let mut broken = rules();
+        broken[1].target = "videos/{camera}".into();
+        let err = compile_targets(&broken).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Template { ref field, .. } if field == "routingRules.videos.target"
+        ));
+    }
+}
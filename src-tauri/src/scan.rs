@@ -1,28 +1,65 @@
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::thread;
 
 use exif::{In, Tag, Value};
+use parking_lot::Mutex;
 use pathdiff::diff_paths;
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use time::{
     format_description::FormatItem, macros::format_description, OffsetDateTime, PrimitiveDateTime,
 };
 use walkdir::WalkDir;
 
-use crate::config::AppConfig;
-use crate::db::{Database, InventoryRecord};
+use crate::cloud::{self, Placeholder};
+use crate::config::{
+    AppConfig, CloudPlaceholderMode, HashStrategy, PhotosLibraryMode, ScanOptions,
+};
+use crate::db::{
+    is_unhashed, source_dir, source_key, CachedHash, CatalogEntry, Database, DuplicateConfirmation,
+    InventoryRecord, ScanStats, UNHASHED_PREFIX,
+};
+use crate::derivative::classify as classify_derivative;
+use crate::diff::{inventory_changes, ScanDiff};
 use crate::error::{AppError, Result};
+use crate::ignore::IgnoreFiles;
+use crate::journal::{self, JournalCursor};
+use crate::media::{MediaCounts, MediaKind};
+use crate::metrics::{MetricsRecorder, RunMetrics};
+use crate::network::IoProfile;
+use crate::permissions::is_permission_denied;
+use crate::pixels::pixel_hash;
+use crate::progress::TaskControl;
+use crate::resolution::trash_dir;
+use crate::takeout::{read_sidecar, TakeoutMetadata};
+use crate::telemetry;
 use crate::utils::{
-    fs::matches_extension,
+    fs::{file_identity, link_count, matches_extension, FileIdentity},
     hash::{blake3_file, md5_file},
-    path::to_posix_string,
+    path::{clean_path, normalize, strip_copy_suffix, to_posix_string},
+    sparse::{allocated_size, is_sparse},
     time as time_utils,
 };
+use crate::video;
+
+const NOMEDIA_MARKER: &str = ".nomedia";
+const PHOTOS_LIBRARY_EXT: &str = "photoslibrary";
+const PHOTOS_ORIGINALS_DIRS: [&str; 2] = ["originals", "Masters"];
+/// The root whose hashes the checkpoint holds.
+const CHECKPOINT_ROOT_KEY: &str = "scan_checkpoint_root";
+const SCAN_DIFF_KEY: &str = "last_scan_diff";
+const JOURNAL_MARK_KEY: &str = "journal_mark";
+/// Read throughput of the last scan that read enough to measure it.
+const SCAN_THROUGHPUT_KEY: &str = "scan_throughput";
+/// Scans reading less than this say little about the disk's speed.
+const THROUGHPUT_SAMPLE_BYTES: u64 = 64 * 1024 * 1024;
+/// Assumed read speeds, in bytes per second, before any scan measured one.
+const LOCAL_THROUGHPUT: u64 = 80 * 1024 * 1024;
+const NETWORK_THROUGHPUT: u64 = 10 * 1024 * 1024;
 
 const EXIF_DATETIME_FORMAT: &[FormatItem<'_>] =
     format_description!("[year]:[month]:[day] [hour]:[minute]:[second]");
@@ -34,15 +71,154 @@ pub struct ScanSummary {
     pub hashed_files: usize,
     pub skipped_files: usize,
     pub duplicate_files: usize,
+    /// Files that look like thumbnails or cache artifacts.
+    pub derivative_files: usize,
+    pub media_counts: MediaCounts,
+    /// Apple Photos bundles left out of the scan.
+    pub skipped_photos_libraries: Vec<String>,
+    /// Apple Photos bundles whose originals were scanned; moving these files
+    /// breaks the library, so the UI should warn before executing.
+    pub scanned_photos_libraries: Vec<String>,
+    /// Folders skipped because they hold a `.nomedia` marker.
+    pub nomedia_folders: Vec<String>,
+    /// Files and folders left out by `.phototidyignore` rules.
+    pub ignored_entries: usize,
+    /// Output or duplicates folders inside the source that were not walked.
+    pub skipped_output_folders: usize,
+    /// Symlinks followed when `followSymlinks` is on.
+    pub followed_links: Vec<String>,
+    /// Extra names of hard-linked files, scanned and planned only once.
+    pub hardlinked_files: usize,
+    /// Pipes, sockets and devices named like media, never opened.
+    pub special_files: Vec<String>,
+    /// Files with holes, whose length overstates the disk they take.
+    pub sparse_files: SparseFiles,
+    /// Where `maxDepth` or `maxFilesPerDirectory` cut the scan short.
+    pub limit_hits: ScanLimitHits,
+    /// Cloud files left out because they are not downloaded.
+    pub cloud_placeholders: Vec<String>,
+    /// Cloud files downloaded so they could be hashed.
+    pub hydrated_placeholders: usize,
+    /// Files hashed by an earlier scan that was interrupted.
+    pub resumed_files: usize,
+    /// Files left unhashed because no other file has their size.
+    pub unhashed_files: usize,
+    /// Files whose hashes were known by identity, typically because they
+    /// were renamed or moved since the last scan.
+    pub cached_files: usize,
+    /// Folders re-read because the change journal listed them; `None` when
+    /// the whole tree was walked.
+    pub journal_folders: Option<usize>,
+    /// Changes since the previous scan.
+    pub diff: ScanDiff,
+    pub metrics: RunMetrics,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanLimitHits {
+    /// Folders below `maxDepth` that were not walked.
+    pub deep_folders: Vec<String>,
+    /// Folders holding more media than `maxFilesPerDirectory`.
+    pub crowded_folders: Vec<String>,
+    /// Media files left out of the crowded folders.
+    pub files_left_out: usize,
+}
+
+impl ScanLimitHits {
+    fn any(&self) -> bool {
+        !self.deep_folders.is_empty() || !self.crowded_folders.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SparseFiles {
+    pub files: usize,
+    /// Their combined length.
+    pub apparent_bytes: u64,
+    /// What they occupy on disk, and what copying them takes.
+    pub allocated_bytes: u64,
+}
+
+impl SparseFiles {
+    fn tally(snapshots: &[FileSnapshot]) -> Self {
+        let mut sparse = Self::default();
+        for snapshot in snapshots {
+            if let Some(allocated) = snapshot.allocated_size {
+                sparse.files += 1;
+                sparse.apparent_bytes += snapshot.file_size;
+                sparse.allocated_bytes += allocated;
+            }
+        }
+        sparse
+    }
+}
+
+/// Scan stages, in the order they start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScanStage {
+    /// Walking the folders for media files.
+    Enumerate,
+    /// Reading size and modification time of each file.
+    Snapshot,
+    /// Matching files against the previous inventory by size and
+    /// modification time, so unchanged files are not read again.
+    QuickHash,
+    /// Reading changed files in full to hash them.
+    FullHash,
+    /// Reading capture date, camera and location of changed files.
+    Exif,
+    /// Flagging duplicates and saving the inventory.
+    Persist,
+}
+
+impl ScanStage {
+    pub const ALL: [ScanStage; 6] = [
+        ScanStage::Enumerate,
+        ScanStage::Snapshot,
+        ScanStage::QuickHash,
+        ScanStage::FullHash,
+        ScanStage::Exif,
+        ScanStage::Persist,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScanStage::Enumerate => "enumerate",
+            ScanStage::Snapshot => "snapshot",
+            ScanStage::QuickHash => "quickHash",
+            ScanStage::FullHash => "fullHash",
+            ScanStage::Exif => "exif",
+            ScanStage::Persist => "persist",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageProgress {
+    pub stage: ScanStage,
+    pub processed: usize,
+    /// Set when the stage starts and fixed from then on; unknown while
+    /// enumerating.
+    pub total: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ScanProgressPayload {
-    pub stage: &'static str,
+    /// The stage that moved last, with its counts below.
+    pub stage: ScanStage,
     pub processed: usize,
-    pub total: usize,
+    pub total: Option<usize>,
     pub current: Option<String>,
+    /// Every stage in order. Hashing and EXIF reading run side by side, so
+    /// more than one stage can be under way.
+    pub stages: Vec<StageProgress>,
+    /// Set on the last payload of a cancelled scan.
+    pub cancelled: bool,
 }
 
 pub type ProgressEmitter = Arc<dyn Fn(ScanProgressPayload) + Send + Sync>;
@@ -50,9 +226,31 @@ pub type ProgressEmitter = Arc<dyn Fn(ScanProgressPayload) + Send + Sync>;
 struct FileSnapshot {
     absolute_path: PathBuf,
     relative_path: String,
+    /// See `InventoryRecord::source_root`.
+    source_root: Option<String>,
     file_name: String,
     file_size: u64,
     modified_at: String,
+    identity: Option<FileIdentity>,
+    /// Names the file has on its volume; above one it is hard-linked.
+    hard_links: u64,
+    /// Bytes on disk, set only for sparse files.
+    allocated_size: Option<u64>,
+    /// No other file has this size, so it is left unhashed when the
+    /// strategy allows.
+    skip_hash: bool,
+    /// Hashes found in the cache for this file's identity.
+    cached: Option<CachedHash>,
+}
+
+impl FileSnapshot {
+    fn needs_hashing(&self) -> bool {
+        !self.skip_hash && self.cached.is_none()
+    }
+
+    fn source_key(&self) -> String {
+        source_key(self.source_root.as_deref(), &self.relative_path)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -61,133 +259,850 @@ struct ExifMetadata {
     camera_model: Option<String>,
     camera_make: Option<String>,
     artist: Option<String>,
+    gps: Option<(f64, f64)>,
 }
 
+/// Scans the image root and any extra roots into the inventory. A cancelled
+/// scan leaves the inventory as it was, keeps the hashes done so far for the
+/// next scan, and ends with a progress payload marked cancelled.
 pub fn perform_scan(
     config: &AppConfig,
     database: &Database,
+    control: &TaskControl,
     emitter: ProgressEmitter,
 ) -> Result<ScanSummary> {
-    let root_dir = config
-        .sample_image_root
-        .as_ref()
-        .unwrap_or(&config.image_root);
+    let _run = tracing::info_span!("scan").entered();
+    let progress = ScanProgress::new(emitter);
+    let result = scan_root(config, database, control, &progress);
+    if matches!(result, Err(AppError::Cancelled)) {
+        progress.cancel();
+    }
+    result
+}
+
+fn scan_root(
+    config: &AppConfig,
+    database: &Database,
+    control: &TaskControl,
+    progress: &ScanProgress,
+) -> Result<ScanSummary> {
+    let source_roots = config.source_roots();
+    let roots: Vec<&Path> = source_roots.iter().map(PathBuf::as_path).collect();
+    let root_dir = &source_roots[0];
+
+    let root_key = roots
+        .iter()
+        .map(|root| to_posix_string(root))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let walk_settings = walk_settings(config)?;
+    // The journal only follows the image root.
+    let single_root = roots.len() == 1;
+    let journal_cursor = journal::cursor(root_dir).filter(|_| single_root);
+    let existing_records = database.inventory_snapshot()?;
+    // The journal only sees the tree itself, not where links lead.
+    let changed = if existing_records.is_empty() || config.scan.follow_symlinks || !single_root {
+        None
+    } else {
+        journal_changes(database, root_dir, &root_key, &walk_settings)?
+    };
 
-    let files = enumerate_files(root_dir, &config.image_exts, &emitter)?;
+    let scope = scan_scope(included_folders(config, root_dir), changed.as_deref());
+    let (mut files, folders) = enumerate_files(
+        &roots,
+        &config.image_exts,
+        &config.scan,
+        scope.as_deref(),
+        &output_folders(config)?,
+        progress,
+    )?;
+    if let Some(changed) = &changed {
+        // Files in folders without changes are taken as they were.
+        files.extend(
+            existing_records
+                .iter()
+                .filter(|record| record.source_root.is_none())
+                .map(|record| root_dir.join(&record.relative_path))
+                .filter(|path| !changed.iter().any(|folder| path.starts_with(folder))),
+        );
+        files.sort();
+    }
     if files.is_empty() {
         database.replace_inventory(&[])?;
-        emit_progress(&emitter, "scan", 0, 0, None);
-        emit_progress(&emitter, "diff", 0, 0, None);
-        emit_progress(&emitter, "hash", 0, 0, None);
+        database.clear_scan_checkpoint()?;
+        let diff = record_history(
+            database,
+            root_dir,
+            &existing_records,
+            &[],
+            ScanStats::default(),
+        )?;
+        for stage in ScanStage::ALL {
+            progress.start(stage, 0);
+        }
+        let metrics = progress.metrics.finish();
+        telemetry::finish_run("scan", metrics.elapsed(), &config.telemetry);
         return Ok(ScanSummary {
             total_files: 0,
             hashed_files: 0,
             skipped_files: 0,
             duplicate_files: 0,
+            derivative_files: 0,
+            media_counts: MediaCounts::default(),
+            skipped_photos_libraries: folders.photos_skipped,
+            scanned_photos_libraries: folders.photos_scanned,
+            nomedia_folders: folders.nomedia,
+            ignored_entries: folders.ignored,
+            skipped_output_folders: folders.output,
+            followed_links: folders.links,
+            hardlinked_files: 0,
+            special_files: folders.special,
+            sparse_files: SparseFiles::default(),
+            limit_hits: folders.limits,
+            cloud_placeholders: folders.placeholders,
+            hydrated_placeholders: folders.hydrated,
+            resumed_files: 0,
+            unhashed_files: 0,
+            cached_files: 0,
+            journal_folders: None,
+            diff,
+            metrics,
         });
     }
 
-    let snapshots = build_snapshots(root_dir, files)?;
+    let mut snapshots = build_snapshots(&roots, files, progress)?;
+    let hardlinked_files = drop_hardlink_aliases(&mut snapshots);
+    let sparse_files = SparseFiles::tally(&snapshots);
+    if sparse_files.files > 0 {
+        tracing::info!(
+            files = sparse_files.files,
+            apparent_bytes = sparse_files.apparent_bytes,
+            allocated_bytes = sparse_files.allocated_bytes,
+            "found sparse files"
+        );
+    }
     let total_files = snapshots.len();
+    if config.scan.hash_strategy == HashStrategy::SizeGrouped {
+        mark_lone_sizes(&mut snapshots);
+    }
 
-    let existing_records = database.inventory_snapshot()?;
     let mut existing_map: HashMap<String, InventoryRecord> = existing_records
-        .into_iter()
-        .map(|record| (record.relative_path.clone(), record))
+        .iter()
+        .map(|record| (record.source_key(), record.clone()))
         .collect();
 
+    // Batches hashed before an interruption are newer than the inventory.
+    let mut checkpointed = HashSet::new();
+    if database.get_meta(CHECKPOINT_ROOT_KEY)?.as_deref() == Some(root_key.as_str()) {
+        for record in database.scan_checkpoint()? {
+            checkpointed.insert(record.source_key());
+            existing_map.insert(record.source_key(), record);
+        }
+    } else {
+        database.clear_scan_checkpoint()?;
+        database.set_meta(CHECKPOINT_ROOT_KEY, &root_key)?;
+    }
+
+    let quick_hash = tracing::debug_span!("quick_hash").entered();
+    progress.start(ScanStage::QuickHash, total_files);
+    let cache: HashMap<FileIdentity, CachedHash> = database
+        .hash_cache()?
+        .into_iter()
+        .map(|entry| (entry.identity, entry))
+        .collect();
+    let identities: HashMap<String, FileIdentity> = snapshots
+        .iter()
+        .filter_map(|snapshot| Some((snapshot.source_key(), snapshot.identity?)))
+        .collect();
     let mut reused_records = Vec::new();
     let mut to_process = Vec::new();
     let mut skipped = 0usize;
+    let mut resumed = 0usize;
+    let mut cached = 0usize;
 
-    for snapshot in snapshots {
-        if let Some(existing) = existing_map.remove(&snapshot.relative_path) {
+    for mut snapshot in snapshots {
+        if let Some(existing) = existing_map.remove(&snapshot.source_key()) {
             if existing.file_size == snapshot.file_size
                 && existing.modified_at == snapshot.modified_at
-                && existing.blake3_hash.is_some()
+                && (existing.blake3_hash.is_some() || snapshot.skip_hash)
+                && (existing.pixel_hash.is_some() || !config.scan.pixel_hash)
             {
                 let mut record = existing;
                 record.file_name = snapshot.file_name.clone();
                 record.relative_path = snapshot.relative_path.clone();
                 record.file_size = snapshot.file_size;
+                record.media_type = config.media_exts.classify(&snapshot.file_name);
                 record.modified_at = snapshot.modified_at.clone();
                 record.is_duplicate = false;
+                record.duplicate_confirmation = None;
+                if checkpointed.contains(&record.source_key()) {
+                    resumed += 1;
+                }
                 reused_records.push(record);
                 skipped += 1;
                 continue;
             }
         }
+        // A file renamed or moved since it was hashed keeps its identity.
+        snapshot.cached = snapshot
+            .identity
+            .and_then(|identity| cache.get(&identity))
+            .filter(|entry| {
+                entry.file_size == snapshot.file_size && entry.modified_at == snapshot.modified_at
+            })
+            .cloned();
+        if snapshot.cached.is_some() {
+            cached += 1;
+        }
         to_process.push(snapshot);
     }
 
-    emit_progress(&emitter, "diff", skipped, total_files, None);
+    progress.finish(ScanStage::QuickHash);
+    drop(quick_hash);
+
+    let catalog: HashMap<String, CatalogEntry> = database
+        .catalog_entries()?
+        .into_iter()
+        .map(|entry| (entry.relative_path.clone(), entry))
+        .collect();
 
-    let hash_total = to_process.len();
-    let hashed_records = hash_and_extract(to_process, &emitter)?;
+    let hash_total = to_process
+        .iter()
+        .filter(|snapshot| snapshot.needs_hashing())
+        .count();
+    control.checkpoint()?;
+    let hashed_records = hash_and_extract(
+        &roots, to_process, config, &catalog, database, control, progress,
+    )?;
 
     let mut all_records = Vec::with_capacity(reused_records.len() + hashed_records.len());
     all_records.extend(reused_records);
     all_records.extend(hashed_records);
 
-    let duplicate_files = mark_duplicates(&mut all_records);
+    let _persist = tracing::debug_span!("persist").entered();
+    progress.start(ScanStage::Persist, all_records.len());
+    all_records.sort_by(InventoryRecord::plan_order);
 
-    all_records.sort_by(|a, b| {
-        let a_key = a.captured_at.as_ref().unwrap_or(&a.modified_at);
-        let b_key = b.captured_at.as_ref().unwrap_or(&b.modified_at);
-        match a_key.cmp(b_key) {
-            std::cmp::Ordering::Equal => a.relative_path.cmp(&b.relative_path),
-            ordering => ordering,
-        }
-    });
+    let duplicate_files = tracing::debug_span!("dedupe")
+        .in_scope(|| mark_duplicates(&mut all_records, root_dir, &config.media_exts.sidecar));
+
+    tracing::debug_span!("db_write").in_scope(|| -> Result<()> {
+        database.replace_inventory(&all_records)?;
+        database.clear_scan_checkpoint()?;
+        update_hash_cache(database, &cache, &identities, &all_records)
+    })?;
+    if let Some(cursor) = journal_cursor {
+        let mark = JournalMark {
+            root: root_key,
+            settings: walk_settings,
+            cursor,
+        };
+        database.set_meta(JOURNAL_MARK_KEY, &serde_json::to_string(&mark)?)?;
+    }
+    let derivative_files = all_records
+        .iter()
+        .filter(|record| record.derivative.is_some())
+        .count();
 
-    database.replace_inventory(&all_records)?;
+    let mut media_counts = MediaCounts::default();
+    for record in &all_records {
+        media_counts.add(record.media_type);
+    }
+    let stats = ScanStats {
+        total_files,
+        total_bytes: all_records.iter().map(|record| record.file_size).sum(),
+        photos: media_counts.photos,
+        videos: media_counts.videos,
+        raw: media_counts.raw,
+        duplicate_files,
+    };
+    let diff = record_history(database, root_dir, &existing_records, &all_records, stats)?;
+    progress.finish(ScanStage::Persist);
+    let metrics = progress.metrics.finish();
+    if metrics.bytes_read >= THROUGHPUT_SAMPLE_BYTES && metrics.throughput > 0 {
+        database.set_meta(SCAN_THROUGHPUT_KEY, &metrics.throughput.to_string())?;
+    }
+    telemetry::record_scan(total_files, metrics.bytes_read);
+    telemetry::finish_run("scan", metrics.elapsed(), &config.telemetry);
 
     Ok(ScanSummary {
         total_files,
         hashed_files: hash_total,
         skipped_files: skipped,
         duplicate_files,
+        derivative_files,
+        media_counts,
+        skipped_photos_libraries: folders.photos_skipped,
+        scanned_photos_libraries: folders.photos_scanned,
+        nomedia_folders: folders.nomedia,
+        ignored_entries: folders.ignored,
+        skipped_output_folders: folders.output,
+        followed_links: folders.links,
+        hardlinked_files,
+        special_files: folders.special,
+        sparse_files,
+        limit_hits: folders.limits,
+        cloud_placeholders: folders.placeholders,
+        hydrated_placeholders: folders.hydrated,
+        resumed_files: resumed,
+        unhashed_files: all_records
+            .iter()
+            .filter(|record| is_unhashed(&record.file_hash))
+            .count(),
+        cached_files: cached,
+        journal_folders: changed.map(|folders| folders.len()),
+        diff,
+        metrics,
     })
 }
 
-fn enumerate_files(
+/// What a scan would cover, from walking and stat-ing the source without
+/// reading any file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanEstimate {
+    pub total_files: usize,
+    pub total_bytes: u64,
+    /// Files the inventory already has at the same path and size; a scan
+    /// does not read them again.
+    pub known_files: usize,
+    /// Bytes a scan would read: the files that are not known.
+    pub bytes_to_read: u64,
+    /// Cloud placeholders, counted apart from the files above: their
+    /// content is not on this disk yet. The estimate never downloads them.
+    pub cloud_files: usize,
+    /// Sizes of those placeholders where the stub reports one.
+    pub cloud_bytes: u64,
+    /// Bytes per second the estimate assumes.
+    pub throughput: u64,
+    /// Whether `throughput` was measured by an earlier scan.
+    pub measured: bool,
+    pub estimated_ms: u64,
+}
+
+/// Estimates a scan of the current source, walking it with the scan's own
+/// rules. The time is the bytes to read over the speed the last sizeable
+/// scan measured, or a guess for local disks or network shares.
+pub fn estimate_scan(config: &AppConfig, database: &Database) -> Result<ScanEstimate> {
+    let _run = tracing::info_span!("estimate_scan").entered();
+    let source_roots = config.source_roots();
+    let roots: Vec<&Path> = source_roots.iter().map(PathBuf::as_path).collect();
+    let root_dir = &source_roots[0];
+    let progress = ScanProgress::new(Arc::new(|_| {}));
+    // Downloading placeholders, or failing on them, is for the scan itself.
+    let options = ScanOptions {
+        cloud_placeholders: CloudPlaceholderMode::Skip,
+        ..config.scan.clone()
+    };
+    let (files, folders) = enumerate_files(
+        &roots,
+        &config.image_exts,
+        &options,
+        included_folders(config, root_dir).as_deref(),
+        &output_folders(config)?,
+        &progress,
+    )?;
+
+    let known: HashMap<String, u64> = database
+        .inventory_snapshot()?
+        .into_iter()
+        .map(|record| (record.source_key(), record.file_size))
+        .collect();
+    let mut estimate = ScanEstimate {
+        total_files: 0,
+        total_bytes: 0,
+        known_files: 0,
+        bytes_to_read: 0,
+        cloud_files: folders.placeholders.len(),
+        cloud_bytes: folders
+            .placeholders
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        throughput: 0,
+        measured: false,
+        estimated_ms: 0,
+    };
+    for path in &files {
+        let Ok(metadata) = path.metadata() else {
+            continue;
+        };
+        let size = metadata.len();
+        estimate.total_files += 1;
+        estimate.total_bytes += size;
+        let key =
+            source_of(&roots, path).map(|(root, relative)| source_key(root.as_deref(), &relative));
+        if key.and_then(|key| known.get(&key).copied()) == Some(size) {
+            estimate.known_files += 1;
+        } else {
+            estimate.bytes_to_read += size;
+        }
+    }
+
+    let measured = database
+        .get_meta(SCAN_THROUGHPUT_KEY)?
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|throughput| *throughput > 0);
+    estimate.measured = measured.is_some();
+    estimate.throughput = measured.unwrap_or_else(|| {
+        if IoProfile::configured(roots.iter().copied(), &config.io).network {
+            NETWORK_THROUGHPUT
+        } else {
+            LOCAL_THROUGHPUT
+        }
+    });
+    estimate.estimated_ms = estimate_ms(estimate.bytes_to_read, estimate.throughput);
+    Ok(estimate)
+}
+
+fn estimate_ms(bytes: u64, throughput: u64) -> u64 {
+    (bytes as u128 * 1000 / throughput.max(1) as u128) as u64
+}
+
+/// Diffs against the previous inventory and appends the scan to the
+/// history. The diff is also kept on its own, so it can be shown again
+/// after a restart.
+fn record_history(
+    database: &Database,
     root: &Path,
+    previous: &[InventoryRecord],
+    current: &[InventoryRecord],
+    stats: ScanStats,
+) -> Result<ScanDiff> {
+    let changes = inventory_changes(previous, current);
+    let diff = ScanDiff::from_changes(&changes);
+    database.record_scan(&to_posix_string(root), &stats, &changes)?;
+    database.set_meta(SCAN_DIFF_KEY, &serde_json::to_string(&diff)?)?;
+    Ok(diff)
+}
+
+/// The diff recorded by the last completed scan.
+pub fn last_scan_diff(database: &Database) -> Result<Option<ScanDiff>> {
+    database
+        .get_meta(SCAN_DIFF_KEY)?
+        .map(|json| serde_json::from_str(&json))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Where the change journal stood when a completed scan started.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalMark {
+    root: String,
+    /// What decided which files the walk kept; a change means files the
+    /// journal never mentioned may now be in or out.
+    settings: String,
+    cursor: JournalCursor,
+}
+
+fn walk_settings(config: &AppConfig) -> Result<String> {
+    let mut extensions: Vec<&String> = config.image_exts.iter().collect();
+    extensions.sort();
+    Ok(serde_json::to_string(&(extensions, &config.scan))?)
+}
+
+/// Folders to re-read since the last completed scan of `root`, or `None`
+/// when everything has to be walked.
+fn journal_changes(
+    database: &Database,
+    root: &Path,
+    root_key: &str,
+    settings: &str,
+) -> Result<Option<Vec<PathBuf>>> {
+    let Some(mark) = database.get_meta(JOURNAL_MARK_KEY)? else {
+        return Ok(None);
+    };
+    let Ok(mark) = serde_json::from_str::<JournalMark>(&mark) else {
+        return Ok(None);
+    };
+    if mark.root != root_key || mark.settings != settings {
+        return Ok(None);
+    }
+    let changed = journal::changed_folders(root, &mark.cursor);
+    match &changed {
+        Some(folders) => tracing::info!(folders = folders.len(), "rescanning changed folders"),
+        None => tracing::info!("change journal unavailable; walking the whole tree"),
+    }
+    Ok(changed)
+}
+
+/// The chosen subfolders of the image root, or `None` to scan it all.
+fn included_folders(config: &AppConfig, root: &Path) -> Option<Vec<PathBuf>> {
+    let folders = &config.scan.include_folders;
+    if folders.is_empty() || config.sample_image_root.is_some() {
+        return None;
+    }
+    tracing::info!(folders = ?folders, "scanning the included subfolders only");
+    Some(folders.iter().map(|folder| root.join(folder)).collect())
+}
+
+/// Narrows the walk to the included folders and, when the journal has
+/// them, the changed ones: each changed folder inside an included one, and
+/// each included folder inside a changed one.
+fn scan_scope(included: Option<Vec<PathBuf>>, changed: Option<&[PathBuf]>) -> Option<Vec<PathBuf>> {
+    match (included, changed) {
+        (None, changed) => changed.map(<[PathBuf]>::to_vec),
+        (Some(included), None) => Some(included),
+        (Some(included), Some(changed)) => {
+            let mut scope: Vec<PathBuf> = changed
+                .iter()
+                .filter(|folder| included.iter().any(|root| folder.starts_with(root)))
+                .cloned()
+                .collect();
+            scope.extend(
+                included
+                    .into_iter()
+                    .filter(|root| changed.iter().any(|folder| root.starts_with(folder))),
+            );
+            Some(scope)
+        }
+    }
+}
+
+/// Folders the app writes into. Walking them would take organized copies
+/// for new files, so enumeration never enters them, even when a config
+/// slipped past the layout check.
+pub fn output_folders(config: &AppConfig) -> Result<Vec<PathBuf>> {
+    config
+        .output_roots()
+        .iter()
+        .chain([&config.duplicates_dir, &trash_dir(config)])
+        .map(normalize)
+        .collect()
+}
+
+/// What a walked entry resolves to: its identity where the platform has
+/// one, its canonical path otherwise.
+#[derive(PartialEq, Eq, Hash)]
+enum LinkTarget {
+    Identity(FileIdentity),
+    Path(PathBuf),
+}
+
+impl LinkTarget {
+    fn of(entry: &walkdir::DirEntry) -> Option<Self> {
+        let metadata = entry.metadata().ok()?;
+        match file_identity(&metadata) {
+            Some(identity) => Some(Self::Identity(identity)),
+            None => std::fs::canonicalize(entry.path()).ok().map(Self::Path),
+        }
+    }
+}
+
+/// Folders enumeration treated specially, as POSIX paths.
+#[derive(Debug, Default)]
+struct FolderNotes {
+    photos_skipped: Vec<String>,
+    photos_scanned: Vec<String>,
+    nomedia: Vec<String>,
+    ignored: usize,
+    /// Output and duplicates folders found inside the scanned tree.
+    output: usize,
+    /// Symlinks followed into folders or to files.
+    links: Vec<String>,
+    special: Vec<String>,
+    limits: ScanLimitHits,
+    denied: Vec<String>,
+    placeholders: Vec<String>,
+    hydrated: usize,
+}
+
+/// Walks `roots` in turn; `only` narrows the first one.
+fn enumerate_files(
+    roots: &[&Path],
     extensions: &HashSet<String>,
-    emitter: &ProgressEmitter,
-) -> Result<Vec<PathBuf>> {
+    options: &ScanOptions,
+    only: Option<&[PathBuf]>,
+    skip: &[PathBuf],
+    progress: &ScanProgress,
+) -> Result<(Vec<PathBuf>, FolderNotes)> {
+    let _span = tracing::debug_span!("enumerate").entered();
+    // The total is only known at the end, but the clock runs from here.
+    progress.metrics.start(ScanStage::Enumerate.as_str());
     let mut files = Vec::new();
-    if !root.exists() {
-        return Ok(files);
+    let mut folders = FolderNotes::default();
+
+    let mut ignores = IgnoreFiles::default();
+    let mut placeholders = Vec::new();
+    let follow = options.follow_symlinks;
+    // Links can lead back up the tree or to a folder walked already; every
+    // folder and file is taken once, by what it resolves to.
+    let mut seen_folders = HashSet::new();
+    let mut seen_files = HashSet::new();
+    let mut file_links = Vec::new();
+    // Media files seen per folder, when they are capped.
+    let mut per_folder: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, &root) in roots.iter().enumerate() {
+        if !root.exists() {
+            continue;
+        }
+        let only = only.filter(|_| index == 0);
+        let mut walk = WalkDir::new(root).follow_links(follow);
+        if options.max_files_per_directory.is_some() {
+            // The files kept in a crowded folder should not depend on the
+            // order the OS happens to list them in.
+            walk = walk.sort_by_file_name();
+        }
+        let walker = walk
+            .into_iter()
+            .filter_entry(|entry| {
+                let is_dir = entry.file_type().is_dir();
+                if follow && is_dir {
+                    if let Some(target) = LinkTarget::of(entry) {
+                        if !seen_folders.insert(target) {
+                            tracing::debug!(folder = %entry.path().display(), "folder reached twice through links");
+                            return false;
+                        }
+                    }
+                    if entry.path_is_symlink() {
+                        folders.links.push(to_posix_string(entry.path()).into_owned());
+                    }
+                }
+                // Limited to `only`, folders leading there are still entered so
+                // their ignore rules apply, but their own files are skipped.
+                if let Some(only) = only {
+                    let path = entry.path();
+                    let wanted = only
+                        .iter()
+                        .any(|folder| path.starts_with(folder) || (is_dir && folder.starts_with(path)));
+                    if !wanted {
+                        return false;
+                    }
+                }
+                if is_dir && options.max_depth.is_some_and(|max| entry.depth() > max) {
+                    let folder = to_posix_string(entry.path()).into_owned();
+                    tracing::warn!(folder = %folder, "folder deeper than maxDepth not scanned");
+                    folders.limits.deep_folders.push(folder);
+                    return false;
+                }
+                if is_dir && entry.depth() > 0 && skip.contains(&clean_path(entry.path())) {
+                    let folder = to_posix_string(entry.path()).into_owned();
+                    tracing::info!(folder = %folder, "skipping output folder inside the scan");
+                    folders.output += 1;
+                    return false;
+                }
+                if entry.depth() > 0 && ignores.is_ignored(entry.path(), is_dir) {
+                    folders.ignored += 1;
+                    return false;
+                }
+                if is_dir {
+                    ignores.enter(entry.path());
+                }
+                // The root was chosen explicitly, so only subfolders are skipped.
+                if is_dir
+                    && options.honor_nomedia
+                    && entry.depth() > 0
+                    && entry.path().join(NOMEDIA_MARKER).exists()
+                {
+                    let folder = to_posix_string(entry.path()).into_owned();
+                    tracing::debug!(folder = %folder, "skipping folder marked .nomedia");
+                    folders.nomedia.push(folder);
+                    return false;
+                }
+                if is_dir && is_photos_library(entry.path()) {
+                    let bundle = to_posix_string(entry.path()).into_owned();
+                    return match options.photos_library {
+                        PhotosLibraryMode::Skip => {
+                            tracing::info!(library = %bundle, "skipping Apple Photos library");
+                            folders.photos_skipped.push(bundle);
+                            false
+                        }
+                        PhotosLibraryMode::Originals => {
+                            tracing::warn!(
+                                library = %bundle,
+                                "scanning Apple Photos originals; moving them out will break the library"
+                            );
+                            folders.photos_scanned.push(bundle);
+                            true
+                        }
+                    };
+                }
+                inside_photos_originals(entry.path())
+            });
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    if err.io_error().is_some_and(is_permission_denied) {
+                        let folder = err.path().unwrap_or(root);
+                        folders.denied.push(to_posix_string(folder).into_owned());
+                    }
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if follow && entry.file_type().is_file() {
+                if let Some(target) = LinkTarget::of(&entry) {
+                    if !seen_files.insert(target) {
+                        continue;
+                    }
+                }
+                if entry.path_is_symlink() {
+                    file_links.push(to_posix_string(path).into_owned());
+                }
+            }
+            if entry.file_type().is_file() {
+                let placeholder = entry
+                    .metadata()
+                    .ok()
+                    .and_then(|metadata| cloud::detect(path, &metadata, extensions));
+                if let Some(placeholder) = placeholder {
+                    placeholders.push(placeholder);
+                    continue;
+                }
+            }
+            if is_special(entry.file_type()) && matches_extension(path, extensions) {
+                let special = to_posix_string(path).into_owned();
+                tracing::warn!(path = %special, "skipping pipe, socket or device named like media");
+                folders.special.push(special);
+                continue;
+            }
+            if path.is_file() && matches_extension(path, extensions) {
+                if let Some(limit) = options.max_files_per_directory {
+                    let folder = path.parent().unwrap_or(root);
+                    let seen = per_folder.entry(folder.to_path_buf()).or_default();
+                    *seen += 1;
+                    if *seen > limit {
+                        if *seen == limit + 1 {
+                            let folder = to_posix_string(folder).into_owned();
+                            tracing::warn!(folder = %folder, limit, "folder holds more media than maxFilesPerDirectory");
+                            folders.limits.crowded_folders.push(folder);
+                        }
+                        folders.limits.files_left_out += 1;
+                        continue;
+                    }
+                }
+                files.push(path.to_path_buf());
+                progress.advance(
+                    ScanStage::Enumerate,
+                    Some(to_posix_string(path).into_owned()),
+                );
+            }
+        }
     }
 
-    for entry in WalkDir::new(root)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file() && matches_extension(path, extensions) {
-            files.push(path.to_path_buf());
-            let processed = files.len();
-            emit_progress(
-                emitter,
-                "scan",
-                processed,
-                processed,
-                Some(to_posix_string(path).into_owned()),
-            );
+    folders.links.extend(file_links);
+    resolve_placeholders(
+        placeholders,
+        options.cloud_placeholders,
+        &mut files,
+        &mut folders,
+    )?;
+
+    // Finding nothing because the OS refused to list folders is not an empty
+    // library; report it so the user can be sent to grant access.
+    if files.is_empty() {
+        if let Some(folder) = folders.denied.first() {
+            return Err(AppError::PermissionDenied(folder.clone()));
         }
     }
+    if !folders.denied.is_empty() {
+        tracing::warn!(folders = ?folders.denied, "some folders could not be read");
+    }
+    if folders.limits.any() {
+        tracing::warn!(
+            deep_folders = folders.limits.deep_folders.len(),
+            crowded_folders = folders.limits.crowded_folders.len(),
+            files_left_out = folders.limits.files_left_out,
+            "scan limits hit; part of the source was not scanned"
+        );
+    }
 
     files.sort();
-    emit_progress(emitter, "scan", files.len(), files.len(), None);
-    Ok(files)
+    progress.start(ScanStage::Enumerate, files.len());
+    progress.finish(ScanStage::Enumerate);
+    Ok((files, folders))
+}
+
+/// Anything but a file, folder or symlink: reading a pipe or device would
+/// block or never end.
+fn is_special(file_type: std::fs::FileType) -> bool {
+    !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink()
+}
+
+/// Applies the cloud placeholder policy: downloaded files join `files`,
+/// the rest are listed in `folders.placeholders`.
+fn resolve_placeholders(
+    mut placeholders: Vec<Placeholder>,
+    mode: CloudPlaceholderMode,
+    files: &mut Vec<PathBuf>,
+    folders: &mut FolderNotes,
+) -> Result<()> {
+    if placeholders.is_empty() {
+        return Ok(());
+    }
+    match mode {
+        CloudPlaceholderMode::Skip => {}
+        CloudPlaceholderMode::Fail => {
+            return Err(AppError::CloudPlaceholders {
+                count: placeholders.len(),
+                example: to_posix_string(placeholders[0].path()).into_owned(),
+            });
+        }
+        CloudPlaceholderMode::Hydrate => {
+            let mut pending = Vec::new();
+            for placeholder in placeholders {
+                match cloud::hydrate(&placeholder) {
+                    Ok(path) => {
+                        files.push(path);
+                        folders.hydrated += 1;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            path = %placeholder.path().display(),
+                            error = %err,
+                            "failed to download cloud placeholder"
+                        );
+                        pending.push(placeholder);
+                    }
+                }
+            }
+            placeholders = pending;
+        }
+    }
+    folders.placeholders = placeholders
+        .iter()
+        .map(|placeholder| to_posix_string(placeholder.path()).into_owned())
+        .collect();
+    Ok(())
+}
+
+fn is_photos_library(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(PHOTOS_LIBRARY_EXT))
+}
+
+/// True unless `path` sits inside a Photos bundle but outside its originals
+/// folder (`originals` since Photos 5, `Masters` before).
+fn inside_photos_originals(path: &Path) -> bool {
+    let mut components = path.components();
+    while let Some(component) = components.next() {
+        if is_photos_library(Path::new(component.as_os_str())) {
+            return match components.next() {
+                Some(child) => PHOTOS_ORIGINALS_DIRS
+                    .iter()
+                    .any(|name| child.as_os_str() == *name),
+                None => true,
+            };
+        }
+    }
+    true
 }
 
-fn build_snapshots(root: &Path, files: Vec<PathBuf>) -> Result<Vec<FileSnapshot>> {
+fn build_snapshots(
+    roots: &[&Path],
+    files: Vec<PathBuf>,
+    progress: &ScanProgress,
+) -> Result<Vec<FileSnapshot>> {
+    let _span = tracing::debug_span!("snapshot").entered();
     let mut snapshots = Vec::with_capacity(files.len());
 
+    progress.start(ScanStage::Snapshot, files.len());
     for path in files {
+        progress.advance(ScanStage::Snapshot, None);
         let metadata = match path.metadata() {
             Ok(meta) => meta,
             Err(err) => {
@@ -196,14 +1111,12 @@ fn build_snapshots(root: &Path, files: Vec<PathBuf>) -> Result<Vec<FileSnapshot>
             }
         };
 
-        let relative_path = diff_paths(&path, root)
-            .and_then(|p| p.to_str().map(|s| s.replace('\\', "/")))
-            .ok_or_else(|| {
-                AppError::Config(format!(
-                    "failed to compute relative path for {}",
-                    path.display()
-                ))
-            })?;
+        let (source_root, relative_path) = source_of(roots, &path).ok_or_else(|| {
+            AppError::Config(format!(
+                "failed to compute relative path for {}",
+                path.display()
+            ))
+        })?;
 
         let file_name = path
             .file_name()
@@ -211,6 +1124,7 @@ fn build_snapshots(root: &Path, files: Vec<PathBuf>) -> Result<Vec<FileSnapshot>
             .ok_or_else(|| AppError::Internal(format!("invalid file name for {}", path.display())))?
             .to_string();
 
+        let identity = file_identity(&metadata);
         let file_size = metadata.len();
         let modified_time = metadata.modified()?;
         let modified_dt = OffsetDateTime::from(modified_time);
@@ -219,85 +1133,474 @@ fn build_snapshots(root: &Path, files: Vec<PathBuf>) -> Result<Vec<FileSnapshot>
         snapshots.push(FileSnapshot {
             absolute_path: path,
             relative_path,
+            source_root,
             file_name,
             file_size,
             modified_at,
+            identity,
+            hard_links: link_count(&metadata),
+            allocated_size: is_sparse(&metadata).then(|| allocated_size(&metadata)),
+            skip_hash: false,
+            cached: None,
         });
     }
 
+    progress.finish(ScanStage::Snapshot);
     Ok(snapshots)
 }
 
+/// The extra root `path` is under, as a POSIX path, or `None` for the image
+/// root, with the path relative to it.
+pub fn source_of(roots: &[&Path], path: &Path) -> Option<(Option<String>, String)> {
+    let extra = roots[1..].iter().find(|root| path.starts_with(root));
+    let root = extra.unwrap_or(&roots[0]);
+    let relative = diff_paths(path, root)?.to_str()?.replace('\\', "/");
+    Some((
+        extra.map(|root| to_posix_string(root).into_owned()),
+        relative,
+    ))
+}
+
+/// Keeps one name of every hard-linked file. The names share one inode, so
+/// they are one file rather than duplicates of each other, and moving both
+/// would break the link. Files are walked in order, so the first name kept
+/// is stable from scan to scan. Returns how many names were dropped.
+fn drop_hardlink_aliases(snapshots: &mut Vec<FileSnapshot>) -> usize {
+    let before = snapshots.len();
+    let mut seen = HashSet::new();
+    snapshots.retain(|snapshot| match snapshot.identity {
+        Some(identity) if snapshot.hard_links > 1 => {
+            let first = seen.insert(identity);
+            if !first {
+                tracing::debug!(path = %snapshot.relative_path, "skipping another name of a hard-linked file");
+            }
+            first
+        }
+        _ => true,
+    });
+    before - snapshots.len()
+}
+
+/// Flags files whose size no other file shares; they cannot be duplicates.
+fn mark_lone_sizes(snapshots: &mut [FileSnapshot]) {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for snapshot in snapshots.iter() {
+        *counts.entry(snapshot.file_size).or_default() += 1;
+    }
+    for snapshot in snapshots {
+        snapshot.skip_hash = counts[&snapshot.file_size] == 1;
+    }
+}
+
+/// Stand-in hash for an unhashed file. It carries the file identity, or the
+/// path where there is none, so it never matches another file; with an
+/// identity it also follows the file when it moves.
+fn unhashed_key(snapshot: &FileSnapshot) -> String {
+    match snapshot.identity {
+        Some(identity) => format!(
+            "{UNHASHED_PREFIX}{}:{}-{}",
+            snapshot.file_size, identity.volume_id, identity.file_id
+        ),
+        None => format!(
+            "{UNHASHED_PREFIX}{}:{}",
+            snapshot.file_size, snapshot.relative_path
+        ),
+    }
+}
+
+/// Remembers the hashes of every hashed file by identity, skipping the ones
+/// the cache already holds.
+fn update_hash_cache(
+    database: &Database,
+    cache: &HashMap<FileIdentity, CachedHash>,
+    identities: &HashMap<String, FileIdentity>,
+    records: &[InventoryRecord],
+) -> Result<()> {
+    let entries: Vec<CachedHash> = records
+        .iter()
+        .filter(|record| !is_unhashed(&record.file_hash))
+        .filter_map(|record| {
+            Some(CachedHash {
+                identity: *identities.get(&record.source_key())?,
+                file_size: record.file_size,
+                modified_at: record.modified_at.clone(),
+                file_hash: record.file_hash.clone(),
+                blake3_hash: record.blake3_hash.clone()?,
+            })
+        })
+        .filter(|entry| cache.get(&entry.identity) != Some(entry))
+        .collect();
+    database.upsert_hash_cache(&entries)
+}
+
 fn hash_and_extract(
+    roots: &[&Path],
     snapshots: Vec<FileSnapshot>,
-    emitter: &ProgressEmitter,
+    config: &AppConfig,
+    catalog: &HashMap<String, CatalogEntry>,
+    database: &Database,
+    control: &TaskControl,
+    progress: &ScanProgress,
 ) -> Result<Vec<InventoryRecord>> {
-    if snapshots.is_empty() {
-        emit_progress(emitter, "hash", 0, 0, None);
-        return Ok(Vec::new());
-    }
-
-    let counter = AtomicUsize::new(0);
+    let options = &config.scan;
     let total = snapshots.len();
-    let emitter_clone = emitter.clone();
-
-    let results: Result<Vec<InventoryRecord>> = snapshots
-        .into_par_iter()
-        .map(|snapshot| {
-            let md5 = md5_file(&snapshot.absolute_path)?;
-            let blake3 = blake3_file(&snapshot.absolute_path)?;
-            let exif = extract_exif(&snapshot.absolute_path);
-
-            let captured_at = exif
-                .captured_at
-                .unwrap_or_else(|| snapshot.modified_at.clone());
-
-            let record = InventoryRecord {
-                id: None,
-                file_hash: md5,
-                blake3_hash: Some(blake3),
-                file_size: snapshot.file_size,
-                file_name: snapshot.file_name,
-                relative_path: snapshot.relative_path.clone(),
-                captured_at: Some(captured_at),
-                modified_at: snapshot.modified_at.clone(),
-                exif_model: exif.camera_model,
-                exif_make: exif.camera_make,
-                exif_artist: exif.artist,
-                is_duplicate: false,
-            };
+    let to_hash = snapshots
+        .iter()
+        .filter(|snapshot| snapshot.needs_hashing())
+        .count();
+    progress.start(ScanStage::FullHash, to_hash);
+    progress.start(ScanStage::Exif, total);
+    // Work on the hashing threads reports to this span explicitly.
+    let span = tracing::debug_span!("hash_and_extract");
+    let _entered = span.enter();
 
-            let processed = counter.fetch_add(1, Ordering::Relaxed) + 1;
-            emit_progress(
-                &emitter_clone,
-                "hash",
-                processed,
-                total,
-                Some(snapshot.relative_path),
-            );
+    let hash_batch = |batch: &[FileSnapshot]| -> Result<Vec<InventoryRecord>> {
+        batch
+            .par_iter()
+            .map(|snapshot| {
+                control.checkpoint()?;
+                let _lane = control.lane();
+                let (file_hash, blake3_hash) = match &snapshot.cached {
+                    Some(entry) => (entry.file_hash.clone(), Some(entry.blake3_hash.clone())),
+                    None if snapshot.skip_hash => (unhashed_key(snapshot), None),
+                    None => {
+                        let _hash = tracing::trace_span!(parent: &span, "hash_file").entered();
+                        let hashes = (
+                            md5_file(&snapshot.absolute_path)?,
+                            Some(blake3_file(&snapshot.absolute_path)?),
+                        );
+                        // Each hash reads the file once.
+                        progress.metrics.read(snapshot.file_size * 2);
+                        progress.advance(ScanStage::FullHash, Some(snapshot.relative_path.clone()));
+                        hashes
+                    }
+                };
+                // A catalog that already dated the file saves the EXIF read.
+                // Catalogs describe the image root only.
+                let known = catalog.get(&snapshot.relative_path).filter(|entry| {
+                    snapshot.source_root.is_none()
+                        && entry
+                            .file_size
+                            .is_none_or(|size| size == snapshot.file_size)
+                });
+                let media_type = config.media_exts.classify(&snapshot.file_name);
+                let exif = match known {
+                    Some(entry) if entry.captured_at.is_some() => ExifMetadata {
+                        captured_at: entry.captured_at.clone(),
+                        ..ExifMetadata::default()
+                    },
+                    _ if media_type == MediaKind::Video => {
+                        tracing::trace_span!(parent: &span, "container")
+                            .in_scope(|| video_metadata(&snapshot.absolute_path))
+                    }
+                    _ => tracing::trace_span!(parent: &span, "exif")
+                        .in_scope(|| extract_exif(&snapshot.absolute_path)),
+                };
+
+                // Takeout exports often strip EXIF; their sidecars fill the gaps.
+                let sidecar = if exif.captured_at.is_none() || exif.gps.is_none() {
+                    read_sidecar(&snapshot.absolute_path).unwrap_or_default()
+                } else {
+                    TakeoutMetadata::default()
+                };
+                let gps = exif.gps.or(sidecar.gps_latitude.zip(sidecar.gps_longitude));
+
+                let captured_at = exif
+                    .captured_at
+                    .or(sidecar.captured_at)
+                    .unwrap_or_else(|| snapshot.modified_at.clone());
 
-            Ok(record)
+                let record = InventoryRecord {
+                    id: None,
+                    file_hash,
+                    blake3_hash,
+                    file_size: snapshot.file_size,
+                    file_name: snapshot.file_name.clone(),
+                    relative_path: snapshot.relative_path.clone(),
+                    source_root: snapshot.source_root.clone(),
+                    media_type,
+                    captured_at: Some(captured_at),
+                    modified_at: snapshot.modified_at.clone(),
+                    exif_model: exif.camera_model,
+                    exif_make: exif.camera_make,
+                    exif_artist: exif.artist,
+                    rating: known.and_then(|entry| entry.rating),
+                    tags: known.map(|entry| entry.tags.clone()).unwrap_or_default(),
+                    gps_latitude: gps.map(|(latitude, _)| latitude),
+                    gps_longitude: gps.map(|(_, longitude)| longitude),
+                    derivative: classify_derivative(
+                        &source_dir(snapshot.source_root.as_deref(), roots[0]),
+                        &snapshot.absolute_path,
+                    ),
+                    is_duplicate: false,
+                    duplicate_confirmation: None,
+                    pixel_hash: options
+                        .pixel_hash
+                        .then(|| pixel_hash(&snapshot.absolute_path))
+                        .flatten(),
+                };
+
+                progress.advance(ScanStage::Exif, Some(snapshot.relative_path.clone()));
+
+                Ok(record)
+            })
+            .collect()
+    };
+    // Each batch is committed to the checkpoint by a writer beside the
+    // hashers, so an interruption loses at most the batches still queued.
+    let hash_all = || -> Result<Vec<InventoryRecord>> {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<InventoryRecord>>(options.queues.persist);
+        let span = &span;
+        thread::scope(|scope| {
+            let writer = scope.spawn(move || -> Result<()> {
+                for batch in receiver {
+                    tracing::trace_span!(parent: span, "db_write")
+                        .in_scope(|| database.append_scan_checkpoint(&batch))?;
+                }
+                Ok(())
+            });
+            let mut records = Vec::with_capacity(total);
+            let mut hashed = Ok(());
+            for batch in snapshots.chunks(options.queues.hash) {
+                match hash_batch(batch) {
+                    Ok(batch) => {
+                        // A closed queue means the writer failed; it reports why.
+                        if sender.send(batch.clone()).is_err() {
+                            break;
+                        }
+                        records.extend(batch);
+                    }
+                    Err(err) => {
+                        hashed = Err(err);
+                        break;
+                    }
+                }
+            }
+            drop(sender);
+            writer
+                .join()
+                .map_err(|_| AppError::internal("checkpoint writer panicked"))??;
+            hashed.map(|()| records)
         })
-        .collect();
+    };
+    // Shares get a small pool of their own instead of one thread per core,
+    // and a spinning disk a single thread so it streams instead of seeking.
+    let profile = IoProfile::configured(roots.iter().copied(), &config.io);
+    tracing::debug!(storage = ?profile.storage(), threads = ?profile.hash_threads(), "hashing");
+    let results = match profile.hash_threads() {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(AppError::internal)?
+            .install(hash_all),
+        None => hash_all(),
+    };
 
-    emit_progress(&emitter_clone, "hash", total, total, None);
+    progress.finish(ScanStage::FullHash);
+    progress.finish(ScanStage::Exif);
     results
 }
 
-fn mark_duplicates(records: &mut [InventoryRecord]) -> usize {
-    let mut seen = HashSet::new();
+/// Flags every copy but one per content. Files count as the same content
+/// only when size and MD5 agree and, where both have one, BLAKE3 agrees too;
+/// the verdict records which of these backed it. The copy with the most
+/// context is kept, see `Richness`, the first in plan order among equals.
+fn mark_duplicates(
+    records: &mut [InventoryRecord],
+    root_dir: &Path,
+    sidecar_exts: &HashSet<String>,
+) -> usize {
+    let mut groups: HashMap<(&str, u64), Vec<Vec<usize>>> = HashMap::new();
+    for (idx, record) in records.iter().enumerate() {
+        let clusters = groups
+            .entry((record.file_hash.as_str(), record.file_size))
+            .or_default();
+        let same_blake3 = |cluster: &&mut Vec<usize>| match (
+            &records[cluster[0]].blake3_hash,
+            &record.blake3_hash,
+        ) {
+            (Some(kept), Some(candidate)) => kept == candidate,
+            _ => true,
+        };
+        match clusters.iter_mut().find(same_blake3) {
+            Some(cluster) => cluster.push(idx),
+            None => {
+                if !clusters.is_empty() {
+                    tracing::warn!(
+                        path = %record.relative_path,
+                        "MD5 and size match another file but BLAKE3 differs; keeping both"
+                    );
+                }
+                clusters.push(vec![idx]);
+            }
+        }
+    }
+    let clusters: Vec<Vec<usize>> = groups.into_values().flatten().collect();
+
     let mut duplicates = 0usize;
+    for cluster in clusters {
+        let mut keeper = cluster[0];
+        if cluster.len() > 1 {
+            let scores: Vec<Richness> = cluster
+                .iter()
+                .map(|&idx| Richness::of(&records[idx], root_dir, sidecar_exts))
+                .collect();
+            let mut best = 0;
+            for (at, score) in scores.iter().enumerate() {
+                if *score > scores[best] {
+                    best = at;
+                }
+            }
+            keeper = cluster[best];
+            log_keeper(records, &cluster, keeper, scores[best]);
+        }
+        let kept_blake3 = records[keeper].blake3_hash.clone();
+        for idx in cluster {
+            let record = &mut records[idx];
+            record.is_duplicate = idx != keeper;
+            let confirmation = match (&kept_blake3, &record.blake3_hash) {
+                (Some(_), Some(_)) => DuplicateConfirmation::Blake3,
+                _ => DuplicateConfirmation::Size,
+            };
+            record.duplicate_confirmation = record.is_duplicate.then_some(confirmation);
+            if record.is_duplicate {
+                duplicates += 1;
+            }
+        }
+    }
 
-    for record in records.iter_mut() {
-        if !seen.insert(record.file_hash.clone()) {
-            record.is_duplicate = true;
-            duplicates += 1;
-        } else {
-            record.is_duplicate = false;
+    duplicates + mark_pixel_duplicates(records, root_dir, sidecar_exts)
+}
+
+/// Flags files whose bytes differ but whose decoded pixels match a file
+/// that is kept, e.g. the same photo with edited EXIF.
+fn mark_pixel_duplicates(
+    records: &mut [InventoryRecord],
+    root_dir: &Path,
+    sidecar_exts: &HashSet<String>,
+) -> usize {
+    let mut keepers: HashMap<String, usize> = HashMap::new();
+    let mut flagged = Vec::new();
+    for (idx, record) in records.iter().enumerate() {
+        let Some(pixels) = record.pixel_hash.clone().filter(|_| !record.is_duplicate) else {
+            continue;
+        };
+        match keepers.get_mut(&pixels) {
+            Some(keeper) => {
+                let score = Richness::of(record, root_dir, sidecar_exts);
+                let kept = Richness::of(&records[*keeper], root_dir, sidecar_exts);
+                if score > kept {
+                    log_keeper(records, &[*keeper, idx], idx, score);
+                    flagged.push(*keeper);
+                    *keeper = idx;
+                } else {
+                    flagged.push(idx);
+                }
+            }
+            None => {
+                keepers.insert(pixels, idx);
+            }
+        }
+    }
+    for &idx in &flagged {
+        records[idx].is_duplicate = true;
+        records[idx].duplicate_confirmation = Some(DuplicateConfirmation::Pixels);
+    }
+    flagged.len()
+}
+
+/// Folder names that mark a copy passed through a messaging app or a
+/// browser, which has usually lost what the camera's copy still has.
+const SECOND_HAND_FOLDERS: &[&str] = &[
+    "whatsapp",
+    "telegram",
+    "signal",
+    "messenger",
+    "wechat",
+    "download",
+];
+
+/// How much context a copy of some content carries, compared field by
+/// field in this order: a name without a copy suffix, a folder that is not
+/// a messaging app's or a download folder, a sidecar next to it, a camera
+/// folder (`DCIM`, `100CANON`) on its path, then the metadata fields it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Richness {
+    original_name: bool,
+    first_hand: bool,
+    has_sidecar: bool,
+    camera_folder: bool,
+    metadata_fields: usize,
+}
+
+impl Richness {
+    fn of(record: &InventoryRecord, root_dir: &Path, sidecar_exts: &HashSet<String>) -> Self {
+        let folders: Vec<String> = Path::new(&record.relative_path)
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .map(|component| component.as_os_str().to_string_lossy().to_lowercase())
+            .collect();
+        let path = record.source_dir(root_dir).join(&record.relative_path);
+        let has_sidecar = sidecar_exts.iter().any(|ext| {
+            let mut appended = path.clone().into_os_string();
+            appended.push(ext);
+            path.with_extension(ext.trim_start_matches('.')).is_file()
+                || Path::new(&appended).is_file()
+        });
+        Self {
+            original_name: strip_copy_suffix(&record.file_name).is_none(),
+            first_hand: !folders.iter().any(|folder| {
+                SECOND_HAND_FOLDERS
+                    .iter()
+                    .any(|marker| folder.contains(marker))
+            }),
+            has_sidecar,
+            camera_folder: folders.iter().any(|folder| is_camera_folder(folder)),
+            metadata_fields: [
+                record.captured_at.is_some(),
+                record.exif_make.is_some(),
+                record.exif_model.is_some(),
+                record.exif_artist.is_some(),
+                record.gps_latitude.is_some(),
+                record.rating.is_some(),
+                !record.tags.is_empty(),
+            ]
+            .into_iter()
+            .filter(|present| *present)
+            .count(),
         }
     }
+}
+
+/// `dcim`, or a DCF folder such as `100canon`: three digits, five more.
+fn is_camera_folder(folder: &str) -> bool {
+    folder == "dcim"
+        || (folder.len() == 8
+            && folder.is_ascii()
+            && folder[..3].bytes().all(|byte| byte.is_ascii_digit())
+            && folder[3..]
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_'))
+}
 
-    duplicates
+/// Records which copy of `cluster` stays and why.
+fn log_keeper(records: &[InventoryRecord], cluster: &[usize], keeper: usize, richness: Richness) {
+    let others: Vec<&str> = cluster
+        .iter()
+        .filter(|&&idx| idx != keeper)
+        .map(|&idx| records[idx].relative_path.as_str())
+        .collect();
+    if keeper == cluster[0] {
+        tracing::debug!(kept = %records[keeper].relative_path, ?others, ?richness, "kept the first copy");
+    } else {
+        tracing::info!(
+            kept = %records[keeper].relative_path,
+            ?others,
+            ?richness,
+            "kept the copy with the most context"
+        );
+    }
 }
 
 fn extract_exif(path: &Path) -> ExifMetadata {
@@ -335,9 +1638,51 @@ fn extract_exif(path: &Path) -> ExifMetadata {
             .get_field(Tag::Artist, In::PRIMARY)
             .and_then(|field| exif_ascii_value(&field.value))
             .map(|s| s.to_string()),
+        gps: exif_coordinate(&exif_reader, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S").zip(
+            exif_coordinate(&exif_reader, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+        ),
     }
 }
 
+/// Videos carry their dates and camera in the container rather than EXIF.
+fn video_metadata(path: &Path) -> ExifMetadata {
+    let metadata = video::read_metadata(path);
+    ExifMetadata {
+        captured_at: metadata.captured_at,
+        camera_model: metadata.camera_model,
+        camera_make: metadata.camera_make,
+        artist: None,
+        gps: metadata.gps,
+    }
+}
+
+/// Decimal degrees from a degrees/minutes/seconds GPS field, negated when
+/// the reference tag names the southern or western hemisphere.
+fn exif_coordinate(
+    exif_reader: &exif::Exif,
+    tag: Tag,
+    reference: Tag,
+    negative_ref: &str,
+) -> Option<f64> {
+    let field = exif_reader.get_field(tag, In::PRIMARY)?;
+    let Value::Rational(ref parts) = field.value else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part.to_f64() / divisor)
+        .sum::<f64>();
+    if !degrees.is_finite() {
+        return None;
+    }
+    let negative = exif_reader
+        .get_field(reference, In::PRIMARY)
+        .and_then(|field| exif_ascii_value(&field.value))
+        .is_some_and(|value| value.eq_ignore_ascii_case(negative_ref));
+    Some(if negative { -degrees } else { degrees })
+}
+
 fn exif_ascii_value(value: &Value) -> Option<&str> {
     match value {
         Value::Ascii(ref vec) if !vec.is_empty() => {
@@ -354,20 +1699,97 @@ fn normalize_exif_timestamp(raw: &str) -> Option<String> {
     time_utils::format_timestamp(offset).ok()
 }
 
-fn emit_progress(
-    emitter: &ProgressEmitter,
-    stage: &'static str,
-    processed: usize,
-    total: usize,
-    current: Option<String>,
-) {
-    let payload = ScanProgressPayload {
-        stage,
-        processed,
-        total,
-        current,
-    };
-    (*emitter)(payload);
+/// Per-stage counts of a running scan; every change is sent with all
+/// stages attached.
+struct ScanProgress {
+    emitter: ProgressEmitter,
+    stages: Mutex<Vec<StageProgress>>,
+    metrics: MetricsRecorder,
+}
+
+impl ScanProgress {
+    fn new(emitter: ProgressEmitter) -> Self {
+        let stages = ScanStage::ALL
+            .iter()
+            .map(|&stage| StageProgress {
+                stage,
+                processed: 0,
+                total: None,
+            })
+            .collect();
+        Self {
+            emitter,
+            stages: Mutex::new(stages),
+            metrics: MetricsRecorder::new(),
+        }
+    }
+
+    fn start(&self, stage: ScanStage, total: usize) {
+        self.metrics.start(stage.as_str());
+        self.update(stage, None, |progress| progress.total = Some(total));
+    }
+
+    fn advance(&self, stage: ScanStage, current: Option<String>) {
+        self.update(stage, current, |progress| progress.processed += 1);
+    }
+
+    fn finish(&self, stage: ScanStage) {
+        self.metrics.stop(stage.as_str());
+        self.update(stage, None, |progress| {
+            progress.processed = progress.total.unwrap_or(progress.processed);
+        });
+    }
+
+    fn update(
+        &self,
+        stage: ScanStage,
+        current: Option<String>,
+        change: impl FnOnce(&mut StageProgress),
+    ) {
+        let payload = {
+            let mut stages = self.stages.lock();
+            let Some(progress) = stages.iter_mut().find(|progress| progress.stage == stage) else {
+                return;
+            };
+            change(progress);
+            ScanProgressPayload {
+                stage,
+                processed: progress.processed,
+                total: progress.total,
+                current,
+                stages: stages.clone(),
+                cancelled: false,
+            }
+        };
+        (self.emitter)(payload);
+    }
+
+    /// Reports the scan stopped, at the first stage it had not finished.
+    fn cancel(&self) {
+        let payload = {
+            let stages = self.stages.lock();
+            let Some(progress) = stages
+                .iter()
+                .find(|progress| {
+                    progress
+                        .total
+                        .is_none_or(|total| progress.processed < total)
+                })
+                .or(stages.last())
+            else {
+                return;
+            };
+            ScanProgressPayload {
+                stage: progress.stage,
+                processed: progress.processed,
+                total: progress.total,
+                current: None,
+                stages: stages.clone(),
+                cancelled: true,
+            }
+        };
+        (self.emitter)(payload);
+    }
 }
 
 #[cfg(test)]
@@ -375,19 +1797,70 @@ mod tests {
     use super::*;
     use std::collections::HashSet;
     use std::fs;
-    use std::path::PathBuf;
     use std::sync::Arc;
     use tempfile::tempdir;
 
-    use crate::config::SCHEMA_VERSION;
+    use crate::config::test_config;
+    use crate::media::MediaKind;
+
+    #[test]
+    fn extra_roots_are_scanned_with_the_image_root() -> Result<()> {
+        let dir = tempdir()?;
+        let extra_dir = tempdir()?;
+        let mut config = test_config(dir.path());
+        fs::write(config.image_root.join("a.jpg"), b"same")?;
+        fs::write(extra_dir.path().join("a.jpg"), b"same")?;
+        fs::write(extra_dir.path().join("b.jpg"), b"other")?;
+
+        config.scan.extra_image_roots = vec![extra_dir.path().to_path_buf()];
+        let database = Database::initialize(&config)?;
+        let summary = perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!((summary.total_files, summary.duplicate_files), (3, 1));
+        assert_eq!(summary.diff.added.count, 3);
+        let stored = database.inventory_snapshot()?;
+        let extra_key = to_posix_string(extra_dir.path()).into_owned();
+        assert_eq!(
+            stored
+                .iter()
+                .filter(|record| record.source_root.as_deref() == Some(extra_key.as_str()))
+                .count(),
+            2
+        );
+        assert!(stored.iter().all(|record| record
+            .source_dir(&config.image_root)
+            .join(&record.relative_path)
+            .is_file()));
+
+        // Same relative paths under different roots stay apart.
+        let again = perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!((again.skipped_files, again.hashed_files), (3, 0));
+
+        config.scan.extra_image_roots.clear();
+        let narrowed = perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!((narrowed.total_files, narrowed.diff.removed.count), (1, 2));
+        Ok(())
+    }
 
-    #[allow(deprecated)]
     #[test]
     fn scan_detects_duplicates_and_skips_cached_files() -> Result<()> {
-        let root_dir = tempdir()?.into_path();
-        let output_dir = tempdir()?.into_path();
-        let duplicates_dir = output_dir.join("duplicates");
-        fs::create_dir_all(&duplicates_dir)?;
+        let config = test_config(&tempdir()?.keep());
+        let root_dir = config.image_root.clone();
+        fs::create_dir_all(&config.duplicates_dir)?;
 
         let file_one = root_dir.join("one.jpg");
         let nested_dir = root_dir.join("nested");
@@ -399,39 +1872,609 @@ mod tests {
         fs::write(&file_duplicate, b"same")?;
         fs::write(&file_unique, b"different")?;
 
-        let config = AppConfig {
-            schema_version: SCHEMA_VERSION,
-            home_dir: root_dir.clone(),
-            app_data_dir: output_dir.clone(),
-            database_path: output_dir.join("scan.sqlite3"),
-            image_root: root_dir.clone(),
-            image_root_default_name: "images".into(),
-            output_root: output_dir.clone(),
-            output_root_name: "output".into(),
-            duplicates_dir: duplicates_dir.clone(),
-            duplicates_folder_name: "duplicates".into(),
-            origin_info_path: output_dir.join("origin.json"),
-            target_plan_path: output_dir.join("plan.json"),
-            image_exts: HashSet::from([".jpg".into()]),
-            config_file_path: PathBuf::from("config/config.json"),
-            sample_image_root: None,
-        };
-
         let database = Database::initialize(&config)?;
-        let emitter: ProgressEmitter = Arc::new(|_| {});
+        let payloads = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&payloads);
+        let emitter: ProgressEmitter =
+            Arc::new(move |payload: ScanProgressPayload| recorded.lock().push(payload));
 
-        let summary_first = perform_scan(&config, &database, emitter.clone())?;
+        let summary_first =
+            perform_scan(&config, &database, &TaskControl::default(), emitter.clone())?;
         assert_eq!(summary_first.total_files, 3);
+        // Totals never move once a stage has started, and every stage ends
+        // complete.
+        let payloads = std::mem::take(&mut *payloads.lock());
+        for stage in ScanStage::ALL {
+            let totals: HashSet<Option<usize>> = payloads
+                .iter()
+                .flat_map(|payload| &payload.stages)
+                .filter(|progress| progress.stage == stage && progress.total.is_some())
+                .map(|progress| progress.total)
+                .collect();
+            assert_eq!(totals.len(), 1, "{stage:?}");
+        }
+        let last = payloads.last().expect("progress was reported");
+        assert_eq!(last.stage, ScanStage::Persist);
+        assert!(last
+            .stages
+            .iter()
+            .all(|progress| progress.total == Some(progress.processed)));
         assert_eq!(summary_first.hashed_files, 3);
         assert_eq!(summary_first.duplicate_files, 1);
+        assert_eq!(summary_first.media_counts.photos, 3);
 
-        let summary_second = perform_scan(&config, &database, emitter)?;
+        let summary_second = perform_scan(&config, &database, &TaskControl::default(), emitter)?;
         assert_eq!(summary_second.hashed_files, 0);
         assert_eq!(summary_second.skipped_files, 3);
 
         let stored = database.inventory_snapshot()?;
         assert_eq!(stored.len(), 3);
         assert!(stored.iter().any(|record| record.is_duplicate));
+
+        // A scan interrupted after one batch resumes from the checkpoint.
+        database.replace_inventory(&[])?;
+        database.set_meta(CHECKPOINT_ROOT_KEY, &to_posix_string(&root_dir))?;
+        database.append_scan_checkpoint(&stored[..1])?;
+        let summary_resumed = perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(summary_resumed.resumed_files, 1);
+        // The rest is still known by file identity where there is one.
+        let from_cache = if cfg!(unix) { 2 } else { 0 };
+        assert_eq!(summary_resumed.cached_files, from_cache);
+        assert_eq!(summary_resumed.hashed_files, 2 - from_cache);
+        assert!(database.scan_checkpoint()?.is_empty());
+
+        // Grouping by size leaves a new file with a size of its own unread.
+        fs::write(root_dir.join("lonely.jpg"), b"a size of its own")?;
+        let mut grouped = config.clone();
+        grouped.scan.hash_strategy = HashStrategy::SizeGrouped;
+        let summary_grouped = perform_scan(
+            &grouped,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(summary_grouped.hashed_files, 0);
+        assert_eq!(summary_grouped.unhashed_files, 1);
+        assert_eq!(summary_grouped.duplicate_files, 1);
+        let lonely = database
+            .inventory_snapshot()?
+            .into_iter()
+            .find(|record| record.file_name == "lonely.jpg")
+            .expect("lonely file");
+        assert!(is_unhashed(&lonely.file_hash));
+        assert_eq!(lonely.blake3_hash, None);
+
+        let summary_again = perform_scan(
+            &grouped,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(summary_again.skipped_files, 4);
+        assert!(summary_again.diff.is_empty());
+
+        // Back under the full strategy, the stand-in is replaced quietly.
+        let summary_full = perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        assert_eq!(summary_full.hashed_files, 1);
+        assert_eq!(summary_full.unhashed_files, 0);
+        assert!(summary_full.diff.is_empty());
+
+        // A renamed file is matched by identity instead of being read again.
+        if cfg!(unix) {
+            fs::rename(&file_unique, root_dir.join("renamed.jpg"))?;
+            let summary_renamed = perform_scan(
+                &config,
+                &database,
+                &TaskControl::default(),
+                Arc::new(|_| {}),
+            )?;
+            assert_eq!(summary_renamed.hashed_files, 0);
+            assert_eq!(summary_renamed.cached_files, 1);
+            assert_eq!(summary_renamed.diff.moved.count, 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn photos_libraries_are_skipped_unless_originals_are_requested() -> Result<()> {
+        let root_dir = tempdir()?;
+        let bundle = root_dir.path().join("Photos Library.photoslibrary");
+        fs::create_dir_all(bundle.join("originals/A"))?;
+        fs::create_dir_all(bundle.join("resources/derivatives"))?;
+        fs::write(bundle.join("originals/A/IMG_0001.jpg"), b"original")?;
+        fs::write(bundle.join("resources/derivatives/IMG_0001.jpg"), b"thumb")?;
+        fs::write(root_dir.path().join("loose.jpg"), b"loose")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+
+        let mut options = ScanOptions::default();
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files, vec![root_dir.path().join("loose.jpg")]);
+        assert_eq!(folders.photos_skipped.len(), 1);
+
+        options.photos_library = PhotosLibraryMode::Originals;
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files.len(), 2);
+        assert!(files.contains(&bundle.join("originals/A/IMG_0001.jpg")));
+        assert_eq!(folders.photos_scanned.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn nomedia_folders_are_skipped_when_honored() -> Result<()> {
+        let root_dir = tempdir()?;
+        let hidden = root_dir.path().join("WhatsApp/Media/.Statuses");
+        fs::create_dir_all(hidden.join("nested"))?;
+        fs::write(hidden.join(".nomedia"), b"")?;
+        fs::write(hidden.join("status.jpg"), b"status")?;
+        fs::write(hidden.join("nested/deeper.jpg"), b"deeper")?;
+        fs::write(root_dir.path().join(".nomedia"), b"")?;
+        fs::write(root_dir.path().join("kept.jpg"), b"kept")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+
+        let mut options = ScanOptions::default();
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files, vec![root_dir.path().join("kept.jpg")]);
+        assert_eq!(folders.nomedia.len(), 1);
+
+        options.honor_nomedia = false;
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files.len(), 3);
+        assert!(folders.nomedia.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn cloud_placeholders_follow_the_configured_mode() -> Result<()> {
+        let root_dir = tempdir()?;
+        fs::write(root_dir.path().join(".IMG_0002.jpg.icloud"), b"stub")?;
+        fs::write(root_dir.path().join("IMG_0001.jpg"), b"local")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+
+        let mut options = ScanOptions::default();
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files, vec![root_dir.path().join("IMG_0001.jpg")]);
+        assert_eq!(
+            folders.placeholders,
+            vec![to_posix_string(&root_dir.path().join("IMG_0002.jpg")).into_owned()]
+        );
+
+        options.cloud_placeholders = CloudPlaceholderMode::Fail;
+        let err =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress).unwrap_err();
+        assert!(matches!(err, AppError::CloudPlaceholders { count: 1, .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_files_prune_the_walk() -> Result<()> {
+        let root_dir = tempdir()?;
+        fs::create_dir_all(root_dir.path().join("exports"))?;
+        fs::create_dir_all(root_dir.path().join("trip"))?;
+        fs::write(root_dir.path().join(".phototidyignore"), "exports/\n")?;
+        fs::write(
+            root_dir.path().join("trip/.phototidyignore"),
+            "*-edit.jpg\n",
+        )?;
+        fs::write(root_dir.path().join("exports/a.jpg"), b"export")?;
+        fs::write(root_dir.path().join("trip/b.jpg"), b"original")?;
+        fs::write(root_dir.path().join("trip/b-edit.jpg"), b"edit")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let (files, folders) = enumerate_files(
+            &[root_dir.path()],
+            &exts,
+            &ScanOptions::default(),
+            None,
+            &[],
+            &progress,
+        )?;
+        assert_eq!(files, vec![root_dir.path().join("trip/b.jpg")]);
+        assert_eq!(folders.ignored, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn output_folders_inside_the_source_are_not_walked() -> Result<()> {
+        let root_dir = tempdir()?;
+        let output = root_dir.path().join("Sorted");
+        fs::create_dir_all(output.join("2024/01"))?;
+        fs::write(output.join("2024/01/a.jpg"), b"organized")?;
+        fs::write(root_dir.path().join("a.jpg"), b"organized")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let (files, folders) = enumerate_files(
+            &[root_dir.path()],
+            &exts,
+            &ScanOptions::default(),
+            None,
+            &[normalize(&output)?],
+            &progress,
+        )?;
+        assert_eq!(files, vec![root_dir.path().join("a.jpg")]);
+        assert_eq!(folders.output, 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_count_as_one_file() -> Result<()> {
+        let root_dir = tempdir()?;
+        let original = root_dir.path().join("a.jpg");
+        fs::write(&original, b"same inode")?;
+        fs::hard_link(&original, root_dir.path().join("b.jpg"))?;
+        fs::write(root_dir.path().join("c.jpg"), b"same inode")?;
+
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let files = ["a.jpg", "b.jpg", "c.jpg"].map(|name| root_dir.path().join(name));
+        let mut snapshots = build_snapshots(&[root_dir.path()], files.to_vec(), &progress)?;
+        assert_eq!(drop_hardlink_aliases(&mut snapshots), 1);
+        let kept: Vec<_> = snapshots
+            .iter()
+            .map(|snapshot| snapshot.relative_path.as_str())
+            .collect();
+        // A separate copy with the same bytes is still a duplicate later on.
+        assert_eq!(kept, ["a.jpg", "c.jpg"]);
+        Ok(())
+    }
+
+    #[test]
+    fn scope_keeps_changes_inside_included_folders() {
+        let root = Path::new("/photos");
+        let included = vec![root.join("trips"), root.join("family/2020")];
+        let changed = [
+            root.join("trips/paris"),
+            root.join("family"),
+            root.join("work"),
+        ];
+
+        let scope = scan_scope(Some(included.clone()), Some(&changed)).unwrap();
+        assert_eq!(scope, [root.join("trips/paris"), root.join("family/2020")]);
+        assert_eq!(scan_scope(Some(included.clone()), None), Some(included));
+        assert_eq!(scan_scope(None, None), None);
+    }
+
+    #[test]
+    fn estimates_count_only_unknown_files_as_reads() -> Result<()> {
+        let dir = tempdir()?;
+        let config = test_config(dir.path());
+        let root_dir = config.image_root.clone();
+        fs::write(root_dir.join("a.jpg"), vec![1u8; 1000])?;
+        fs::write(root_dir.join("b.jpg"), vec![2u8; 3000])?;
+        let database = Database::initialize(&config)?;
+
+        let estimate = estimate_scan(&config, &database)?;
+        assert_eq!((estimate.total_files, estimate.total_bytes), (2, 4000));
+        assert_eq!(estimate.bytes_to_read, 4000);
+        assert!(!estimate.measured);
+
+        perform_scan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            Arc::new(|_| {}),
+        )?;
+        fs::write(root_dir.join("c.jpg"), vec![3u8; 500])?;
+        database.set_meta(SCAN_THROUGHPUT_KEY, "1000")?;
+        let estimate = estimate_scan(&config, &database)?;
+        assert_eq!(estimate.known_files, 2);
+        assert_eq!(estimate.bytes_to_read, 500);
+        assert!(estimate.measured);
+        assert_eq!(estimate.estimated_ms, 500);
+        Ok(())
+    }
+
+    #[test]
+    fn estimates_leave_cloud_placeholders_in_the_cloud() -> Result<()> {
+        let dir = tempdir()?;
+        let mut config = test_config(dir.path());
+        fs::write(config.image_root.join(".IMG_0002.jpg.icloud"), b"stub")?;
+        fs::write(config.image_root.join("IMG_0001.jpg"), b"local")?;
+        let database = Database::initialize(&config)?;
+
+        for mode in [CloudPlaceholderMode::Hydrate, CloudPlaceholderMode::Fail] {
+            config.scan.cloud_placeholders = mode;
+            let estimate = estimate_scan(&config, &database)?;
+            assert_eq!((estimate.total_files, estimate.total_bytes), (1, 5));
+            assert_eq!(estimate.cloud_files, 1);
+            assert!(config.image_root.join(".IMG_0002.jpg.icloud").exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn depth_and_folder_limits_cut_the_walk_short() -> Result<()> {
+        let root_dir = tempdir()?;
+        fs::create_dir_all(root_dir.path().join("a/b"))?;
+        for name in ["top.jpg", "a/1.jpg", "a/2.jpg", "a/3.jpg", "a/b/deep.jpg"] {
+            fs::write(root_dir.path().join(name), name)?;
+        }
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let options = ScanOptions {
+            max_depth: Some(1),
+            max_files_per_directory: Some(2),
+            ..ScanOptions::default()
+        };
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        let names: Vec<_> = files
+            .iter()
+            .map(|path| diff_paths(path, root_dir.path()).unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Path::new("a/1.jpg"),
+                Path::new("a/2.jpg"),
+                Path::new("top.jpg")
+            ]
+        );
+        assert_eq!(folders.limits.deep_folders.len(), 1);
+        assert!(folders.limits.deep_folders[0].ends_with("/a/b"));
+        assert_eq!(folders.limits.crowded_folders.len(), 1);
+        assert_eq!(folders.limits.files_left_out, 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sockets_named_like_media_are_reported_not_read() -> Result<()> {
+        use std::os::unix::net::UnixListener;
+
+        let root_dir = tempdir()?;
+        fs::write(root_dir.path().join("a.jpg"), b"photo")?;
+        let _socket = UnixListener::bind(root_dir.path().join("b.jpg"))?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let (files, folders) = enumerate_files(
+            &[root_dir.path()],
+            &exts,
+            &ScanOptions::default(),
+            None,
+            &[],
+            &progress,
+        )?;
+        assert_eq!(files, [root_dir.path().join("a.jpg")]);
+        assert_eq!(folders.special.len(), 1);
+        assert!(folders.special[0].ends_with("/b.jpg"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_links_are_walked_once_without_looping() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let root_dir = tempdir()?;
+        let elsewhere = tempdir()?;
+        fs::create_dir_all(root_dir.path().join("trip"))?;
+        fs::write(root_dir.path().join("trip/a.jpg"), b"trip")?;
+        fs::write(elsewhere.path().join("b.jpg"), b"linked")?;
+        symlink(root_dir.path(), root_dir.path().join("trip/loop"))?;
+        symlink(root_dir.path().join("trip"), root_dir.path().join("again"))?;
+        symlink(elsewhere.path(), root_dir.path().join("linked"))?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let mut options = ScanOptions {
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let (mut files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        files.sort();
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert!(files.contains(&root_dir.path().join("linked/b.jpg")));
+        assert!(folders
+            .links
+            .contains(&to_posix_string(&root_dir.path().join("linked")).into_owned()));
+
+        options.follow_symlinks = false;
+        let (files, folders) =
+            enumerate_files(&[root_dir.path()], &exts, &options, None, &[], &progress)?;
+        assert_eq!(files, vec![root_dir.path().join("trip/a.jpg")]);
+        assert!(folders.links.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn journal_walks_only_the_changed_folders() -> Result<()> {
+        let root_dir = tempdir()?;
+        fs::create_dir_all(root_dir.path().join("trip/day1"))?;
+        fs::create_dir_all(root_dir.path().join("home"))?;
+        fs::write(root_dir.path().join(".phototidyignore"), "*-edit.jpg\n")?;
+        fs::write(root_dir.path().join("loose.jpg"), b"loose")?;
+        fs::write(root_dir.path().join("home/a.jpg"), b"home")?;
+        fs::write(root_dir.path().join("trip/day1/b.jpg"), b"trip")?;
+        fs::write(root_dir.path().join("trip/day1/b-edit.jpg"), b"edit")?;
+
+        let exts = HashSet::from([".jpg".to_string()]);
+        let progress = ScanProgress::new(Arc::new(|_| {}));
+        let only = [root_dir.path().join("trip")];
+        let (files, _) = enumerate_files(
+            &[root_dir.path()],
+            &exts,
+            &ScanOptions::default(),
+            Some(&only),
+            &[],
+            &progress,
+        )?;
+        // Ignore rules above the changed folder still apply.
+        assert_eq!(files, vec![root_dir.path().join("trip/day1/b.jpg")]);
+        Ok(())
+    }
+
+    #[test]
+    fn copy_suffixed_files_yield_to_their_base_file() {
+        let record = |name: &str, hash: &str| InventoryRecord {
+            id: None,
+            file_hash: hash.into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: name.into(),
+            relative_path: name.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        };
+        let mut records = vec![
+            record("IMG_0001 (1).JPG", "same"),
+            record("IMG_0001.JPG", "same"),
+            record("IMG_0002 (1).JPG", "other"),
+        ];
+
+        assert_eq!(
+            mark_duplicates(&mut records, Path::new(""), &HashSet::new()),
+            1
+        );
+        assert!(records[0].is_duplicate);
+        assert!(!records[1].is_duplicate);
+        assert!(!records[2].is_duplicate);
+        assert_eq!(
+            records[0].duplicate_confirmation,
+            Some(DuplicateConfirmation::Size)
+        );
+        assert_eq!(records[1].duplicate_confirmation, None);
+    }
+
+    #[test]
+    fn duplicates_need_size_and_blake3_to_agree() {
+        let record = |name: &str, size: u64, blake3: Option<&str>| InventoryRecord {
+            id: None,
+            file_hash: "collision".into(),
+            blake3_hash: blake3.map(Into::into),
+            file_size: size,
+            file_name: name.into(),
+            relative_path: name.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        };
+        let mut records = vec![
+            record("a.jpg", 10, Some("b3-a")),
+            record("b.jpg", 10, Some("b3-b")),
+            record("c.jpg", 11, Some("b3-a")),
+            record("d.jpg", 10, Some("b3-a")),
+        ];
+
+        assert_eq!(
+            mark_duplicates(&mut records, Path::new(""), &HashSet::new()),
+            1
+        );
+        let flagged: Vec<&str> = records
+            .iter()
+            .filter(|record| record.is_duplicate)
+            .map(|record| record.file_name.as_str())
+            .collect();
+        assert_eq!(flagged, vec!["d.jpg"]);
+        assert_eq!(
+            records[3].duplicate_confirmation,
+            Some(DuplicateConfirmation::Blake3)
+        );
+
+        // Different bytes, same picture.
+        records[1].pixel_hash = Some("pixels".into());
+        records[2].pixel_hash = Some("pixels".into());
+        assert_eq!(
+            mark_duplicates(&mut records, Path::new(""), &HashSet::new()),
+            2
+        );
+        assert!(!records[1].is_duplicate);
+        assert_eq!(
+            records[2].duplicate_confirmation,
+            Some(DuplicateConfirmation::Pixels)
+        );
+    }
+
+    #[test]
+    fn keepers_prefer_camera_folders_and_sidecars() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join("Camera"))?;
+        fs::write(dir.path().join("Camera/IMG_0002.xmp"), b"<x/>")?;
+        let record = |relative_path: &str| InventoryRecord {
+            id: None,
+            file_hash: "same".into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: relative_path.rsplit('/').next().unwrap_or_default().into(),
+            relative_path: relative_path.into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: None,
+            modified_at: "2024-01-01_00-00-00".into(),
+            exif_model: None,
+            exif_make: None,
+            exif_artist: None,
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        };
+        let sidecars = HashSet::from([".xmp".to_string()]);
+
+        let mut records = vec![
+            record("WhatsApp Images/IMG_0001.JPG"),
+            record("Phone/DCIM/100APPLE/IMG_0001.JPG"),
+        ];
+        mark_duplicates(&mut records, dir.path(), &sidecars);
+        assert!(records[0].is_duplicate && !records[1].is_duplicate);
+
+        let mut records = vec![record("Backup/IMG_0002.JPG"), record("Camera/IMG_0002.JPG")];
+        mark_duplicates(&mut records, dir.path(), &sidecars);
+        assert!(records[0].is_duplicate && !records[1].is_duplicate);
         Ok(())
     }
 }
@@ -0,0 +1,214 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::config::{AppConfig, ConfigService};
+use crate::db::Database;
+use crate::error::Result;
+use crate::library::{check_entry, EntryCheck, ModifiedFile};
+use crate::utils::time::{format_timestamp, parse_timestamp};
+
+const SCRUB_TICK: StdDuration = StdDuration::from_secs(60 * 60);
+const SCRUB_WINDOW: Duration = Duration::weeks(1);
+
+const META_CURSOR: &str = "scrub_cursor";
+const META_WINDOW_STARTED_AT: &str = "scrub_window_started_at";
+const META_WINDOW_BYTES: &str = "scrub_window_bytes";
+const META_LAST_REPORT: &str = "scrub_last_report";
+
+pub type ScrubReportEmitter = Arc<dyn Fn(ScrubReport) + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrubReport {
+    pub scrubbed_at: String,
+    pub checked_files: usize,
+    pub checked_bytes: u64,
+    pub missing: Vec<String>,
+    pub modified: Vec<ModifiedFile>,
+    /// True when this slice reached the end of the index and started over.
+    pub wrapped: bool,
+}
+
+impl ScrubReport {
+    pub fn has_errors(&self) -> bool {
+        !self.missing.is_empty() || !self.modified.is_empty()
+    }
+}
+
+/// Runs one hourly scrub step. Within the current weekly window it verifies
+/// up to `bytesPerHour`, stopping once the window's share of the library
+/// (`total / rotationWeeks`) is covered. The cursor persists in `app_meta` so
+/// the rotation survives restarts. Returns `None` when there is nothing to do.
+pub fn scrub_tick(
+    config: &AppConfig,
+    database: &Database,
+    now: OffsetDateTime,
+) -> Result<Option<ScrubReport>> {
+    let options = &config.scrub;
+    if !options.enabled || options.bytes_per_hour == 0 {
+        return Ok(None);
+    }
+
+    let entries = database.library_entries()?;
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let window_started = database
+        .get_meta(META_WINDOW_STARTED_AT)?
+        .and_then(|value| parse_timestamp(&value).ok());
+    let mut window_bytes = match window_started {
+        Some(started) if now - started < SCRUB_WINDOW => database
+            .get_meta(META_WINDOW_BYTES)?
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0),
+        _ => {
+            database.set_meta(META_WINDOW_STARTED_AT, &format_timestamp(now)?)?;
+            0
+        }
+    };
+
+    let library_bytes: u64 = entries.iter().map(|entry| entry.file_size).sum();
+    let weekly_quota = library_bytes.div_ceil(u64::from(options.rotation_weeks.max(1)));
+    let budget = options
+        .bytes_per_hour
+        .min(weekly_quota.saturating_sub(window_bytes));
+    if budget == 0 {
+        return Ok(None);
+    }
+
+    let cursor = database.get_meta(META_CURSOR)?.unwrap_or_default();
+    let start = entries
+        .iter()
+        .position(|entry| entry.path > cursor)
+        .unwrap_or(entries.len());
+
+    let mut report = ScrubReport {
+        scrubbed_at: format_timestamp(now)?,
+        checked_files: 0,
+        checked_bytes: 0,
+        missing: Vec::new(),
+        modified: Vec::new(),
+        wrapped: false,
+    };
+    let mut last_path = cursor;
+
+    for offset in 0..entries.len() {
+        let idx = (start + offset) % entries.len();
+        let entry = &entries[idx];
+        if report.checked_files > 0 && report.checked_bytes + entry.file_size > budget {
+            break;
+        }
+        if idx < start {
+            report.wrapped = true;
+        }
+
+        match check_entry(entry) {
+            EntryCheck::Intact => {}
+            EntryCheck::Missing => report.missing.push(entry.path.clone()),
+            EntryCheck::Modified(file) => report.modified.push(file),
+        }
+        report.checked_files += 1;
+        report.checked_bytes += entry.file_size;
+        last_path = entry.path.clone();
+    }
+
+    window_bytes += report.checked_bytes;
+    database.set_meta(META_CURSOR, &last_path)?;
+    database.set_meta(META_WINDOW_BYTES, &window_bytes.to_string())?;
+    database.set_meta(META_LAST_REPORT, &serde_json::to_string(&report)?)?;
+
+    if report.has_errors() {
+        tracing::error!(
+            missing = report.missing.len(),
+            modified = report.modified.len(),
+            "library scrub found damaged files"
+        );
+    } else {
+        tracing::info!(
+            files = report.checked_files,
+            bytes = report.checked_bytes,
+            "library scrub slice verified"
+        );
+    }
+
+    Ok(Some(report))
+}
+
+/// Starts the background scrub thread. The config is re-read every tick so
+/// enabling or disabling the scrub takes effect without a restart.
+pub fn spawn_scrub_scheduler(
+    config: Arc<ConfigService>,
+    database: Arc<Database>,
+    on_error: ScrubReportEmitter,
+) {
+    let spawned = thread::Builder::new()
+        .name("library-scrub".into())
+        .spawn(move || loop {
+            let snapshot = config.snapshot();
+            match scrub_tick(&snapshot, database.as_ref(), OffsetDateTime::now_utc()) {
+                Ok(Some(report)) if report.has_errors() => (on_error)(report),
+                Ok(_) => {}
+                Err(err) => tracing::warn!(error = %err, "library scrub failed"),
+            }
+            thread::sleep(SCRUB_TICK);
+        });
+    if let Err(err) = spawned {
+        tracing::error!(error = %err, "failed to start library scrub thread");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::library::{index_output, LibraryProgressEmitter};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn scrub_rotates_through_the_library_within_budget() -> Result<()> {
+        let mut config = test_config(&tempdir()?.keep());
+        config.scrub.enabled = true;
+        config.scrub.bytes_per_hour = 10;
+        config.scrub.rotation_weeks = 1;
+        let database = Database::initialize(&config)?;
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            fs::write(config.output_root.join(name), b"12345")?;
+        }
+        let emitter: LibraryProgressEmitter = Arc::new(|_| {});
+        index_output(&config, &database, emitter)?;
+
+        let now = OffsetDateTime::now_utc();
+        let first = scrub_tick(&config, &database, now)?.expect("first slice");
+        assert_eq!(first.checked_files, 2);
+        assert!(!first.has_errors());
+
+        fs::write(config.output_root.join("a.jpg"), b"rot!!")?;
+        let second = scrub_tick(&config, &database, now)?.expect("second slice");
+        assert_eq!(second.checked_files, 1);
+        assert!(!second.wrapped);
+
+        // The weekly quota is spent until the window rolls over.
+        assert!(scrub_tick(&config, &database, now)?.is_none());
+
+        let next_week =
+            scrub_tick(&config, &database, now + Duration::days(8))?.expect("next window");
+        assert!(next_week.wrapped);
+        assert_eq!(next_week.modified.len(), 1);
+        assert!(next_week.has_errors());
+        Ok(())
+    }
+
+    #[test]
+    fn scrub_is_opt_in() -> Result<()> {
+        let config = test_config(&tempdir()?.keep());
+        let database = Database::initialize(&config)?;
+        assert!(scrub_tick(&config, &database, OffsetDateTime::now_utc())?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::{AppConfig, SCHEMA_VERSION};
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::execute::{
+    run_execution, undo_moves, ExecutionMode, ExecutionProgressEmitter, ExecutionSummary,
+};
+use crate::library::verify_library;
+use crate::media::MediaExtensions;
+use crate::metadata::insert_capture_date;
+use crate::plan::{generate_plan, PlanProgressEmitter};
+use crate::progress::TaskControl;
+use crate::scan::{perform_scan, ProgressEmitter};
+use crate::utils::hash::blake3_file;
+
+/// Photos in the throwaway library, with their EXIF capture dates.
+const PHOTOS: [(&str, &str); 3] = [
+    ("beach.jpg", "2021:05:01 09:30:00"),
+    ("party.jpg", "2022:07:14 20:15:00"),
+    ("snow.jpg", "2023:12:24 08:00:00"),
+];
+/// A byte-for-byte copy of the first photo.
+const DUPLICATE: &str = "copies/beach copy.jpg";
+const VIDEO: &str = "clip.mp4";
+const LIBRARY_FILES: usize = PHOTOS.len() + 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    /// Not run because an earlier step failed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub status: StepStatus,
+    /// What was checked, or why it failed.
+    pub detail: String,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub steps: Vec<SelfTestStep>,
+    pub elapsed_ms: u64,
+}
+
+/// Runs scan, plan, a dry run, a copy, a library check and a move with its
+/// undo against a small library made up on the spot, all inside a temporary
+/// folder that is removed afterwards. None of the user's folders, settings
+/// or database are touched.
+pub fn run_self_test() -> Result<SelfTestReport> {
+    // Keeps these runs from replacing the user's last profile.
+    let _run = tracing::info_span!("self_test").entered();
+    let started = Instant::now();
+    let workspace = Workspace::create()?;
+    let originals = workspace.populate()?;
+    let config = workspace.config("output");
+    let database = Database::initialize(&config)?;
+    let control = TaskControl::default();
+    let scan_emitter: ProgressEmitter = Arc::new(|_| {});
+    let plan_emitter: PlanProgressEmitter = Arc::new(|_| {});
+    let execution_emitter: ExecutionProgressEmitter = Arc::new(|_| {});
+
+    let mut steps = Steps::default();
+    steps.run("scan", || {
+        let summary = perform_scan(&config, &database, &control, scan_emitter.clone())?;
+        check(
+            summary.total_files == LIBRARY_FILES,
+            format!("found {} of {LIBRARY_FILES} files", summary.total_files),
+        )?;
+        check(
+            summary.duplicate_files == 1,
+            format!("found {} duplicates, expected 1", summary.duplicate_files),
+        )?;
+        check(
+            summary.media_counts.videos == 1,
+            format!("found {} videos, expected 1", summary.media_counts.videos),
+        )?;
+        let days: Vec<String> = PHOTOS
+            .iter()
+            .map(|(_, date)| date[..10].replace(':', "-"))
+            .collect();
+        // Files without a readable date fall back to their modified time.
+        let dated = database
+            .inventory_snapshot()?
+            .iter()
+            .filter_map(|record| record.captured_at.as_deref())
+            .filter(|captured| days.iter().any(|day| captured.starts_with(day.as_str())))
+            .count();
+        check(
+            dated == PHOTOS.len() + 1,
+            format!("read capture dates from {dated} photos"),
+        )?;
+        Ok(format!(
+            "{} files, {} duplicate, capture dates read",
+            summary.total_files, summary.duplicate_files
+        ))
+    });
+    steps.run("plan", || {
+        let summary = generate_plan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            plan_emitter.clone(),
+        )?;
+        check(
+            summary.total_entries == LIBRARY_FILES,
+            format!("planned {} of {LIBRARY_FILES} files", summary.total_entries),
+        )?;
+        check(
+            summary.duplicate_entries == 1,
+            format!("planned {} duplicates", summary.duplicate_entries),
+        )?;
+        Ok(format!(
+            "{} entries into {} folders",
+            summary.total_entries, summary.destination_buckets
+        ))
+    });
+    steps.run("dry-run", || {
+        let summary = run_execution(
+            &config,
+            &database,
+            ExecutionMode::Copy,
+            true,
+            false,
+            &control,
+            execution_emitter.clone(),
+        )?;
+        all_succeeded(&summary)?;
+        let written = count_files(&config.output_root)?;
+        check(written == 0, format!("dry run wrote {written} files"))?;
+        Ok(format!("{} entries would copy", summary.succeeded))
+    });
+    steps.run("copy", || {
+        let summary = run_execution(
+            &config,
+            &database,
+            ExecutionMode::Copy,
+            false,
+            true,
+            &control,
+            execution_emitter.clone(),
+        )?;
+        all_succeeded(&summary)?;
+        unchanged(&originals)?;
+        Ok(format!("copied {} files", summary.succeeded))
+    });
+    steps.run("verify", || {
+        let report = verify_library(&config, &database, Arc::new(|_| {}))?;
+        check(
+            report.clean && report.intact_files == LIBRARY_FILES,
+            format!(
+                "{} intact, {} missing, {} modified, {} unexpected",
+                report.intact_files,
+                report.missing.len(),
+                report.modified.len(),
+                report.extra.len()
+            ),
+        )?;
+        Ok(format!("{} copies match their hashes", report.intact_files))
+    });
+    // Only moves can be undone, so the library is planned again into a
+    // second output, moved there and moved back.
+    steps.run("undo", || {
+        let config = workspace.config("moved");
+        database.configure_paths(&config);
+        generate_plan(
+            &config,
+            &database,
+            &TaskControl::default(),
+            plan_emitter.clone(),
+        )?;
+        let moved = run_execution(
+            &config,
+            &database,
+            ExecutionMode::Move,
+            false,
+            true,
+            &control,
+            execution_emitter.clone(),
+        )?;
+        all_succeeded(&moved)?;
+        let summary = undo_moves(&config, &database, &control, execution_emitter.clone())?;
+        check(
+            summary.restored == moved.succeeded && summary.failed + summary.missing == 0,
+            format!(
+                "restored {} of {} moved files",
+                summary.restored, moved.succeeded
+            ),
+        )?;
+        unchanged(&originals)?;
+        Ok(format!("moved and restored {} files", summary.restored))
+    });
+
+    let steps = steps.0;
+    Ok(SelfTestReport {
+        passed: steps.iter().all(|step| step.status == StepStatus::Passed),
+        steps,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[derive(Default)]
+struct Steps(Vec<SelfTestStep>);
+
+impl Steps {
+    fn run(&mut self, name: &'static str, op: impl FnOnce() -> Result<String>) {
+        if self.0.iter().any(|step| step.status != StepStatus::Passed) {
+            self.0.push(SelfTestStep {
+                name,
+                status: StepStatus::Skipped,
+                detail: "an earlier step failed".into(),
+                elapsed_ms: 0,
+            });
+            return;
+        }
+        let started = Instant::now();
+        let (status, detail) = match op() {
+            Ok(detail) => (StepStatus::Passed, detail),
+            Err(err) => {
+                tracing::warn!(step = name, error = %err, "self-test step failed");
+                (StepStatus::Failed, err.to_string())
+            }
+        };
+        self.0.push(SelfTestStep {
+            name,
+            status,
+            detail,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+fn check(condition: bool, failure: String) -> Result<()> {
+    if condition {
+        Ok(())
+    } else {
+        Err(AppError::internal(failure))
+    }
+}
+
+fn all_succeeded(summary: &ExecutionSummary) -> Result<()> {
+    check(
+        summary.failed == 0 && summary.succeeded == summary.total_entries,
+        format!(
+            "{} of {} entries failed",
+            summary.failed, summary.total_entries
+        ),
+    )
+}
+
+/// Fails when an original is gone or its content changed.
+fn unchanged(originals: &[(PathBuf, String)]) -> Result<()> {
+    for (path, hash) in originals {
+        let intact = path.is_file() && blake3_file(path)? == *hash;
+        check(intact, format!("{} changed", path.display()))?;
+    }
+    Ok(())
+}
+
+fn count_files(dir: &Path) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut count = 0;
+    for entry in walkdir::WalkDir::new(dir) {
+        if entry.map_err(AppError::internal)?.file_type().is_file() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// A temporary folder holding the library, outputs and database of one
+/// self-test; removed when dropped.
+struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    fn create() -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let root = std::env::temp_dir().join(format!(
+            "phototidy-self-test-{}-{nanos}",
+            std::process::id()
+        ));
+        fs::create_dir_all(root.join("data"))?;
+        Ok(Self { root })
+    }
+
+    fn library(&self) -> PathBuf {
+        self.root.join("library")
+    }
+
+    /// Writes the library and returns every file with its hash.
+    fn populate(&self) -> Result<Vec<(PathBuf, String)>> {
+        let library = self.library();
+        fs::create_dir_all(library.join(Path::new(DUPLICATE).parent().unwrap_or(Path::new(""))))?;
+        let mut files = Vec::new();
+        for (name, date) in PHOTOS {
+            let path = library.join(name);
+            fs::write(&path, jpeg(name))?;
+            insert_capture_date(&path, date)?;
+            files.push(path);
+        }
+        let duplicate = library.join(DUPLICATE);
+        fs::copy(&files[0], &duplicate)?;
+        files.push(duplicate);
+        let video = library.join(VIDEO);
+        fs::write(&video, mp4())?;
+        files.push(video);
+
+        files
+            .into_iter()
+            .map(|path| {
+                let hash = blake3_file(&path)?;
+                Ok((path, hash))
+            })
+            .collect()
+    }
+
+    /// Settings for a run into `output`, built from defaults so the user's
+    /// routing rules, share and export folders stay out of it.
+    fn config(&self, output: &str) -> AppConfig {
+        let data = self.root.join("data");
+        let output_root = self.root.join(output);
+        let media_exts = MediaExtensions::from_legacy([".jpg".to_string(), ".mp4".to_string()]);
+        AppConfig {
+            schema_version: SCHEMA_VERSION,
+            home_dir: self.root.clone(),
+            app_data_dir: data.clone(),
+            database_path: data.join("phototidy.sqlite3"),
+            image_root: self.library(),
+            image_root_default_name: "library".into(),
+            output_root: output_root.clone(),
+            output_root_name: output.into(),
+            duplicates_dir: output_root.join("duplicates"),
+            duplicates_folder_name: "duplicates".into(),
+            origin_info_path: data.join("origin_info.json"),
+            target_plan_path: data.join(format!("{output}_plan.json")),
+            image_exts: media_exts.scannable(),
+            media_exts,
+            config_file_path: data.join("config.json"),
+            sample_image_root: None,
+            plan: Default::default(),
+            scan: Default::default(),
+            scrub: Default::default(),
+            path_storage: Default::default(),
+            power: Default::default(),
+            io: Default::default(),
+            telemetry: Default::default(),
+        }
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.root) {
+            tracing::warn!(path = %self.root.display(), error = %err, "failed to remove self-test folder");
+        }
+    }
+}
+
+/// A JPEG with no frame, just a scan whose bytes differ per `seed`. Enough
+/// for hashing and EXIF; nothing here decodes pixels.
+fn jpeg(seed: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xDA];
+    bytes.extend(((seed.len() + 2) as u16).to_be_bytes());
+    bytes.extend(seed.as_bytes());
+    bytes.extend([0xFF, 0xD9]);
+    bytes
+}
+
+/// An MP4 with a file type box and a little media data.
+fn mp4() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(24u32.to_be_bytes());
+    bytes.extend(b"ftypisom");
+    bytes.extend(0x200u32.to_be_bytes());
+    bytes.extend(b"isommp41");
+    let data = b"self-test";
+    bytes.extend((8 + data.len() as u32).to_be_bytes());
+    bytes.extend(b"mdat");
+    bytes.extend(data);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_step_passes_and_the_workspace_is_removed() -> Result<()> {
+        let report = run_self_test()?;
+        let failed: Vec<_> = report
+            .steps
+            .iter()
+            .filter(|step| step.status != StepStatus::Passed)
+            .collect();
+        assert!(failed.is_empty(), "{failed:?}");
+        let names: Vec<_> = report.steps.iter().map(|step| step.name).collect();
+        assert_eq!(names, ["scan", "plan", "dry-run", "copy", "verify", "undo"]);
+        assert!(report.passed);
+
+        let leftovers = fs::read_dir(std::env::temp_dir())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&format!("phototidy-self-test-{}-", std::process::id()))
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use std::path::Path;
 use serde::Serialize;
 
 use crate::error::Result;
+use crate::network::is_network_path;
 use crate::utils::path::to_posix_string;
 
 #[derive(Debug, Clone, Serialize)]
@@ -13,6 +14,8 @@ pub struct DiskStatus {
     pub available_bytes: u64,
     #[serde(rename = "totalBytes")]
     pub total_bytes: u64,
+    /// The path is on a network share.
+    pub network: bool,
 }
 
 pub fn disk_status(path: &Path) -> Result<DiskStatus> {
@@ -27,6 +30,7 @@ pub fn disk_status(path: &Path) -> Result<DiskStatus> {
         path: to_posix_string(path).into_owned(),
         available_bytes,
         total_bytes,
+        network: is_network_path(path),
     })
 }
 
@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::utils::json::read_json;
+use crate::utils::time::format_timestamp;
+
+const SIDECAR_EXT: &str = ".json";
+const SUPPLEMENTAL_SUFFIX: &str = ".supplemental-metadata";
+
+/// What a Google Takeout sidecar knows about its photo.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TakeoutMetadata {
+    pub captured_at: Option<String>,
+    pub gps_latitude: Option<f64>,
+    pub gps_longitude: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sidecar {
+    photo_taken_time: Option<SidecarTime>,
+    geo_data: Option<SidecarGeo>,
+    geo_data_exif: Option<SidecarGeo>,
+}
+
+#[derive(Deserialize)]
+struct SidecarTime {
+    timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct SidecarGeo {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl SidecarGeo {
+    /// Takeout writes `0.0, 0.0` when it has no location.
+    fn known(&self) -> Option<(f64, f64)> {
+        (self.latitude != 0.0 || self.longitude != 0.0).then_some((self.latitude, self.longitude))
+    }
+}
+
+/// Reads the Takeout sidecar next to `media`, if there is one. Takeout's
+/// `photoTakenTime` is a UTC epoch, so unlike EXIF dates it is not local
+/// wall-clock time.
+pub fn read_sidecar(media: &Path) -> Option<TakeoutMetadata> {
+    let sidecar_path = find_sidecar(media)?;
+    let sidecar: Sidecar = match read_json(&sidecar_path) {
+        Ok(sidecar) => sidecar,
+        Err(err) => {
+            tracing::debug!(path = %sidecar_path.display(), error = %err, "unreadable Takeout sidecar");
+            return None;
+        }
+    };
+
+    let captured_at = sidecar
+        .photo_taken_time
+        .and_then(|taken| taken.timestamp.parse::<i64>().ok())
+        .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+        .and_then(|moment| format_timestamp(moment).ok());
+    let location = sidecar
+        .geo_data
+        .as_ref()
+        .and_then(SidecarGeo::known)
+        .or_else(|| sidecar.geo_data_exif.as_ref().and_then(SidecarGeo::known));
+
+    Some(TakeoutMetadata {
+        captured_at,
+        gps_latitude: location.map(|(latitude, _)| latitude),
+        gps_longitude: location.map(|(_, longitude)| longitude),
+    })
+}
+
+/// Takeout names sidecars `IMG_1.JPG.json`, newer exports
+/// `IMG_1.JPG.supplemental-metadata.json`, both truncated to fit a name
+/// limit, and moves the copy counter of `IMG_1(1).JPG` to `IMG_1.JPG(1).json`.
+fn find_sidecar(media: &Path) -> Option<PathBuf> {
+    let dir = media.parent()?;
+    let name = media.file_name()?.to_str()?;
+    let stem = media.file_stem()?.to_str()?;
+
+    let mut candidates = vec![
+        format!("{name}{SIDECAR_EXT}"),
+        format!("{name}{SUPPLEMENTAL_SUFFIX}{SIDECAR_EXT}"),
+    ];
+    if let Some((base, counter)) = split_copy_counter(stem) {
+        let ext = media
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| format!(".{ext}"))
+            .unwrap_or_default();
+        candidates.push(format!("{base}{ext}({counter}){SIDECAR_EXT}"));
+        candidates.push(format!(
+            "{base}{ext}{SUPPLEMENTAL_SUFFIX}({counter}){SIDECAR_EXT}"
+        ));
+    }
+    candidates.push(format!("{stem}{SIDECAR_EXT}"));
+
+    if let Some(found) = candidates
+        .into_iter()
+        .map(|candidate| dir.join(candidate))
+        .find(|candidate| candidate.is_file())
+    {
+        return Some(found);
+    }
+
+    // Truncated names: the sidecar name, minus `.json`, is a prefix of the
+    // full supplemental name that still covers the photo's stem.
+    let full = format!("{name}{SUPPLEMENTAL_SUFFIX}");
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|file| file.to_str())
+                .and_then(|file| file.strip_suffix(SIDECAR_EXT))
+                .is_some_and(|prefix| prefix.len() > stem.len() && full.starts_with(prefix))
+        })
+}
+
+/// Splits `IMG_1(2)` into `("IMG_1", "2")`.
+fn split_copy_counter(stem: &str) -> Option<(&str, &str)> {
+    let inner = stem.strip_suffix(')')?;
+    let (base, counter) = inner.rsplit_once('(')?;
+    (!counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit())).then_some((base, counter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const SIDECAR: &str = r#"{
+        "title": "IMG_0001.JPG",
+        "photoTakenTime": { "timestamp": "1562264400", "formatted": "Jul 4, 2019" },
+        "geoData": { "latitude": 0.0, "longitude": 0.0, "altitude": 0.0 },
+        "geoDataExif": { "latitude": 48.8584, "longitude": 2.2945, "altitude": 35.0 }
+    }"#;
+
+    #[test]
+    fn sidecars_are_found_under_takeout_naming_schemes() -> std::io::Result<()> {
+        let dir = tempdir()?;
+        let cases = [
+            ("IMG_0001.JPG", "IMG_0001.JPG.json"),
+            ("IMG_0002(1).JPG", "IMG_0002.JPG(1).json"),
+            (
+                "PXL_20230101_123456789.MP.jpg",
+                "PXL_20230101_123456789.MP.jpg.supplemental-metad.json",
+            ),
+        ];
+        for (media, sidecar) in cases {
+            fs::write(dir.path().join(media), b"")?;
+            fs::write(dir.path().join(sidecar), SIDECAR)?;
+            let metadata = read_sidecar(&dir.path().join(media)).expect(media);
+            assert_eq!(metadata.captured_at.as_deref(), Some("2019-07-04_18-20-00"));
+            assert_eq!(metadata.gps_latitude, Some(48.8584));
+            assert_eq!(metadata.gps_longitude, Some(2.2945));
+        }
+
+        fs::write(dir.path().join("lonely.jpg"), b"")?;
+        assert!(read_sidecar(&dir.path().join("lonely.jpg")).is_none());
+        Ok(())
+    }
+}
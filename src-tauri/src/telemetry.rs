@@ -0,0 +1,452 @@
+//! Counters and histograms about runs, for headless installs. Builds with
+//! the `telemetry` feature write them to a Prometheus text file and push
+//! them to an OTLP/HTTP collector after every run; other builds record
+//! nothing.
+
+use std::time::Duration;
+
+use crate::config::TelemetryOptions;
+
+/// Why a file could not be organized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureKind {
+    OriginMissing,
+    TargetExists,
+    /// Another app kept the file locked through the retry pass.
+    Locked,
+    Io,
+}
+
+#[cfg(feature = "telemetry")]
+impl FailureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::OriginMissing => "origin_missing",
+            FailureKind::TargetExists => "target_exists",
+            FailureKind::Locked => "locked",
+            FailureKind::Io => "io",
+        }
+    }
+}
+
+pub fn record_scan(files: usize, bytes_hashed: u64) {
+    imp::record_scan(files, bytes_hashed);
+}
+
+pub fn record_copy(elapsed: Duration) {
+    imp::record_copy(elapsed);
+}
+
+pub fn record_failure(kind: FailureKind) {
+    imp::record_failure(kind);
+}
+
+/// Counts a finished scan, plan or execution and exports everything
+/// recorded so far. Export problems are logged, never returned, so they
+/// cannot fail the run.
+pub fn finish_run(task: &'static str, elapsed: Duration, options: &TelemetryOptions) {
+    imp::finish_run(task, elapsed, options);
+}
+
+#[cfg(feature = "telemetry")]
+mod imp {
+    use std::collections::BTreeMap;
+    use std::fmt::Write as _;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::path::Path;
+    use std::sync::OnceLock;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use parking_lot::Mutex;
+    use serde_json::{json, Value};
+
+    use super::FailureKind;
+    use crate::config::TelemetryOptions;
+    use crate::error::{AppError, Result};
+    use crate::utils::fs::ensure_parent_dir;
+
+    const FILES_SCANNED: &str = "phototidy_files_scanned_total";
+    const BYTES_HASHED: &str = "phototidy_bytes_hashed_total";
+    const FAILURES: &str = "phototidy_failures_total";
+    const RUNS: &str = "phototidy_runs_total";
+    const COPY_SECONDS: &str = "phototidy_copy_duration_seconds";
+    const RUN_SECONDS: &str = "phototidy_run_duration_seconds";
+
+    const COPY_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 30.0];
+    const RUN_BUCKETS: &[f64] = &[1.0, 10.0, 60.0, 300.0, 1800.0, 3600.0, 14400.0];
+
+    const OTLP_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// One optional `name="value"` label per series keeps the exporters
+    /// simple; nothing here needs more.
+    type Label = Option<(&'static str, &'static str)>;
+
+    #[derive(Debug, Clone)]
+    struct Histogram {
+        bounds: &'static [f64],
+        /// One more than `bounds`, the last counting everything above.
+        counts: Vec<u64>,
+        sum: f64,
+    }
+
+    impl Histogram {
+        fn new(bounds: &'static [f64]) -> Self {
+            Self {
+                bounds,
+                counts: vec![0; bounds.len() + 1],
+                sum: 0.0,
+            }
+        }
+
+        fn observe(&mut self, value: f64) {
+            let bucket = self
+                .bounds
+                .iter()
+                .position(|bound| value <= *bound)
+                .unwrap_or(self.bounds.len());
+            self.counts[bucket] += 1;
+            self.sum += value;
+        }
+
+        fn count(&self) -> u64 {
+            self.counts.iter().sum()
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Registry {
+        started: SystemTime,
+        counters: BTreeMap<(&'static str, Label), u64>,
+        histograms: BTreeMap<(&'static str, Label), Histogram>,
+    }
+
+    impl Registry {
+        fn new() -> Self {
+            Self {
+                started: SystemTime::now(),
+                counters: BTreeMap::new(),
+                histograms: BTreeMap::new(),
+            }
+        }
+
+        fn add(&mut self, name: &'static str, label: Label, value: u64) {
+            *self.counters.entry((name, label)).or_insert(0) += value;
+        }
+
+        fn observe(
+            &mut self,
+            name: &'static str,
+            label: Label,
+            bounds: &'static [f64],
+            value: f64,
+        ) {
+            self.histograms
+                .entry((name, label))
+                .or_insert_with(|| Histogram::new(bounds))
+                .observe(value);
+        }
+    }
+
+    fn registry() -> &'static Mutex<Registry> {
+        static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+        REGISTRY.get_or_init(|| Mutex::new(Registry::new()))
+    }
+
+    pub fn record_scan(files: usize, bytes_hashed: u64) {
+        let mut registry = registry().lock();
+        registry.add(FILES_SCANNED, None, files as u64);
+        registry.add(BYTES_HASHED, None, bytes_hashed);
+    }
+
+    pub fn record_copy(elapsed: Duration) {
+        registry()
+            .lock()
+            .observe(COPY_SECONDS, None, COPY_BUCKETS, elapsed.as_secs_f64());
+    }
+
+    pub fn record_failure(kind: FailureKind) {
+        registry()
+            .lock()
+            .add(FAILURES, Some(("kind", kind.as_str())), 1);
+    }
+
+    pub fn finish_run(task: &'static str, elapsed: Duration, options: &TelemetryOptions) {
+        let snapshot = {
+            let mut registry = registry().lock();
+            let label = Some(("task", task));
+            registry.add(RUNS, label, 1);
+            registry.observe(RUN_SECONDS, label, RUN_BUCKETS, elapsed.as_secs_f64());
+            registry.clone()
+        };
+        if let Some(path) = &options.prometheus_file {
+            if let Err(err) = write_prometheus(path, &snapshot) {
+                tracing::warn!(path = %path.display(), error = %err, "failed to write metrics file");
+            }
+        }
+        if let Some(endpoint) = &options.otlp_endpoint {
+            if let Err(err) = push_otlp(endpoint, &snapshot) {
+                tracing::warn!(endpoint, error = %err, "failed to push metrics");
+            }
+        }
+    }
+
+    fn help(name: &str) -> &'static str {
+        match name {
+            FILES_SCANNED => "Media files found by scans.",
+            BYTES_HASHED => "Bytes read to hash files.",
+            FAILURES => "Files that could not be organized, by kind.",
+            RUNS => "Finished scans, plans and executions.",
+            COPY_SECONDS => "Time taken to copy or move one file.",
+            RUN_SECONDS => "Time taken by a scan, plan or execution.",
+            _ => "",
+        }
+    }
+
+    fn labels(label: Label, extra: Option<String>) -> String {
+        let mut pairs: Vec<String> = label
+            .map(|(key, value)| format!("{key}=\"{value}\""))
+            .into_iter()
+            .collect();
+        pairs.extend(extra);
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    }
+
+    fn render_prometheus(registry: &Registry) -> String {
+        let mut out = String::new();
+        let mut described = None;
+        for ((name, label), value) in &registry.counters {
+            if described != Some(*name) {
+                let _ = writeln!(out, "# HELP {name} {}\n# TYPE {name} counter", help(name));
+                described = Some(*name);
+            }
+            let _ = writeln!(out, "{name}{} {value}", labels(*label, None));
+        }
+        for ((name, label), histogram) in &registry.histograms {
+            if described != Some(*name) {
+                let _ = writeln!(out, "# HELP {name} {}\n# TYPE {name} histogram", help(name));
+                described = Some(*name);
+            }
+            let mut cumulative = 0;
+            for (idx, count) in histogram.counts.iter().enumerate() {
+                cumulative += count;
+                let le = histogram
+                    .bounds
+                    .get(idx)
+                    .map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+                let bucket = labels(*label, Some(format!("le=\"{le}\"")));
+                let _ = writeln!(out, "{name}_bucket{bucket} {cumulative}");
+            }
+            let series = labels(*label, None);
+            let _ = writeln!(out, "{name}_sum{series} {}", histogram.sum);
+            let _ = writeln!(out, "{name}_count{series} {}", histogram.count());
+        }
+        out
+    }
+
+    /// Written aside and renamed, so a collector never reads half a file.
+    fn write_prometheus(path: &Path, registry: &Registry) -> Result<()> {
+        ensure_parent_dir(path)?;
+        let partial = path.with_extension("prom.partial");
+        fs::write(&partial, render_prometheus(registry))?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    fn unix_nanos(time: SystemTime) -> String {
+        time.duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos())
+            .unwrap_or(0)
+            .to_string()
+    }
+
+    fn attributes(label: Label) -> Value {
+        Value::Array(
+            label
+                .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// The OTLP/HTTP JSON encoding of an `ExportMetricsServiceRequest`, with
+    /// cumulative temporality since the app started.
+    fn otlp_request(registry: &Registry, now: SystemTime) -> Value {
+        let start = unix_nanos(registry.started);
+        let now = unix_nanos(now);
+        let mut metrics: Vec<Value> = Vec::new();
+        let mut push =
+            |name: &'static str, data_key: &str, point: Value, extra: Value| match metrics
+                .iter_mut()
+                .find(|metric| metric["name"] == name)
+            {
+                Some(metric) => metric[data_key]["dataPoints"]
+                    .as_array_mut()
+                    .expect("data points")
+                    .push(point),
+                None => {
+                    let mut data = json!({ "dataPoints": [point], "aggregationTemporality": 2 });
+                    if let (Value::Object(data), Value::Object(extra)) = (&mut data, extra) {
+                        data.extend(extra);
+                    }
+                    metrics
+                        .push(json!({ "name": name, "description": help(name), data_key: data }));
+                }
+            };
+        for ((name, label), value) in &registry.counters {
+            let point = json!({
+                "attributes": attributes(*label),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "asInt": value.to_string(),
+            });
+            push(name, "sum", point, json!({ "isMonotonic": true }));
+        }
+        for ((name, label), histogram) in &registry.histograms {
+            let point = json!({
+                "attributes": attributes(*label),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+                "count": histogram.count().to_string(),
+                "sum": histogram.sum,
+                "bucketCounts": histogram.counts.iter().map(u64::to_string).collect::<Vec<_>>(),
+                "explicitBounds": histogram.bounds,
+            });
+            push(name, "histogram", point, json!({}));
+        }
+        json!({
+            "resourceMetrics": [{
+                "resource": {
+                    "attributes": [{ "key": "service.name", "value": { "stringValue": "photoTidy" } }]
+                },
+                "scopeMetrics": [{
+                    "scope": { "name": "phototidy", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics,
+                }]
+            }]
+        })
+    }
+
+    /// Splits `http://host:port/base` into the address and the path for
+    /// the metrics signal.
+    fn otlp_target(endpoint: &str) -> Result<(String, String)> {
+        let rest = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| AppError::Config(format!("unsupported OTLP endpoint {endpoint}")))?;
+        let (authority, base) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok((
+            address,
+            format!("{}/v1/metrics", base.trim_end_matches('/')),
+        ))
+    }
+
+    fn push_otlp(endpoint: &str, registry: &Registry) -> Result<()> {
+        let (address, path) = otlp_target(endpoint)?;
+        let body = serde_json::to_vec(&otlp_request(registry, SystemTime::now()))?;
+        let socket = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| AppError::Config(format!("cannot resolve {address}")))?;
+        let mut stream = TcpStream::connect_timeout(&socket, OTLP_TIMEOUT)?;
+        stream.set_read_timeout(Some(OTLP_TIMEOUT))?;
+        stream.set_write_timeout(Some(OTLP_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: {address}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+
+        let mut response = String::new();
+        stream.take(4096).read_to_string(&mut response)?;
+        let status = response.split_whitespace().nth(1).unwrap_or_default();
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(AppError::internal(format!(
+                "collector answered {}",
+                response.lines().next().unwrap_or("nothing")
+            )))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn sample() -> Registry {
+            let mut registry = Registry::new();
+            registry.add(FILES_SCANNED, None, 12);
+            registry.add(FAILURES, Some(("kind", "io")), 1);
+            registry.add(FAILURES, Some(("kind", "origin_missing")), 2);
+            registry.observe(COPY_SECONDS, None, COPY_BUCKETS, 0.2);
+            registry.observe(COPY_SECONDS, None, COPY_BUCKETS, 90.0);
+            registry
+        }
+
+        #[test]
+        fn prometheus_text_lists_counters_and_cumulative_buckets() {
+            let text = render_prometheus(&sample());
+            assert!(text.contains("# TYPE phototidy_failures_total counter\n"));
+            assert_eq!(text.matches("# TYPE phototidy_failures_total").count(), 1);
+            assert!(text.contains("phototidy_failures_total{kind=\"io\"} 1\n"));
+            assert!(text.contains("phototidy_files_scanned_total 12\n"));
+            assert!(text.contains("phototidy_copy_duration_seconds_bucket{le=\"0.1\"} 0\n"));
+            assert!(text.contains("phototidy_copy_duration_seconds_bucket{le=\"0.25\"} 1\n"));
+            assert!(text.contains("phototidy_copy_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+            assert!(text.contains("phototidy_copy_duration_seconds_count 2\n"));
+        }
+
+        #[test]
+        fn otlp_request_groups_series_by_metric() {
+            let request = otlp_request(&sample(), SystemTime::now());
+            let metrics = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+            let failures = metrics
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|metric| metric["name"] == FAILURES)
+                .unwrap();
+            assert_eq!(failures["sum"]["dataPoints"].as_array().unwrap().len(), 2);
+            assert_eq!(failures["sum"]["isMonotonic"], true);
+
+            assert_eq!(
+                otlp_target("http://nas:4318/").unwrap(),
+                ("nas:4318".to_string(), "/v1/metrics".to_string())
+            );
+            assert_eq!(
+                otlp_target("http://collector/otlp").unwrap(),
+                ("collector:80".to_string(), "/otlp/v1/metrics".to_string())
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "telemetry"))]
+mod imp {
+    use std::time::Duration;
+
+    use super::FailureKind;
+    use crate::config::TelemetryOptions;
+
+    pub fn record_scan(_files: usize, _bytes_hashed: u64) {}
+
+    pub fn record_copy(_elapsed: Duration) {}
+
+    pub fn record_failure(_kind: FailureKind) {}
+
+    pub fn finish_run(_task: &'static str, _elapsed: Duration, _options: &TelemetryOptions) {}
+}
@@ -0,0 +1,586 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::db::InventoryRecord;
+use crate::utils::path::sanitize_component;
+
+const ILLEGAL_LITERAL_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+/// Folder name for `{camera_make}` and `{camera_model}` when EXIF or the
+/// container names no camera.
+const UNKNOWN_CAMERA: &str = "Unknown Camera";
+
+/// A token recognised inside `{...}` in folder templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Year,
+    Month,
+    Day,
+    Artist,
+    CameraMake,
+    CameraModel,
+}
+
+impl Token {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "year" => Some(Self::Year),
+            "month" => Some(Self::Month),
+            "day" => Some(Self::Day),
+            "artist" => Some(Self::Artist),
+            "camera_make" => Some(Self::CameraMake),
+            "camera_model" => Some(Self::CameraModel),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+    Separator,
+}
+
+/// Structured template problems, reported when config is loaded so broken
+/// templates never reach the planner.
+#[derive(Debug, Clone, Error, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TemplateError {
+    #[error("template is empty")]
+    Empty,
+    #[error("unknown token `{{{token}}}`")]
+    UnknownToken { token: String },
+    #[error("unclosed `{{` at position {position}")]
+    UnclosedBrace { position: usize },
+    #[error("unexpected `}}` at position {position}")]
+    UnexpectedBrace { position: usize },
+    #[error("illegal character `{character}` in path")]
+    IllegalCharacter { character: char },
+    #[error("`{segment}` is not allowed as a path segment")]
+    IllegalSegment { segment: String },
+    #[error("template renders an empty path segment")]
+    EmptyResult,
+}
+
+/// A parsed folder template such as `{year}/{artist}` or
+/// `{camera_model}/{year}-{month}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+/// Values a template is rendered against.
+pub struct TemplateContext<'a> {
+    pub record: &'a InventoryRecord,
+    pub artist_fallback: &'a str,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let trimmed = source.trim().replace('\\', "/");
+        let trimmed = trimmed.trim_matches('/');
+        if trimmed.is_empty() {
+            return Err(TemplateError::Empty);
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = trimmed.char_indices().peekable();
+
+        while let Some((position, ch)) = chars.next() {
+            match ch {
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for (_, next) in chars.by_ref() {
+                        if next == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(next);
+                    }
+                    if !closed {
+                        return Err(TemplateError::UnclosedBrace { position });
+                    }
+                    let token = Token::parse(name.trim())
+                        .ok_or(TemplateError::UnknownToken { token: name })?;
+                    flush_literal(&mut literal, &mut segments);
+                    segments.push(Segment::Token(token));
+                }
+                '}' => return Err(TemplateError::UnexpectedBrace { position }),
+                '/' => {
+                    flush_literal(&mut literal, &mut segments);
+                    if !matches!(segments.last(), Some(Segment::Separator)) {
+                        segments.push(Segment::Separator);
+                    }
+                }
+                ch if ILLEGAL_LITERAL_CHARS.contains(&ch) || ch.is_control() => {
+                    return Err(TemplateError::IllegalCharacter { character: ch });
+                }
+                ch => literal.push(ch),
+            }
+        }
+        flush_literal(&mut literal, &mut segments);
+
+        for component in literal_components(&segments) {
+            if component == "." || component == ".." {
+                return Err(TemplateError::IllegalSegment { segment: component });
+            }
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// Renders the template into a relative directory. Token values are
+    /// sanitized so metadata can never introduce separators.
+    pub fn render(&self, context: &TemplateContext<'_>) -> Result<PathBuf, TemplateError> {
+        let timestamp = context
+            .record
+            .captured_at
+            .as_deref()
+            .unwrap_or(&context.record.modified_at);
+        let date = timestamp.split('_').next().unwrap_or(timestamp);
+        let mut date_parts = date.split('-');
+        let year = date_parts.next().unwrap_or_default();
+        let month = date_parts.next().unwrap_or_default();
+        let day = date_parts.next().unwrap_or_default();
+
+        let mut path = PathBuf::new();
+        let mut component = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(value) => component.push_str(value),
+                Segment::Token(Token::Year) => component.push_str(year),
+                Segment::Token(Token::Month) => component.push_str(month),
+                Segment::Token(Token::Day) => component.push_str(day),
+                Segment::Token(Token::Artist) => component.push_str(&metadata_value(
+                    context.record.exif_artist.as_deref(),
+                    context.artist_fallback,
+                )),
+                Segment::Token(Token::CameraMake) => component.push_str(&metadata_value(
+                    context.record.exif_make.as_deref(),
+                    UNKNOWN_CAMERA,
+                )),
+                Segment::Token(Token::CameraModel) => component.push_str(&metadata_value(
+                    context.record.exif_model.as_deref(),
+                    UNKNOWN_CAMERA,
+                )),
+                Segment::Separator => push_component(&mut path, &mut component)?,
+            }
+        }
+        push_component(&mut path, &mut component)?;
+        Ok(path)
+    }
+}
+
+/// A token recognised inside `{...}` in file name templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameToken {
+    Date,
+    Time,
+    Timestamp,
+    Original,
+    Camera,
+    Seq,
+    Hash8,
+}
+
+impl NameToken {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "date" => Some(Self::Date),
+            "time" => Some(Self::Time),
+            "timestamp" => Some(Self::Timestamp),
+            "original" => Some(Self::Original),
+            "camera" => Some(Self::Camera),
+            "seq" => Some(Self::Seq),
+            "hash8" => Some(Self::Hash8),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NamePiece {
+    Literal(String),
+    Token(NameToken),
+}
+
+/// A parsed file name template such as `{date}_{seq}` or
+/// `{camera}-{original}`. It names the stem; the original extension is
+/// always kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    source: String,
+    pieces: Vec<NamePiece>,
+}
+
+/// Values a file name template is rendered against.
+pub struct NameContext<'a> {
+    pub record: &'a InventoryRecord,
+    /// The name as found, less any copy suffix.
+    pub original: &'a str,
+    /// The capture time in the configured name timestamp format.
+    pub timestamp: &'a str,
+    /// 1-based position of the file in its target folder.
+    pub seq: usize,
+}
+
+impl NameTemplate {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let trimmed = source.trim();
+        if trimmed.is_empty() {
+            return Err(TemplateError::Empty);
+        }
+
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = trimmed.char_indices();
+        while let Some((position, ch)) = chars.next() {
+            match ch {
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for (_, next) in chars.by_ref() {
+                        if next == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(next);
+                    }
+                    if !closed {
+                        return Err(TemplateError::UnclosedBrace { position });
+                    }
+                    let token = NameToken::parse(name.trim())
+                        .ok_or(TemplateError::UnknownToken { token: name })?;
+                    if !literal.is_empty() {
+                        pieces.push(NamePiece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(NamePiece::Token(token));
+                }
+                '}' => return Err(TemplateError::UnexpectedBrace { position }),
+                ch if ch == '/'
+                    || ch == '\\'
+                    || ILLEGAL_LITERAL_CHARS.contains(&ch)
+                    || ch.is_control() =>
+                {
+                    return Err(TemplateError::IllegalCharacter { character: ch });
+                }
+                ch => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(NamePiece::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            pieces,
+        })
+    }
+
+    /// Renders a file name: the template's stem and the original
+    /// extension. Token values are sanitized like folder tokens.
+    pub fn render(&self, context: &NameContext<'_>) -> Result<String, TemplateError> {
+        let record = context.record;
+        let timestamp = record.captured_at.as_deref().unwrap_or(&record.modified_at);
+        let (date, time) = timestamp.split_once('_').unwrap_or((timestamp, ""));
+        let original = Path::new(context.original);
+        let stem = original
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+
+        let mut name = String::new();
+        for piece in &self.pieces {
+            match piece {
+                NamePiece::Literal(value) => name.push_str(value),
+                NamePiece::Token(NameToken::Date) => name.push_str(date),
+                NamePiece::Token(NameToken::Time) => name.push_str(time),
+                NamePiece::Token(NameToken::Timestamp) => name.push_str(context.timestamp),
+                NamePiece::Token(NameToken::Original) => name.push_str(&sanitize_component(&stem)),
+                NamePiece::Token(NameToken::Camera) => name.push_str(&metadata_value(
+                    record.exif_model.as_deref().or(record.exif_make.as_deref()),
+                    UNKNOWN_CAMERA,
+                )),
+                NamePiece::Token(NameToken::Seq) => name.push_str(&format!("{:04}", context.seq)),
+                NamePiece::Token(NameToken::Hash8) => {
+                    name.push_str(record.file_hash.get(..8).unwrap_or(&record.file_hash))
+                }
+            }
+        }
+        let name = name.trim();
+        if name.trim_matches('.').is_empty() {
+            return Err(TemplateError::EmptyResult);
+        }
+        Ok(match original.extension() {
+            Some(ext) => format!("{name}.{}", ext.to_string_lossy()),
+            None => name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn push_component(path: &mut PathBuf, component: &mut String) -> Result<(), TemplateError> {
+    let value = std::mem::take(component);
+    if value.trim().is_empty() || value == "." || value == ".." {
+        return Err(TemplateError::EmptyResult);
+    }
+    path.push(value);
+    Ok(())
+}
+
+fn flush_literal(literal: &mut String, segments: &mut Vec<Segment>) {
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(std::mem::take(literal)));
+    }
+}
+
+/// Components made only of literals, for static checks such as `..`.
+fn literal_components(segments: &[Segment]) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut current = String::new();
+    let mut literal_only = true;
+    for segment in segments {
+        match segment {
+            Segment::Literal(value) => current.push_str(value),
+            Segment::Token(_) => literal_only = false,
+            Segment::Separator => {
+                if literal_only {
+                    components.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                literal_only = true;
+            }
+        }
+    }
+    if literal_only {
+        components.push(current);
+    }
+    components
+}
+
+fn metadata_value(value: Option<&str>, fallback: &str) -> String {
+    let value = value.map(sanitize_component).unwrap_or_default();
+    if value.is_empty() {
+        sanitize_component(fallback)
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MediaKind;
+
+    fn record() -> InventoryRecord {
+        InventoryRecord {
+            id: None,
+            file_hash: "hash".into(),
+            blake3_hash: None,
+            file_size: 1,
+            file_name: "a.jpg".into(),
+            relative_path: "a.jpg".into(),
+            source_root: None,
+            media_type: MediaKind::Photo,
+            captured_at: Some("2024-06-14_10-00-00".into()),
+            modified_at: "2024-06-14_10-00-00".into(),
+            exif_model: Some("EOS R6".into()),
+            exif_make: Some("Canon".into()),
+            exif_artist: Some("Jane: Doe".into()),
+            rating: None,
+            tags: Vec::new(),
+            gps_latitude: None,
+            gps_longitude: None,
+            derivative: None,
+            is_duplicate: false,
+            duplicate_confirmation: None,
+            pixel_hash: None,
+        }
+    }
+
+    fn render(source: &str) -> Result<PathBuf, TemplateError> {
+        let record = record();
+        Template::parse(source)?.render(&TemplateContext {
+            record: &record,
+            artist_fallback: "Unknown",
+        })
+    }
+
+    #[test]
+    fn renders_tokens_and_literals() {
+        assert_eq!(
+            render("photos/{year}/{year}-{month}").unwrap(),
+            PathBuf::from("photos").join("2024").join("2024-06")
+        );
+        assert_eq!(
+            render("{artist}\\{day}").unwrap(),
+            PathBuf::from("Jane_ Doe").join("14")
+        );
+    }
+
+    #[test]
+    fn renders_each_token() {
+        for (token, expected) in [
+            ("{year}", "2024"),
+            ("{month}", "06"),
+            ("{day}", "14"),
+            ("{artist}", "Jane_ Doe"),
+            ("{camera_make}", "Canon"),
+            ("{camera_model}", "EOS R6"),
+        ] {
+            assert_eq!(render(token).unwrap(), PathBuf::from(expected), "{token}");
+        }
+        assert_eq!(
+            render("{camera_model}/{year}").unwrap(),
+            PathBuf::from("EOS R6").join("2024")
+        );
+    }
+
+    #[test]
+    fn camera_tokens_fall_back_when_missing() {
+        let mut record = record();
+        record.exif_make = None;
+        record.exif_model = Some(" ".into());
+        let template = Template::parse("{camera_make}/{camera_model}").unwrap();
+        let context = TemplateContext {
+            record: &record,
+            artist_fallback: "Unknown Artist",
+        };
+        assert_eq!(
+            template.render(&context).unwrap(),
+            PathBuf::from(UNKNOWN_CAMERA).join(UNKNOWN_CAMERA)
+        );
+    }
+
+    #[test]
+    fn name_templates_render_each_token() {
+        let mut record = record();
+        record.file_hash = "0123456789abcdef".into();
+        let render = |source: &str, record: &InventoryRecord| {
+            NameTemplate::parse(source)?.render(&NameContext {
+                record,
+                original: "IMG_0001.JPG",
+                timestamp: "20240614T100000",
+                seq: 7,
+            })
+        };
+        for (token, expected) in [
+            ("{date}", "2024-06-14.JPG"),
+            ("{time}", "10-00-00.JPG"),
+            ("{timestamp}", "20240614T100000.JPG"),
+            ("{original}", "IMG_0001.JPG"),
+            ("{camera}", "EOS R6.JPG"),
+            ("{seq}", "0007.JPG"),
+            ("{hash8}", "01234567.JPG"),
+        ] {
+            assert_eq!(render(token, &record).unwrap(), expected, "{token}");
+        }
+        assert_eq!(
+            render("{date} {camera}-{seq}", &record).unwrap(),
+            "2024-06-14 EOS R6-0007.JPG"
+        );
+
+        record.exif_model = None;
+        assert_eq!(render("{camera}", &record).unwrap(), "Canon.JPG");
+        record.exif_make = None;
+        assert_eq!(render("{camera}", &record).unwrap(), "Unknown Camera.JPG");
+    }
+
+    #[test]
+    fn name_templates_reject_paths_and_unknown_tokens() {
+        assert_eq!(NameTemplate::parse(" "), Err(TemplateError::Empty));
+        assert_eq!(
+            NameTemplate::parse("{date}/{seq}"),
+            Err(TemplateError::IllegalCharacter { character: '/' })
+        );
+        assert_eq!(
+            NameTemplate::parse("{year}"),
+            Err(TemplateError::UnknownToken {
+                token: "year".into()
+            })
+        );
+        assert_eq!(
+            NameTemplate::parse("{seq"),
+            Err(TemplateError::UnclosedBrace { position: 0 })
+        );
+    }
+
+    #[test]
+    fn reports_structured_errors() {
+        assert_eq!(Template::parse("  "), Err(TemplateError::Empty));
+        assert_eq!(
+            Template::parse("{year}/{camera}"),
+            Err(TemplateError::UnknownToken {
+                token: "camera".into()
+            })
+        );
+        assert_eq!(
+            Template::parse("{year"),
+            Err(TemplateError::UnclosedBrace { position: 0 })
+        );
+        assert_eq!(
+            Template::parse("year}"),
+            Err(TemplateError::UnexpectedBrace { position: 4 })
+        );
+        assert_eq!(
+            Template::parse("a|b"),
+            Err(TemplateError::IllegalCharacter { character: '|' })
+        );
+        assert_eq!(
+            Template::parse("../{year}"),
+            Err(TemplateError::IllegalSegment {
+                segment: "..".into()
+            })
+        );
+    }
+
+    #[test]
+    fn artist_falls_back_when_missing() {
+        let mut record = record();
+        record.exif_artist = None;
+        let template = Template::parse("{artist}/{year}").unwrap();
+        let context = TemplateContext {
+            record: &record,
+            artist_fallback: "Unknown Artist",
+        };
+        assert_eq!(
+            template.render(&context).unwrap(),
+            PathBuf::from("Unknown Artist").join("2024")
+        );
+    }
+
+    #[test]
+    fn empty_rendered_segment_is_an_error() {
+        let record = record();
+        let template = Template::parse("{artist}").unwrap();
+        let mut anonymous = record.clone();
+        anonymous.exif_artist = None;
+        assert_eq!(
+            template.render(&TemplateContext {
+                record: &anonymous,
+                artist_fallback: "..",
+            }),
+            Err(TemplateError::EmptyResult)
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::metadata;
+
+/// Extensions of the HEIF family that get a JPEG twin.
+const HEIF_EXTENSIONS: [&str; 2] = ["heic", "heif"];
+
+pub fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            HEIF_EXTENSIONS
+                .iter()
+                .any(|heif| ext.eq_ignore_ascii_case(heif))
+        })
+}
+
+/// Writes a JPEG rendition of `source` to `dest` with the first converter
+/// found on this machine: `sips` on macOS, then libheif's `heif-convert`,
+/// then ImageMagick. The capture date and modification time carry over.
+/// A machine without any converter gets an `Unsupported` error.
+pub fn heic_to_jpeg(source: &Path, dest: &Path, quality: u8) -> io::Result<()> {
+    if dest.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ));
+    }
+    run_first(
+        heic_converters(source, dest, quality),
+        source,
+        dest,
+        "no HEIC converter found; install libheif or ImageMagick",
+    )
+}
+
+/// Writes a JPEG of `source` no larger than `max_edge` pixels on its long
+/// edge, replacing any earlier rendition at `dest`. Uses `sips` on macOS,
+/// then ImageMagick, then libvips; dates carry over as in [`heic_to_jpeg`].
+pub fn resize_to_jpeg(source: &Path, dest: &Path, max_edge: u32, quality: u8) -> io::Result<()> {
+    if dest.exists() {
+        fs::remove_file(dest)?;
+    }
+    run_first(
+        resizers(source, dest, max_edge, quality),
+        source,
+        dest,
+        "no image resizer found; install ImageMagick or libvips",
+    )
+}
+
+/// Runs the first command whose program exists, then restores dates. Any
+/// partial output is removed on failure.
+fn run_first(commands: Vec<Command>, source: &Path, dest: &Path, missing: &str) -> io::Result<()> {
+    for mut command in commands {
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        if !output.status.success() {
+            let _ = fs::remove_file(dest);
+            return Err(io::Error::other(format!(
+                "{} failed: {}",
+                command.get_program().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        if let Err(err) = carry_over_dates(source, dest) {
+            let _ = fs::remove_file(dest);
+            return Err(err);
+        }
+        return Ok(());
+    }
+    Err(io::Error::new(io::ErrorKind::Unsupported, missing))
+}
+
+fn heic_converters(source: &Path, dest: &Path, quality: u8) -> Vec<Command> {
+    let quality = quality.to_string();
+    let mut commands = Vec::new();
+
+    #[cfg(target_os = "macos")]
+    {
+        let mut sips = Command::new("sips");
+        sips.args(["-s", "format", "jpeg", "-s", "formatOptions", &quality])
+            .arg(source)
+            .arg("--out")
+            .arg(dest);
+        commands.push(sips);
+    }
+
+    let mut heif_convert = Command::new("heif-convert");
+    heif_convert.args(["-q", &quality]).arg(source).arg(dest);
+    commands.push(heif_convert);
+
+    let mut magick = Command::new("magick");
+    magick.arg(source).args(["-quality", &quality]).arg(dest);
+    commands.push(magick);
+
+    commands
+}
+
+fn resizers(source: &Path, dest: &Path, max_edge: u32, quality: u8) -> Vec<Command> {
+    let quality = quality.to_string();
+    let edge = max_edge.to_string();
+    let mut commands = Vec::new();
+
+    #[cfg(target_os = "macos")]
+    {
+        let mut sips = Command::new("sips");
+        sips.args([
+            "-Z",
+            &edge,
+            "-s",
+            "format",
+            "jpeg",
+            "-s",
+            "formatOptions",
+            &quality,
+        ])
+        .arg(source)
+        .arg("--out")
+        .arg(dest);
+        commands.push(sips);
+    }
+
+    // `>` only ever shrinks; smaller pictures keep their size.
+    let mut magick = Command::new("magick");
+    magick
+        .arg(source)
+        .args(["-auto-orient", "-resize", &format!("{edge}x{edge}>")])
+        .args(["-quality", &quality])
+        .arg(dest);
+    commands.push(magick);
+
+    let mut vips = Command::new("vipsthumbnail");
+    vips.arg(source)
+        .args(["--size", &format!("{edge}x{edge}>")])
+        .arg("-o")
+        .arg(format!("{}[Q={quality}]", dest.display()));
+    commands.push(vips);
+
+    commands
+}
+
+/// Converters differ in whether they copy EXIF, so the capture date is put
+/// back when it went missing.
+fn carry_over_dates(source: &Path, dest: &Path) -> io::Result<()> {
+    if metadata::capture_date(dest).is_none() {
+        if let Some(date) = metadata::capture_date(source) {
+            if !metadata::insert_capture_date(dest, &date)? {
+                tracing::debug!(path = %dest.display(), "converted JPEG kept EXIF without a capture date");
+            }
+        }
+    }
+    let modified = fs::metadata(source)?.modified()?;
+    File::options()
+        .write(true)
+        .open(dest)?
+        .set_modified(modified)
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, CloseRequestApi, Manager, Window};
+
+use crate::progress::TaskKind;
+use crate::AppState;
+
+pub const TRAY_ID: &str = "phototidy";
+
+const MENU_SHOW: &str = "show";
+const MENU_PAUSE: &str = "pause";
+const MENU_RESUME: &str = "resume";
+const MENU_CANCEL: &str = "cancel";
+const MENU_QUIT: &str = "quit";
+
+/// Last percentage shown on the tray, so per-file progress does not redraw
+/// the icon thousands of times.
+pub struct TrayBadge {
+    percent: AtomicU8,
+}
+
+impl Default for TrayBadge {
+    fn default() -> Self {
+        Self {
+            percent: AtomicU8::new(u8::MAX),
+        }
+    }
+}
+
+pub fn setup_tray(app: &AppHandle) -> tauri::Result<()> {
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(app, MENU_SHOW, "Show phototidy", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, MENU_PAUSE, "Pause", true, None::<&str>)?,
+            &MenuItem::with_id(app, MENU_RESUME, "Resume", true, None::<&str>)?,
+            &MenuItem::with_id(app, MENU_CANCEL, "Cancel", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, MENU_QUIT, "Quit", true, None::<&str>)?,
+        ],
+    )?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&menu)
+        .tooltip("phototidy")
+        .on_menu_event(|app, event| handle_menu(app, event.id.as_ref()));
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    app.manage(TrayBadge::default());
+    Ok(())
+}
+
+fn handle_menu(app: &AppHandle, id: &str) {
+    let control = app.state::<AppState>().progress().control();
+    match (id, control) {
+        (MENU_SHOW, _) => {
+            for window in app.webview_windows().values() {
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }
+        (MENU_PAUSE, Some(control)) => control.pause(),
+        (MENU_RESUME, Some(control)) => control.resume(),
+        (MENU_CANCEL, Some(control)) => control.cancel(),
+        (MENU_QUIT, _) => app.exit(0),
+        _ => {}
+    }
+}
+
+/// Closing a window while a task runs hides it instead, so the task keeps
+/// going in the background until the tray's Quit.
+pub fn keep_running_in_tray(window: &Window, api: &CloseRequestApi) {
+    let busy = window.state::<AppState>().progress().control().is_some();
+    if busy && window.app_handle().tray_by_id(TRAY_ID).is_some() {
+        api.prevent_close();
+        if let Err(err) = window.hide() {
+            tracing::warn!(error = ?err, "failed hiding window to the tray");
+        }
+    }
+}
+
+/// Shows the running task's progress as a percentage on the tray.
+pub fn show_progress(app: &AppHandle, task: TaskKind, processed: usize, total: usize) {
+    let percent = (processed.min(total) * 100).checked_div(total).unwrap_or(0) as u8;
+    let Some(badge) = app.try_state::<TrayBadge>() else {
+        return;
+    };
+    if badge.percent.swap(percent, Ordering::Relaxed) == percent {
+        return;
+    }
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_title(Some(format!("{percent}%")));
+        let _ = tray.set_tooltip(Some(format!("phototidy: {} {percent}%", task.as_str())));
+    }
+}
+
+pub fn clear_progress(app: &AppHandle) {
+    if let Some(badge) = app.try_state::<TrayBadge>() {
+        badge.percent.store(u8::MAX, Ordering::Relaxed);
+    }
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_title(None::<&str>);
+        let _ = tray.set_tooltip(Some("phototidy"));
+    }
+}
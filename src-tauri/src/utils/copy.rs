@@ -0,0 +1,357 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Files at least this large are copied chunk by chunk, so progress moves
+/// while they copy.
+pub const CHUNKED_COPY_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Called with the bytes of the file copied so far after every chunk. An
+/// error stops the copy.
+pub type ChunkProgress<'a> = &'a mut dyn FnMut(u64) -> io::Result<()>;
+
+/// Marks the error an `on_chunk` callback returns to stop a copy because
+/// the run was cancelled, so it is neither retried nor reported as a
+/// failed copy.
+#[derive(Debug)]
+struct CopyCancelled;
+
+impl fmt::Display for CopyCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("copy cancelled")
+    }
+}
+
+impl Error for CopyCancelled {}
+
+pub fn cancelled() -> io::Error {
+    io::Error::other(CopyCancelled)
+}
+
+pub fn is_cancelled(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<CopyCancelled>())
+}
+
+/// Copies `origin` to `target` a chunk at a time, reporting each chunk to
+/// `on_chunk`. A copy that fails or is stopped leaves no partial target.
+/// Returns the bytes copied.
+pub fn copy_chunked(origin: &Path, target: &Path, on_chunk: ChunkProgress<'_>) -> io::Result<u64> {
+    let mut input = File::open(origin)?;
+    let permissions = input.metadata()?.permissions();
+    let mut output = File::create(target)?;
+    let copied = (|| {
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut copied = 0u64;
+        loop {
+            let read = match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            output.write_all(&buffer[..read])?;
+            copied += read as u64;
+            on_chunk(copied)?;
+        }
+        output.set_permissions(permissions)?;
+        Ok(copied)
+    })();
+    if copied.is_err() {
+        drop(output);
+        let _ = fs::remove_file(target);
+    }
+    copied
+}
+
+/// Makes sure `target` is a whole copy of `origin` that survives a power
+/// loss, so the original can be deleted: sizes must match, and hashes too
+/// with `compare_hashes`, then the copy and its directory entry are
+/// flushed to disk. A copy that does not match is removed.
+pub fn confirm_copy(origin: &Path, target: &Path, compare_hashes: bool) -> io::Result<()> {
+    let expected = fs::metadata(origin)?.len();
+    let actual = fs::metadata(target)?.len();
+    let mismatch = if actual != expected {
+        Some(format!("copy has {actual} bytes, original {expected}"))
+    } else if compare_hashes && hash(origin)? != hash(target)? {
+        Some("copy does not match the original's hash".to_string())
+    } else {
+        None
+    };
+    if let Some(message) = mismatch {
+        let _ = fs::remove_file(target);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    File::open(target)?.sync_all()?;
+    sync_parent(target)
+}
+
+fn hash(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize())
+}
+
+/// Flushes the directory entry for `path`, without which a synced file can
+/// still vanish in a crash.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+/// NTFS journals directory entries with the file; opening a directory to
+/// flush it needs backup semantics and buys nothing.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Copies with the platform's own API, which clones where the filesystem
+/// can and keeps metadata, resource forks and alternate streams, still
+/// reporting to `on_chunk`. Fails with `Unsupported` where there is none
+/// that reports progress; `fs::copy` already uses these APIs without it.
+#[cfg(windows)]
+pub fn copy_native(origin: &Path, target: &Path, on_chunk: ChunkProgress<'_>) -> io::Result<u64> {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::Storage::FileSystem::CopyFileExW;
+
+    const PROGRESS_CONTINUE: u32 = 0;
+    const PROGRESS_CANCEL: u32 = 1;
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "system" fn progress(
+        _total_size: i64,
+        transferred: i64,
+        _stream_size: i64,
+        _stream_transferred: i64,
+        _stream: u32,
+        _reason: u32,
+        _source: HANDLE,
+        _target: HANDLE,
+        data: *const c_void,
+    ) -> u32 {
+        let report = &mut *(data as *mut Report<'_>);
+        match (report.on_chunk)(transferred as u64) {
+            Ok(()) => PROGRESS_CONTINUE,
+            Err(err) => {
+                report.error = Some(err);
+                PROGRESS_CANCEL
+            }
+        }
+    }
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(origin), wide(target));
+    let mut report = Report {
+        on_chunk,
+        error: None,
+    };
+    // A cancelled copy is deleted by the API itself.
+    let copied = unsafe {
+        CopyFileExW(
+            from.as_ptr(),
+            to.as_ptr(),
+            Some(progress),
+            &mut report as *mut Report<'_> as *const c_void,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if copied == 0 {
+        let err = io::Error::last_os_error();
+        return Err(report.error.take().unwrap_or(err));
+    }
+    Ok(fs::metadata(target)?.len())
+}
+
+#[cfg(target_os = "macos")]
+pub fn copy_native(origin: &Path, target: &Path, on_chunk: ChunkProgress<'_>) -> io::Result<u64> {
+    use std::ffi::{c_char, c_int, c_void, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    type CopyfileState = *mut c_void;
+    type StatusCallback = extern "C" fn(
+        c_int,
+        c_int,
+        CopyfileState,
+        *const c_char,
+        *const c_char,
+        *mut c_void,
+    ) -> c_int;
+
+    // ACL, STAT, XATTR and DATA.
+    const COPYFILE_ALL: u32 = 0xf;
+    const COPYFILE_CLONE: u32 = 1 << 24;
+    const COPYFILE_STATE_STATUS_CB: u32 = 6;
+    const COPYFILE_STATE_STATUS_CTX: u32 = 7;
+    const COPYFILE_STATE_COPIED: u32 = 8;
+    const COPYFILE_COPY_DATA: c_int = 4;
+    const COPYFILE_PROGRESS: c_int = 4;
+    const COPYFILE_CONTINUE: c_int = 0;
+    const COPYFILE_QUIT: c_int = 2;
+
+    extern "C" {
+        fn copyfile(
+            from: *const c_char,
+            to: *const c_char,
+            state: CopyfileState,
+            flags: u32,
+        ) -> c_int;
+        fn copyfile_state_alloc() -> CopyfileState;
+        fn copyfile_state_free(state: CopyfileState) -> c_int;
+        fn copyfile_state_get(state: CopyfileState, flag: u32, dst: *mut c_void) -> c_int;
+        fn copyfile_state_set(state: CopyfileState, flag: u32, src: *const c_void) -> c_int;
+    }
+
+    extern "C" fn status(
+        what: c_int,
+        stage: c_int,
+        state: CopyfileState,
+        _source: *const c_char,
+        _target: *const c_char,
+        context: *mut c_void,
+    ) -> c_int {
+        if what != COPYFILE_COPY_DATA || stage != COPYFILE_PROGRESS {
+            return COPYFILE_CONTINUE;
+        }
+        let report = unsafe { &mut *(context as *mut Report<'_>) };
+        let mut copied: i64 = 0;
+        unsafe {
+            copyfile_state_get(
+                state,
+                COPYFILE_STATE_COPIED,
+                &mut copied as *mut i64 as *mut c_void,
+            );
+        }
+        match (report.on_chunk)(copied as u64) {
+            Ok(()) => COPYFILE_CONTINUE,
+            Err(err) => {
+                report.error = Some(err);
+                COPYFILE_QUIT
+            }
+        }
+    }
+
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    };
+    let (from, to) = (c_path(origin)?, c_path(target)?);
+    let mut report = Report {
+        on_chunk,
+        error: None,
+    };
+    let (result, err) = unsafe {
+        let state = copyfile_state_alloc();
+        let callback: StatusCallback = status;
+        copyfile_state_set(state, COPYFILE_STATE_STATUS_CB, callback as *const c_void);
+        copyfile_state_set(
+            state,
+            COPYFILE_STATE_STATUS_CTX,
+            &mut report as *mut Report<'_> as *const c_void,
+        );
+        let result = copyfile(
+            from.as_ptr(),
+            to.as_ptr(),
+            state,
+            COPYFILE_ALL | COPYFILE_CLONE,
+        );
+        let err = io::Error::last_os_error();
+        copyfile_state_free(state);
+        (result, err)
+    };
+    if result != 0 {
+        let _ = fs::remove_file(target);
+        return Err(report.error.take().unwrap_or(err));
+    }
+    Ok(fs::metadata(target)?.len())
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn copy_native(
+    _origin: &Path,
+    _target: &Path,
+    _on_chunk: ChunkProgress<'_>,
+) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no native copy with progress on this platform",
+    ))
+}
+
+/// What the native progress callbacks get: where to report, and the
+/// error that stopped the copy, since the APIs only pass on "cancelled".
+#[cfg(any(windows, target_os = "macos"))]
+struct Report<'a> {
+    on_chunk: ChunkProgress<'a>,
+    error: Option<io::Error>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn chunks_are_reported_and_stopped_copies_removed() -> io::Result<()> {
+        let dir = tempdir()?;
+        let origin = dir.path().join("clip.mov");
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|idx| idx as u8).collect();
+        fs::write(&origin, &data)?;
+
+        let target = dir.path().join("copy.mov");
+        let mut reports = Vec::new();
+        let copied = copy_chunked(&origin, &target, &mut |copied| {
+            reports.push(copied);
+            Ok(())
+        })?;
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(fs::read(&target)?, data);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last(), Some(&copied));
+
+        let stopped = dir.path().join("stopped.mov");
+        let result = copy_chunked(&origin, &stopped, &mut |_| Err(cancelled()));
+        assert!(result.is_err_and(|err| is_cancelled(&err)));
+        assert!(!stopped.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn confirmed_copies_must_match_the_original() -> io::Result<()> {
+        let dir = tempdir()?;
+        let origin = dir.path().join("photo.jpg");
+        fs::write(&origin, b"original pixels")?;
+
+        let good = dir.path().join("good.jpg");
+        fs::copy(&origin, &good)?;
+        confirm_copy(&origin, &good, true)?;
+
+        let short = dir.path().join("short.jpg");
+        fs::write(&short, b"original")?;
+        assert!(confirm_copy(&origin, &short, false).is_err());
+        assert!(!short.exists());
+
+        let corrupt = dir.path().join("corrupt.jpg");
+        fs::write(&corrupt, b"original pixelz")?;
+        confirm_copy(&origin, &corrupt, false)?;
+        assert!(confirm_copy(&origin, &corrupt, true).is_err());
+        assert!(!corrupt.exists());
+        Ok(())
+    }
+}
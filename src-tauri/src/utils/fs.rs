@@ -1,11 +1,70 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 use walkdir::WalkDir;
 
 use crate::error::Result;
 
+/// Where a file lives on disk regardless of its path: the volume and the
+/// inode on it. A rename or move within the volume keeps both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    pub volume_id: u64,
+    pub file_id: u64,
+}
+
+#[cfg(unix)]
+pub fn file_identity(metadata: &fs::Metadata) -> Option<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(FileIdentity {
+        volume_id: metadata.dev(),
+        file_id: metadata.ino(),
+    })
+}
+
+/// File ids need an open handle on Windows, so files have no identity there.
+#[cfg(not(unix))]
+pub fn file_identity(_metadata: &fs::Metadata) -> Option<FileIdentity> {
+    None
+}
+
+/// How many names the file has; above one it is hard-linked.
+#[cfg(unix)]
+pub fn link_count(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+pub fn link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// Whether `err` means another program holds the file open or locked,
+/// which usually clears once it lets go: Windows sharing and lock
+/// violations, `EBUSY` and `ETXTBSY` elsewhere.
+#[cfg(windows)]
+pub fn is_locked(err: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    )
+}
+
+#[cfg(not(windows))]
+pub fn is_locked(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy
+    )
+}
+
 pub fn ensure_parent_dir(path: &Path) -> Result<()> {
     if let Some(parent) = path.parent() {
         if !parent.exists() {
@@ -42,6 +101,51 @@ pub fn collect_files(root: &Path, exts: &HashSet<String>) -> Result<Vec<PathBuf>
     Ok(files)
 }
 
+/// Files the OS drops into folders it shows, which don't keep a folder.
+const FOLDER_CLUTTER: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// Removes each of `dirs` that is empty or holds only OS clutter, then its
+/// parents while they are left empty, never reaching `roots` themselves or
+/// anything outside them. Returns the number of folders removed.
+pub fn remove_empty_dirs(dirs: impl IntoIterator<Item = PathBuf>, roots: &[PathBuf]) -> usize {
+    // Deepest first, so a folder is tried after the ones inside it.
+    let dirs: BTreeSet<PathBuf> = dirs.into_iter().collect();
+    let mut removed = 0;
+    for dir in dirs.into_iter().rev() {
+        let mut current = dir.as_path();
+        while !roots.iter().any(|root| current == root)
+            && roots.iter().any(|root| current.starts_with(root))
+            && remove_if_empty(current)
+        {
+            removed += 1;
+            match current.parent() {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+    }
+    removed
+}
+
+fn remove_if_empty(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let mut clutter = Vec::new();
+    for entry in entries {
+        let Ok(entry) = entry else {
+            return false;
+        };
+        let is_clutter = FOLDER_CLUTTER.iter().any(|name| entry.file_name() == *name)
+            && entry.file_type().is_ok_and(|kind| kind.is_file());
+        if !is_clutter {
+            return false;
+        }
+        clutter.push(entry.path());
+    }
+    clutter.iter().all(|path| fs::remove_file(path).is_ok()) && fs::remove_dir(dir).is_ok()
+}
+
 pub fn matches_extension(path: &Path, exts: &HashSet<String>) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
@@ -51,3 +155,51 @@ pub fn matches_extension(path: &Path, exts: &HashSet<String>) -> bool {
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn locked_files_are_told_apart_from_other_failures() {
+        #[cfg(windows)]
+        let locked = io::Error::from_raw_os_error(32);
+        #[cfg(not(windows))]
+        let locked = io::Error::from(io::ErrorKind::ResourceBusy);
+        assert!(is_locked(&locked));
+        assert!(!is_locked(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+        assert!(!is_locked(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+
+    #[test]
+    fn emptied_folders_are_removed_up_to_the_root() -> io::Result<()> {
+        let dir = tempdir()?;
+        let roots = [dir.path().join("images")];
+        let root = &roots[0];
+        fs::create_dir_all(root.join("2019/trip/day1"))?;
+        fs::create_dir_all(root.join("2019/kept"))?;
+        fs::create_dir_all(root.join("2020"))?;
+        fs::write(root.join("2019/trip/Thumbs.db"), b"")?;
+        fs::write(root.join("2019/kept/a.jpg"), b"")?;
+        fs::write(root.join("2020/b.jpg"), b"")?;
+
+        let dirs = [
+            root.join("2019/trip/day1"),
+            root.join("2019/trip"),
+            root.join("2020"),
+        ];
+        assert_eq!(remove_empty_dirs(dirs, &roots), 2);
+        assert!(!root.join("2019/trip").exists());
+        assert!(root.join("2019/kept").exists() && root.join("2020").exists());
+
+        fs::remove_file(root.join("2019/kept/a.jpg"))?;
+        fs::remove_file(root.join("2020/b.jpg"))?;
+        let dirs = [root.join("2019/kept"), root.join("2020")];
+        assert_eq!(remove_empty_dirs(dirs, &roots), 3);
+        assert!(root.exists());
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
+pub mod copy;
 pub mod fs;
 pub mod hash;
 pub mod json;
 pub mod path;
+pub mod pattern;
+pub mod sparse;
 pub mod time;
+pub mod zip;
@@ -44,8 +44,14 @@ pub fn ensure_trailing_separator(path: &Path) -> PathBuf {
     buf
 }
 
+/// UNC paths keep their leading `//` (`//server/share/photos`), so they
+/// round-trip through `to_native_path`.
 pub fn to_posix_string(path: &Path) -> Cow<'_, str> {
     let path_str = path.to_string_lossy();
+    let path_str = match strip_verbatim(&path_str) {
+        Cow::Borrowed(plain) if plain.len() == path_str.len() => path_str,
+        plain => Cow::Owned(plain.into_owned()),
+    };
     if path_str.contains('\\') {
         Cow::Owned(path_str.replace('\\', "/"))
     } else {
@@ -53,7 +59,148 @@ pub fn to_posix_string(path: &Path) -> Cow<'_, str> {
     }
 }
 
+/// Rewrites the verbatim prefixes `canonicalize` produces on Windows
+/// (`\\?\C:\`, `\\?\UNC\server\share`) to their plain form.
+pub fn strip_verbatim(path: &str) -> Cow<'_, str> {
+    if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        Cow::Owned(format!(r"\\{share}"))
+    } else if let Some(plain) = path.strip_prefix(r"\\?\") {
+        Cow::Borrowed(plain)
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Turns a stored POSIX-style path back into one the OS accepts.
+pub fn to_native_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(path.replace('/', "\\"))
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// True for `\\server\share` paths in either separator style.
+pub fn is_unc(path: &str) -> bool {
+    let plain = strip_verbatim(path);
+    let mut chars = plain.chars();
+    let leading = (chars.next(), chars.next(), chars.next());
+    matches!(
+        leading,
+        (Some('\\' | '/'), Some('\\' | '/'), Some(ch)) if ch != '\\' && ch != '/' && ch != '?'
+    )
+}
+
+/// Makes a metadata value usable as a single path component on every
+/// platform: separators, reserved characters and control characters become
+/// `_`, and leading/trailing dots and spaces are trimmed.
+pub fn sanitize_component(value: &str) -> String {
+    let replaced: String = value
+        .chars()
+        .map(|ch| match ch {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect();
+    let collapsed = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed
+        .trim_matches(|ch: char| ch == '.' || ch == ' ')
+        .to_string()
+}
+
 pub fn join_and_normalize(base: impl AsRef<Path>, segment: impl AsRef<Path>) -> Result<PathBuf> {
     let joined = base.as_ref().join(segment);
     normalize(joined)
 }
+
+/// Strips the suffix browsers and file managers add to repeated copies:
+/// `IMG_0001 (1).JPG`, `photo copy 2.jpg`, `photo - Copy (3).jpg`. Returns
+/// the original name, or `None` when there is no such suffix.
+pub fn strip_copy_suffix(file_name: &str) -> Option<String> {
+    let (stem, ext) = match file_name.rfind('.') {
+        Some(idx) if idx > 0 => file_name.split_at(idx),
+        _ => (file_name, ""),
+    };
+
+    let mut base = stem;
+    if let Some(inner) = base.strip_suffix(')') {
+        if let Some((head, counter)) = inner.rsplit_once('(') {
+            if !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit()) {
+                base = head.trim_end();
+            }
+        }
+    }
+    if let Some((head, counter)) = base.rsplit_once(' ') {
+        if !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit()) {
+            if let Some(head) = strip_suffix_ignore_case(head, " copy") {
+                base = head;
+            }
+        }
+    }
+    for marker in [" - copy", " copy"] {
+        if let Some(head) = strip_suffix_ignore_case(base, marker) {
+            base = head;
+            break;
+        }
+    }
+
+    let base = base.trim_end();
+    (base.len() < stem.len() && !base.is_empty()).then(|| format!("{base}{ext}"))
+}
+
+fn strip_suffix_ignore_case<'a>(value: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = value.len().checked_sub(suffix.len())?;
+    (value.is_char_boundary(split) && value[split..].eq_ignore_ascii_case(suffix))
+        .then(|| &value[..split])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_component_strips_unsafe_characters() {
+        assert_eq!(sanitize_component("Jane Doe"), "Jane Doe");
+        assert_eq!(sanitize_component("A/B: C?"), "A_B_ C_");
+        assert_eq!(sanitize_component("  ..hidden.. "), "hidden");
+        assert_eq!(sanitize_component("\0\0"), "__");
+        assert_eq!(sanitize_component(" . "), "");
+    }
+
+    #[test]
+    fn unc_and_verbatim_paths_stay_recognizable() {
+        assert_eq!(
+            to_posix_string(Path::new(r"\\?\UNC\nas\photos\2021")),
+            "//nas/photos/2021"
+        );
+        assert_eq!(
+            to_posix_string(Path::new(r"\\?\C:\Users\me")),
+            "C:/Users/me"
+        );
+        assert!(is_unc(r"\\nas\photos"));
+        assert!(is_unc("//nas/photos"));
+        assert!(is_unc(r"\\?\UNC\nas\photos"));
+        assert!(!is_unc(r"\\?\C:\photos"));
+        assert!(!is_unc("/home/me/photos"));
+    }
+
+    #[test]
+    fn strip_copy_suffix_recognizes_download_and_finder_copies() {
+        let cases = [
+            ("IMG_0001 (1).JPG", Some("IMG_0001.JPG")),
+            ("IMG_0001(12).JPG", Some("IMG_0001.JPG")),
+            ("photo copy.jpg", Some("photo.jpg")),
+            ("photo copy 2.jpg", Some("photo.jpg")),
+            ("photo - Copy.jpg", Some("photo.jpg")),
+            ("photo - Copy (3).jpg", Some("photo.jpg")),
+            ("IMG_0001.JPG", None),
+            ("(1).jpg", None),
+            ("Holiday 2019.jpg", None),
+            ("Mum (Paris).jpg", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(strip_copy_suffix(name).as_deref(), expected, "{name}");
+        }
+    }
+}
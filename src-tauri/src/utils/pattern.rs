@@ -0,0 +1,92 @@
+/// Matches `text` against a glob `pattern` using `/` as the separator.
+///
+/// Supports `*` (any run within a segment), `?` (single character) and `**`
+/// (any number of segments). Matching is ASCII case-insensitive because media
+/// extensions and camera folder names are routinely mixed-case.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let text: Vec<&str> = text.trim_matches('/').split('/').collect();
+    match_segments(&pattern, &text)
+}
+
+/// Matches a pattern against a relative path the way ignore files do: patterns
+/// without a separator match any single path component, everything else is
+/// anchored at the root.
+pub fn path_pattern_match(pattern: &str, relative_path: &str) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return false;
+    }
+    if pattern.trim_end_matches('/').contains('/') {
+        return glob_match(pattern, relative_path)
+            || glob_match(&format!("{pattern}/**"), relative_path);
+    }
+    relative_path.trim_matches('/').split('/').any(|component| {
+        match_segment(
+            pattern.trim_end_matches('/').as_bytes(),
+            component.as_bytes(),
+        )
+    })
+}
+
+fn match_segments(pattern: &[&str], text: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((&"**", rest)) => (0..=text.len()).any(|skip| match_segments(rest, &text[skip..])),
+        Some((head, rest)) => match text.split_first() {
+            Some((segment, text_rest)) => {
+                match_segment(head.as_bytes(), segment.as_bytes())
+                    && match_segments(rest, text_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0usize, 0usize);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p].eq_ignore_ascii_case(&text[t])) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_handles_wildcards_and_globstar() {
+        assert!(glob_match("memes/*.jpg", "memes/cat.JPG"));
+        assert!(!glob_match("memes/*.jpg", "memes/nested/cat.jpg"));
+        assert!(glob_match("**/cache/**", "app/data/cache/a/b.png"));
+        assert!(glob_match("IMG_00??.jpg", "img_0012.jpg"));
+        assert!(!glob_match("IMG_00??.jpg", "IMG_012.jpg"));
+    }
+
+    #[test]
+    fn bare_patterns_match_any_component() {
+        assert!(path_pattern_match("Screenshots", "2024/Screenshots/a.png"));
+        assert!(path_pattern_match("*.gif", "a/b/c.gif"));
+        assert!(path_pattern_match("app/assets", "app/assets/icon.png"));
+        assert!(!path_pattern_match(
+            "app/assets",
+            "other/app/assets/icon.png"
+        ));
+    }
+}
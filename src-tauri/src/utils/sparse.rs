@@ -0,0 +1,182 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::copy::ChunkProgress;
+
+/// Zero runs at least this long become holes in a sparse copy; also the
+/// read size.
+const HOLE_CHUNK: usize = 64 * 1024;
+
+/// Bytes the file takes on disk. Platforms that do not report it get the
+/// apparent size.
+#[cfg(unix)]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // `blocks` counts 512-byte units whatever the filesystem block size.
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Whether the file has holes: less on disk than its length, by more than
+/// the last partly filled block.
+#[cfg(unix)]
+pub fn is_sparse(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.is_file() && allocated_size(metadata) + metadata.blksize() < metadata.len()
+}
+
+#[cfg(windows)]
+pub fn is_sparse(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+    metadata.is_file() && metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn is_sparse(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Copies `origin` to `target`, seeking over zero runs instead of writing
+/// them so the copy keeps the original's holes. Each chunk, hole or data,
+/// is reported to `on_chunk` with the length covered so far. A copy that
+/// fails or is stopped leaves no partial target. Returns the bytes copied,
+/// holes included, as `fs::copy` would.
+pub fn copy_sparse(origin: &Path, target: &Path, on_chunk: ChunkProgress<'_>) -> io::Result<u64> {
+    let mut input = File::open(origin)?;
+    let permissions = input.metadata()?.permissions();
+    let mut output = File::create(target)?;
+    let copied = (|| {
+        mark_sparse(&output)?;
+        let mut buffer = vec![0u8; HOLE_CHUNK];
+        let mut length = 0u64;
+        loop {
+            let read = read_full(&mut input, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            let chunk = &buffer[..read];
+            if chunk.iter().all(|byte| *byte == 0) {
+                output.seek(SeekFrom::Current(read as i64))?;
+            } else {
+                output.write_all(chunk)?;
+            }
+            length += read as u64;
+            on_chunk(length)?;
+        }
+        // A trailing hole is only a seek so far; the length makes it real.
+        output.set_len(length)?;
+        output.set_permissions(permissions)?;
+        Ok(length)
+    })();
+    if copied.is_err() {
+        drop(output);
+        let _ = fs::remove_file(target);
+    }
+    copied
+}
+
+/// NTFS only leaves the skipped ranges unallocated in a file flagged
+/// sparse; otherwise it fills them with zeros on disk.
+#[cfg(windows)]
+fn mark_sparse(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_SPARSE;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    let mut returned = 0u32;
+    // SAFETY: the handle stays open for the call, and no input buffer
+    // means "set the flag".
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle(),
+            FSCTL_SET_SPARSE,
+            ptr::null(),
+            0,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Seeking past the end already leaves a hole elsewhere.
+#[cfg(not(windows))]
+fn mark_sparse(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Fills `buffer` unless the file ends first.
+fn read_full(input: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::copy::{cancelled, is_cancelled};
+    use tempfile::tempdir;
+
+    #[test]
+    fn sparse_copies_match_and_skip_zero_runs() -> io::Result<()> {
+        let dir = tempdir()?;
+        let origin = dir.path().join("container.mkv");
+        let mut file = File::create(&origin)?;
+        file.write_all(b"header")?;
+        file.seek(SeekFrom::Start(4 * HOLE_CHUNK as u64))?;
+        file.write_all(b"middle")?;
+        file.set_len(8 * HOLE_CHUNK as u64)?;
+        drop(file);
+
+        let target = dir.path().join("copy.mkv");
+        let mut reported = Vec::new();
+        let copied = copy_sparse(&origin, &target, &mut |copied| {
+            reported.push(copied);
+            Ok(())
+        })?;
+        assert_eq!(fs::read(&origin)?, fs::read(&target)?);
+        // Holes count toward the bytes copied, like `fs::copy`.
+        assert_eq!(copied, 8 * HOLE_CHUNK as u64);
+        #[cfg(unix)]
+        assert!(allocated_size(&fs::metadata(&target)?) < copied);
+        assert_eq!(reported.len(), 8);
+        assert_eq!(reported.last(), Some(&(8 * HOLE_CHUNK as u64)));
+
+        // A copy stopped partway is removed.
+        let stopped = dir.path().join("stopped.mkv");
+        let err = copy_sparse(&origin, &stopped, &mut |copied| {
+            if copied > HOLE_CHUNK as u64 {
+                Err(cancelled())
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap_err();
+        assert!(is_cancelled(&err));
+        assert!(!stopped.exists());
+        Ok(())
+    }
+}
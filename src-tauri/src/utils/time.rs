@@ -23,3 +23,122 @@ pub fn parse_timestamp(value: &str) -> Result<OffsetDateTime> {
 
     OffsetDateTime::parse(value, &Rfc3339).map_err(AppError::time)
 }
+
+/// Fields of a stored `YYYY-MM-DD_HH-MM-SS` timestamp, in order, with the
+/// strftime code that names each and its byte range.
+const NAME_FIELDS: [(char, std::ops::Range<usize>); 6] = [
+    ('Y', 0..4),
+    ('m', 5..7),
+    ('d', 8..10),
+    ('H', 11..13),
+    ('M', 14..16),
+    ('S', 17..19),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NamePiece {
+    Field(std::ops::Range<usize>),
+    Literal(String),
+}
+
+/// A strftime-like format for the timestamp that prefixes planned file
+/// names, such as `%Y-%m-%d_%H-%M-%S` or ISO basic `%Y%m%dT%H%M%S`. Only
+/// the date and time fields are allowed, largest first and none skipped,
+/// so names still sort by time; literals must be safe in file names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTimestamp(Vec<NamePiece>);
+
+impl NameTimestamp {
+    pub fn parse(format: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| AppError::Config(format!("name timestamp {format:?} {reason}"));
+        let mut pieces = Vec::new();
+        let mut fields = 0;
+        let mut chars = format.chars();
+        while let Some(ch) = chars.next() {
+            let literal = match ch {
+                '%' => match chars.next() {
+                    Some('%') => '%',
+                    Some(code) => {
+                        let Some((expected, range)) = NAME_FIELDS.get(fields).cloned() else {
+                            return Err(invalid("repeats a field"));
+                        };
+                        if code != expected {
+                            return Err(invalid(&format!(
+                                "needs %{expected} before %{code}; fields go from year to second"
+                            )));
+                        }
+                        fields += 1;
+                        pieces.push(NamePiece::Field(range));
+                        continue;
+                    }
+                    None => return Err(invalid("ends in a lone %")),
+                },
+                ch if ch.is_control() || r#"<>:"/\|?*"#.contains(ch) => {
+                    return Err(invalid(&format!("has {ch:?}, which file names cannot")));
+                }
+                ch => ch,
+            };
+            match pieces.last_mut() {
+                Some(NamePiece::Literal(text)) => text.push(literal),
+                _ => pieces.push(NamePiece::Literal(literal.to_string())),
+            }
+        }
+        if fields < 3 {
+            return Err(invalid("needs at least the year, month and day"));
+        }
+        Ok(Self(pieces))
+    }
+
+    /// Renders a stored timestamp; anything not in the stored format is
+    /// kept as it is.
+    pub fn render(&self, timestamp: &str) -> String {
+        if timestamp.len() != 19 || !timestamp.is_ascii() {
+            return timestamp.to_string();
+        }
+        self.0
+            .iter()
+            .map(|piece| match piece {
+                NamePiece::Field(range) => &timestamp[range.clone()],
+                NamePiece::Literal(text) => text.as_str(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_timestamps_render_and_stay_sortable() -> Result<()> {
+        let stored = "2024-06-14_10-20-30";
+        assert_eq!(
+            NameTimestamp::parse("%Y-%m-%d_%H-%M-%S")?.render(stored),
+            stored
+        );
+        assert_eq!(
+            NameTimestamp::parse("%Y%m%dT%H%M%S")?.render(stored),
+            "20240614T102030"
+        );
+        assert_eq!(
+            NameTimestamp::parse("%Y-%m-%d %Hh%M")?.render(stored),
+            "2024-06-14 10h20"
+        );
+        assert_eq!(
+            NameTimestamp::parse("%Y-%m-%d")?.render("unknown"),
+            "unknown"
+        );
+
+        for bad in [
+            "%d-%m-%Y",
+            "%Y-%m",
+            "%Y-%m-%d_%H:%M",
+            "%Y-%m-%d%",
+            "%Y%m%d%H%M%S%S",
+        ] {
+            assert!(NameTimestamp::parse(bad).is_err(), "{bad}");
+        }
+        Ok(())
+    }
+}
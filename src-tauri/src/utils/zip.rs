@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use time::OffsetDateTime;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// 2.0, the first version with deflate.
+const VERSION: u16 = 20;
+/// Names are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_DEFLATE: u16 = 8;
+
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a deflated zip archive of in-memory files. Enough for the small
+/// bundles made here; there is no ZIP64, so the archive stays under 4 GiB.
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        let (dos_date, dos_time) = dos_datetime(OffsetDateTime::now_utc());
+        Self {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+            dos_time,
+            dos_date,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let entry = CentralEntry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            compressed: fits(compressed.len() as u64)?,
+            size: fits(data.len() as u64)?,
+            offset: fits(self.offset)?,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        put32(&mut header, LOCAL_HEADER);
+        put16(&mut header, VERSION);
+        self.put_common(&mut header, &entry);
+        put16(&mut header, 0);
+        header.extend_from_slice(name.as_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(&compressed)?;
+        self.offset += (header.len() + compressed.len()) as u64;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and hands the writer back.
+    pub fn finish(mut self) -> io::Result<W> {
+        let start = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            put32(&mut directory, CENTRAL_HEADER);
+            put16(&mut directory, VERSION);
+            put16(&mut directory, VERSION);
+            self.put_common(&mut directory, entry);
+            // Extra field, comment, disk, internal and external attributes.
+            put16(&mut directory, 0);
+            put16(&mut directory, 0);
+            put16(&mut directory, 0);
+            put16(&mut directory, 0);
+            put32(&mut directory, 0);
+            put32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many zip entries"))?;
+        let mut end = Vec::with_capacity(22);
+        put32(&mut end, END_OF_CENTRAL_DIRECTORY);
+        put16(&mut end, 0);
+        put16(&mut end, 0);
+        put16(&mut end, count);
+        put16(&mut end, count);
+        put32(&mut end, fits(directory.len() as u64)?);
+        put32(&mut end, fits(start)?);
+        put16(&mut end, 0);
+        self.out.write_all(&directory)?;
+        self.out.write_all(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Flags through name length, shared by both headers.
+    fn put_common(&self, buf: &mut Vec<u8>, entry: &CentralEntry) {
+        put16(buf, FLAG_UTF8);
+        put16(buf, METHOD_DEFLATE);
+        put16(buf, self.dos_time);
+        put16(buf, self.dos_date);
+        put32(buf, entry.crc);
+        put32(buf, entry.compressed);
+        put32(buf, entry.size);
+        put16(buf, entry.name.len() as u16);
+    }
+}
+
+fn fits(value: u64) -> io::Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "zip archive over 4 GiB"))
+}
+
+fn put16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// MS-DOS date and time, which cannot go before 1980.
+fn dos_datetime(at: OffsetDateTime) -> (u16, u16) {
+    let year = at.year().clamp(1980, 2107) as u16;
+    let date = ((year - 1980) << 9) | ((at.month() as u16) << 5) | u16::from(at.day());
+    let time =
+        (u16::from(at.hour()) << 11) | (u16::from(at.minute()) << 5) | (u16::from(at.second()) / 2);
+    (date, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use flate2::read::DeflateDecoder;
+
+    fn u16_at(buf: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([buf[at], buf[at + 1]])
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn entries_round_trip_through_the_central_directory() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("config.json", b"{\"a\":1}").unwrap();
+        zip.add("logs.txt", "line one\nline two\n".repeat(50).as_bytes())
+            .unwrap();
+        let archive = zip.finish().unwrap();
+
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(&archive, end), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16_at(&archive, end + 10), 2);
+
+        let mut at = u32_at(&archive, end + 16) as usize;
+        let mut names = Vec::new();
+        for _ in 0..2 {
+            assert_eq!(u32_at(&archive, at), CENTRAL_HEADER);
+            let crc = u32_at(&archive, at + 16);
+            let compressed = u32_at(&archive, at + 20) as usize;
+            let name_len = u16_at(&archive, at + 28) as usize;
+            let local = u32_at(&archive, at + 42) as usize;
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_len].to_vec()).unwrap();
+
+            assert_eq!(u32_at(&archive, local), LOCAL_HEADER);
+            let data_at = local + 30 + u16_at(&archive, local + 26) as usize;
+            let mut data = Vec::new();
+            DeflateDecoder::new(&archive[data_at..data_at + compressed])
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(crc32fast::hash(&data), crc);
+
+            names.push(name);
+            at += 46 + name_len;
+        }
+        assert_eq!(names, ["config.json", "logs.txt"]);
+    }
+
+    #[test]
+    fn dos_dates_pack_fields() {
+        let at = time::macros::datetime!(2024-03-05 13:45:30 UTC);
+        assert_eq!(
+            dos_datetime(at),
+            ((44 << 9) | (3 << 5) | 5, (13 << 11) | (45 << 5) | 15)
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::utils::time::format_timestamp;
+
+/// Seconds between the QuickTime epoch, 1904-01-01, and the Unix one.
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+/// Boxes and chunks read whole are capped, so a corrupt size cannot make a
+/// scan allocate gigabytes. Real `moov` boxes of long clips stay well below.
+const MAX_METADATA_BYTES: u64 = 32 * 1024 * 1024;
+
+/// What a video container says about the clip.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoMetadata {
+    pub captured_at: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub gps: Option<(f64, f64)>,
+}
+
+/// Reads the creation time, camera and location from an MP4/QuickTime or
+/// AVI container. Apple's `creationdate` and AVI dates are wall-clock time
+/// like EXIF; the `mvhd` creation time, used when there is nothing else,
+/// is UTC.
+pub fn read_metadata(path: &Path) -> VideoMetadata {
+    let result = File::open(path).and_then(|file| {
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 12];
+        reader.read_exact(&mut magic)?;
+        reader.rewind()?;
+        if &magic[..4] == b"RIFF" && &magic[8..] == b"AVI " {
+            read_avi(&mut reader)
+        } else {
+            read_quicktime(&mut reader)
+        }
+    });
+    match result {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            tracing::debug!(path = %path.display(), error = ?err, "no container metadata");
+            VideoMetadata::default()
+        }
+    }
+}
+
+fn read_quicktime<R: Read + Seek>(reader: &mut R) -> io::Result<VideoMetadata> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.rewind()?;
+    let mut position = 0;
+    while position + 8 <= end {
+        let (kind, header, size) = read_box_header(reader, end - position)?;
+        if &kind == b"moov" {
+            let moov = read_capped(reader, size - header)?;
+            return Ok(parse_moov(&moov));
+        }
+        position += size;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+    Ok(VideoMetadata::default())
+}
+
+/// Reads a box header at the reader's position; returns the box type, the
+/// header length and the whole box's length.
+fn read_box_header<R: Read>(reader: &mut R, remaining: u64) -> io::Result<([u8; 4], u64, u64)> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let kind = [header[4], header[5], header[6], header[7]];
+    let (header_len, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]])
+    {
+        0 => (8, remaining),
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            (16, u64::from_be_bytes(large))
+        }
+        size => (8, u64::from(size)),
+    };
+    if size < header_len || size > remaining {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad box size"));
+    }
+    Ok((kind, header_len, size))
+}
+
+fn read_capped<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_METADATA_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "metadata box too large",
+        ));
+    }
+    let mut buffer = vec![0u8; len as usize];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// The child boxes of an in-memory box body, as type and body.
+fn child_boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut children = Vec::new();
+    while data.len() >= 8 {
+        let (header_len, size) = match u32::from_be_bytes([data[0], data[1], data[2], data[3]]) {
+            0 => (8, data.len()),
+            1 if data.len() >= 16 => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&data[8..16]);
+                (16, usize::try_from(u64::from_be_bytes(large)).unwrap_or(0))
+            }
+            size => (8, size as usize),
+        };
+        if size < header_len || size > data.len() {
+            break;
+        }
+        children.push((
+            [data[4], data[5], data[6], data[7]],
+            &data[header_len..size],
+        ));
+        data = &data[size..];
+    }
+    children
+}
+
+fn parse_moov(moov: &[u8]) -> VideoMetadata {
+    let mut metadata = VideoMetadata::default();
+    let mut created_utc = None;
+    for (kind, body) in child_boxes(moov) {
+        match &kind {
+            b"mvhd" => created_utc = mvhd_creation_time(body),
+            b"udta" => {
+                for (item, value) in child_boxes(body) {
+                    match &item {
+                        b"\xa9mak" => fill(&mut metadata.camera_make, user_data_text(value)),
+                        b"\xa9mod" => fill(&mut metadata.camera_model, user_data_text(value)),
+                        b"\xa9xyz" if metadata.gps.is_none() => {
+                            metadata.gps = user_data_text(value).as_deref().and_then(iso6709);
+                        }
+                        b"meta" => apply_apple_keys(&mut metadata, body_of_meta(value)),
+                        _ => {}
+                    }
+                }
+            }
+            b"meta" => apply_apple_keys(&mut metadata, body_of_meta(body)),
+            _ => {}
+        }
+    }
+    if metadata.captured_at.is_none() {
+        metadata.captured_at = created_utc.and_then(|created| format_timestamp(created).ok());
+    }
+    metadata
+}
+
+fn fill(slot: &mut Option<String>, value: Option<String>) {
+    if slot.is_none() {
+        *slot = value;
+    }
+}
+
+/// `mvhd` creation time: 32-bit seconds since 1904 in version 0, 64-bit
+/// in version 1. Zero means the muxer did not set it.
+fn mvhd_creation_time(body: &[u8]) -> Option<OffsetDateTime> {
+    let seconds = match body.first()? {
+        0 => u64::from(u32::from_be_bytes(body.get(4..8)?.try_into().ok()?)),
+        1 => u64::from_be_bytes(body.get(4..12)?.try_into().ok()?),
+        _ => return None,
+    };
+    if seconds == 0 {
+        return None;
+    }
+    let unix = i64::try_from(seconds).ok()? - QUICKTIME_EPOCH_OFFSET;
+    OffsetDateTime::from_unix_timestamp(unix).ok()
+}
+
+/// A QuickTime user data text item: a 16-bit length, a language code and
+/// the text.
+fn user_data_text(value: &[u8]) -> Option<String> {
+    let len = usize::from(u16::from_be_bytes(value.get(0..2)?.try_into().ok()?));
+    clean_text(value.get(4..4 + len)?)
+}
+
+fn clean_text(bytes: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// ISO `meta` boxes start with a version and flags; QuickTime's do not.
+fn body_of_meta(body: &[u8]) -> &[u8] {
+    if body.starts_with(&[0, 0, 0, 0]) {
+        &body[4..]
+    } else {
+        body
+    }
+}
+
+/// Apple's `keys`/`ilst` metadata, written by iPhones and most recent
+/// cameras recording QuickTime.
+fn apply_apple_keys(metadata: &mut VideoMetadata, meta: &[u8]) {
+    let children = child_boxes(meta);
+    let Some(keys) = children
+        .iter()
+        .find(|(kind, _)| kind == b"keys")
+        .map(|(_, body)| key_names(body))
+    else {
+        return;
+    };
+    let Some((_, ilst)) = children.iter().find(|(kind, _)| kind == b"ilst") else {
+        return;
+    };
+    for (index, item) in child_boxes(ilst) {
+        let index = u32::from_be_bytes(index) as usize;
+        let Some(key) = index.checked_sub(1).and_then(|index| keys.get(index)) else {
+            continue;
+        };
+        let Some(value) = child_boxes(item)
+            .into_iter()
+            .find(|(kind, _)| kind == b"data")
+            .and_then(|(_, data)| clean_text(data.get(8..)?))
+        else {
+            continue;
+        };
+        match key.as_str() {
+            "com.apple.quicktime.make" => metadata.camera_make = Some(value),
+            "com.apple.quicktime.model" => metadata.camera_model = Some(value),
+            "com.apple.quicktime.creationdate" => {
+                if let Some(captured_at) = wall_clock_iso(&value) {
+                    metadata.captured_at = Some(captured_at);
+                }
+            }
+            "com.apple.quicktime.location.ISO6709" => {
+                if let Some(gps) = iso6709(&value) {
+                    metadata.gps = Some(gps);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn key_names(keys: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = keys.get(8..).unwrap_or_default();
+    while rest.len() >= 8 {
+        let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if size < 8 || size > rest.len() {
+            break;
+        }
+        names.push(String::from_utf8_lossy(&rest[8..size]).into_owned());
+        rest = &rest[size..];
+    }
+    names
+}
+
+/// Decimal latitude and longitude from an ISO 6709 string such as
+/// `+48.8584+002.2945+035.000/`.
+fn iso6709(value: &str) -> Option<(f64, f64)> {
+    let value = value.trim_end_matches('/');
+    let split = value
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '+' || *c == '-')?
+        .0;
+    let (latitude, rest) = value.split_at(split);
+    let longitude_end = rest
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '+' || *c == '-')
+        .map_or(rest.len(), |(idx, _)| idx);
+    let latitude: f64 = latitude.parse().ok()?;
+    let longitude: f64 = rest[..longitude_end].parse().ok()?;
+    ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+        .then_some((latitude, longitude))
+}
+
+/// The wall-clock part of an ISO 8601 date such as
+/// `2024-01-02T10:00:00+0100`, dropping the offset as EXIF dates have none.
+fn wall_clock_iso(value: &str) -> Option<String> {
+    let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+    timestamp(
+        number(0..4)?,
+        number(5..7)?,
+        number(8..10)?,
+        (number(11..13)?, number(14..16)?, number(17..19)?),
+    )
+}
+
+fn timestamp(
+    year: u32,
+    month: u32,
+    day: u32,
+    (hour, minute, second): (u32, u32, u32),
+) -> Option<String> {
+    let date = Date::from_calendar_date(
+        i32::try_from(year).ok()?,
+        Month::try_from(u8::try_from(month).ok()?).ok()?,
+        u8::try_from(day).ok()?,
+    )
+    .ok()?;
+    let time = Time::from_hms(
+        u8::try_from(hour).ok()?,
+        u8::try_from(minute).ok()?,
+        u8::try_from(second).ok()?,
+    )
+    .ok()?;
+    format_timestamp(PrimitiveDateTime::new(date, time).assume_utc()).ok()
+}
+
+fn read_avi<R: Read + Seek>(reader: &mut R) -> io::Result<VideoMetadata> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let mut metadata = VideoMetadata::default();
+    let mut position = 12;
+    while position + 8 <= end {
+        reader.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header[..8])?;
+        let size = u64::from(u32::from_le_bytes([
+            header[4], header[5], header[6], header[7],
+        ]));
+        if &header[..4] == b"LIST" && size >= 4 {
+            reader.read_exact(&mut header[8..])?;
+            if &header[8..] == b"hdrl" || &header[8..] == b"INFO" {
+                let list = read_capped(reader, size - 4)?;
+                apply_riff_chunks(&mut metadata, &list);
+            }
+        }
+        // Chunks are padded to an even length.
+        position += 8 + size + (size & 1);
+    }
+    Ok(metadata)
+}
+
+/// Walks the chunks of a RIFF list, descending into nested lists, for the
+/// `IDIT` and `ICRD` dates.
+fn apply_riff_chunks(metadata: &mut VideoMetadata, mut data: &[u8]) {
+    while data.len() >= 8 {
+        let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let Some(body) = data.get(8..8 + size) else {
+            break;
+        };
+        match &data[..4] {
+            b"LIST" if body.len() >= 4 => apply_riff_chunks(metadata, &body[4..]),
+            b"IDIT" => {
+                if let Some(captured_at) = clean_text(body).as_deref().and_then(avi_date) {
+                    metadata.captured_at = Some(captured_at);
+                }
+            }
+            b"ICRD" if metadata.captured_at.is_none() => {
+                metadata.captured_at = clean_text(body).as_deref().and_then(|value| {
+                    wall_clock_iso(value).or_else(|| {
+                        let number =
+                            |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+                        timestamp(number(0..4)?, number(5..7)?, number(8..10)?, (0, 0, 0))
+                    })
+                });
+            }
+            _ => {}
+        }
+        data = &data[(8 + size + (size & 1)).min(data.len())..];
+    }
+}
+
+/// An AVI `IDIT` date, as in `Mon Jan 02 10:00:00 2024`.
+fn avi_date(value: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, month, day, clock, year] = parts.as_slice() else {
+        return None;
+    };
+    let month = MONTHS
+        .iter()
+        .position(|name| month.eq_ignore_ascii_case(name))?
+        + 1;
+    let mut clock = clock.split(':').map(|part| part.parse::<u32>().ok());
+    timestamp(
+        year.parse().ok()?,
+        month as u32,
+        day.parse().ok()?,
+        (clock.next()??, clock.next()??, clock.next()??),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn user_text(kind: &[u8; 4], text: &str) -> Vec<u8> {
+        let mut body = (text.len() as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x15, 0xc7]);
+        body.extend_from_slice(text.as_bytes());
+        mp4_box(kind, &body)
+    }
+
+    fn riff_chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    #[test]
+    fn quicktime_and_avi_containers_are_read() -> io::Result<()> {
+        let dir = tempdir()?;
+
+        // 2024-01-02 10:00:00 UTC in seconds since 1904.
+        let created = (1_704_189_600 + QUICKTIME_EPOCH_OFFSET) as u32;
+        let mut mvhd = vec![0u8; 4];
+        mvhd.extend_from_slice(&created.to_be_bytes());
+        mvhd.extend_from_slice(&[0u8; 92]);
+        let mut udta = user_text(b"\xa9mak", "Canon");
+        udta.extend(user_text(b"\xa9mod", "EOS R6"));
+        udta.extend(user_text(b"\xa9xyz", "+48.8584+002.2945+035.000/"));
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(mp4_box(b"udta", &udta));
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(mp4_box(b"mdat", &[0u8; 64]));
+        file.extend(mp4_box(b"moov", &moov));
+        let clip = dir.path().join("clip.mp4");
+        fs::write(&clip, &file)?;
+
+        let metadata = read_metadata(&clip);
+        assert_eq!(metadata.captured_at.as_deref(), Some("2024-01-02_10-00-00"));
+        assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(metadata.camera_model.as_deref(), Some("EOS R6"));
+        assert_eq!(metadata.gps, Some((48.8584, 2.2945)));
+
+        let idit = riff_chunk(b"IDIT", b"Tue Mar 05 18:30:15 2024\n\0");
+        let mut hdrl = b"hdrl".to_vec();
+        hdrl.extend(riff_chunk(b"avih", &[0u8; 56]));
+        hdrl.extend(idit);
+        let mut body = b"AVI ".to_vec();
+        body.extend(riff_chunk(b"LIST", &hdrl));
+        let mut movi = b"movi".to_vec();
+        movi.extend_from_slice(&[0u8; 32]);
+        body.extend(riff_chunk(b"LIST", &movi));
+        let avi = dir.path().join("clip.avi");
+        fs::write(&avi, riff_chunk(b"RIFF", &body))?;
+        assert_eq!(
+            read_metadata(&avi).captured_at.as_deref(),
+            Some("2024-03-05_18-30-15")
+        );
+
+        let junk = dir.path().join("junk.mov");
+        fs::write(&junk, b"not a container")?;
+        assert_eq!(read_metadata(&junk), VideoMetadata::default());
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+use crate::error::Result;
+use crate::system::disk_status;
+
+/// Hands destination buckets to spanned output volumes in order. A bucket
+/// stays on the volume it started on while it fits; new buckets go to the
+/// volume currently being filled, never back to an earlier one.
+pub struct VolumeAllocator {
+    roots: Vec<PathBuf>,
+    remaining: Vec<u64>,
+    current: usize,
+    buckets: HashMap<PathBuf, usize>,
+}
+
+impl VolumeAllocator {
+    /// Builds an allocator from the configured volumes, or `None` when the
+    /// library lives on a single root.
+    pub fn from_config(config: &AppConfig) -> Result<Option<Self>> {
+        if config.plan.output_volumes.is_empty() {
+            return Ok(None);
+        }
+
+        let mut budgets = Vec::with_capacity(config.plan.output_volumes.len());
+        for (root, volume) in config
+            .volume_roots()
+            .into_iter()
+            .zip(&config.plan.output_volumes)
+        {
+            let status = disk_status(&root)?;
+            let budget = status.available_bytes.saturating_sub(volume.min_free_bytes);
+            budgets.push((root, budget));
+        }
+        Ok(Some(Self::new(budgets)))
+    }
+
+    pub fn new(budgets: Vec<(PathBuf, u64)>) -> Self {
+        let (roots, remaining) = budgets.into_iter().unzip();
+        Self {
+            roots,
+            remaining,
+            current: 0,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Picks the volume for one file of `size` bytes in `bucket`. When no
+    /// volume has room the last one is used and preflight reports the
+    /// shortfall.
+    pub fn assign(&mut self, bucket: &Path, size: u64) -> usize {
+        let last = self.roots.len().saturating_sub(1);
+        let start = self.buckets.get(bucket).copied().unwrap_or(self.current);
+        let idx = (start..self.roots.len())
+            .find(|idx| self.remaining[*idx] >= size)
+            .unwrap_or(last);
+
+        self.remaining[idx] = self.remaining[idx].saturating_sub(size);
+        self.current = self.current.max(idx);
+        self.buckets.insert(bucket.to_path_buf(), idx);
+        idx
+    }
+
+    pub fn root(&self, idx: usize) -> &Path {
+        &self.roots[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_spill_to_the_next_volume() {
+        let mut allocator = VolumeAllocator::new(vec![
+            (PathBuf::from("/disk-a"), 100),
+            (PathBuf::from("/disk-b"), 1_000),
+        ]);
+
+        assert_eq!(allocator.assign(Path::new("2024-01-01"), 60), 0);
+        assert_eq!(allocator.assign(Path::new("2024-01-02"), 30), 0);
+        assert_eq!(allocator.assign(Path::new("2024-01-02"), 30), 1);
+        // Later buckets keep filling the second disk even if a small file
+        // would still fit on the first.
+        assert_eq!(allocator.assign(Path::new("2024-01-03"), 5), 1);
+        assert_eq!(allocator.root(1), Path::new("/disk-b"));
+
+        assert_eq!(allocator.assign(Path::new("2024-01-04"), 5_000), 1);
+    }
+}
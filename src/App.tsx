@@ -1,20 +1,38 @@
-import { confirm } from '@tauri-apps/plugin-dialog'
+import { confirm, open } from '@tauri-apps/plugin-dialog'
 // import { confirm } from '@tauri-apps/api/dialog'
 import { listen } from '@tauri-apps/api/event'
+import { openUrl } from '@tauri-apps/plugin-opener'
 import type { UnlistenFn } from '@tauri-apps/api/event'
 import { useEffect, useMemo, useRef, useState, type ReactNode } from 'react'
 
-import { normalizeConfig, CONFIG_BOOTSTRAP_EVENT } from './services/config'
+import {
+  normalizeConfig,
+  setImageRoot,
+  setIncludedFolders,
+  setOutputRoot,
+  CONFIG_BOOTSTRAP_EVENT,
+  CONFIG_UPDATED_EVENT,
+} from './services/config'
 import { EXECUTION_PROGRESS_EVENT, PLAN_PROGRESS_EVENT } from './services/plan'
-import { SCAN_PROGRESS_EVENT } from './services/scan'
-import { checkDiskSpace } from './services/system'
+import { estimateScan, listFolders, SCAN_PROGRESS_EVENT } from './services/scan'
+import {
+  cancelTask,
+  checkDiskSpace,
+  checkPermissions,
+  isPermissionDenied,
+} from './services/system'
 import { useConfigStore } from './state/configStore'
 import { useWorkflowStore, type StageStatus } from './state/workflowStore'
-import type { ExecutionMode, PlanItem, PlanProgressPayload } from './types/plan'
-import type { DiskStatus } from './types/system'
+import type { ExecutionMode, PlanItem, PlanProgressPayload, PlanReason } from './types/plan'
+import type { DiskStatus, PermissionReport, RunMetrics } from './types/system'
 import type { RawConfigPayload } from './types/config'
 import type { ExecutionProgressPayload } from './types/plan'
-import type { ScanProgressPayload } from './types/scan'
+import type {
+  FolderNode,
+  ScanEstimate,
+  ScanProgressPayload,
+  ScanStage,
+} from './types/scan'
 import './App.css'
 
 function App() {
@@ -40,6 +58,7 @@ function App() {
     generatePlan,
     runExecution,
     runUndo,
+    restoreProgress,
     resetAfterConfig,
   } = useWorkflowStore((state) => ({
     scan: state.scan,
@@ -53,27 +72,78 @@ function App() {
     generatePlan: state.generatePlan,
     runExecution: state.runExecution,
     runUndo: state.runUndo,
+    restoreProgress: state.restoreProgress,
     resetAfterConfig: state.resetAfterConfig,
   }))
 
   const [executionMode, setExecutionMode] = useState<ExecutionMode>('copy')
   const [dryRun, setDryRun] = useState(true)
+  const [verifyCopies, setVerifyCopies] = useState(false)
   const [diskStatus, setDiskStatus] = useState<DiskStatus | null>(null)
 
   useEffect(() => {
     void bootstrap()
-    const unlisten = listen<RawConfigPayload>(
-      CONFIG_BOOTSTRAP_EVENT,
-      (event) => {
-        setFromEvent(normalizeConfig(event.payload))
-      },
+    const subscriptions = [CONFIG_BOOTSTRAP_EVENT, CONFIG_UPDATED_EVENT].map(
+      (name) =>
+        listen<RawConfigPayload>(name, (event) => {
+          setFromEvent(normalizeConfig(event.payload))
+        }),
     )
 
     return () => {
-      unlisten.then((fn) => fn()).catch(() => undefined)
+      subscriptions.forEach((unlisten) => {
+        unlisten.then((fn) => fn()).catch(() => undefined)
+      })
     }
   }, [bootstrap, setFromEvent])
 
+  const [rootError, setRootError] = useState<string | null>(null)
+
+  async function chooseRoot(kind: 'image' | 'output') {
+    const picked = await open({
+      directory: true,
+      title: kind === 'image' ? 'Choose the image folder' : 'Choose the output folder',
+    })
+    if (typeof picked !== 'string') {
+      return
+    }
+    try {
+      setFromEvent(
+        kind === 'image' ? await setImageRoot(picked) : await setOutputRoot(picked),
+      )
+      setRootError(null)
+    } catch (err) {
+      setRootError(String(err))
+    }
+  }
+
+  const [pickingFolders, setPickingFolders] = useState(false)
+  const [estimate, setEstimate] = useState<ScanEstimate | null>(null)
+  const [estimating, setEstimating] = useState(false)
+  const [estimateError, setEstimateError] = useState<string | null>(null)
+
+  async function runEstimate() {
+    setEstimating(true)
+    try {
+      setEstimate(await estimateScan())
+      setEstimateError(null)
+    } catch (err) {
+      setEstimateError(String(err))
+    } finally {
+      setEstimating(false)
+    }
+  }
+
+  async function applyIncludedFolders(folders: string[]) {
+    try {
+      setFromEvent(await setIncludedFolders(folders))
+      setRootError(null)
+      setPickingFolders(false)
+    } catch (err) {
+      setRootError(String(err))
+    }
+  }
+
   useEffect(() => {
     const subscriptions: Array<Promise<UnlistenFn>> = [
       listen<ScanProgressPayload>(SCAN_PROGRESS_EVENT, (event) => {
@@ -105,10 +175,15 @@ function App() {
       config.outputRoot,
     ].join('|')
     if (configFingerprint.current !== fingerprint) {
+      const firstLoad = configFingerprint.current === null
       resetAfterConfig()
       configFingerprint.current = fingerprint
+      if (firstLoad) {
+        // After a reload the backend may still be busy with a task.
+        void restoreProgress().catch(() => undefined)
+      }
     }
-  }, [status, config, resetAfterConfig])
+  }, [status, config, resetAfterConfig, restoreProgress])
 
   const planSummary = plan.summary
   const planBuckets = useMemo(() => {
@@ -175,6 +250,20 @@ function App() {
       }
     }
 
+    if (
+      !dryRun &&
+      executionMode === 'move' &&
+      (scan.summary?.scannedPhotosLibraries.length ?? 0) > 0
+    ) {
+      const proceedOnLibraries = await confirm(
+        'Some files were scanned from inside Apple Photos libraries. Moving them will break those libraries. Continue anyway?',
+        { title: 'Apple Photos library warning', kind: 'warning' },
+      )
+      if (!proceedOnLibraries) {
+        return
+      }
+    }
+
     const proceed = await confirm(
       `Ready to ${dryRun ? 'dry run' : executionMode} ${planSummary.totalEntries} planned files?`,
       {
@@ -187,7 +276,7 @@ function App() {
       return
     }
 
-    await runExecution(executionMode, dryRun)
+    await runExecution(executionMode, dryRun, verifyCopies)
   }
 
   const handleUndo = async () => {
@@ -226,8 +315,19 @@ function App() {
               <ConfigSummary
                 config={config}
                 onRefresh={() => void bootstrap()}
+                onChooseRoot={(kind) => void chooseRoot(kind)}
+                onChooseFolders={() => setPickingFolders(true)}
+                onScanWholeRoot={() => void applyIncludedFolders([])}
+              />
+            )}
+            {status === 'ready' && config && pickingFolders && (
+              <FolderPicker
+                included={config.includeFolders}
+                onApply={(folders) => void applyIncludedFolders(folders)}
+                onCancel={() => setPickingFolders(false)}
               />
             )}
+            {rootError && <StatusBanner kind="error">{rootError}</StatusBanner>}
             {status === 'idle' && (
               <StatusBanner>Click refresh to load configuration.</StatusBanner>
             )}
@@ -238,26 +338,124 @@ function App() {
           title="2. Scan Media"
           status={scan.status}
           actions={
-            <button
-              type="button"
-              className="action"
-              onClick={() => void runScan()}
-              disabled={scan.status === 'running' || status !== 'ready'}
-            >
-              {scan.status === 'running' ? 'Scanning…' : 'Start scan'}
-            </button>
+            <>
+              <button
+                type="button"
+                className="action ghost"
+                onClick={() => void runEstimate()}
+                disabled={estimating || scan.status === 'running' || status !== 'ready'}
+              >
+                {estimating ? 'Estimating…' : 'Estimate'}
+              </button>
+              <button
+                type="button"
+                className="action"
+                onClick={() => void runScan()}
+                disabled={scan.status === 'running' || status !== 'ready'}
+              >
+                {scan.status === 'running' ? 'Scanning…' : 'Start scan'}
+              </button>
+              <CancelButton visible={scan.status === 'running'} />
+            </>
           }
         >
+          {estimate && scan.status !== 'running' && (
+            <StatusBanner>{formatEstimate(estimate)}</StatusBanner>
+          )}
+          {estimateError && <StatusBanner kind="error">{estimateError}</StatusBanner>}
           <OperationProgress
             operation={scan}
             progressLabel={(payload) => formatScanProgress(payload)}
           />
+          {scan.status === 'error' && isPermissionDenied(scan.error) && (
+            <PermissionHelp />
+          )}
           {scan.summary && (
             <ul className="metrics">
               <li>Total files: {scan.summary.totalFiles}</li>
               <li>Hashed this run: {scan.summary.hashedFiles}</li>
               <li>Reused from cache: {scan.summary.skippedFiles}</li>
+              <li>
+                Since last scan: {scan.summary.diff.added.count} new,{' '}
+                {scan.summary.diff.removed.count} removed,{' '}
+                {scan.summary.diff.changed.count} changed,{' '}
+                {scan.summary.diff.moved.count} moved
+              </li>
+              {scan.summary.resumedFiles > 0 && (
+                <li>Resumed from interrupted scan: {scan.summary.resumedFiles}</li>
+              )}
+              {scan.summary.unhashedFiles > 0 && (
+                <li>Not hashed (unique size): {scan.summary.unhashedFiles}</li>
+              )}
+              {scan.summary.cachedFiles > 0 && (
+                <li>Recognized after rename/move: {scan.summary.cachedFiles}</li>
+              )}
+              {scan.summary.journalFolders !== null && (
+                <li>
+                  Quick rescan: {scan.summary.journalFolders} changed folders
+                </li>
+              )}
               <li>Duplicates flagged: {scan.summary.duplicateFiles}</li>
+              <li>Thumbnails/cache files: {scan.summary.derivativeFiles}</li>
+              {scan.summary.skippedPhotosLibraries.length > 0 && (
+                <li>
+                  Apple Photos libraries skipped: {scan.summary.skippedPhotosLibraries.length}
+                </li>
+              )}
+              {scan.summary.nomediaFolders.length > 0 && (
+                <li>Folders marked .nomedia: {scan.summary.nomediaFolders.length}</li>
+              )}
+              {scan.summary.ignoredEntries > 0 && (
+                <li>Ignored by .phototidyignore: {scan.summary.ignoredEntries}</li>
+              )}
+              {scan.summary.skippedOutputFolders > 0 && (
+                <li>
+                  Output folders inside the source, not scanned:{' '}
+                  {scan.summary.skippedOutputFolders}
+                </li>
+              )}
+              {scan.summary.followedLinks.length > 0 && (
+                <li>Symlinks followed: {scan.summary.followedLinks.length}</li>
+              )}
+              {scan.summary.hardlinkedFiles > 0 && (
+                <li>
+                  Hard-linked names counted once: {scan.summary.hardlinkedFiles}
+                </li>
+              )}
+              {scan.summary.specialFiles.length > 0 && (
+                <li>
+                  Pipes, sockets or devices skipped: {scan.summary.specialFiles.length}
+                </li>
+              )}
+              {scan.summary.sparseFiles.files > 0 && (
+                <li>
+                  Sparse files: {scan.summary.sparseFiles.files} (
+                  {formatBytes(scan.summary.sparseFiles.allocatedBytes)} on disk of{' '}
+                  {formatBytes(scan.summary.sparseFiles.apparentBytes)})
+                </li>
+              )}
+              {scan.summary.limitHits.deepFolders.length > 0 && (
+                <li>
+                  Folders below the depth limit not scanned:{' '}
+                  {scan.summary.limitHits.deepFolders.length}
+                </li>
+              )}
+              {scan.summary.limitHits.crowdedFolders.length > 0 && (
+                <li>
+                  Files over the per-folder limit left out:{' '}
+                  {scan.summary.limitHits.filesLeftOut} in{' '}
+                  {scan.summary.limitHits.crowdedFolders.length} folders
+                </li>
+              )}
+              {scan.summary.cloudPlaceholders.length > 0 && (
+                <li>
+                  Cloud files not downloaded: {scan.summary.cloudPlaceholders.length}
+                </li>
+              )}
+              {scan.summary.hydratedPlaceholders > 0 && (
+                <li>Cloud files downloaded: {scan.summary.hydratedPlaceholders}</li>
+              )}
+              <li>Took: {formatMetrics(scan.summary.metrics)}</li>
             </ul>
           )}
         </WorkflowStep>
@@ -266,14 +464,17 @@ function App() {
           title="3. Plan Targets"
           status={plan.status}
           actions={
-            <button
-              type="button"
-              className="action"
-              onClick={() => void generatePlan()}
-              disabled={!canGeneratePlan || plan.status === 'running'}
-            >
-              {plan.status === 'running' ? 'Planning…' : 'Generate plan'}
-            </button>
+            <>
+              <button
+                type="button"
+                className="action"
+                onClick={() => void generatePlan()}
+                disabled={!canGeneratePlan || plan.status === 'running'}
+              >
+                {plan.status === 'running' ? 'Planning…' : 'Generate plan'}
+              </button>
+              <CancelButton visible={plan.status === 'running'} />
+            </>
           }
         >
           <OperationProgress
@@ -288,8 +489,15 @@ function App() {
                 <li>Total planned: {planSummary.totalEntries}</li>
                 <li>Duplicates: {planSummary.duplicateEntries}</li>
                 <li>Unique: {planSummary.uniqueEntries}</li>
+                {planSummary.derivativeEntries > 0 && (
+                  <li>Thumbnails skipped: {planSummary.derivativeEntries}</li>
+                )}
+                {planSummary.undecidedEntries > 0 && (
+                  <li>Duplicates awaiting a decision: {planSummary.undecidedEntries}</li>
+                )}
                 <li>Destination folders: {planSummary.destinationBuckets}</li>
                 <li>Estimated size: {formatBytes(planSummary.totalBytes)}</li>
+                <li>Took: {formatMetrics(planSummary.metrics)}</li>
               </ul>
               <PlanPreview buckets={planBuckets} />
             </div>
@@ -329,6 +537,14 @@ function App() {
                 />{' '}
                 Dry run
               </label>
+              <label className="checkbox">
+                <input
+                  type="checkbox"
+                  checked={verifyCopies}
+                  onChange={(event) => setVerifyCopies(event.target.checked)}
+                />{' '}
+                Verify by hash
+              </label>
               <button
                 type="button"
                 className="action"
@@ -353,6 +569,9 @@ function App() {
               >
                 {undo.status === 'running' ? 'Undoing…' : 'Undo moves'}
               </button>
+              <CancelButton
+                visible={execution.status === 'running' || undo.status === 'running'}
+              />
             </div>
           }
         >
@@ -366,8 +585,35 @@ function App() {
               <li>Dry run: {execution.summary.dryRun ? 'Yes' : 'No'}</li>
               <li>Succeeded: {execution.summary.succeeded}</li>
               <li>Failed: {execution.summary.failed}</li>
+              <li>Already present: {execution.summary.alreadyPresent}</li>
               <li>Processed: {execution.summary.processedEntries}</li>
               <li>Duplicates touched: {execution.summary.duplicateEntries}</li>
+              {execution.summary.transcoded > 0 && (
+                <li>HEIC converted to JPEG: {execution.summary.transcoded}</li>
+              )}
+              {execution.summary.metadataStripped > 0 && (
+                <li>Metadata stripped: {execution.summary.metadataStripped}</li>
+              )}
+              {execution.summary.shared > 0 && (
+                <li>Shared copies: {execution.summary.shared}</li>
+              )}
+              {execution.summary.locked > 0 && (
+                <li>
+                  Still in use by another app: {execution.summary.locked}
+                </li>
+              )}
+              {execution.summary.removedFolders > 0 && (
+                <li>
+                  Empty folders removed: {execution.summary.removedFolders}
+                </li>
+              )}
+              {execution.summary.verified > 0 && (
+                <li>Verified by hash: {execution.summary.verified}</li>
+              )}
+              <li>Took: {formatMetrics(execution.summary.metrics)}</li>
+              {execution.summary.reportPath && (
+                <li>Report: {execution.summary.reportPath}</li>
+              )}
             </ul>
           )}
           {undo.summary && (
@@ -377,6 +623,9 @@ function App() {
                 <li>Restored: {undo.summary.restored}</li>
                 <li>Missing at target: {undo.summary.missing}</li>
                 <li>Failures: {undo.summary.failed}</li>
+                {undo.summary.removedFolders > 0 && (
+                  <li>Empty folders removed: {undo.summary.removedFolders}</li>
+                )}
               </ul>
             </div>
           )}
@@ -385,6 +634,7 @@ function App() {
               Last disk check ({diskStatus.path}):{' '}
               {formatBytes(diskStatus.availableBytes)} free /{' '}
               {formatBytes(diskStatus.totalBytes)} total
+              {diskStatus.network && ' (network share: slower, copied with retries)'}
             </p>
           )}
         </WorkflowStep>
@@ -396,15 +646,29 @@ function App() {
 function ConfigSummary({
   config,
   onRefresh,
+  onChooseRoot,
+  onChooseFolders,
+  onScanWholeRoot,
 }: {
   config: ReturnType<typeof normalizeConfig>
   onRefresh: () => void
+  onChooseRoot: (kind: 'image' | 'output') => void
+  onChooseFolders: () => void
+  onScanWholeRoot: () => void
 }) {
   return (
     <div className="config-summary">
       <dl>
         <InfoItem label="Database">{config.databasePath}</InfoItem>
         <InfoItem label="Image root">{config.imageRoot}</InfoItem>
+        {config.extraImageRoots.length > 0 && (
+          <InfoItem label="More image roots">{config.extraImageRoots.join(', ')}</InfoItem>
+        )}
+        {config.includeFolders.length > 0 && (
+          <InfoItem label="Scanned subfolders">
+            {config.includeFolders.join(', ')}
+          </InfoItem>
+        )}
         <InfoItem label="Output root">{config.outputRoot}</InfoItem>
         <InfoItem label="Duplicates">{config.duplicatesDir}</InfoItem>
         <InfoItem label="Origin JSON">{config.originInfoPath}</InfoItem>
@@ -425,6 +689,28 @@ function ConfigSummary({
       </div>
       <div className="config-actions">
         <span>Schema v{config.schemaVersion}</span>
+        <button
+          type="button"
+          onClick={() => onChooseRoot('image')}
+          className="action ghost"
+        >
+          Choose image folder…
+        </button>
+        <button
+          type="button"
+          onClick={() => onChooseRoot('output')}
+          className="action ghost"
+        >
+          Choose output folder…
+        </button>
+        <button type="button" onClick={onChooseFolders} className="action ghost">
+          Choose subfolders…
+        </button>
+        {config.includeFolders.length > 0 && (
+          <button type="button" onClick={onScanWholeRoot} className="action ghost">
+            Scan whole image folder
+          </button>
+        )}
         <button type="button" onClick={onRefresh} className="action ghost">
           Refresh configuration
         </button>
@@ -433,6 +719,16 @@ function ConfigSummary({
   )
 }
 
+// The task stops at the next file and fails with "task was cancelled".
+function CancelButton({ visible }: { visible: boolean }) {
+  if (!visible) return null
+  return (
+    <button type="button" className="action ghost" onClick={() => void cancelTask()}>
+      Cancel
+    </button>
+  )
+}
+
 function WorkflowStep({
   title,
   status,
@@ -519,6 +815,7 @@ function PlanPreview({
               <li
                 key={`${item.fileHash}-${item.newFileName}`}
                 className={item.isDuplicate ? 'duplicate' : undefined}
+                title={describeReason(item.reason)}
               >
                 <span>{item.newFileName}</span>
                 {item.isDuplicate && <span className="flag">duplicate</span>}
@@ -540,6 +837,143 @@ function InfoItem({ label, children }: { label: string; children: ReactNode }) {
   )
 }
 
+function FolderPicker({
+  included,
+  onApply,
+  onCancel,
+}: {
+  included: string[]
+  onApply: (folders: string[]) => void
+  onCancel: () => void
+}) {
+  const [folders, setFolders] = useState<FolderNode[] | null>(null)
+  const [selected, setSelected] = useState(() => new Set(included))
+  const [loadError, setLoadError] = useState<string | null>(null)
+
+  useEffect(() => {
+    listFolders()
+      .then(setFolders)
+      .catch((err) => setLoadError(String(err)))
+  }, [])
+
+  async function expand(node: FolderNode) {
+    try {
+      const children = await listFolders(node.path)
+      setFolders((current) => current && withChildren(current, node.path, children))
+    } catch (err) {
+      setLoadError(String(err))
+    }
+  }
+
+  function toggle(relativePath: string) {
+    setSelected((current) => {
+      const next = new Set(current)
+      if (!next.delete(relativePath)) {
+        next.add(relativePath)
+      }
+      return next
+    })
+  }
+
+  function renderNodes(nodes: FolderNode[]): ReactNode {
+    return (
+      <ul className="folder-tree">
+        {nodes.map((node) => (
+          <li key={node.path}>
+            <label className="checkbox">
+              <input
+                type="checkbox"
+                disabled={node.relativePath === null}
+                checked={node.relativePath !== null && selected.has(node.relativePath)}
+                onChange={() => node.relativePath && toggle(node.relativePath)}
+              />{' '}
+              {node.name} · {node.mediaFiles} files · {formatBytes(node.totalBytes)}
+            </label>
+            {node.hasSubfolders && node.children.length === 0 && (
+              <button
+                type="button"
+                className="action ghost"
+                onClick={() => void expand(node)}
+              >
+                Show subfolders
+              </button>
+            )}
+            {node.children.length > 0 && renderNodes(node.children)}
+          </li>
+        ))}
+      </ul>
+    )
+  }
+
+  return (
+    <div className="folder-picker">
+      <h3>Subfolders to scan</h3>
+      {loadError && <StatusBanner kind="error">{loadError}</StatusBanner>}
+      {folders === null && !loadError && <StatusBanner>Counting media…</StatusBanner>}
+      {folders && renderNodes(folders)}
+      <div className="config-actions">
+        <button
+          type="button"
+          className="action"
+          onClick={() => onApply([...selected])}
+          disabled={selected.size === 0}
+        >
+          Scan selected folders
+        </button>
+        <button type="button" className="action ghost" onClick={onCancel}>
+          Cancel
+        </button>
+      </div>
+    </div>
+  )
+}
+
+function withChildren(
+  nodes: FolderNode[],
+  path: string,
+  children: FolderNode[],
+): FolderNode[] {
+  return nodes.map((node) =>
+    node.path === path
+      ? { ...node, children }
+      : { ...node, children: withChildren(node.children, path, children) },
+  )
+}
+
+function PermissionHelp() {
+  const [report, setReport] = useState<PermissionReport | null>(null)
+
+  useEffect(() => {
+    checkPermissions()
+      .then(setReport)
+      .catch((err) => console.warn('Failed to check permissions', err))
+  }, [])
+
+  if (!report || report.ok) {
+    return null
+  }
+  const settingsUrl = report.settingsUrl
+  return (
+    <StatusBanner>
+      phototidy is not allowed to read {report.denied.join(', ')}.
+      {settingsUrl ? (
+        <>
+          {' '}Grant Full Disk Access in System Settings, then scan again.{' '}
+          <button
+            type="button"
+            className="action ghost"
+            onClick={() => void openUrl(settingsUrl)}
+          >
+            Open System Settings
+          </button>
+        </>
+      ) : (
+        ' Check the folder permissions, then scan again.'
+      )}
+    </StatusBanner>
+  )
+}
+
 function StatusBanner({
   kind = 'info',
   children,
@@ -574,17 +1008,38 @@ function statusToDisplay(status: string): string {
 }
 
 function percentageFromProgress(
-  payload: { processed: number; total: number } | null,
+  payload: {
+    processed: number
+    total: number | null
+    bytesProcessed?: number | null
+    bytesTotal?: number | null
+  } | null,
 ): number {
-  if (!payload || payload.total === 0) {
+  // Byte-weighted where the backend tracks bytes, so large files count.
+  if (payload?.bytesTotal && payload.bytesProcessed != null) {
+    return Math.min(100, Math.round((payload.bytesProcessed / payload.bytesTotal) * 100))
+  }
+  if (!payload || !payload.total) {
     return payload && payload.processed > 0 ? 100 : 0
   }
   return Math.min(100, Math.round((payload.processed / payload.total) * 100))
 }
 
+const SCAN_STAGE_LABELS: Record<ScanStage, string> = {
+  enumerate: 'Finding files',
+  snapshot: 'Reading file info',
+  quickHash: 'Checking for changes',
+  fullHash: 'Hashing',
+  exif: 'Reading EXIF',
+  persist: 'Saving',
+}
+
 function formatScanProgress(progress: ScanProgressPayload): string {
-  const stage = progress.stage.toUpperCase()
-  const counts = `${progress.processed}/${progress.total}`
+  const stage = SCAN_STAGE_LABELS[progress.stage]
+  const counts =
+    progress.total === null
+      ? `${progress.processed}`
+      : `${progress.processed}/${progress.total}`
   return progress.current
     ? `${stage} ${counts} — ${progress.current}`
     : `${stage} ${counts}`
@@ -592,7 +1047,15 @@ function formatScanProgress(progress: ScanProgressPayload): string {
 
 function formatExecutionProgress(progress: ExecutionProgressPayload): string {
   const stage = progress.stage === 'undo' ? 'UNDO' : 'EXECUTE'
-  const counts = `${progress.processed}/${progress.total}`
+  const eta = progress.etaMs != null ? `, ${formatDuration(progress.etaMs)} left` : ''
+  const bytes =
+    progress.bytesTotal != null
+      ? ` (${formatBytes(progress.bytesProcessed ?? 0)} of ${formatBytes(progress.bytesTotal)})`
+      : ''
+  const counts = `${progress.processed}/${progress.total}${bytes}${eta}`
+  if (progress.paused === 'diskFull') {
+    return `${stage} ${counts} — paused: disk full, free space to resume`
+  }
   return progress.current
     ? `${stage} ${counts} — ${progress.current}`
     : `${stage} ${counts}`
@@ -612,4 +1075,68 @@ function formatBytes(bytes: number): string {
   return `${value.toFixed(precision)} ${units[exponent]}`
 }
 
+function formatEstimate(estimate: ScanEstimate): string {
+  const known =
+    estimate.knownFiles > 0 ? `, ${estimate.knownFiles.toLocaleString()} already scanned` : ''
+  const cloud =
+    estimate.cloudFiles > 0
+      ? `, plus ${estimate.cloudFiles.toLocaleString()} still in the cloud`
+      : ''
+  const basis = estimate.measured ? '' : ' (before any scan measured this disk)'
+  return `About ${estimate.totalFiles.toLocaleString()} files / ${formatBytes(
+    estimate.totalBytes,
+  )}${known}${cloud}, estimated ${formatDuration(estimate.estimatedMs)}${basis}`
+}
+
+function formatDuration(ms: number): string {
+  const minutes = Math.round(ms / 60000)
+  if (minutes < 1) {
+    return 'under a minute'
+  }
+  if (minutes < 60) {
+    return `${minutes} min`
+  }
+  return `${Math.floor(minutes / 60)} h ${minutes % 60} min`
+}
+
+function describeReason(reason: PlanReason): string {
+  const { route } = reason
+  let placed: string
+  if (route.kind === 'rule') {
+    placed = `Rule "${route.name}" (${route.template})`
+  } else if (route.kind === 'duplicate') {
+    placed = route.confirmation
+      ? `Duplicate, confirmed by ${route.confirmation}`
+      : 'Duplicate'
+  } else if (route.template) {
+    placed = `Folder template ${route.template}`
+    if (route.skippedRule) {
+      placed += `; rule "${route.skippedRule}" had no value for its folder`
+    }
+  } else if (route.layout === 'original') {
+    placed = 'Original folder'
+    if (route.skippedRule) {
+      placed += `; rule "${route.skippedRule}" had no value for its folder`
+    }
+  } else {
+    placed = `Date folder (${route.layout}, by ${route.granularity})`
+    if (route.skippedRule) {
+      placed += `; rule "${route.skippedRule}" had no value for its folder`
+    }
+  }
+  const dated = reason.dateSource === 'captured' ? 'capture date' : 'file modified date'
+  const part = reason.splitPart ? `, overflow part ${reason.splitPart}` : ''
+  return `${placed}; named by ${dated}${part}`
+}
+
+function formatMetrics(metrics: RunMetrics): string {
+  const seconds = (metrics.elapsedMs / 1000).toFixed(1)
+  const stages = metrics.stages
+    .map((timing) => `${timing.stage} ${(timing.elapsedMs / 1000).toFixed(1)} s`)
+    .join(', ')
+  const rate =
+    metrics.throughput > 0 ? ` at ${formatBytes(metrics.throughput)}/s` : ''
+  return stages ? `${seconds} s${rate} (${stages})` : `${seconds} s${rate}`
+}
+
 export default App
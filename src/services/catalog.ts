@@ -0,0 +1,7 @@
+import { invoke } from "@tauri-apps/api/core"
+
+import type { CatalogImportSummary, CatalogKind } from "../types/catalog"
+
+export function importCatalog(kind: CatalogKind, path: string): Promise<CatalogImportSummary> {
+  return invoke<CatalogImportSummary>("import_catalog", { kind, path })
+}
@@ -1,14 +1,65 @@
 import { invoke } from "@tauri-apps/api/core"
 
-import type { AppConfig, RawConfigPayload } from "../types/config"
+import type {
+  AppConfig,
+  ConfigChanges,
+  RawConfigPayload,
+  RootRemap,
+  StateManifest,
+} from "../types/config"
 
 export const CONFIG_BOOTSTRAP_EVENT = "config://bootstrap"
+export const CONFIG_UPDATED_EVENT = "config://updated"
 
 export async function fetchBootstrapConfig(): Promise<AppConfig> {
   const payload = await invoke<RawConfigPayload>("bootstrap_paths")
   return normalizeConfig(payload)
 }
 
+export function detectRemaps(): Promise<RootRemap[]> {
+  return invoke<RootRemap[]>("detect_remaps")
+}
+
+export async function remapRoots(remaps: RootRemap[]): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("remap_roots", { remaps })
+  return normalizeConfig(payload)
+}
+
+export async function setImageRoot(path: string): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("set_image_root", { path })
+  return normalizeConfig(payload)
+}
+
+export async function setOutputRoot(path: string): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("set_output_root", { path })
+  return normalizeConfig(payload)
+}
+
+export async function setIncludedFolders(folders: string[]): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("set_included_folders", { folders })
+  return normalizeConfig(payload)
+}
+
+// Takes only the fields to change; derived ones such as databasePath are refused.
+export async function updateConfig(changes: ConfigChanges): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("update_config", { changes })
+  return normalizeConfig(payload)
+}
+
+export async function setSampleMode(root: string | null): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("set_sample_mode", { root })
+  return normalizeConfig(payload)
+}
+
+export function exportAppState(destination: string): Promise<StateManifest> {
+  return invoke<StateManifest>("export_app_state", { destination })
+}
+
+export async function importAppState(source: string): Promise<AppConfig> {
+  const payload = await invoke<RawConfigPayload>("import_app_state", { source })
+  return normalizeConfig(payload)
+}
+
 export function normalizeConfig(payload: RawConfigPayload): AppConfig {
   return {
     schemaVersion: payload.schema_version,
@@ -22,6 +73,40 @@ export function normalizeConfig(payload: RawConfigPayload): AppConfig {
     originInfoPath: payload.origin_info_json,
     targetPlanPath: payload.target_plan_json,
     imageExtensions: [...payload.image_exts].sort(),
+    extensionGroups: {
+      photo: payload.photo_exts ?? [],
+      video: payload.video_exts ?? [],
+      raw: payload.raw_exts ?? [],
+      sidecar: payload.sidecar_exts ?? [],
+    },
     sampleImageRoot: payload.sample_image_root ?? undefined,
+    routingRules: payload.routing_rules ?? [],
+    folderLayout: payload.folder_layout,
+    folderTemplate: payload.folder_template ?? undefined,
+    bucketGranularity: payload.bucket_granularity,
+    keepOriginalNames: payload.keep_original_names,
+    normalizeExtensions: payload.normalize_extensions,
+    nameTemplate: payload.name_template ?? undefined,
+    maxFilesPerFolder: payload.max_files_per_folder ?? undefined,
+    outputVolumes: payload.output_volumes ?? [],
+    share: payload.share,
+    transcode: payload.transcode,
+    export: payload.export,
+    photosLibrary: payload.photos_library,
+    honorNomedia: payload.honor_nomedia,
+    cloudPlaceholders: payload.cloud_placeholders,
+    followSymlinks: payload.follow_symlinks,
+    maxDepth: payload.max_depth ?? undefined,
+    maxFilesPerDirectory: payload.max_files_per_directory ?? undefined,
+    includeFolders: payload.include_folders ?? [],
+    extraImageRoots: payload.extra_image_roots ?? [],
+    pixelHash: payload.pixel_hash,
+    hashStrategy: payload.hash_strategy,
+    queues: payload.scan_queues,
+    scrub: payload.scrub,
+    power: payload.power,
+    io: payload.io,
+    telemetry: payload.telemetry,
+    pathStorage: payload.path_storage,
   }
 }
@@ -0,0 +1,19 @@
+import { invoke } from "@tauri-apps/api/core"
+
+import type { ExclusionRecord } from "../types/exclusion"
+
+export function excludeFile(path: string): Promise<ExclusionRecord> {
+  return invoke<ExclusionRecord>("exclude_file", { path })
+}
+
+export function excludePattern(pattern: string): Promise<ExclusionRecord> {
+  return invoke<ExclusionRecord>("exclude_pattern", { pattern })
+}
+
+export function listExclusions(): Promise<ExclusionRecord[]> {
+  return invoke<ExclusionRecord[]>("list_exclusions")
+}
+
+export function removeExclusion(id: number): Promise<boolean> {
+  return invoke<boolean>("remove_exclusion", { id })
+}
@@ -0,0 +1,19 @@
+import { invoke } from "@tauri-apps/api/core"
+
+import type { IndexSummary, RelocationReport, VerifyReport } from "../types/library"
+
+export const INDEX_PROGRESS_EVENT = "library://index-progress"
+export const VERIFY_PROGRESS_EVENT = "library://verify-progress"
+export const SCRUB_ERROR_EVENT = "library://scrub-error"
+
+export function indexOutput(): Promise<IndexSummary> {
+  return invoke<IndexSummary>("index_output")
+}
+
+export function verifyLibrary(): Promise<VerifyReport> {
+  return invoke<VerifyReport>("verify_library")
+}
+
+export function relocateLibrary(oldRoot: string, newRoot: string): Promise<RelocationReport> {
+  return invoke<RelocationReport>("relocate_library", { oldRoot, newRoot })
+}
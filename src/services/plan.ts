@@ -1,10 +1,19 @@
 import { invoke } from "@tauri-apps/api/core"
 
 import type {
+  AuditExport,
   ExecutionMode,
   ExecutionSummary,
+  ExportSummary,
+  FileExplanation,
+  PlanDiff,
+  PlanOverrides,
+  PlanPreview,
+  PlanSource,
   PlanSummary,
+  RuleReport,
   UndoSummary,
+  VerificationSummary,
 } from "../types/plan"
 
 export const PLAN_PROGRESS_EVENT = "plan://progress"
@@ -14,10 +23,42 @@ export function planTargets(): Promise<PlanSummary> {
   return invoke<PlanSummary>("plan_targets")
 }
 
-export function executePlan(mode: ExecutionMode, dryRun = false): Promise<ExecutionSummary> {
-  return invoke<ExecutionSummary>("execute_plan", { mode, dryRun })
+export function previewPlan(configOverrides: PlanOverrides): Promise<PlanPreview> {
+  return invoke<PlanPreview>("preview_plan", { configOverrides })
+}
+
+export function diffPlans(planA: PlanSource, planB: PlanSource): Promise<PlanDiff> {
+  return invoke<PlanDiff>("diff_plans", { planA, planB })
+}
+
+export function explainRules(): Promise<RuleReport[]> {
+  return invoke<RuleReport[]>("explain_rules")
+}
+
+export function explainFile(pathOrHash: string): Promise<FileExplanation> {
+  return invoke<FileExplanation>("explain_file", { pathOrHash })
+}
+
+export function executePlan(
+  mode: ExecutionMode,
+  dryRun = false,
+  verify = false,
+): Promise<ExecutionSummary> {
+  return invoke<ExecutionSummary>("execute_plan", { mode, dryRun, verify })
+}
+
+export function verifyExecution(): Promise<VerificationSummary> {
+  return invoke<VerificationSummary>("verify_execution")
 }
 
 export function undoMoves(): Promise<UndoSummary> {
   return invoke<UndoSummary>("undo_moves")
-}
\ No newline at end of file
+}
+
+export function exportResized(): Promise<ExportSummary> {
+  return invoke<ExportSummary>("export_resized")
+}
+
+export function exportAuditLog(destination: string): Promise<AuditExport> {
+  return invoke<AuditExport>("export_audit_log", { destination })
+}
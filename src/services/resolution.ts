@@ -0,0 +1,45 @@
+import { invoke } from "@tauri-apps/api/core"
+
+import type {
+  DeletionPreview,
+  DeletionSummary,
+  DuplicateDecision,
+  DuplicateDecisionRecord,
+  DuplicateGroup,
+} from "../types/resolution"
+
+export function listDuplicateGroups(): Promise<DuplicateGroup[]> {
+  return invoke<DuplicateGroup[]>("list_duplicate_groups")
+}
+
+export function decideDuplicateGroup(
+  groupId: string,
+  decision: DuplicateDecision | null,
+): Promise<DuplicateDecisionRecord | null> {
+  return invoke<DuplicateDecisionRecord | null>("decide_duplicate_group", {
+    groupId,
+    decision,
+  })
+}
+
+export function prepareDuplicateDeletion(
+  groupIds: string[],
+  toTrash: boolean,
+): Promise<DeletionPreview> {
+  return invoke<DeletionPreview>("prepare_duplicate_deletion", {
+    groupIds,
+    toTrash,
+  })
+}
+
+export function deleteDuplicates(
+  groupIds: string[],
+  toTrash: boolean,
+  token: string,
+): Promise<DeletionSummary> {
+  return invoke<DeletionSummary>("delete_duplicates", {
+    groupIds,
+    toTrash,
+    token,
+  })
+}
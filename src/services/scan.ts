@@ -1,9 +1,36 @@
 import { invoke } from "@tauri-apps/api/core"
 
-import type { ScanSummary } from "../types/scan"
+import type {
+  FileChangeRecord,
+  FolderNode,
+  ScanEstimate,
+  ScanDiff,
+  ScanHistoryEntry,
+  ScanSummary,
+} from "../types/scan"
 
 export const SCAN_PROGRESS_EVENT = "scan://progress"
 
 export async function scanMedia(): Promise<ScanSummary> {
   return invoke<ScanSummary>("scan_media")
+}
+export async function listFolders(
+  path?: string,
+  depth?: number,
+): Promise<FolderNode[]> {
+  return invoke<FolderNode[]>("list_folders", { path, depth })
+}
+export async function estimateScan(): Promise<ScanEstimate> {
+  return invoke<ScanEstimate>("estimate_scan")
+}
+export async function lastScanDiff(): Promise<ScanDiff | null> {
+  return invoke<ScanDiff | null>("last_scan_diff")
+}
+export async function scanHistory(): Promise<ScanHistoryEntry[]> {
+  return invoke<ScanHistoryEntry[]>("scan_history")
+}
+export async function fileHistory(
+  relativePath: string,
+): Promise<FileChangeRecord[]> {
+  return invoke<FileChangeRecord[]>("file_history", { relativePath })
 }
\ No newline at end of file
@@ -1,7 +1,55 @@
 import { invoke } from "@tauri-apps/api/core"
 
-import type { DiskStatus } from "../types/system"
+import type {
+  DiagnosticsManifest,
+  DiskStatus,
+  PermissionReport,
+  PreflightReport,
+  ProfileNode,
+  ProgressSnapshot,
+  ReplayedEvent,
+  SelfTestReport,
+  TaskKind,
+} from "../types/system"
 
 export async function checkDiskSpace(): Promise<DiskStatus> {
   return invoke<DiskStatus>("check_disk_space")
-}
\ No newline at end of file
+}
+export async function checkPermissions(): Promise<PermissionReport> {
+  return invoke<PermissionReport>("check_permissions")
+}
+
+// Scans fail with this prefix when the OS refused to list the source.
+export function isPermissionDenied(message: string | undefined): boolean {
+  return message?.startsWith("permission denied:") ?? false
+}
+export async function currentProgress(): Promise<ProgressSnapshot> {
+  return invoke<ProgressSnapshot>("current_progress")
+}
+export async function cancelTask(): Promise<TaskKind | null> {
+  return invoke<TaskKind | null>("cancel_task")
+}
+export async function replayEvents<P = unknown>(
+  channel: string,
+  since?: number,
+): Promise<ReplayedEvent<P>[]> {
+  return invoke<ReplayedEvent<P>[]>("replay_events", { channel, since })
+}
+export async function createDiagnosticsBundle(
+  destination: string,
+  redactPaths: boolean,
+): Promise<DiagnosticsManifest> {
+  return invoke<DiagnosticsManifest>("create_diagnostics_bundle", {
+    destination,
+    redactPaths,
+  })
+}
+export async function profileLastRun(): Promise<ProfileNode | null> {
+  return invoke<ProfileNode | null>("profile_last_run")
+}
+export async function runPreflight(): Promise<PreflightReport> {
+  return invoke<PreflightReport>("preflight")
+}
+export async function runSelfTest(): Promise<SelfTestReport> {
+  return invoke<SelfTestReport>("self_test")
+}
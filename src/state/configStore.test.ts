@@ -29,7 +29,10 @@ describe("config store", () => {
       originInfoPath: "/origin.json",
       targetPlanPath: "/plan.json",
       imageExtensions: [".jpg"],
+      extensionGroups: { photo: [".jpg"], video: [], raw: [], sidecar: [] },
       sampleImageRoot: undefined,
+      routingRules: [],
+      outputVolumes: [],
     }
     mockedFetch.mockResolvedValue(payload)
 
@@ -2,6 +2,7 @@ import { create } from "zustand"
 
 import { executePlan, planTargets, undoMoves } from "../services/plan"
 import { scanMedia } from "../services/scan"
+import { currentProgress } from "../services/system"
 import type {
   ExecutionMode,
   ExecutionProgressPayload,
@@ -11,6 +12,7 @@ import type {
   UndoSummary,
 } from "../types/plan"
 import type { ScanProgressPayload, ScanSummary } from "../types/scan"
+import type { ProgressSnapshot } from "../types/system"
 
 export type StageStatus = "idle" | "running" | "success" | "error"
 
@@ -31,11 +33,32 @@ interface WorkflowState {
   setExecutionProgress: (payload: ExecutionProgressPayload) => void
   runScan: () => Promise<void>
   generatePlan: () => Promise<void>
-  runExecution: (mode: ExecutionMode, dryRun: boolean) => Promise<void>
+  runExecution: (mode: ExecutionMode, dryRun: boolean, verify: boolean) => Promise<void>
   runUndo: () => Promise<void>
+  restoreProgress: () => Promise<void>
   resetAfterConfig: () => void
 }
 
+const RESTORE_POLL_MS = 1000
+
+function restoredOperationState<P, S>(
+  snapshot: ProgressSnapshot,
+  progress: P | null,
+): OperationState<P, S> {
+  const status: StageStatus =
+    snapshot.state === "running"
+      ? "running"
+      : snapshot.state === "failed"
+        ? "error"
+        : "success"
+  return {
+    status,
+    progress,
+    summary: null,
+    error: snapshot.error ?? undefined,
+  }
+}
+
 function initialOperationState<P, S>(): OperationState<P, S> {
   return {
     status: "idle",
@@ -126,7 +149,7 @@ export const useWorkflowStore = create<WorkflowState>((set, get) => ({
     }
   },
 
-  async runExecution(mode, dryRun) {
+  async runExecution(mode, dryRun, verify) {
     if (get().execution.status === "running") {
       return
     }
@@ -136,7 +159,7 @@ export const useWorkflowStore = create<WorkflowState>((set, get) => ({
     })
 
     try {
-      const summary = await executePlan(mode, dryRun)
+      const summary = await executePlan(mode, dryRun, verify)
       set((state) => ({
         execution: { ...state.execution, status: "success", summary, error: undefined },
       }))
@@ -170,6 +193,43 @@ export const useWorkflowStore = create<WorkflowState>((set, get) => ({
     }
   },
 
+  // A reloaded window lost the invoke that started the running task, so
+  // follow it through the backend's progress cache until it ends.
+  async restoreProgress() {
+    let snapshot = await currentProgress()
+    const task = snapshot.task
+    if (snapshot.state !== "running" || !task) {
+      return
+    }
+
+    for (;;) {
+      const current = snapshot
+      switch (task) {
+        case "scan":
+          set({ scan: restoredOperationState(current, current.scan) })
+          break
+        case "plan":
+          set({ plan: restoredOperationState(current, current.plan) })
+          break
+        case "execute":
+        case "export":
+          set({ execution: restoredOperationState(current, current.execute) })
+          break
+        case "undo":
+          set({ undo: restoredOperationState(current, current.execute) })
+          break
+      }
+      if (current.state !== "running") {
+        return
+      }
+      await new Promise((resolve) => setTimeout(resolve, RESTORE_POLL_MS))
+      snapshot = await currentProgress()
+      if (snapshot.task !== task) {
+        return
+      }
+    }
+  },
+
   resetAfterConfig() {
     set({
       scan: initialOperationState<ScanProgressPayload, ScanSummary>(),
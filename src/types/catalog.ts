@@ -0,0 +1,9 @@
+export type CatalogKind = "digikam" | "lightroom"
+
+export interface CatalogImportSummary {
+  kind: CatalogKind
+  readEntries: number
+  importedEntries: number
+  outsideRoot: number
+  seededRecords: number
+}
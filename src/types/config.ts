@@ -1,3 +1,5 @@
+import type { BucketGranularity, FolderLayout } from "./plan"
+
 export interface RawConfigPayload {
   schema_version: number
   database_path: string
@@ -10,7 +12,113 @@ export interface RawConfigPayload {
   origin_info_json: string
   target_plan_json: string
   image_exts: string[]
+  photo_exts?: string[]
+  video_exts?: string[]
+  raw_exts?: string[]
+  sidecar_exts?: string[]
   sample_image_root?: string | null
+  routing_rules?: RoutingRule[]
+  folder_layout?: FolderLayout
+  folder_template?: string | null
+  bucket_granularity?: BucketGranularity
+  keep_original_names?: boolean
+  normalize_extensions?: boolean
+  name_template?: string | null
+  max_files_per_folder?: number | null
+  output_volumes?: OutputVolume[]
+  share?: ShareOptions
+  transcode?: TranscodeOptions
+  export?: ExportOptions
+  photos_library?: PhotosLibraryMode
+  honor_nomedia?: boolean
+  cloud_placeholders?: CloudPlaceholderMode
+  follow_symlinks?: boolean
+  max_depth?: number | null
+  max_files_per_directory?: number | null
+  include_folders?: string[]
+  extra_image_roots?: string[]
+  pixel_hash?: boolean
+  hash_strategy?: HashStrategy
+  scan_queues?: ScanQueues
+  scrub?: ScrubOptions
+  power?: PowerOptions
+  io?: IoOptions
+  telemetry?: TelemetryOptions
+  path_storage?: PathStorage
+}
+
+export type PhotosLibraryMode = "skip" | "originals"
+
+export type CloudPlaceholderMode = "skip" | "hydrate" | "fail"
+
+export type HashStrategy = "full" | "sizeGrouped"
+
+export interface ScanQueues {
+  hash: number
+  persist: number
+}
+
+export type PathStorage = "absolute" | "portable"
+
+export interface RoutingRule {
+  name: string
+  pathContains?: string | null
+  extensions: string[]
+  target: string
+  stripMetadata?: boolean
+}
+
+export interface OutputVolume {
+  root: string
+  minFreeBytes: number
+}
+
+export interface ShareOptions {
+  root: string | null
+  stripGps: boolean
+}
+
+export interface ExportOptions {
+  root: string | null
+  maxEdge: number
+  quality: number
+}
+
+export type OriginalsPolicy = "keep" | "discard"
+
+export interface TranscodeOptions {
+  heicToJpeg: boolean
+  quality: number
+  originals: OriginalsPolicy
+}
+
+export interface ScrubOptions {
+  enabled: boolean
+  bytesPerHour: number
+  rotationWeeks: number
+}
+
+export type BatteryMode = "ignore" | "throttle" | "pause"
+
+export interface PowerOptions {
+  batteryMode: BatteryMode
+  batteryThreshold: number
+}
+
+export type StorageKind = "ssd" | "hdd" | "network"
+
+export interface IoOptions {
+  storage: StorageKind | null
+  hashThreads: number | null
+  verifyMoves: boolean
+  freeSpaceReserve: number
+  removeEmptyFolders: boolean
+}
+
+/** Only builds with the `telemetry` feature export anything. */
+export interface TelemetryOptions {
+  prometheusFile: string | null
+  otlpEndpoint: string | null
 }
 
 export interface AppConfig {
@@ -25,5 +133,98 @@ export interface AppConfig {
   originInfoPath: string
   targetPlanPath: string
   imageExtensions: string[]
+  extensionGroups: ExtensionGroups
   sampleImageRoot?: string
+  routingRules: RoutingRule[]
+  folderLayout?: FolderLayout
+  folderTemplate?: string
+  bucketGranularity?: BucketGranularity
+  keepOriginalNames?: boolean
+  normalizeExtensions?: boolean
+  nameTemplate?: string
+  maxFilesPerFolder?: number
+  outputVolumes: OutputVolume[]
+  share?: ShareOptions
+  transcode?: TranscodeOptions
+  export?: ExportOptions
+  photosLibrary?: PhotosLibraryMode
+  honorNomedia?: boolean
+  cloudPlaceholders?: CloudPlaceholderMode
+  followSymlinks?: boolean
+  maxDepth?: number
+  maxFilesPerDirectory?: number
+  includeFolders: string[]
+  extraImageRoots: string[]
+  pixelHash?: boolean
+  hashStrategy?: HashStrategy
+  queues?: ScanQueues
+  scrub?: ScrubOptions
+  power?: PowerOptions
+  io?: IoOptions
+  telemetry?: TelemetryOptions
+  pathStorage?: PathStorage
+}
+
+/** Fields `update_config` takes, named like `RawConfigPayload`'s in camelCase. */
+export interface ConfigChanges {
+  imageRoot?: string
+  outputRoot?: string
+  duplicatesFolderName?: string
+  originInfoJson?: string
+  targetPlanJson?: string
+  photoExts?: string[]
+  videoExts?: string[]
+  rawExts?: string[]
+  sidecarExts?: string[]
+  routingRules?: RoutingRule[]
+  folderLayout?: FolderLayout
+  folderTemplate?: string | null
+  bucketGranularity?: BucketGranularity
+  keepOriginalNames?: boolean
+  normalizeExtensions?: boolean
+  nameTemplate?: string | null
+  maxFilesPerFolder?: number | null
+  outputVolumes?: OutputVolume[]
+  share?: ShareOptions
+  transcode?: TranscodeOptions
+  export?: ExportOptions
+  photosLibrary?: PhotosLibraryMode
+  honorNomedia?: boolean
+  cloudPlaceholders?: CloudPlaceholderMode
+  followSymlinks?: boolean
+  maxDepth?: number | null
+  maxFilesPerDirectory?: number | null
+  includeFolders?: string[]
+  extraImageRoots?: string[]
+  pixelHash?: boolean
+  hashStrategy?: HashStrategy
+  scanQueues?: ScanQueues
+  scrub?: ScrubOptions
+  pathStorage?: PathStorage
+  power?: PowerOptions
+  io?: IoOptions
+  telemetry?: TelemetryOptions
+}
+
+export interface ExtensionGroups {
+  photo: string[]
+  video: string[]
+  raw: string[]
+  sidecar: string[]
+}
+
+export interface StateManifest {
+  formatVersion: number
+  appVersion: string
+  configSchemaVersion: number
+  databaseVersion: number
+  exportedAt: string
+  artifacts: string[]
+}
+
+export interface RootRemap {
+  kind: "image" | "output"
+  oldRoot: string
+  newRoot: string
+  matchedSamples: number
 }
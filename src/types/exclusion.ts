@@ -0,0 +1,9 @@
+export type ExclusionKind = "hash" | "pattern"
+
+export interface ExclusionRecord {
+  id: number
+  kind: ExclusionKind
+  value: string
+  note?: string | null
+  createdAt: string
+}
@@ -0,0 +1,49 @@
+export interface IndexSummary {
+  indexedAt: string
+  roots: string[]
+  indexedFiles: number
+  hashedFiles: number
+  unchangedFiles: number
+  removedFiles: number
+  totalBytes: number
+}
+
+export interface ModifiedFile {
+  path: string
+  expectedHash: string
+  actualHash: string
+}
+
+export interface VerifyReport {
+  verifiedAt: string
+  clean: boolean
+  checkedFiles: number
+  intactFiles: number
+  missing: string[]
+  modified: ModifiedFile[]
+  extra: string[]
+}
+
+export interface LibraryProgressPayload {
+  stage: "index" | "verify"
+  processed: number
+  total: number
+  current?: string
+}
+
+export interface ScrubReport {
+  scrubbedAt: string
+  checkedFiles: number
+  checkedBytes: number
+  missing: string[]
+  modified: ModifiedFile[]
+  wrapped: boolean
+}
+
+export interface RelocationReport {
+  oldRoot: string
+  newRoot: string
+  planEntriesUpdated: number
+  libraryEntriesUpdated: number
+  sampledFiles: number
+}
@@ -1,3 +1,7 @@
+import type { RoutingRule } from "./config"
+import type { DiffGroup } from "./scan"
+import type { HoldReason, RunMetrics } from "./system"
+
 export type ExecutionMode = "copy" | "move"
 
 export interface PlanItem {
@@ -8,6 +12,132 @@ export interface PlanItem {
   newFileName: string
   newPath: string
   isDuplicate: boolean
+  volume?: string | null
+  reason: PlanReason
+}
+
+export type FolderLayout =
+  | "flat"
+  | "yearMonthDay"
+  | "yearMonth"
+  | "single"
+  | "original"
+
+export type DuplicatesLayout = "flat" | "original" | "bucket"
+
+export type BucketGranularity = "day" | "week" | "month" | "year"
+
+export interface GranularityPeriod {
+  from?: number | null
+  to?: number | null
+  granularity: BucketGranularity
+}
+
+export type PlanRoute =
+  | { kind: "rule"; name: string; template: string }
+  | {
+      kind: "dateBucket"
+      layout: FolderLayout
+      template?: string | null
+      granularity: BucketGranularity
+      skippedRule: string | null
+    }
+  | { kind: "duplicate"; confirmation: "size" | "blake3" | "pixels" | null }
+
+export interface PlanReason {
+  route: PlanRoute
+  dateSource: "captured" | "modified"
+  splitPart: number | null
+}
+
+/** Plan settings keyed as in config.json; only the ones given change. */
+export interface PlanOverrides {
+  routingRules?: RoutingRule[]
+  maxFilesPerFolder?: number | null
+  folderLayout?: FolderLayout
+  folderTemplate?: string | null
+  duplicatesLayout?: DuplicatesLayout
+  bucketGranularity?: BucketGranularity
+  granularityPeriods?: GranularityPeriod[]
+  keepOriginalNames?: boolean
+  normalizeExtensions?: boolean
+  nameTimestamp?: string
+  nameTemplate?: string | null
+  artistFallback?: string
+  includeDerivatives?: boolean
+}
+
+export interface BucketNode {
+  name: string
+  path: string
+  files: number
+  totalBytes: number
+  children: BucketNode[]
+}
+
+export interface PlanPreview {
+  totalEntries: number
+  duplicateEntries: number
+  uniqueEntries: number
+  destinationBuckets: number
+  excludedEntries: number
+  derivativeEntries: number
+  undecidedEntries: number
+  totalBytes: number
+  ruleReports: RuleReport[]
+  tree: BucketNode[]
+  entries: PlanItem[]
+}
+
+export type PlanSource =
+  | { kind: "current" }
+  | { kind: "previous" }
+  | { kind: "preview"; overrides: PlanOverrides }
+
+export interface PlannedTarget {
+  origin: string
+  target: string
+}
+
+export interface RetargetedFile {
+  origin: string
+  from: string
+  to: string
+}
+
+export interface PlanDiff {
+  added: DiffGroup<PlannedTarget>
+  removed: DiffGroup<PlannedTarget>
+  changed: DiffGroup<RetargetedFile>
+  unchanged: number
+}
+
+export interface PlanStep {
+  step: "date" | "bucket" | "duplicate" | "rule" | "output" | "split" | "name" | "collision"
+  detail: string
+}
+
+export interface FileExplanation {
+  relativePath: string
+  originFullPath: string
+  leftOut: "excluded" | "derivative" | "undecided" | null
+  newPath: string | null
+  newFileName: string | null
+  reason: PlanReason | null
+  steps: PlanStep[]
+}
+
+export interface RuleSample {
+  relativePath: string
+  targetDir: string
+}
+
+export interface RuleReport {
+  order: number
+  name: string
+  target: string
+  matched: number
+  samples: RuleSample[]
 }
 
 export interface PlanSummary {
@@ -16,9 +146,14 @@ export interface PlanSummary {
   duplicateEntries: number
   uniqueEntries: number
   destinationBuckets: number
+  excludedEntries: number
+  derivativeEntries: number
+  undecidedEntries: number
   totalBytes: number
   planJsonPath: string
+  ruleReports: RuleReport[]
   entries: PlanItem[]
+  metrics: RunMetrics
 }
 
 export interface PlanProgressPayload {
@@ -26,6 +161,7 @@ export interface PlanProgressPayload {
   processed: number
   total: number
   current?: string
+  cancelled: boolean
 }
 
 export interface ExecutionSummary {
@@ -35,14 +171,45 @@ export interface ExecutionSummary {
   processedEntries: number
   succeeded: number
   failed: number
+  alreadyPresent: number
   duplicateEntries: number
+  transcoded: number
+  metadataStripped: number
+  shared: number
+  locked: number
+  removedFolders: number
+  verified: number
+  reportPath: string | null
+  metrics: RunMetrics
+}
+
+export interface ExportSummary {
+  exportRoot: string
+  totalEntries: number
+  exported: number
+  upToDate: number
+  skipped: number
+  failed: number
 }
 
 export interface ExecutionProgressPayload {
-  stage: "execute" | "undo"
+  stage: "execute" | "undo" | "export" | "verify"
   processed: number
   total: number
   current?: string
+  paused?: HoldReason | null
+  bytesProcessed?: number | null
+  bytesTotal?: number | null
+  etaMs?: number | null
+  cancelled: boolean
+}
+
+export interface VerificationSummary {
+  checked: number
+  matched: number
+  mismatched: number
+  missing: number
+  skipped: number
 }
 
 export interface UndoSummary {
@@ -50,4 +217,11 @@ export interface UndoSummary {
   restored: number
   missing: number
   failed: number
-}
\ No newline at end of file
+  removedFolders: number
+}
+export interface AuditExport {
+  path: string
+  appended: number
+  totalLines: number
+  headHash: string
+}
@@ -0,0 +1,56 @@
+export type DuplicateDecision =
+  | { kind: "keep"; keeper: string }
+  | { kind: "keepAll" }
+  | { kind: "later" }
+
+export interface DuplicateDecisionRecord {
+  groupId: string
+  decision: DuplicateDecision
+  decidedAt: string
+}
+
+export interface GroupFile {
+  relativePath: string
+  fileSize: number
+  capturedAt?: string | null
+  confirmation: "size" | "blake3" | "pixels" | null
+}
+
+export interface DuplicateGroup {
+  id: string
+  files: GroupFile[]
+  automaticKeeper: string
+  decision: DuplicateDecisionRecord | null
+}
+
+export interface DeletionCandidate {
+  groupId: string
+  relativePath: string
+  keeper: string
+  fileSize: number
+}
+
+export interface SkippedGroup {
+  groupId: string
+  reason: string
+}
+
+/** Pass `token` back to `deleteDuplicates` to confirm this list. */
+export interface DeletionPreview {
+  token: string
+  files: DeletionCandidate[]
+  totalBytes: number
+  skipped: SkippedGroup[]
+}
+
+export interface FailedDeletion {
+  relativePath: string
+  error: string
+}
+
+export interface DeletionSummary {
+  deleted: number
+  freedBytes: number
+  trashDir: string | null
+  failed: FailedDeletion[]
+}
@@ -1,15 +1,135 @@
+import type { RunMetrics } from "./system"
+
+export interface MediaCounts {
+  photos: number
+  videos: number
+  raw: number
+}
+
+export interface ScanLimitHits {
+  deepFolders: string[]
+  crowdedFolders: string[]
+  filesLeftOut: number
+}
+
+export interface SparseFiles {
+  files: number
+  apparentBytes: number
+  allocatedBytes: number
+}
+
 export interface ScanSummary {
   totalFiles: number
   hashedFiles: number
   skippedFiles: number
   duplicateFiles: number
+  derivativeFiles: number
+  mediaCounts: MediaCounts
+  skippedPhotosLibraries: string[]
+  scannedPhotosLibraries: string[]
+  nomediaFolders: string[]
+  ignoredEntries: number
+  skippedOutputFolders: number
+  followedLinks: string[]
+  hardlinkedFiles: number
+  specialFiles: string[]
+  sparseFiles: SparseFiles
+  limitHits: ScanLimitHits
+  cloudPlaceholders: string[]
+  hydratedPlaceholders: number
+  resumedFiles: number
+  unhashedFiles: number
+  cachedFiles: number
+  journalFolders: number | null
+  diff: ScanDiff
+  metrics: RunMetrics
 }
 
-export type ScanStage = "scan" | "diff" | "hash"
+export interface DiffGroup<T> {
+  count: number
+  items: T[]
+}
+
+export interface MovedFile {
+  from: string
+  to: string
+}
+
+export type ChangeKind = "added" | "removed" | "changed" | "moved"
+
+export interface ScanHistoryEntry {
+  id: number
+  scannedAt: string
+  root: string
+  totalFiles: number
+  totalBytes: number
+  photos: number
+  videos: number
+  raw: number
+  duplicateFiles: number
+  added: number
+  removed: number
+  changed: number
+  moved: number
+}
+
+export interface FileChangeRecord {
+  scanId: number
+  scannedAt: string
+  kind: ChangeKind
+  relativePath: string
+  previousPath: string | null
+}
+
+export interface ScanEstimate {
+  totalFiles: number
+  totalBytes: number
+  knownFiles: number
+  bytesToRead: number
+  /** Cloud placeholders, not counted above; the estimate never downloads them. */
+  cloudFiles: number
+  cloudBytes: number
+  throughput: number
+  measured: boolean
+  estimatedMs: number
+}
+
+export interface FolderNode {
+  name: string
+  path: string
+  relativePath: string | null
+  mediaFiles: number
+  totalBytes: number
+  children: FolderNode[]
+  hasSubfolders: boolean
+}
+
+export interface ScanDiff {
+  added: DiffGroup<string>
+  removed: DiffGroup<string>
+  changed: DiffGroup<string>
+  moved: DiffGroup<MovedFile>
+}
+
+export type ScanStage =
+  | "enumerate"
+  | "snapshot"
+  | "quickHash"
+  | "fullHash"
+  | "exif"
+  | "persist"
+
+export interface StageProgress {
+  stage: ScanStage
+  processed: number
+  total: number | null
+}
 
 export interface ScanProgressPayload {
   stage: ScanStage
   processed: number
-  total: number
+  total: number | null
   current?: string
+  stages: StageProgress[]
+  cancelled: boolean
 }
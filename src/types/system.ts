@@ -1,5 +1,129 @@
+import type { ExecutionProgressPayload, PlanProgressPayload } from "./plan"
+import type { ScanProgressPayload } from "./scan"
+
+export interface StageTiming {
+  stage: string
+  elapsedMs: number
+}
+
+export interface RunMetrics {
+  elapsedMs: number
+  bytesRead: number
+  bytesWritten: number
+  /** Bytes per second, counting a copy's read and write once. */
+  throughput: number
+  stages: StageTiming[]
+}
+
+/** One span of the last run's timing tree; same-named siblings are merged. */
+export interface ProfileNode {
+  name: string
+  elapsedMs: number
+  calls: number
+  children: ProfileNode[]
+}
+
+export interface DiagnosticsManifest {
+  path: string
+  createdAt: string
+  appVersion: string
+  os: string
+  arch: string
+  redacted: boolean
+  files: string[]
+}
+
+export type SelfTestStepStatus = "passed" | "failed" | "skipped"
+
+export interface SelfTestStep {
+  name: string
+  status: SelfTestStepStatus
+  detail: string
+  elapsedMs: number
+}
+
+export interface SelfTestReport {
+  passed: boolean
+  steps: SelfTestStep[]
+  elapsedMs: number
+}
+
 export interface DiskStatus {
   path: string
   availableBytes: number
   totalBytes: number
-}
\ No newline at end of file
+  network: boolean
+}
+export interface VolumeCheck {
+  root: string
+  requiredBytes: number
+  reserveBytes: number
+  availableBytes: number
+  totalBytes: number
+  ok: boolean
+}
+
+export type PathConflictCode =
+  | "same_folder"
+  | "output_inside_source"
+  | "source_inside_output"
+  | "duplicates_outside_output"
+  | "sources_overlap"
+
+export type PreflightCheckKind =
+  | "sourceReadable"
+  | "outputWritable"
+  | "outputOutsideSource"
+  | "duplicatesInsideOutput"
+  | "diskSpace"
+  | "databaseHealthy"
+  | "planFresh"
+
+export interface PreflightCheck {
+  kind: PreflightCheckKind
+  ok: boolean
+  detail: string
+  code: PathConflictCode | null
+}
+
+export interface PreflightReport {
+  ok: boolean
+  checks: PreflightCheck[]
+  volumes: VolumeCheck[]
+}
+
+export interface PermissionReport {
+  ok: boolean
+  denied: string[]
+  settingsUrl: string | null
+}
+
+export type TaskKind =
+  | "scan"
+  | "plan"
+  | "execute"
+  | "undo"
+  | "export"
+  | "maintenance"
+
+export type HoldReason = "battery" | "diskFull"
+
+export type TaskState = "idle" | "running" | "succeeded" | "failed"
+
+export interface ProgressSnapshot {
+  task: TaskKind | null
+  state: TaskState
+  paused: boolean
+  held: HoldReason[]
+  throttled: boolean
+  error: string | null
+  scan: ScanProgressPayload | null
+  plan: PlanProgressPayload | null
+  execute: ExecutionProgressPayload | null
+}
+
+export interface ReplayedEvent<P = unknown> {
+  sequence: number
+  channel: string
+  payload: P
+}